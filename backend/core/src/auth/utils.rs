@@ -11,10 +11,7 @@ pub async fn get_current_user(req: &Request, pool: &PgPool) -> PoemResult<super:
             if let Some(api_key) = auth_str.strip_prefix("Bearer ") {
                 if api_key.starts_with("sk_") {
                     // It's an API Key. Validate it.
-                    // Hash the secret
-                    let mut hasher = Sha256::new();
-                    hasher.update(api_key.as_bytes());
-                    let key_hash = hex::encode(hasher.finalize());
+                    let key_hash = hash_api_key(api_key);
                     
                     let now = chrono::Utc::now().timestamp();
 
@@ -89,6 +86,24 @@ pub async fn get_current_user(req: &Request, pool: &PgPool) -> PoemResult<super:
     Ok(user)
 }
 
+/// SHA-256 hex digest of an API key, as stored in `api_keys.key_hash`
+pub fn hash_api_key(api_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(api_key.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Hash of the `sk_` API key in the Authorization header, if the request used one
+pub fn api_key_hash(req: &Request) -> Option<String> {
+    req.headers()
+        .get(poem::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .filter(|key| key.starts_with("sk_"))
+        .map(hash_api_key)
+}
+
 pub fn get_session_token(req: &Request) -> Option<String> {
     let cookie_header = req.headers().get(poem::http::header::COOKIE)?.to_str().ok()?;
     for cookie in cookie_header.split(';') {
@@ -15,6 +15,35 @@ pub struct Provider {
     pub description: Option<String>,
     pub created_at: Option<i64>,
     pub icon_url: Option<String>,
+    // Per-key limits the provider enforces; the gateway throttles to stay under them
+    #[sqlx(default)]
+    pub requests_per_minute: Option<i32>,
+    #[sqlx(default)]
+    pub tokens_per_minute: Option<i32>,
+    // Calls in flight at once across the provider; more wait in the admission queue
+    #[sqlx(default)]
+    pub max_concurrent: Option<i32>,
+    // How calls pick from the provider's key pool: round_robin, least_used or remaining_quota
+    #[sqlx(default)]
+    pub key_selection: Option<String>,
+}
+
+/// Key selection policies for providers with pooled keys
+pub const KEY_SELECTION_POLICIES: [&str; 3] = ["round_robin", "least_used", "remaining_quota"];
+
+// Pooled API keys (belong to providers)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProviderKey {
+    pub id: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    #[serde(skip_serializing)]
+    pub api_key: String,
+    pub api_endpoint: Option<String>,
+    pub enabled: bool,
+    pub quarantined_at: Option<i64>,
+    pub quarantine_reason: Option<String>,
+    pub created_at: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,6 +56,10 @@ pub struct CreateProvider {
     pub api_key: Option<String>,
     pub description: Option<String>,
     pub icon_url: Option<String>,
+    pub requests_per_minute: Option<i32>,
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrent: Option<i32>,
+    pub key_selection: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +72,10 @@ pub struct UpdateProvider {
     pub api_key: Option<String>,
     pub description: Option<String>,
     pub icon_url: Option<String>,
+    pub requests_per_minute: Option<i32>,
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrent: Option<i32>,
+    pub key_selection: Option<String>,
 }
 
 // Models (belong to providers)
@@ -63,9 +100,39 @@ pub struct Model {
     pub avg_ttft_ms: i32, // Time to first token
     pub max_tps: i32, // Tokens per second
     
-    // Limits
+    // Limits (None = unknown, not pre-checked)
+    #[sqlx(default)]
+    pub context_window: Option<i32>,
+    #[sqlx(default)]
+    pub max_output_tokens: Option<i32>,
+    
+    // Capabilities (None = unknown, treated as supported)
+    #[sqlx(default)]
+    pub supports_vision: Option<bool>,
+    #[sqlx(default)]
+    pub supports_tools: Option<bool>,
+    #[sqlx(default)]
+    pub supports_json_mode: Option<bool>,
+    #[sqlx(default)]
+    pub supports_json_schema: Option<bool>,
+    #[sqlx(default)]
+    pub supports_streaming: Option<bool>,
+    #[sqlx(default)]
+    pub supports_reasoning: Option<bool>,
+    
+    // Timeouts in milliseconds (None = inherit from the service, then the default)
+    #[sqlx(default)]
+    pub connect_timeout_ms: Option<i32>,
+    #[sqlx(default)]
+    pub timeout_ms: Option<i32>,
+    #[sqlx(default)]
+    pub first_token_timeout_ms: Option<i32>,
+    #[sqlx(default)]
+    pub idle_timeout_ms: Option<i32>,
+    
+    // Calls in flight at once (None = unlimited); more wait in the admission queue
     #[sqlx(default)]
-    pub context_window: i32, 
+    pub max_concurrent: Option<i32>,
     
     pub api_endpoint: Option<String>,  // Azure: deployment-specific endpoint
     pub api_version: Option<String>,   // Azure: API version
@@ -94,6 +161,25 @@ pub struct CreateModel {
     pub api_endpoint: Option<String>,
     pub api_version: Option<String>,
     pub api_key: Option<String>,
+    
+    // Limits and capabilities
+    pub context_window: Option<i32>,
+    pub max_output_tokens: Option<i32>,
+    pub supports_vision: Option<bool>,
+    pub supports_tools: Option<bool>,
+    pub supports_json_mode: Option<bool>,
+    pub supports_json_schema: Option<bool>,
+    pub supports_streaming: Option<bool>,
+    pub supports_reasoning: Option<bool>,
+    
+    // Timeouts in milliseconds
+    pub connect_timeout_ms: Option<i32>,
+    pub timeout_ms: Option<i32>,
+    pub first_token_timeout_ms: Option<i32>,
+    pub idle_timeout_ms: Option<i32>,
+    
+    // Calls in flight at once
+    pub max_concurrent: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -113,4 +199,162 @@ pub struct UpdateModel {
     pub api_endpoint: Option<String>,
     pub api_version: Option<String>,
     pub api_key: Option<String>,
+    
+    // Limits and capabilities
+    pub context_window: Option<i32>,
+    pub max_output_tokens: Option<i32>,
+    pub supports_vision: Option<bool>,
+    pub supports_tools: Option<bool>,
+    pub supports_json_mode: Option<bool>,
+    pub supports_json_schema: Option<bool>,
+    pub supports_streaming: Option<bool>,
+    pub supports_reasoning: Option<bool>,
+    
+    // Timeouts in milliseconds
+    pub connect_timeout_ms: Option<i32>,
+    pub timeout_ms: Option<i32>,
+    pub first_token_timeout_ms: Option<i32>,
+    pub idle_timeout_ms: Option<i32>,
+    
+    // Calls in flight at once
+    pub max_concurrent: Option<i32>,
+}
+
+/// What a request needs from a model, derived before candidate selection
+#[derive(Debug, Clone, Default)]
+pub struct ModelRequirements {
+    pub vision: bool,
+    pub tools: bool,
+    pub json_mode: bool,
+    pub json_schema: bool,
+    pub streaming: bool,
+    pub reasoning: bool,
+    /// Estimated prompt size in tokens
+    pub prompt_tokens: usize,
+    /// Output tokens requested via `max_tokens`
+    pub max_output_tokens: Option<i32>,
+}
+
+impl Model {
+    /// This model's timeout settings; unset fields inherit from the service
+    pub fn timeouts(&self) -> crate::services::TimeoutPolicy {
+        let ms = |v: Option<i32>| v.filter(|v| *v > 0).map(|v| v as u64);
+        crate::services::TimeoutPolicy {
+            connect_ms: ms(self.connect_timeout_ms),
+            total_ms: ms(self.timeout_ms),
+            first_token_ms: ms(self.first_token_timeout_ms),
+            idle_ms: ms(self.idle_timeout_ms),
+        }
+    }
+
+    /// Explain why this model can't serve a request, or `None` if it qualifies
+    pub fn unmet_requirement(&self, req: &ModelRequirements) -> Option<String> {
+        let lacks = |capability: Option<bool>| capability == Some(false);
+
+        if req.vision && lacks(self.supports_vision) {
+            return Some("does not support image input".to_string());
+        }
+        if req.tools && lacks(self.supports_tools) {
+            return Some("does not support tool calling".to_string());
+        }
+        if req.json_schema && lacks(self.supports_json_schema) {
+            return Some("does not support JSON schema output".to_string());
+        }
+        if req.json_mode && lacks(self.supports_json_mode) {
+            return Some("does not support JSON mode".to_string());
+        }
+        if req.streaming && lacks(self.supports_streaming) {
+            return Some("does not support streaming".to_string());
+        }
+        if req.reasoning && lacks(self.supports_reasoning) {
+            return Some("does not support reasoning effort".to_string());
+        }
+        if let (Some(requested), Some(limit)) = (req.max_output_tokens, self.max_output_tokens) {
+            if requested > limit {
+                return Some(format!("max_tokens {} exceeds output limit {}", requested, limit));
+            }
+        }
+        if let Some(window) = self.context_window.filter(|w| *w > 0) {
+            // Leave room for the response: the requested max_tokens, or a small default
+            let output = req.max_output_tokens.map(|t| t.max(0) as usize).unwrap_or(256);
+            if req.prompt_tokens + output > window as usize {
+                return Some(format!(
+                    "prompt of ~{} tokens plus {} output tokens exceeds context window of {}",
+                    req.prompt_tokens, output, window
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(context_window: Option<i32>) -> Model {
+        Model {
+            id: "model1".to_string(),
+            name: "gpt-4o".to_string(),
+            provider: "provider1".to_string(),
+            modality: "text".to_string(),
+            description: None,
+            cost_per_1k_tokens: None,
+            cost_per_1k_input_tokens: None,
+            cost_per_1k_output_tokens: None,
+            tier: "standard".to_string(),
+            avg_latency_ms: 0,
+            avg_ttft_ms: 0,
+            max_tps: 0,
+            context_window,
+            max_output_tokens: None,
+            supports_vision: None,
+            supports_tools: None,
+            supports_json_mode: None,
+            supports_json_schema: None,
+            supports_streaming: None,
+            supports_reasoning: None,
+            connect_timeout_ms: None,
+            timeout_ms: None,
+            first_token_timeout_ms: None,
+            idle_timeout_ms: None,
+            max_concurrent: None,
+            api_endpoint: None,
+            api_version: None,
+            api_key: None,
+            created_at: None,
+            tier_required: "A".to_string(),
+            worker_type: "text".to_string(),
+            created_by: None,
+            user_id: None,
+        }
+    }
+
+    #[test]
+    fn test_unknown_capabilities_are_assumed_supported() {
+        let req = ModelRequirements { vision: true, json_mode: true, prompt_tokens: 100, ..Default::default() };
+        assert_eq!(model(Some(8192)).unmet_requirement(&req), None);
+    }
+
+    #[test]
+    fn test_missing_capability_disqualifies() {
+        let mut m = model(Some(8192));
+        m.supports_json_mode = Some(false);
+        let req = ModelRequirements { json_mode: true, ..Default::default() };
+        assert!(m.unmet_requirement(&req).unwrap().contains("JSON mode"));
+    }
+
+    #[test]
+    fn test_context_window_and_output_limits() {
+        let req = ModelRequirements { prompt_tokens: 100_000, ..Default::default() };
+        assert!(model(Some(8192)).unmet_requirement(&req).is_some());
+        assert_eq!(model(Some(128_000)).unmet_requirement(&req), None);
+        // Unknown windows are not pre-checked
+        assert_eq!(model(None).unmet_requirement(&req), None);
+
+        let mut m = model(Some(128_000));
+        m.max_output_tokens = Some(4096);
+        let req = ModelRequirements { prompt_tokens: 100, max_output_tokens: Some(8000), ..Default::default() };
+        assert!(m.unmet_requirement(&req).unwrap().contains("output limit"));
+    }
 }
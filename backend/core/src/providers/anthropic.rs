@@ -60,11 +60,7 @@ impl ProviderAdapter for AnthropicAdapter {
             .await?;
         
         // Check for error status codes before creating stream
-        if !response.status().is_success() {
-             let status = response.status();
-             let error_text = response.text().await.unwrap_or_default();
-             return Err(anyhow::anyhow!("Anthropic API error: {} - {}", status, error_text));
-        }
+        let response = super::UpstreamHttpError::check("Anthropic", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
@@ -57,14 +57,10 @@ impl ProviderAdapter for AzureProvider {
             .send()
             .await?;
 
-        let status = response.status();
-        eprintln!("📥 Response status: {}", status);
-
-        if !status.is_success() {
-            let error_text = response.text().await?;
-            eprintln!("❌ Azure error response: {}", error_text);
-            anyhow::bail!("Azure API error {}: {}", status, error_text);
-        }
+        eprintln!("📥 Response status: {}", response.status());
+        let response = super::UpstreamHttpError::check("Azure", &self.api_key, response).await.inspect_err(|e| {
+            eprintln!("❌ Azure error response: {}", e);
+        })?;
 
         // Stream SSE responses (same approach as OpenAI)
         let mut stream = response.bytes_stream();
@@ -38,15 +38,7 @@ impl ProviderAdapter for DeepSeekAdapter {
             .await?;
 
         // Check response status before streaming
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!(
-                "DeepSeek API error ({}): {}",
-                status.as_u16(),
-                error_body
-            ));
-        }
+        let response = super::UpstreamHttpError::check("DeepSeek", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
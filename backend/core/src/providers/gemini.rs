@@ -45,6 +45,7 @@ impl ProviderAdapter for GeminiAdapter {
             }))
             .send()
             .await?;
+        let response = super::UpstreamHttpError::check("Gemini", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
@@ -0,0 +1,203 @@
+//! Upstream rate limits learned from provider responses
+//!
+//! Providers report what is left of a key's per-minute budget in response headers
+//! (`x-ratelimit-*` for OpenAI-compatible APIs, `anthropic-ratelimit-*` for Anthropic).
+//! The last report per key is kept here so the gateway can throttle and route around
+//! keys that are about to be rejected. Keys are identified by a hash, never stored.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// Budget a provider reported for one key
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UpstreamLimits {
+    pub requests_limit: Option<u64>,
+    pub requests_remaining: Option<u64>,
+    /// Until the request budget is fully restored
+    pub requests_reset: Option<Duration>,
+    pub tokens_limit: Option<u64>,
+    pub tokens_remaining: Option<u64>,
+    pub tokens_reset: Option<Duration>,
+}
+
+impl UpstreamLimits {
+    /// Parse OpenAI-style or Anthropic rate-limit headers; `None` when the response has none
+    pub fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+        let number = |names: &[&str]| names.iter().find_map(|n| header(n)?.parse::<u64>().ok());
+        let reset = |names: &[&str]| names.iter().find_map(|n| parse_reset(header(n)?));
+
+        let limits = Self {
+            requests_limit: number(&["x-ratelimit-limit-requests", "anthropic-ratelimit-requests-limit"]),
+            requests_remaining: number(&["x-ratelimit-remaining-requests", "anthropic-ratelimit-requests-remaining"]),
+            requests_reset: reset(&["x-ratelimit-reset-requests", "anthropic-ratelimit-requests-reset"]),
+            tokens_limit: number(&["x-ratelimit-limit-tokens", "anthropic-ratelimit-tokens-limit"]),
+            tokens_remaining: number(&["x-ratelimit-remaining-tokens", "anthropic-ratelimit-tokens-remaining"]),
+            tokens_reset: reset(&["x-ratelimit-reset-tokens", "anthropic-ratelimit-tokens-reset"]),
+        };
+        (limits != Self::default()).then_some(limits)
+    }
+}
+
+/// Reset as a duration (`1s`, `6m0s`, `20ms`), plain seconds, or an RFC 3339 timestamp (Anthropic).
+/// Non-finite values are rejected and huge ones capped, as for `Retry-After`.
+fn parse_reset(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.parse::<f64>() {
+        return super::header_delay(secs);
+    }
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(value) {
+        let reset = (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default();
+        return Some(reset.min(super::MAX_HEADER_DELAY));
+    }
+
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let amount: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        total += amount * match &rest[..unit_len] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+    }
+    super::header_delay(total)
+}
+
+/// Last report for a key and when it arrived
+#[derive(Debug, Clone, Copy)]
+pub struct Observed {
+    pub limits: UpstreamLimits,
+    pub at: Instant,
+}
+
+impl Observed {
+    /// How long until the key can take a request of `tokens`, if the last report says it can't now
+    pub fn wait_for(&self, tokens: u64, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.at);
+        let pending = |reset: Option<Duration>| reset.and_then(|r| r.checked_sub(elapsed)).filter(|d| !d.is_zero());
+
+        let requests = pending(self.limits.requests_reset)
+            .filter(|_| self.limits.requests_remaining == Some(0));
+        let tokens = pending(self.limits.tokens_reset)
+            .filter(|_| self.limits.tokens_remaining.is_some_and(|left| left < tokens.max(1)));
+        requests.max(tokens)
+    }
+
+    /// Fraction of the budget left (0.0 to 1.0) on the tighter of requests and tokens,
+    /// or `None` when the report has expired or gave no limits
+    pub fn headroom(&self, now: Instant) -> Option<f64> {
+        let elapsed = now.saturating_duration_since(self.at);
+        let fraction = |remaining: Option<u64>, limit: Option<u64>, reset: Option<Duration>| {
+            if reset.is_some_and(|r| r <= elapsed) {
+                return None;
+            }
+            match (remaining?, limit) {
+                (0, _) => Some(0.0),
+                (remaining, Some(limit)) if limit > 0 => Some((remaining as f64 / limit as f64).min(1.0)),
+                _ => None,
+            }
+        };
+        let requests = fraction(self.limits.requests_remaining, self.limits.requests_limit, self.limits.requests_reset);
+        let tokens = fraction(self.limits.tokens_remaining, self.limits.tokens_limit, self.limits.tokens_reset);
+        match (requests, tokens) {
+            (Some(r), Some(t)) => Some(r.min(t)),
+            (r, t) => r.or(t),
+        }
+    }
+}
+
+static OBSERVED: LazyLock<RwLock<HashMap<String, Observed>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Stable identifier for an API key
+pub fn key_id(api_key: &str) -> String {
+    let digest = Sha256::digest(api_key.as_bytes());
+    digest.iter().take(8).map(|b| format!("{:02x}", b)).collect()
+}
+
+fn record(api_key: &str, limits: UpstreamLimits) {
+    if api_key.is_empty() {
+        return;
+    }
+    if let Ok(mut observed) = OBSERVED.write() {
+        observed.insert(key_id(api_key), Observed { limits, at: Instant::now() });
+    }
+}
+
+/// Record the rate-limit headers of a provider response
+pub fn observe(api_key: &str, headers: &reqwest::header::HeaderMap) {
+    if let Some(limits) = UpstreamLimits::from_headers(headers) {
+        record(api_key, limits);
+    }
+}
+
+/// Record a 429: the key has nothing left until `retry_after` (or what the headers said)
+pub fn observe_rejection(api_key: &str, headers: &reqwest::header::HeaderMap, retry_after: Option<Duration>) {
+    let mut limits = UpstreamLimits::from_headers(headers).unwrap_or_default();
+    let reset = retry_after.or(limits.requests_reset).unwrap_or(Duration::from_secs(1));
+    limits.requests_remaining = Some(0);
+    limits.requests_reset = Some(limits.requests_reset.map_or(reset, |r| r.max(reset)));
+    record(api_key, limits);
+}
+
+/// Last report for a key, by `key_id`
+pub fn observed(key_id: &str) -> Option<Observed> {
+    OBSERVED.read().ok()?.get(key_id).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reset_formats() {
+        assert_eq!(parse_reset("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset("1h2m3.5s"), Some(Duration::from_secs_f64(3723.5)));
+        assert_eq!(parse_reset("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_reset("soon"), None);
+    }
+
+    #[test]
+    fn test_parse_reset_rejects_or_caps_extremes() {
+        assert_eq!(parse_reset("inf"), None);
+        assert_eq!(parse_reset("NaN"), None);
+        assert_eq!(parse_reset("1e30s"), None);
+        assert_eq!(parse_reset("1e30"), Some(crate::providers::MAX_HEADER_DELAY));
+        let huge = format!("{}h", "9".repeat(400));
+        assert_eq!(parse_reset(&huge), None);
+        assert_eq!(parse_reset(&format!("{}h", "9".repeat(30))), Some(crate::providers::MAX_HEADER_DELAY));
+        assert_eq!(parse_reset("9999-12-31T23:59:59Z"), Some(crate::providers::MAX_HEADER_DELAY));
+    }
+
+    #[test]
+    fn test_observed_wait_and_headroom() {
+        let now = Instant::now();
+        let observed = Observed {
+            limits: UpstreamLimits {
+                requests_limit: Some(100),
+                requests_remaining: Some(20),
+                requests_reset: Some(Duration::from_secs(10)),
+                tokens_limit: Some(10_000),
+                tokens_remaining: Some(500),
+                tokens_reset: Some(Duration::from_secs(30)),
+            },
+            at: now,
+        };
+
+        assert_eq!(observed.headroom(now), Some(0.05));
+        assert_eq!(observed.wait_for(100, now), None);
+        assert_eq!(observed.wait_for(1_000, now + Duration::from_secs(10)), Some(Duration::from_secs(20)));
+        // Reports expire once their reset has passed
+        assert_eq!(observed.wait_for(1_000, now + Duration::from_secs(31)), None);
+        assert_eq!(observed.headroom(now + Duration::from_secs(31)), None);
+    }
+}
@@ -39,11 +39,7 @@ impl ProviderAdapter for MistralAdapter {
             .send()
             .await?;
 
-        if !response.status().is_success() {
-             let status = response.status();
-             let error_text = response.text().await.unwrap_or_default();
-             return Err(anyhow::anyhow!("Mistral API error: {} - {}", status, error_text));
-        }
+        let response = super::UpstreamHttpError::check("Mistral", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
@@ -76,4 +72,16 @@ impl ProviderAdapter for MistralAdapter {
 
         Ok(Box::pin(parsed_stream))
     }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        let response = self.client
+            .post(format!("{}/embeddings", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&json!({ "model": model, "input": inputs }))
+            .send()
+            .await?;
+        let response = super::UpstreamHttpError::check("Mistral", &self.api_key, response).await?;
+        let json: serde_json::Value = response.json().await?;
+        super::parse_embeddings(&json)
+    }
 }
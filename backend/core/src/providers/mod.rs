@@ -73,8 +73,104 @@ pub trait ProviderAdapter: Send + Sync {
     async fn get_video_content(&self, _generation_id: &str) -> Result<Vec<u8>, anyhow::Error> {
         Err(anyhow::anyhow!("Video content fetching not supported by this provider"))
     }
+
+    /// Embed texts with an embedding model, one vector per input
+    async fn embed(&self, _model: &str, _inputs: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        Err(anyhow::anyhow!("Embeddings not supported by this provider"))
+    }
+}
+
+/// Vectors from an OpenAI-style `/embeddings` response, in input order
+pub(crate) fn parse_embeddings(json: &serde_json::Value) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+    let data = json["data"].as_array().ok_or_else(|| anyhow::anyhow!("No data in embeddings response"))?;
+    let mut indexed = data
+        .iter()
+        .map(|item| {
+            let vector = item["embedding"]
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("No embedding in embeddings response"))?
+                .iter()
+                .map(|v| v.as_f64().unwrap_or(0.0) as f32)
+                .collect::<Vec<f32>>();
+            Ok((item["index"].as_u64().unwrap_or(0), vector))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    indexed.sort_by_key(|(index, _)| *index);
+    Ok(indexed.into_iter().map(|(_, vector)| vector).collect())
+}
+
+/// Non-success HTTP response from a provider, kept typed so the executor can tell
+/// rate limits and overload apart from bad requests and honour `Retry-After`
+#[derive(Debug)]
+pub struct UpstreamHttpError {
+    pub provider: String,
+    pub status: u16,
+    /// Delay the provider asked for before retrying
+    pub retry_after: Option<std::time::Duration>,
+    pub body: String,
+}
+
+impl UpstreamHttpError {
+    /// Pass successful responses through; turn anything else into an `UpstreamHttpError`.
+    /// Rate-limit headers are recorded against `api_key` either way.
+    pub async fn check(provider: &str, api_key: &str, response: reqwest::Response) -> Result<reqwest::Response, anyhow::Error> {
+        if response.status().is_success() {
+            limits::observe(api_key, response.headers());
+            return Ok(response);
+        }
+        let status = response.status().as_u16();
+        let retry_after = retry_after(response.headers());
+        if status == 429 {
+            limits::observe_rejection(api_key, response.headers(), retry_after);
+        } else {
+            limits::observe(api_key, response.headers());
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(Self { provider: provider.to_string(), status, retry_after, body }.into())
+    }
+}
+
+impl std::fmt::Display for UpstreamHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} API error {}: {}", self.provider, self.status, self.body)
+    }
+}
+
+impl std::error::Error for UpstreamHttpError {}
+
+/// Longest delay taken from provider headers, enough for daily quotas to reset. Anything
+/// longer is capped here, which is still past any retry budget (`RetryPolicy::max_backoff_ms`),
+/// so the call isn't retried.
+pub(crate) const MAX_HEADER_DELAY: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
+/// Seconds from a header as a duration, capped at `MAX_HEADER_DELAY`. Garbage such as
+/// `inf` or `NaN` is ignored rather than trusted.
+pub(crate) fn header_delay(secs: f64) -> Option<std::time::Duration> {
+    if !secs.is_finite() {
+        return None;
+    }
+    let secs = secs.clamp(0.0, MAX_HEADER_DELAY.as_secs_f64());
+    std::time::Duration::try_from_secs_f64(secs).ok()
 }
 
+/// Requested retry delay from `retry-after-ms` (OpenAI, Azure) or `Retry-After`
+/// (seconds or an HTTP date)
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<std::time::Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return header_delay(ms / 1000.0);
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return header_delay(secs);
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default();
+    Some(delay.min(MAX_HEADER_DELAY))
+}
+
+pub mod limits;
 pub mod openai;
 pub mod azure;
 pub mod gemini;
@@ -96,3 +192,37 @@ pub use perplexity::PerplexityAdapter;
 pub use selfhosted::SelfHostedAdapter;
 pub use deepseek::DeepSeekAdapter;
 pub use elevenlabs::ElevenLabsAdapter;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use std::time::Duration;
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_parses_retry_after_seconds_and_ms() {
+        assert_eq!(retry_after(&headers("retry-after", "2")), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after(&headers("retry-after", "-5")), Some(Duration::ZERO));
+        assert_eq!(retry_after(&headers("retry-after-ms", "1500")), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn test_ignores_non_finite_retry_after() {
+        assert_eq!(retry_after(&headers("retry-after", "inf")), None);
+        assert_eq!(retry_after(&headers("retry-after", "NaN")), None);
+        assert_eq!(retry_after(&headers("retry-after-ms", "-inf")), None);
+    }
+
+    #[test]
+    fn test_caps_huge_retry_after() {
+        assert_eq!(retry_after(&headers("retry-after", "1e30")), Some(MAX_HEADER_DELAY));
+        assert_eq!(retry_after(&headers("retry-after-ms", "1e300")), Some(MAX_HEADER_DELAY));
+        assert_eq!(retry_after(&headers("retry-after", "Fri, 31 Dec 9999 23:59:59 GMT")), Some(MAX_HEADER_DELAY));
+    }
+}
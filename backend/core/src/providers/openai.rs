@@ -56,6 +56,7 @@ impl ProviderAdapter for OpenAIAdapter {
             .json(&body)
             .send()
             .await?;
+        let response = super::UpstreamHttpError::check("OpenAI", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
@@ -192,6 +193,18 @@ impl ProviderAdapter for OpenAIAdapter {
         let bytes = response.bytes().await?;
         Ok(bytes.to_vec())
     }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        let response = self.client
+            .post(format!("{}/embeddings", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&json!({ "model": model, "input": inputs }))
+            .send()
+            .await?;
+        let response = super::UpstreamHttpError::check("OpenAI", &self.api_key, response).await?;
+        let json: serde_json::Value = response.json().await?;
+        super::parse_embeddings(&json)
+    }
 }
 
 // Additional methods for OpenAIAdapter (not part of ProviderAdapter trait)
@@ -216,10 +229,7 @@ impl OpenAIAdapter {
             .send()
             .await?;
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("OpenAI /responses error: {}", error_text));
-        }
+        let response = super::UpstreamHttpError::check("OpenAI /responses", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
@@ -35,6 +35,7 @@ impl ProviderAdapter for PerplexityAdapter {
             }))
             .send()
             .await?;
+        let response = super::UpstreamHttpError::check("Perplexity", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
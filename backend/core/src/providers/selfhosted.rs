@@ -36,6 +36,39 @@ impl ProviderAdapter for SelfHostedAdapter {
             self.stream_chat_openai_compat(req).await
         }
     }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        // Ollama's native endpoint returns bare vectors; others follow OpenAI's format
+        let url = if self.is_ollama() {
+            format!("{}/api/embed", self.base_url)
+        } else {
+            format!("{}/v1/embeddings", self.base_url)
+        };
+        let mut request_builder = self.client
+            .post(&url)
+            .json(&json!({ "model": model, "input": inputs }));
+        if !self.api_key.is_empty() {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", self.api_key));
+        }
+
+        let response = request_builder.send().await?;
+        let response = super::UpstreamHttpError::check("Self-hosted", &self.api_key, response).await?;
+        let json: serde_json::Value = response.json().await?;
+        if !self.is_ollama() {
+            return super::parse_embeddings(&json);
+        }
+        let embeddings = json["embeddings"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("No embeddings in Ollama response"))?;
+        Ok(embeddings
+            .iter()
+            .map(|vector| {
+                vector.as_array()
+                    .map(|values| values.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect())
+                    .unwrap_or_default()
+            })
+            .collect())
+    }
 }
 
 impl SelfHostedAdapter {
@@ -93,13 +126,7 @@ impl SelfHostedAdapter {
                 .await;
         }
 
-        let response = response?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Ollama error {}: {}", status, body));
-        }
+        let response = super::UpstreamHttpError::check("Ollama", &self.api_key, response?).await?;
 
         let stream = response.bytes_stream();
         
@@ -150,6 +177,7 @@ impl SelfHostedAdapter {
         }
 
         let response = request_builder.send().await?;
+        let response = super::UpstreamHttpError::check("Self-hosted", &self.api_key, response).await?;
         let stream = response.bytes_stream();
         
         let parsed_stream = stream.map(|chunk_result| {
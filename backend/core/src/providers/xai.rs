@@ -38,11 +38,7 @@ impl ProviderAdapter for XaiAdapter {
             .send()
             .await?;
 
-        if !response.status().is_success() {
-             let status = response.status();
-             let error_text = response.text().await.unwrap_or_default();
-             return Err(anyhow::anyhow!("X.ai API error: {} - {}", status, error_text));
-        }
+        let response = super::UpstreamHttpError::check("X.ai", &self.api_key, response).await?;
 
         let stream = response.bytes_stream();
         
@@ -56,6 +56,41 @@ impl QuotaManager {
         Ok(false)
     }
 
+    /// Share of the user's spendable budget already used (0-100)
+    ///
+    /// Personal and org quotas are pooled the way `check_quota` spends them, so 100
+    /// means requests are about to be rejected. Free tier users are never rejected and report 0.
+    pub async fn usage_percent(&self, user_id: &str) -> Result<f64> {
+        let user: crate::auth::User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await?;
+
+        if user.is_free_tier {
+            return Ok(0.0);
+        }
+
+        let mut quota = user.monthly_quota_usd.max(0.0);
+        let mut used = user.current_usage_usd.clamp(0.0, quota);
+
+        if let Some(org_id) = &user.org_id {
+            if let Ok(org) = sqlx::query_as::<_, Organization>("SELECT * FROM organizations WHERE id = $1")
+                .bind(org_id)
+                .fetch_one(&self.db)
+                .await
+            {
+                let org_quota = org.monthly_quota_usd.max(0.0);
+                quota += org_quota;
+                used += org.current_usage_usd.clamp(0.0, org_quota);
+            }
+        }
+
+        if quota <= 0.0 {
+            return Ok(100.0);
+        }
+        Ok((used / quota * 100.0).clamp(0.0, 100.0))
+    }
+
     // ------------------------------------------------------------------------
     // Quota Charging
     // ------------------------------------------------------------------------
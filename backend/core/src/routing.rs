@@ -12,8 +12,10 @@ pub enum RoutingStrategy {
     LeastLatency,
     /// Weighted random distribution based on configured weights
     WeightedRandom,
-    /// No load balancing (single model or multi-modality services)
+    /// No load balancing (single model services)
     None,
+    /// Call several models in parallel and combine their answers
+    Ensemble,
 }
 
 impl RoutingStrategy {
@@ -24,6 +26,7 @@ impl RoutingStrategy {
             RoutingStrategy::LeastLatency => "least_latency",
             RoutingStrategy::WeightedRandom => "weighted_random",
             RoutingStrategy::None => "none",
+            RoutingStrategy::Ensemble => "ensemble",
         }
     }
     
@@ -34,6 +37,7 @@ impl RoutingStrategy {
             "least_latency" => Ok(RoutingStrategy::LeastLatency),
             "weighted_random" => Ok(RoutingStrategy::WeightedRandom),
             "none" => Ok(RoutingStrategy::None),
+            "ensemble" => Ok(RoutingStrategy::Ensemble),
             _ => Err(format!("Invalid routing strategy: {}", s)),
         }
     }
@@ -79,9 +83,18 @@ impl StrategySelector {
             return RoutingStrategy::None;
         }
         
-        // Multi-modality → none (for now)
+        // Multi-modality → the service strategy is the default for every sub-pool,
+        // so recommend it for the chat sub-pool (see `recommend_sub_pool_strategies`)
         if pool_type == "MULTI_MODALITY" {
-            return RoutingStrategy::None;
+            let text_models: Vec<ModelRoutingMetadata> = models
+                .iter()
+                .filter(|m| m.modality.eq_ignore_ascii_case("text"))
+                .cloned()
+                .collect();
+            if text_models.is_empty() {
+                return RoutingStrategy::None;
+            }
+            return Self::recommend_strategy(&text_models, "SINGLE_MODALITY");
         }
         
         // Single modality with multiple models - analyze characteristics
@@ -159,9 +172,13 @@ impl StrategySelector {
             return Err("Single model services must use 'none' strategy".to_string());
         }
         
-        // Multi-modality can only use 'none' strategy (for now)
-        if pool_type == "MULTI_MODALITY" && *strategy != RoutingStrategy::None {
-            return Err("Multi-modality services must use 'none' strategy".to_string());
+        // Multi-modality strategies apply to each modality sub-pool independently
+        if pool_type == "MULTI_MODALITY" {
+            for (modality, sub_pool) in Self::sub_pools(models) {
+                Self::validate_sub_pool_strategy(strategy, &sub_pool)
+                    .map_err(|e| format!("{} sub-pool: {}", modality, e))?;
+            }
+            return Ok(());
         }
         
         // Weighted strategy requires all weights to sum to 100
@@ -177,6 +194,125 @@ impl StrategySelector {
         
         Ok(())
     }
+
+    /// Recommend a strategy for each modality sub-pool of a MULTI_MODALITY service
+    pub fn recommend_sub_pool_strategies(
+        models: &[ModelRoutingMetadata],
+    ) -> std::collections::HashMap<String, RoutingStrategy> {
+        Self::sub_pools(models)
+            .into_iter()
+            .map(|(modality, sub_pool)| {
+                let strategy = Self::recommend_strategy(&sub_pool, "SINGLE_MODALITY");
+                (modality, strategy)
+            })
+            .collect()
+    }
+
+    /// Validate a strategy against one modality sub-pool. Unlike whole services, a sub-pool
+    /// with a single model may inherit any strategy from the service default.
+    pub fn validate_sub_pool_strategy(
+        strategy: &RoutingStrategy,
+        models: &[ModelRoutingMetadata],
+    ) -> Result<(), String> {
+        if models.len() > 1 {
+            return Self::validate_strategy(strategy, models, "SINGLE_MODALITY");
+        }
+        Ok(())
+    }
+
+    /// Group models by modality (lowercased)
+    fn sub_pools(models: &[ModelRoutingMetadata]) -> std::collections::BTreeMap<String, Vec<ModelRoutingMetadata>> {
+        let mut pools: std::collections::BTreeMap<String, Vec<ModelRoutingMetadata>> = std::collections::BTreeMap::new();
+        for model in models {
+            pools.entry(model.modality.to_lowercase()).or_default().push(model.clone());
+        }
+        pools
+    }
+}
+
+/// Conversation affinity via weighted rendezvous hashing.
+///
+/// Every (key, model) pair gets a stable score and candidates are ranked by it, so a
+/// conversation keeps landing on the same model. When a model leaves the candidate list
+/// (unhealthy, circuit open, removed from the service) only conversations pinned to it
+/// move, and they move to their next-ranked model.
+pub struct AffinityRouter;
+
+impl AffinityRouter {
+    /// Rank candidates `(model_id, weight)` for `key`, returning indices best first.
+    /// Weights bias the share of conversations each model receives; non-positive weights count as 1.
+    pub fn rank(key: &str, candidates: &[(&str, i32)]) -> Vec<usize> {
+        let mut scored: Vec<(usize, f64)> = candidates
+            .iter()
+            .enumerate()
+            .map(|(idx, (model_id, weight))| (idx, Self::score(key, model_id, *weight)))
+            .collect();
+
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.into_iter().map(|(idx, _)| idx).collect()
+    }
+
+    fn score(key: &str, model_id: &str, weight: i32) -> f64 {
+        // Map the hash into (0, 1) and apply the weighted rendezvous formula w / -ln(u)
+        let hash = Self::hash(key, model_id);
+        let unit = ((hash >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        weight.max(1) as f64 / -unit.ln()
+    }
+
+    fn hash(key: &str, model_id: &str) -> u64 {
+        stable_hash(&[key, model_id])
+    }
+}
+
+/// FNV-1a with a splitmix64 finalizer over separator-joined parts.
+/// Stable across processes and releases, unlike `DefaultHasher`.
+pub fn stable_hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            hash ^= 0xff;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        for byte in part.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Normalise a free-text answer for voting: trimmed, lowercased, whitespace collapsed,
+/// and surrounding quotes and trailing punctuation removed
+pub fn normalize_answer(answer: &str) -> String {
+    let collapsed = answer.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    collapsed
+        .trim_matches(['"', '\'', '`'])
+        .trim_end_matches(['.', '!', '?', ';', ':'])
+        .trim()
+        .to_string()
+}
+
+/// Majority vote over answers compared after `normalize_answer`.
+/// Returns the index of the first answer in the largest group and that group's size;
+/// ties go to the group that appeared first.
+pub fn majority_vote(answers: &[&str]) -> Option<(usize, usize)> {
+    let normalized: Vec<String> = answers.iter().map(|a| normalize_answer(a)).collect();
+    let mut best: Option<(usize, usize)> = None;
+    for (idx, answer) in normalized.iter().enumerate() {
+        if normalized[..idx].contains(answer) {
+            continue;
+        }
+        let votes = normalized.iter().filter(|a| *a == answer).count();
+        if best.is_none_or(|(_, best_votes)| votes > best_votes) {
+            best = Some((idx, votes));
+        }
+    }
+    best
 }
 
 #[cfg(test)]
@@ -277,4 +413,113 @@ mod tests {
         let strategy = StrategySelector::recommend_strategy(&models, "SINGLE_MODALITY");
         assert_eq!(strategy, RoutingStrategy::WeightedRandom);
     }
+
+    #[test]
+    fn test_multi_modality_sub_pool_strategies() {
+        let models = vec![
+            ModelRoutingMetadata {
+                id: "chat-a".to_string(),
+                name: "Chat A".to_string(),
+                modality: "text".to_string(),
+                health_status: "healthy".to_string(),
+                success_rate: 0.99,
+                cost_per_1k_tokens: Some(0.01),
+                tier: "standard".to_string(),
+                avg_latency_ms: 500,
+                avg_ttft_ms: 200,
+                weight: 60,
+                priority: 1,
+                enabled: true,
+            },
+            ModelRoutingMetadata {
+                id: "chat-b".to_string(),
+                name: "Chat B".to_string(),
+                modality: "text".to_string(),
+                health_status: "healthy".to_string(),
+                success_rate: 0.99,
+                cost_per_1k_tokens: Some(0.01),
+                tier: "standard".to_string(),
+                avg_latency_ms: 500,
+                avg_ttft_ms: 200,
+                weight: 40,
+                priority: 2,
+                enabled: true,
+            },
+            ModelRoutingMetadata {
+                id: "image".to_string(),
+                name: "Image".to_string(),
+                modality: "image".to_string(),
+                health_status: "healthy".to_string(),
+                success_rate: 0.99,
+                cost_per_1k_tokens: None,
+                tier: "standard".to_string(),
+                avg_latency_ms: 4000,
+                avg_ttft_ms: 0,
+                weight: 100,
+                priority: 1,
+                enabled: true,
+            },
+        ];
+
+        let strategy = StrategySelector::recommend_strategy(&models, "MULTI_MODALITY");
+        assert_eq!(strategy, RoutingStrategy::WeightedRandom);
+
+        let sub_pools = StrategySelector::recommend_sub_pool_strategies(&models);
+        assert_eq!(sub_pools.get("text"), Some(&RoutingStrategy::WeightedRandom));
+        assert_eq!(sub_pools.get("image"), Some(&RoutingStrategy::None));
+
+        // A single-model sub-pool can inherit the service strategy
+        assert!(StrategySelector::validate_strategy(&RoutingStrategy::WeightedRandom, &models, "MULTI_MODALITY").is_ok());
+    }
+
+    #[test]
+    fn test_affinity_is_stable() {
+        let candidates = [("model1", 50), ("model2", 30), ("model3", 20)];
+
+        let first = AffinityRouter::rank("conversation-42", &candidates);
+        let second = AffinityRouter::rank("conversation-42", &candidates);
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 3);
+    }
+
+    #[test]
+    fn test_affinity_minimal_rebalancing() {
+        let full = [("model1", 1), ("model2", 1), ("model3", 1), ("model4", 1)];
+        let without_model2 = [("model1", 1), ("model3", 1), ("model4", 1)];
+
+        for i in 0..200 {
+            let key = format!("conversation-{}", i);
+            let before = full[AffinityRouter::rank(&key, &full)[0]].0;
+            let after = without_model2[AffinityRouter::rank(&key, &without_model2)[0]].0;
+
+            if before != "model2" {
+                // Conversations not pinned to the removed model stay put
+                assert_eq!(before, after);
+            } else {
+                // Displaced conversations fall through to their second choice
+                let second_choice = full[AffinityRouter::rank(&key, &full)[1]].0;
+                assert_eq!(after, second_choice);
+            }
+        }
+    }
+
+    #[test]
+    fn test_affinity_respects_weights() {
+        let candidates = [("heavy", 90), ("light", 10)];
+
+        let heavy_wins = (0..1000)
+            .filter(|i| AffinityRouter::rank(&format!("conversation-{}", i), &candidates)[0] == 0)
+            .count();
+        assert!(heavy_wins > 800, "heavy model won {} of 1000", heavy_wins);
+    }
+
+    #[test]
+    fn test_majority_vote_normalises_answers() {
+        let answers = ["Positive.", "negative", "  positive ", "\"POSITIVE\""];
+        assert_eq!(majority_vote(&answers), Some((0, 3)));
+
+        // Ties go to the answer seen first
+        assert_eq!(majority_vote(&["spam", "ham"]), Some((0, 1)));
+        assert_eq!(majority_vote(&[]), None);
+    }
 }
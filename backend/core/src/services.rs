@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Row, postgres::PgRow};
+use std::collections::HashMap;
 
 // Service type enums
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +92,776 @@ pub struct Service {
     
     // Ownership
     pub user_id: Option<String>,
+
+    // Routing policies (JSON stored as string)
+    pub hedge: Option<HedgeConfig>,
+    pub experiment: Option<ExperimentConfig>,
+    pub shadow: Option<ShadowConfig>,
+    /// Services to try, in order, when every model in this one fails
+    pub fallback_services: Vec<String>,
+    /// MULTI_MODALITY sub-pool strategies keyed by modality ("text", "image", "audio", "video");
+    /// sub-pools without an entry use `strategy`
+    pub modality_strategies: HashMap<String, String>,
+    /// Combiner settings for the `ensemble` strategy
+    pub ensemble: Option<EnsembleConfig>,
+    pub budget_policy: Option<BudgetPolicy>,
+    /// Circuit breaker policy for this service's models (model-level overrides win)
+    pub circuit_policy: Option<CircuitPolicy>,
+    /// Availability / latency objective, set by admins
+    pub slo: Option<SloConfig>,
+    /// Upstream timeouts for this service's models (model-level settings win)
+    pub timeouts: Option<TimeoutPolicy>,
+    /// Retries against the same model before failing over
+    pub retry_policy: Option<RetryPolicy>,
+    /// Opt-in cache of identical chat requests
+    pub response_cache: Option<ResponseCachePolicy>,
+}
+
+impl Service {
+    /// Routing strategy for one modality sub-pool
+    pub fn strategy_for(&self, modality: &str) -> &str {
+        self.modality_strategies
+            .get(modality)
+            .map(String::as_str)
+            .unwrap_or(&self.strategy)
+    }
+}
+
+/// Kinds of request a pool service can be dispatched by, each served by one modality sub-pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispatchKind {
+    Chat,
+    ImageGeneration,
+    TextToSpeech,
+    Transcription,
+    SpeechToSpeech,
+    VideoGeneration,
+}
+
+impl DispatchKind {
+    /// Model modality (and sub-pool key) serving this kind of request
+    pub fn modality(&self) -> &'static str {
+        match self {
+            DispatchKind::Chat => "text",
+            DispatchKind::ImageGeneration => "image",
+            DispatchKind::TextToSpeech | DispatchKind::Transcription | DispatchKind::SpeechToSpeech => "audio",
+            DispatchKind::VideoGeneration => "video",
+        }
+    }
+
+    /// Whether a model can serve this kind of request. Audio models are split between
+    /// speech synthesis and transcription only when their worker type says which one they are.
+    pub fn accepts(&self, modality: &str, worker_type: &str) -> bool {
+        if !modality.eq_ignore_ascii_case(self.modality()) {
+            return false;
+        }
+        let worker_type = worker_type.to_lowercase();
+        match self {
+            DispatchKind::TextToSpeech => !matches!(worker_type.as_str(), "stt" | "transcription"),
+            DispatchKind::Transcription => !matches!(worker_type.as_str(), "tts" | "speech"),
+            _ => true,
+        }
+    }
+}
+
+impl std::fmt::Display for DispatchKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DispatchKind::Chat => write!(f, "chat"),
+            DispatchKind::ImageGeneration => write!(f, "image generation"),
+            DispatchKind::TextToSpeech => write!(f, "text-to-speech"),
+            DispatchKind::Transcription => write!(f, "transcription"),
+            DispatchKind::SpeechToSpeech => write!(f, "speech-to-speech"),
+            DispatchKind::VideoGeneration => write!(f, "video generation"),
+        }
+    }
+}
+
+/// Hedged request settings for latency-sensitive pool services
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct HedgeConfig {
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub enabled: bool,
+    /// Wait this long for a first token before hedging (defaults to the primary model's p90 TTFT)
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    /// Maximum concurrent attempts, including the primary
+    #[serde(default = "default_hedge_fanout")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_hedge_fanout"))]
+    pub max_fanout: u32,
+    /// Charge estimated prompt cost for cancelled attempts (providers that bill aborted requests)
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub charge_cancelled: bool,
+}
+
+fn default_hedge_fanout() -> u32 {
+    2
+}
+
+/// Shadow traffic: mirror a sample of requests to candidate models for offline comparison
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct ShadowConfig {
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub enabled: bool,
+    /// Percentage of successful requests to mirror (0-100)
+    pub sample_percent: f64,
+    /// Models the sampled requests are replayed against
+    pub model_ids: Vec<String>,
+    /// Score each shadow answer's similarity to the primary answer
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub similarity: bool,
+    /// Monthly spend cap for shadow calls, separate from caller quotas (unlimited when unset)
+    #[serde(default)]
+    pub monthly_budget_usd: Option<f64>,
+}
+
+/// When a circuit breaker trips and how long it stays open
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct CircuitPolicy {
+    /// Trip after this many consecutive failures (0 disables)
+    #[serde(default = "default_consecutive_failures")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_consecutive_failures"))]
+    pub consecutive_failures: u32,
+    /// Trip when the error rate over the window reaches this percentage
+    #[serde(default)]
+    pub error_rate_percent: Option<f64>,
+    /// Sliding window for the error rate
+    #[serde(default = "default_circuit_window_secs")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_circuit_window_secs"))]
+    pub window_secs: u64,
+    /// Requests needed in the window before the error rate is considered
+    #[serde(default = "default_circuit_min_requests")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_circuit_min_requests"))]
+    pub min_requests: u32,
+    /// How long the circuit stays open after the first trip
+    #[serde(default = "default_circuit_open_secs")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_circuit_open_secs"))]
+    pub open_secs: u64,
+    /// Open duration multiplier for each consecutive failed recovery probe
+    #[serde(default = "default_circuit_backoff")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_circuit_backoff"))]
+    pub backoff_multiplier: f64,
+    #[serde(default = "default_circuit_max_open_secs")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_circuit_max_open_secs"))]
+    pub max_open_secs: u64,
+}
+
+fn default_consecutive_failures() -> u32 {
+    3
+}
+
+fn default_circuit_window_secs() -> u64 {
+    60
+}
+
+fn default_circuit_min_requests() -> u32 {
+    10
+}
+
+fn default_circuit_open_secs() -> u64 {
+    60
+}
+
+fn default_circuit_backoff() -> f64 {
+    2.0
+}
+
+fn default_circuit_max_open_secs() -> u64 {
+    600
+}
+
+impl Default for CircuitPolicy {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: default_consecutive_failures(),
+            error_rate_percent: None,
+            window_secs: default_circuit_window_secs(),
+            min_requests: default_circuit_min_requests(),
+            open_secs: default_circuit_open_secs(),
+            backoff_multiplier: default_circuit_backoff(),
+            max_open_secs: default_circuit_max_open_secs(),
+        }
+    }
+}
+
+impl CircuitPolicy {
+    /// Default for provider-level breakers: a provider serves many models, so one bad model
+    /// shouldn't trip it; it opens on sustained errors across all of them
+    pub fn provider_default() -> Self {
+        Self {
+            consecutive_failures: 10,
+            error_rate_percent: Some(50.0),
+            min_requests: 20,
+            ..Self::default()
+        }
+    }
+
+    /// Open duration after `reopens` consecutive failed recovery probes
+    pub fn open_duration(&self, reopens: u32) -> std::time::Duration {
+        let factor = self.backoff_multiplier.max(1.0).powi(reopens.min(32) as i32);
+        let secs = (self.open_secs as f64 * factor).min(self.max_open_secs.max(self.open_secs) as f64);
+        std::time::Duration::from_secs_f64(secs)
+    }
+
+    /// Whether the recorded outcomes should trip the circuit
+    pub fn should_trip(&self, consecutive_failures: u32, window_requests: u32, window_failures: u32) -> bool {
+        if self.consecutive_failures > 0 && consecutive_failures >= self.consecutive_failures {
+            return true;
+        }
+        match self.error_rate_percent {
+            Some(threshold) if window_requests >= self.min_requests.max(1) => {
+                window_failures as f64 * 100.0 / window_requests as f64 >= threshold
+            }
+            _ => false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.consecutive_failures == 0 && self.error_rate_percent.is_none() {
+            return Err("Circuit policy needs consecutive_failures or error_rate_percent".to_string());
+        }
+        if let Some(rate) = self.error_rate_percent {
+            if !(0.0..=100.0).contains(&rate) || rate == 0.0 {
+                return Err("Circuit error_rate_percent must be between 0 and 100".to_string());
+            }
+        }
+        if self.window_secs == 0 || self.open_secs == 0 {
+            return Err("Circuit window_secs and open_secs must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Upstream timeouts; unset fields inherit from the next level (model, then service, then default)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct TimeoutPolicy {
+    /// TCP/TLS connect timeout
+    #[serde(default)]
+    pub connect_ms: Option<u64>,
+    /// Whole request, including the full streamed response
+    #[serde(default)]
+    pub total_ms: Option<u64>,
+    /// Time from sending the request to the first response chunk
+    #[serde(default)]
+    pub first_token_ms: Option<u64>,
+    /// Longest gap between two stream chunks
+    #[serde(default)]
+    pub idle_ms: Option<u64>,
+}
+
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 10_000;
+pub const DEFAULT_TOTAL_TIMEOUT_MS: u64 = 120_000;
+
+impl TimeoutPolicy {
+    /// Fill unset fields from `fallback`
+    pub fn or(self, fallback: &TimeoutPolicy) -> TimeoutPolicy {
+        TimeoutPolicy {
+            connect_ms: self.connect_ms.or(fallback.connect_ms),
+            total_ms: self.total_ms.or(fallback.total_ms),
+            first_token_ms: self.first_token_ms.or(fallback.first_token_ms),
+            idle_ms: self.idle_ms.or(fallback.idle_ms),
+        }
+    }
+
+    pub fn connect(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.connect_ms.unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS))
+    }
+
+    pub fn total(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.total_ms.unwrap_or(DEFAULT_TOTAL_TIMEOUT_MS))
+    }
+
+    pub fn first_token(&self) -> Option<std::time::Duration> {
+        self.first_token_ms.map(std::time::Duration::from_millis)
+    }
+
+    pub fn idle(&self) -> Option<std::time::Duration> {
+        self.idle_ms.map(std::time::Duration::from_millis)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [self.connect_ms, self.total_ms, self.first_token_ms, self.idle_ms];
+        if fields.iter().flatten().any(|ms| *ms == 0) {
+            return Err("Timeouts must be positive".to_string());
+        }
+        if let (Some(first), Some(total)) = (self.first_token_ms, self.total_ms) {
+            if first > total {
+                return Err("first_token_ms can't exceed total_ms".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error classes a retry policy can retry: 429s, 503/529s, other 5xx responses,
+/// timeouts, and connection failures
+pub const RETRY_CLASSES: &[&str] = &["rate_limited", "overloaded", "server_error", "timeout", "connection"];
+
+/// Retries against the same model before failing over to the next candidate
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct RetryPolicy {
+    /// Attempts per model, including the first
+    #[serde(default = "default_retry_max_attempts")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_retry_max_attempts"))]
+    pub max_attempts: u32,
+    /// Backoff before the first retry; doubles on each further retry
+    #[serde(default = "default_retry_initial_backoff_ms")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_retry_initial_backoff_ms"))]
+    pub initial_backoff_ms: u64,
+    /// Longest backoff; a provider asking for a longer Retry-After is failed over instead
+    #[serde(default = "default_retry_max_backoff_ms")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_retry_max_backoff_ms"))]
+    pub max_backoff_ms: u64,
+    /// Error classes worth retrying (see `RETRY_CLASSES`)
+    #[serde(default = "default_retry_on")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_retry_on"))]
+    pub retry_on: Vec<String>,
+    /// Budget for the whole request, retries and failover included
+    #[serde(default)]
+    pub deadline_ms: Option<u64>,
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_backoff_ms() -> u64 {
+    200
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+    5_000
+}
+
+fn default_retry_on() -> Vec<String> {
+    vec!["rate_limited".to_string(), "overloaded".to_string(), "timeout".to_string(), "connection".to_string()]
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            initial_backoff_ms: default_retry_initial_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+            retry_on: default_retry_on(),
+            deadline_ms: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn retries(&self, class: &str) -> bool {
+        self.retry_on.iter().any(|c| c == class)
+    }
+
+    /// Delay before retry number `retry` (1 for the first retry), or `None` once the model's
+    /// attempts are used up. A provider's Retry-After is honoured as-is unless it exceeds
+    /// `max_backoff_ms`; otherwise the exponential backoff is jittered by `jitter` (0..1)
+    /// into 50-100% of its value.
+    pub fn backoff(&self, retry: u32, retry_after: Option<std::time::Duration>, jitter: f64) -> Option<std::time::Duration> {
+        if retry == 0 || retry >= self.max_attempts {
+            return None;
+        }
+        let max = std::time::Duration::from_millis(self.max_backoff_ms);
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= max).then_some(retry_after);
+        }
+        let exponential = self.initial_backoff_ms as f64 * 2f64.powi(retry.min(32) as i32 - 1);
+        let capped = exponential.min(self.max_backoff_ms as f64);
+        Some(std::time::Duration::from_millis((capped * (0.5 + jitter.clamp(0.0, 1.0) / 2.0)) as u64))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=10).contains(&self.max_attempts) {
+            return Err("Retry max_attempts must be between 1 and 10".to_string());
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            return Err("Retry initial_backoff_ms can't exceed max_backoff_ms".to_string());
+        }
+        if let Some(class) = self.retry_on.iter().find(|c| !RETRY_CLASSES.contains(&c.as_str())) {
+            return Err(format!("Unknown retry class '{}' (expected one of {})", class, RETRY_CLASSES.join(", ")));
+        }
+        if self.deadline_ms == Some(0) {
+            return Err("Retry deadline_ms must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Longest a cached response may be served
+pub const MAX_RESPONSE_CACHE_TTL_SECS: u64 = 30 * 24 * 3600;
+
+/// Opt-in cache of non-streamed chat responses. Applies to requests at temperature 0
+/// and to callers that ask for caching; entries are never shared between tenants.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct ResponseCachePolicy {
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub enabled: bool,
+    /// How long a response is served from the cache
+    #[serde(default = "default_response_cache_ttl_secs")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_response_cache_ttl_secs"))]
+    pub ttl_secs: u64,
+    /// Responses larger than this (in bytes) aren't cached
+    #[serde(default = "default_response_cache_max_entry_bytes")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_response_cache_max_entry_bytes"))]
+    pub max_entry_bytes: u64,
+    /// Also store entries in Postgres, so hits survive restarts and are shared across replicas
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub persistent: bool,
+    /// Also serve answers to earlier prompts that are close enough in meaning
+    #[serde(default)]
+    pub semantic: Option<SemanticCacheConfig>,
+}
+
+/// Semantic matching for the response cache: prompts are embedded and a new prompt is
+/// answered from the closest earlier one above the similarity threshold
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct SemanticCacheConfig {
+    /// Embedding model (model ID) used for prompts
+    pub embedding_model_id: String,
+    /// Lowest cosine similarity (0..1) served as a hit
+    #[serde(default = "default_semantic_similarity_threshold")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_semantic_similarity_threshold"))]
+    pub similarity_threshold: f64,
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub index: VectorIndexKind,
+    /// Prompts kept per service and tenant; the oldest are dropped first
+    #[serde(default = "default_semantic_max_entries")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_semantic_max_entries"))]
+    pub max_entries: u32,
+}
+
+fn default_semantic_similarity_threshold() -> f64 {
+    0.95
+}
+
+fn default_semantic_max_entries() -> u32 {
+    10_000
+}
+
+/// How a semantic cache is searched
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Enum))]
+#[cfg_attr(feature = "openapi", oai(rename_all = "snake_case"))]
+pub enum VectorIndexKind {
+    /// Exact search over every entry; fine for a few thousand prompts
+    #[default]
+    BruteForce,
+    /// Approximate nearest-neighbour graph, for larger caches
+    Hnsw,
+}
+
+fn default_response_cache_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_response_cache_max_entry_bytes() -> u64 {
+    256 * 1024
+}
+
+impl ResponseCachePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_RESPONSE_CACHE_TTL_SECS).contains(&self.ttl_secs) {
+            return Err(format!("Response cache ttl_secs must be between 1 and {}", MAX_RESPONSE_CACHE_TTL_SECS));
+        }
+        if self.max_entry_bytes == 0 {
+            return Err("Response cache max_entry_bytes must be positive".to_string());
+        }
+        if let Some(semantic) = &self.semantic {
+            if semantic.embedding_model_id.trim().is_empty() {
+                return Err("Semantic cache embedding_model_id is required".to_string());
+            }
+            if !(semantic.similarity_threshold > 0.0 && semantic.similarity_threshold <= 1.0) {
+                return Err("Semantic cache similarity_threshold must be in (0, 1]".to_string());
+            }
+            if !(1..=1_000_000).contains(&semantic.max_entries) {
+                return Err("Semantic cache max_entries must be between 1 and 1000000".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Service level objective, measured over a rolling window of per-minute rollups
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct SloConfig {
+    /// Target availability in percent (e.g. 99.9)
+    pub availability_target: f64,
+    /// Target p95 latency in milliseconds
+    #[serde(default)]
+    pub latency_p95_ms: Option<i64>,
+    /// Rolling compliance window in days
+    #[serde(default = "default_slo_window_days")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_slo_window_days"))]
+    pub window_days: i64,
+}
+
+fn default_slo_window_days() -> i64 {
+    30
+}
+
+impl SloConfig {
+    /// Fraction of requests allowed to fail (0.001 for 99.9%)
+    pub fn error_budget_fraction(&self) -> f64 {
+        (100.0 - self.availability_target) / 100.0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.availability_target > 0.0 && self.availability_target < 100.0) {
+            return Err("SLO availability_target must be between 0 and 100 (exclusive)".to_string());
+        }
+        if self.latency_p95_ms.is_some_and(|ms| ms <= 0) {
+            return Err("SLO latency_p95_ms must be positive".to_string());
+        }
+        if !(1..=90).contains(&self.window_days) {
+            return Err("SLO window_days must be between 1 and 90".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Budget-aware downgrade: as the caller's monthly quota runs down, route to cheaper models
+/// instead of failing once `check_quota` starts rejecting
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct BudgetPolicy {
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub enabled: bool,
+    /// The highest threshold reached applies
+    pub thresholds: Vec<BudgetThreshold>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct BudgetThreshold {
+    /// Share of the monthly quota used (0-100) at which this step applies
+    pub usage_percent: f64,
+    /// Keep only models in these tiers (e.g. "free", "standard")
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub allowed_tiers: Vec<String>,
+    /// Model to route to, tried after any allowed-tier models
+    #[serde(default)]
+    pub fallback_model_id: Option<String>,
+}
+
+impl BudgetPolicy {
+    /// Highest threshold at or below `usage_percent`
+    pub fn threshold_for(&self, usage_percent: f64) -> Option<&BudgetThreshold> {
+        if !self.enabled {
+            return None;
+        }
+        self.thresholds
+            .iter()
+            .filter(|t| usage_percent >= t.usage_percent)
+            .max_by(|a, b| a.usage_percent.partial_cmp(&b.usage_percent).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for threshold in &self.thresholds {
+            if !(0.0..=100.0).contains(&threshold.usage_percent) {
+                return Err(format!("Budget threshold {} must be between 0 and 100", threshold.usage_percent));
+            }
+            if threshold.allowed_tiers.is_empty() && threshold.fallback_model_id.is_none() {
+                return Err(format!(
+                    "Budget threshold {}% needs allowed_tiers or a fallback_model_id",
+                    threshold.usage_percent
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A/B experiment or canary rollout splitting a service's traffic between arms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct ExperimentConfig {
+    pub name: String,
+    pub arms: Vec<ExperimentArm>,
+    /// What assignment is sticky to
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub sticky_by: ExperimentSubject,
+    /// Unix timestamps bounding the experiment (open-ended when unset)
+    #[serde(default)]
+    pub starts_at: Option<i64>,
+    #[serde(default)]
+    pub ends_at: Option<i64>,
+}
+
+/// One arm of an experiment: a share of traffic pinned to a subset of the service's models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct ExperimentArm {
+    pub name: String,
+    /// Percentage of subjects assigned to this arm (arms must sum to 100)
+    pub percentage: u32,
+    /// Preferred models for this arm; the rest of the service remains available for failover
+    pub model_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Enum))]
+#[cfg_attr(feature = "openapi", oai(rename_all = "snake_case"))]
+pub enum ExperimentSubject {
+    #[default]
+    User,
+    ApiKey,
+}
+
+/// Ensemble strategy: send the request to several member models in parallel and combine the answers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct EnsembleConfig {
+    /// Number of member models called, best-ranked first
+    #[serde(default = "default_ensemble_size")]
+    #[cfg_attr(feature = "openapi", oai(default = "default_ensemble_size"))]
+    pub size: u32,
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", oai(default))]
+    pub combiner: EnsembleCombiner,
+    /// Model that picks or merges the best answer (required for the judge combiner)
+    #[serde(default)]
+    pub judge_model_id: Option<String>,
+}
+
+impl Default for EnsembleConfig {
+    fn default() -> Self {
+        Self { size: default_ensemble_size(), combiner: EnsembleCombiner::default(), judge_model_id: None }
+    }
+}
+
+fn default_ensemble_size() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Enum))]
+#[cfg_attr(feature = "openapi", oai(rename_all = "snake_case"))]
+pub enum EnsembleCombiner {
+    /// Most common answer after normalisation (classification, extraction)
+    #[default]
+    MajorityVote,
+    /// A judge model picks or merges the best answer
+    Judge,
+    /// Every member answer, one choice each
+    ReturnAll,
+}
+
+impl EnsembleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.size < 2 {
+            return Err("Ensemble size must be at least 2".to_string());
+        }
+        if self.combiner == EnsembleCombiner::Judge && self.judge_model_id.is_none() {
+            return Err("Judge combiner requires judge_model_id".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl ExperimentConfig {
+    pub fn is_running(&self, now: i64) -> bool {
+        self.starts_at.is_none_or(|start| now >= start) && self.ends_at.is_none_or(|end| now < end)
+    }
+
+    /// Deterministically assign a subject (user ID or API key hash) to an arm
+    pub fn assign(&self, subject: &str) -> Option<&ExperimentArm> {
+        let bucket = (crate::routing::stable_hash(&[&self.name, subject]) % 100) as u32;
+        let mut upper = 0;
+        for arm in &self.arms {
+            upper += arm.percentage;
+            if bucket < upper {
+                return Some(arm);
+            }
+        }
+        None
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Experiment name is required".to_string());
+        }
+        if self.arms.len() < 2 {
+            return Err("Experiment requires at least two arms".to_string());
+        }
+        let total: u32 = self.arms.iter().map(|a| a.percentage).sum();
+        if total != 100 {
+            return Err(format!("Experiment arm percentages must sum to 100, got {}", total));
+        }
+        let mut names = std::collections::HashSet::new();
+        for arm in &self.arms {
+            if !names.insert(arm.name.as_str()) {
+                return Err(format!("Duplicate experiment arm '{}'", arm.name));
+            }
+            if arm.model_ids.is_empty() {
+                return Err(format!("Experiment arm '{}' has no models", arm.name));
+            }
+        }
+        if let (Some(start), Some(end)) = (self.starts_at, self.ends_at) {
+            if end <= start {
+                return Err("Experiment ends_at must be after starts_at".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find a cycle reachable from `origin` in a service -> fallback services graph.
+/// Returns the looping path (e.g. `a -> b -> a`) if one exists.
+pub fn find_fallback_cycle(origin: &str, graph: &std::collections::HashMap<String, Vec<String>>) -> Option<Vec<String>> {
+    fn visit(
+        node: &str,
+        graph: &std::collections::HashMap<String, Vec<String>>,
+        stack: &mut Vec<String>,
+        done: &mut std::collections::HashSet<String>,
+    ) -> Option<Vec<String>> {
+        if let Some(pos) = stack.iter().position(|s| s == node) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(node.to_string());
+            return Some(cycle);
+        }
+        if done.contains(node) {
+            return None;
+        }
+        stack.push(node.to_string());
+        for next in graph.get(node).into_iter().flatten() {
+            if let Some(cycle) = visit(next, graph, stack, done) {
+                return Some(cycle);
+            }
+        }
+        stack.pop();
+        done.insert(node.to_string());
+        None
+    }
+
+    visit(origin, graph, &mut Vec::new(), &mut std::collections::HashSet::new())
+}
+
+/// Parse an optional JSON config column, ignoring missing columns and malformed values
+fn parse_json_column<T: serde::de::DeserializeOwned>(row: &PgRow, column: &str) -> Option<T> {
+    let raw: Option<String> = row.try_get(column).ok().flatten();
+    raw.and_then(|s| serde_json::from_str(&s).ok())
 }
 
 impl<'r> FromRow<'r, PgRow> for Service {
@@ -131,6 +902,18 @@ impl<'r> FromRow<'r, PgRow> for Service {
             system_prompt: row.try_get("system_prompt").ok(),
             max_iterations: row.try_get("max_iterations").ok(),
             user_id: row.try_get("user_id").ok(),
+            hedge: parse_json_column(row, "hedge_config"),
+            experiment: parse_json_column(row, "experiment_config"),
+            shadow: parse_json_column(row, "shadow_config"),
+            fallback_services: parse_json_column(row, "fallback_services").unwrap_or_default(),
+            modality_strategies: parse_json_column(row, "modality_strategies").unwrap_or_default(),
+            ensemble: parse_json_column(row, "ensemble_config"),
+            budget_policy: parse_json_column(row, "budget_policy"),
+            circuit_policy: parse_json_column(row, "circuit_policy"),
+            slo: parse_json_column(row, "slo"),
+            timeouts: parse_json_column(row, "timeouts"),
+            retry_policy: parse_json_column(row, "retry_policy"),
+            response_cache: parse_json_column(row, "response_cache"),
         })
     }
 }
@@ -167,6 +950,19 @@ pub struct UpdateService {
     pub planner_model_id: Option<String>,
     pub system_prompt: Option<String>,
     pub max_iterations: Option<u32>,
+
+    // Routing policies
+    pub hedge: Option<HedgeConfig>,
+    pub experiment: Option<ExperimentConfig>,
+    pub shadow: Option<ShadowConfig>,
+    pub fallback_services: Option<Vec<String>>,
+    pub modality_strategies: Option<HashMap<String, String>>,
+    pub ensemble: Option<EnsembleConfig>,
+    pub budget_policy: Option<BudgetPolicy>,
+    pub circuit_policy: Option<CircuitPolicy>,
+    pub timeouts: Option<TimeoutPolicy>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub response_cache: Option<ResponseCachePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -223,3 +1019,152 @@ pub struct BulkUpdateModelAssignment {
 pub struct BulkUpdateServiceModels {
     pub models: Vec<BulkUpdateModelAssignment>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canary(percentage: u32) -> ExperimentConfig {
+        ExperimentConfig {
+            name: "new-model-rollout".to_string(),
+            arms: vec![
+                ExperimentArm { name: "control".to_string(), percentage: 100 - percentage, model_ids: vec!["gpt-4o".to_string()] },
+                ExperimentArm { name: "canary".to_string(), percentage, model_ids: vec!["new-model".to_string()] },
+            ],
+            sticky_by: ExperimentSubject::User,
+            starts_at: None,
+            ends_at: None,
+        }
+    }
+
+    #[test]
+    fn test_experiment_assignment_is_sticky() {
+        let experiment = canary(50);
+        for i in 0..100 {
+            let user = format!("user-{}", i);
+            let first = experiment.assign(&user).map(|a| a.name.clone());
+            let second = experiment.assign(&user).map(|a| a.name.clone());
+            assert!(first.is_some());
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn test_experiment_split_matches_percentages() {
+        let experiment = canary(5);
+        let canary_count = (0..10_000)
+            .filter(|i| experiment.assign(&format!("user-{}", i)).map(|a| a.name.as_str()) == Some("canary"))
+            .count();
+        assert!((350..650).contains(&canary_count), "canary got {} of 10000", canary_count);
+    }
+
+    #[test]
+    fn test_fallback_cycle_detection() {
+        let mut graph = std::collections::HashMap::new();
+        graph.insert("chat-premium".to_string(), vec!["chat-standard".to_string()]);
+        graph.insert("chat-standard".to_string(), vec!["chat-basic".to_string()]);
+        assert_eq!(find_fallback_cycle("chat-premium", &graph), None);
+
+        graph.insert("chat-basic".to_string(), vec!["chat-premium".to_string()]);
+        assert_eq!(
+            find_fallback_cycle("chat-premium", &graph),
+            Some(vec!["chat-premium", "chat-standard", "chat-basic", "chat-premium"].into_iter().map(String::from).collect())
+        );
+    }
+
+    #[test]
+    fn test_experiment_validation_and_window() {
+        assert!(canary(5).validate().is_ok());
+
+        let mut bad = canary(5);
+        bad.arms[0].percentage = 90;
+        assert!(bad.validate().is_err());
+
+        let mut windowed = canary(5);
+        windowed.starts_at = Some(100);
+        windowed.ends_at = Some(200);
+        assert!(!windowed.is_running(99));
+        assert!(windowed.is_running(150));
+        assert!(!windowed.is_running(200));
+    }
+
+    #[test]
+    fn test_dispatch_kind_matches_modality_sub_pools() {
+        assert!(DispatchKind::Chat.accepts("text", "text"));
+        assert!(!DispatchKind::Chat.accepts("image", "image"));
+        assert!(DispatchKind::ImageGeneration.accepts("Image", "image"));
+
+        // Untyped audio models serve every audio request kind
+        assert!(DispatchKind::TextToSpeech.accepts("audio", "audio"));
+        assert!(DispatchKind::Transcription.accepts("audio", "audio"));
+        assert!(!DispatchKind::TextToSpeech.accepts("audio", "stt"));
+        assert!(!DispatchKind::Transcription.accepts("audio", "tts"));
+        assert!(DispatchKind::SpeechToSpeech.accepts("audio", "tts"));
+    }
+
+    #[test]
+    fn test_budget_policy_picks_highest_threshold_reached() {
+        let policy = BudgetPolicy {
+            enabled: true,
+            thresholds: vec![
+                BudgetThreshold { usage_percent: 95.0, allowed_tiers: vec![], fallback_model_id: Some("mini".to_string()) },
+                BudgetThreshold { usage_percent: 80.0, allowed_tiers: vec!["standard".to_string()], fallback_model_id: None },
+            ],
+        };
+        assert!(policy.validate().is_ok());
+        assert!(policy.threshold_for(50.0).is_none());
+        assert_eq!(policy.threshold_for(80.0).map(|t| t.usage_percent), Some(80.0));
+        assert_eq!(policy.threshold_for(97.5).map(|t| t.usage_percent), Some(95.0));
+
+        let disabled = BudgetPolicy { enabled: false, ..policy };
+        assert!(disabled.threshold_for(99.0).is_none());
+    }
+
+    #[test]
+    fn test_timeout_policy_inherits_unset_fields() {
+        let service = TimeoutPolicy { total_ms: Some(30_000), idle_ms: Some(5_000), ..Default::default() };
+        let model = TimeoutPolicy { total_ms: Some(60_000), first_token_ms: Some(10_000), ..Default::default() };
+
+        let effective = model.or(&service);
+        assert_eq!(effective.total(), std::time::Duration::from_secs(60));
+        assert_eq!(effective.first_token(), Some(std::time::Duration::from_secs(10)));
+        assert_eq!(effective.idle(), Some(std::time::Duration::from_secs(5)));
+        assert_eq!(effective.connect(), std::time::Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS));
+
+        assert!(TimeoutPolicy { first_token_ms: Some(90_000), total_ms: Some(60_000), ..Default::default() }.validate().is_err());
+        assert!(TimeoutPolicy { idle_ms: Some(0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy { max_attempts: 4, initial_backoff_ms: 100, max_backoff_ms: 300, ..RetryPolicy::default() };
+        let ms = |d: Option<std::time::Duration>| d.map(|d| d.as_millis() as u64);
+
+        assert_eq!(ms(policy.backoff(1, None, 1.0)), Some(100));
+        assert_eq!(ms(policy.backoff(2, None, 1.0)), Some(200));
+        assert_eq!(ms(policy.backoff(3, None, 1.0)), Some(300));
+        assert_eq!(ms(policy.backoff(2, None, 0.0)), Some(100));
+        // Attempts used up
+        assert_eq!(policy.backoff(4, None, 1.0), None);
+        // Retry-After wins, unless it's longer than we're willing to wait
+        assert_eq!(ms(policy.backoff(1, Some(std::time::Duration::from_millis(250)), 0.0)), Some(250));
+        assert_eq!(policy.backoff(1, Some(std::time::Duration::from_secs(30)), 0.0), None);
+
+        assert!(policy.retries("rate_limited"));
+        assert!(!policy.retries("server_error"));
+        assert!(RetryPolicy { retry_on: vec!["teapot".to_string()], ..RetryPolicy::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_circuit_policy_trips_and_backs_off() {
+        let policy = CircuitPolicy { error_rate_percent: Some(50.0), ..CircuitPolicy::default() };
+        assert!(!policy.should_trip(2, 4, 2)); // below min_requests
+        assert!(policy.should_trip(3, 3, 3));
+        assert!(policy.should_trip(1, 10, 5));
+        assert!(!policy.should_trip(1, 10, 4));
+
+        assert_eq!(policy.open_duration(0).as_secs(), 60);
+        assert_eq!(policy.open_duration(2).as_secs(), 240);
+        assert_eq!(policy.open_duration(10).as_secs(), 600);
+    }
+}
@@ -78,7 +78,7 @@ pub struct Delta {
 }
 
 /// Image generation request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
 pub struct ImageGenerationRequest {
     pub prompt: String,
@@ -110,7 +110,7 @@ pub struct ImageData {
 }
 
 /// Text-to-speech request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
 pub struct TextToSpeechRequest {
     pub input: String,
@@ -119,7 +119,7 @@ pub struct TextToSpeechRequest {
 }
 
 /// Speech-to-text (transcription) request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
 pub struct AudioTranscriptionRequest {
     pub model: String,
@@ -135,7 +135,7 @@ pub struct AudioTranscriptionResponse {
 }
 
 /// Speech-to-speech request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
 pub struct SpeechToSpeechRequest {
     pub model: String,
@@ -144,7 +144,7 @@ pub struct SpeechToSpeechRequest {
 }
 
 /// Video generation request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
 pub struct VideoGenerationRequest {
     pub prompt: String,
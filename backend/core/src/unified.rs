@@ -40,6 +40,28 @@ pub struct UnifiedChatRequest {
     // NEW: Response format (JSON Mode)
     #[serde(default)]
     pub response_format: Option<crate::types::ResponseFormat>,
+    
+    // Optional affinity key (e.g. conversation ID) to keep a conversation on one model
+    #[serde(default)]
+    pub affinity_key: Option<String>,
+    
+    // Optional queue priority (low, normal or high), capped by the API key and plan
+    #[serde(default)]
+    pub priority: Option<String>,
+    
+    // Ask for the service's response cache even when temperature isn't 0
+    #[serde(default)]
+    pub cache: Option<bool>,
+    
+    // Skip response cache lookups (set by the gateway from `Cache-Control: no-cache`)
+    #[serde(skip)]
+    #[cfg_attr(feature = "openapi", oai(skip))]
+    pub cache_bypass: bool,
+    
+    // Hash of the API key that authenticated the request (set by the gateway, never by clients)
+    #[serde(skip)]
+    #[cfg_attr(feature = "openapi", oai(skip))]
+    pub api_key_hash: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -75,6 +97,9 @@ pub struct TokenUsage {
 pub struct RoutingMetadata {
     pub requested_routing: RequestedRouting,
     pub actual_routing: ActualRouting,
+    /// Member results when the service ran as an ensemble
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ensemble: Option<EnsembleMetadata>,
 }
 
 #[derive(Debug, Serialize)]
@@ -91,6 +116,61 @@ pub struct ActualRouting {
     pub provider: String,
     pub model: String,
     pub fallback_used: bool,
+    /// Service that produced the response
+    pub service: String,
+    /// Services tried in order when a cross-service fallback was taken
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_path: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experiment_arm: Option<String>,
+    /// Quota usage threshold (percent) whose budget policy restricted the candidates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_downgrade: Option<f64>,
+    /// Served from the service's response cache
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cache_hit: bool,
+    /// Cosine similarity to the cached prompt, for semantic cache hits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_similarity: Option<f64>,
+    /// Guardrails with the `warn` action that matched the request or response
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub guardrail_warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct EnsembleMetadata {
+    /// "majority_vote", "judge" or "return_all"
+    pub combiner: String,
+    /// Model whose answer was returned (majority vote) or that produced it (judge)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_model: Option<String>,
+    /// Members that agreed with the returned answer (majority vote)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub votes: Option<u32>,
+    pub members: Vec<EnsembleMemberResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judge: Option<EnsembleMemberResult>,
+    /// Cost of every call made, members and judge
+    pub total_cost_usd: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(poem_openapi::Object))]
+pub struct EnsembleMemberResult {
+    pub model: String,
+    pub provider: String,
+    /// "success" or "error"
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
 }
 
 /// Events emitted during agentic execution stream
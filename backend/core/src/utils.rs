@@ -41,6 +41,29 @@ pub fn safe_percentage(used: f64, total: f64) -> u8 {
     }
 }
 
+/// Lexical similarity between two texts in [0, 1]: cosine over lowercase word counts.
+/// Cheap enough to score every shadow response without an embedding call.
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    use std::collections::HashMap;
+
+    fn word_counts(text: &str) -> HashMap<String, f64> {
+        let mut counts = HashMap::new();
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            *counts.entry(word.to_lowercase()).or_insert(0.0) += 1.0;
+        }
+        counts
+    }
+
+    let (a, b) = (word_counts(a), word_counts(b));
+    if a.is_empty() || b.is_empty() {
+        return if a.is_empty() && b.is_empty() { 1.0 } else { 0.0 };
+    }
+
+    let dot: f64 = a.iter().filter_map(|(w, n)| b.get(w).map(|m| n * m)).sum();
+    let norm = |v: &HashMap<String, f64>| v.values().map(|n| n * n).sum::<f64>().sqrt();
+    dot / (norm(&a) * norm(&b))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -60,6 +83,15 @@ mod tests {
         assert_eq!(safe_percentage(-10.0, 100.0), 0);
     }
 
+    #[test]
+    fn test_text_similarity() {
+        assert!((text_similarity("The answer is 42.", "the ANSWER is 42") - 1.0).abs() < 1e-9);
+        assert_eq!(text_similarity("apples and pears", "quantum chromodynamics"), 0.0);
+        let partial = text_similarity("paris is the capital of france", "the capital of france is paris, of course");
+        assert!(partial > 0.7 && partial < 1.0);
+        assert_eq!(text_similarity("", "something"), 0.0);
+    }
+
     #[test]
     fn test_next_month_timestamp() {
         let ts = next_month_timestamp();
@@ -0,0 +1,430 @@
+//! Admission Control
+//!
+//! Caps the calls in flight to a model or provider (`max_concurrent`) and queues the rest.
+//! Waiting calls go by priority (low, normal, high: from the request, capped by the API
+//! key and the caller's plan), then by start-time fair queueing across tenants (orgs, or
+//! users without one) weighted by plan, so a burst from one tenant can't starve the
+//! others. A call that can't get a slot within the queue timeout fails with 503. Queues
+//! are per replica and shared by every executor in the process.
+
+use mawi_core::models::{Model, Provider};
+use mawi_core::plans::{PLAN_ENTERPRISE_ID, PLAN_PRO_TIER_ID};
+use moka::future::Cache;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// Queue priorities, lowest first
+pub const PRIORITIES: [&str; 3] = ["low", "normal", "high"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "low" => Some(Self::Low),
+            "normal" => Some(Self::Normal),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        PRIORITIES[*self as usize]
+    }
+}
+
+/// Highest priority a plan may use, and its tenants' share of a busy lane
+fn plan_queueing(plan: &str) -> (Priority, f64) {
+    match plan {
+        PLAN_ENTERPRISE_ID => (Priority::High, 4.0),
+        PLAN_PRO_TIER_ID => (Priority::High, 2.0),
+        _ => (Priority::Normal, 1.0),
+    }
+}
+
+/// How a call waits in a queue
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    /// Org ID, or user ID for users outside an org
+    pub tenant: String,
+    pub priority: Priority,
+    pub weight: f64,
+}
+
+impl Ticket {
+    /// Gateway-funded background calls (shadow replays) wait behind everyone
+    pub fn background() -> Self {
+        Self { tenant: "gateway".to_string(), priority: Priority::Low, weight: 1.0 }
+    }
+}
+
+/// A caller's queueing settings, from their plan and API key
+#[derive(Debug, Clone)]
+struct Caller {
+    tenant: String,
+    default: Priority,
+    ceiling: Priority,
+    weight: f64,
+}
+
+impl Caller {
+    /// A key's priority is its requests' default and may lower, never raise, the plan's ceiling
+    fn new(tenant: String, plan: &str, key_priority: Option<Priority>) -> Self {
+        let (plan_ceiling, weight) = plan_queueing(plan);
+        let ceiling = key_priority.map_or(plan_ceiling, |p| p.min(plan_ceiling));
+        Self {
+            tenant,
+            default: key_priority.unwrap_or(Priority::Normal).min(ceiling),
+            ceiling,
+            weight,
+        }
+    }
+
+    fn ticket(&self, requested: Option<Priority>) -> Ticket {
+        Ticket {
+            tenant: self.tenant.clone(),
+            priority: requested.unwrap_or(self.default).min(self.ceiling),
+            weight: self.weight,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdmissionConfig {
+    /// Longest a call waits for a concurrency slot
+    pub queue_timeout: Duration,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self { queue_timeout: Duration::from_secs(30) }
+    }
+}
+
+impl AdmissionConfig {
+    /// Read `ADMISSION_QUEUE_TIMEOUT_MS`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            queue_timeout: std::env::var("ADMISSION_QUEUE_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.queue_timeout),
+        }
+    }
+}
+
+/// No concurrency slot freed up in time. Not an upstream failure: the attempt fails over
+/// without counting against health or circuits, and a request left with nothing else gets a 503.
+#[derive(Debug)]
+pub struct QueueTimeout {
+    /// e.g. "model gpt-4o"
+    pub lane: String,
+    pub waited: Duration,
+}
+
+impl std::fmt::Display for QueueTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is at its concurrency limit, no slot freed up within {:.1}s", self.lane, self.waited.as_secs_f64())
+    }
+}
+
+impl std::error::Error for QueueTimeout {}
+
+struct Waiter {
+    seq: u64,
+    priority: Priority,
+    /// Start tag: the lane's virtual time when the tenant's earlier calls are through
+    start: f64,
+    granted: oneshot::Sender<()>,
+}
+
+/// Calls to one model or provider
+struct Lane {
+    scope: &'static str,
+    name: String,
+    limit: u32,
+    running: u32,
+    waiting: Vec<Waiter>,
+    next_seq: u64,
+    virtual_time: f64,
+    // Finish tag of each tenant's latest call
+    finish: HashMap<String, f64>,
+}
+
+impl Lane {
+    fn new(scope: &'static str, name: &str) -> Self {
+        Self {
+            scope,
+            name: name.to_string(),
+            limit: 0,
+            running: 0,
+            waiting: Vec::new(),
+            next_seq: 0,
+            virtual_time: 0.0,
+            finish: HashMap::new(),
+        }
+    }
+
+    fn enqueue(&mut self, ticket: &Ticket, granted: oneshot::Sender<()>) -> u64 {
+        let start = self.finish.get(&ticket.tenant).copied().unwrap_or(0.0).max(self.virtual_time);
+        self.finish.insert(ticket.tenant.clone(), start + 1.0 / ticket.weight.max(0.01));
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.waiting.push(Waiter { seq, priority: ticket.priority, start, granted });
+        seq
+    }
+
+    /// Hand free slots to the highest priority, then earliest start tag
+    fn dispatch(&mut self) {
+        while self.running < self.limit {
+            let next = self.waiting
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    b.priority.cmp(&a.priority).then(a.start.total_cmp(&b.start)).then(a.seq.cmp(&b.seq))
+                })
+                .map(|(i, _)| i);
+            let Some(next) = next else { break };
+            let waiter = self.waiting.swap_remove(next);
+            self.virtual_time = self.virtual_time.max(waiter.start);
+            if waiter.granted.send(()).is_ok() {
+                self.running += 1;
+            }
+        }
+        // Tenants with nothing ahead of the virtual time start from it anyway
+        let virtual_time = self.virtual_time;
+        self.finish.retain(|_, finish| *finish > virtual_time);
+        crate::metrics::ADMISSION_QUEUE_DEPTH
+            .with_label_values(&[self.scope, &self.name])
+            .set(self.waiting.len() as i64);
+    }
+}
+
+type Lanes = Arc<Mutex<HashMap<String, Lane>>>;
+
+fn lock(lanes: &Lanes) -> std::sync::MutexGuard<'_, HashMap<String, Lane>> {
+    lanes.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A place in a lane's queue, then the slot it was given. Dropping it leaves the queue,
+/// or frees the slot for the next call.
+struct Permit {
+    lanes: Lanes,
+    key: String,
+    seq: u64,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut lanes = lock(&self.lanes);
+        let Some(lane) = lanes.get_mut(&self.key) else { return };
+        match lane.waiting.iter().position(|w| w.seq == self.seq) {
+            Some(i) => {
+                lane.waiting.swap_remove(i);
+            }
+            None => lane.running = lane.running.saturating_sub(1),
+        }
+        lane.dispatch();
+    }
+}
+
+/// Concurrency slots held by a call until dropped
+pub struct Slots {
+    _permits: Vec<Permit>,
+}
+
+pub struct Scheduler {
+    pool: PgPool,
+    config: AdmissionConfig,
+    lanes: Lanes,
+    callers: Cache<String, Caller>,
+}
+
+static SCHEDULER: OnceLock<Arc<Scheduler>> = OnceLock::new();
+
+impl Scheduler {
+    pub fn new(pool: PgPool, config: AdmissionConfig) -> Self {
+        Self {
+            pool,
+            config,
+            lanes: Arc::new(Mutex::new(HashMap::new())),
+            callers: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(60))
+                .build(),
+        }
+    }
+
+    /// Shared scheduler, so caps hold across every executor in the process
+    pub fn global(pool: &PgPool) -> Arc<Self> {
+        SCHEDULER
+            .get_or_init(|| Arc::new(Self::new(pool.clone(), AdmissionConfig::from_env())))
+            .clone()
+    }
+
+    async fn caller(&self, user_id: &str, api_key_hash: Option<&str>) -> Caller {
+        let cache_key = format!("{}:{}", user_id, api_key_hash.unwrap_or(""));
+        if let Some(caller) = self.callers.get(&cache_key).await {
+            return caller;
+        }
+        let row = sqlx::query_as::<_, (Option<String>, String, Option<String>)>(
+            "SELECT u.org_id, u.tier, k.priority FROM users u
+             LEFT JOIN api_keys k ON k.user_id = u.id AND k.key_hash = $2
+             WHERE u.id = $1"
+        )
+        .bind(user_id)
+        .bind(api_key_hash)
+        .fetch_optional(&self.pool)
+        .await;
+        let (org_id, plan, key_priority) = match row {
+            Ok(row) => row.unwrap_or((None, String::new(), None)),
+            Err(e) => {
+                warn!(user = user_id, error = %e, "could not load queue priority, using defaults");
+                return Caller::new(user_id.to_string(), "", None);
+            }
+        };
+        let tenant = org_id.unwrap_or_else(|| user_id.to_string());
+        let caller = Caller::new(tenant, &plan, key_priority.as_deref().and_then(Priority::parse));
+        self.callers.insert(cache_key, caller.clone()).await;
+        caller
+    }
+
+    /// Queue ticket for a call. `user_id` is `None` for gateway-funded calls; an unknown
+    /// `requested` priority falls back to the caller's default.
+    pub async fn ticket(&self, user_id: Option<&str>, api_key_hash: Option<&str>, requested: Option<&str>) -> Ticket {
+        let Some(user_id) = user_id else { return Ticket::background() };
+        self.caller(user_id, api_key_hash).await.ticket(requested.and_then(Priority::parse))
+    }
+
+    /// Wait for a slot on the model and on its provider, where they cap concurrency.
+    /// Fails with `QueueTimeout` when none frees up within the queue timeout.
+    pub async fn admit(&self, provider: &Provider, model: &Model, ticket: &Ticket) -> Result<Slots, QueueTimeout> {
+        let deadline = Instant::now() + self.config.queue_timeout;
+        let lanes = [
+            ("model", format!("model:{}", model.id), &model.id, model.max_concurrent),
+            ("provider", format!("provider:{}", provider.id), &provider.name, provider.max_concurrent),
+        ];
+        let mut permits = Vec::new();
+        for (scope, key, name, limit) in lanes {
+            let Some(limit) = limit.filter(|n| *n > 0) else { continue };
+            permits.push(self.wait_for_slot(scope, key, name, limit as u32, ticket, deadline).await?);
+        }
+        Ok(Slots { _permits: permits })
+    }
+
+    async fn wait_for_slot(
+        &self,
+        scope: &'static str,
+        key: String,
+        name: &str,
+        limit: u32,
+        ticket: &Ticket,
+        deadline: Instant,
+    ) -> Result<Permit, QueueTimeout> {
+        let started = Instant::now();
+        let (tx, mut granted) = oneshot::channel();
+        let seq = {
+            let mut lanes = lock(&self.lanes);
+            let lane = lanes.entry(key.clone()).or_insert_with(|| Lane::new(scope, name));
+            lane.limit = limit;
+            let seq = lane.enqueue(ticket, tx);
+            lane.dispatch();
+            seq
+        };
+        // Dropped on timeout: leaves the queue, or hands back a slot granted as time ran out.
+        // Declared after `granted` so it is dropped first and a grant can't go unreceived.
+        let permit = Permit { lanes: self.lanes.clone(), key, seq };
+
+        let outcome = tokio::time::timeout_at(deadline, &mut granted).await;
+        let waited = started.elapsed();
+        let priority = ticket.priority.as_str();
+        match outcome {
+            Ok(Ok(())) => {
+                crate::metrics::ADMISSION_QUEUE_WAIT
+                    .with_label_values(&[scope, priority])
+                    .observe(waited.as_secs_f64());
+                if waited > Duration::from_millis(100) {
+                    debug!(scope, lane = name, priority, waited_ms = waited.as_millis() as u64, "admitted after queueing");
+                }
+                Ok(permit)
+            }
+            _ => {
+                crate::metrics::ADMISSION_QUEUE_TIMEOUTS.with_label_values(&[scope, priority]).inc();
+                warn!(scope, lane = name, priority, tenant = %ticket.tenant, "timed out waiting for a concurrency slot");
+                Err(QueueTimeout { lane: format!("{} {}", scope, name), waited })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(tenant: &str, priority: Priority) -> Ticket {
+        Ticket { tenant: tenant.to_string(), priority, weight: 1.0 }
+    }
+
+    #[test]
+    fn test_dispatch_by_priority_then_fair_share() {
+        let mut lane = Lane::new("model", "test");
+        lane.limit = 1;
+        lane.running = 1;
+
+        // Tenant a bursts three calls before b and a high-priority call arrive
+        let mut queued = Vec::new();
+        for (label, tenant, priority) in [
+            ("a1", "a", Priority::Normal),
+            ("a2", "a", Priority::Normal),
+            ("a3", "a", Priority::Normal),
+            ("b1", "b", Priority::Normal),
+            ("c1", "c", Priority::High),
+        ] {
+            let (tx, rx) = oneshot::channel();
+            lane.enqueue(&ticket(tenant, priority), tx);
+            queued.push((label, rx));
+        }
+        lane.dispatch();
+        assert_eq!(lane.waiting.len(), 5);
+
+        let mut order = Vec::new();
+        for _ in 0..5 {
+            lane.running -= 1;
+            lane.dispatch();
+            for (label, rx) in queued.iter_mut() {
+                if rx.try_recv().is_ok() {
+                    order.push(*label);
+                }
+            }
+        }
+        assert_eq!(order, ["c1", "a1", "b1", "a2", "a3"]);
+    }
+
+    #[test]
+    fn test_priority_capped_by_key_and_plan() {
+        let free = Caller::new("u1".to_string(), "community", None);
+        assert_eq!(free.ticket(Some(Priority::High)).priority, Priority::Normal);
+        assert_eq!(free.ticket(Some(Priority::Low)).priority, Priority::Low);
+
+        let batch_key = Caller::new("org1".to_string(), PLAN_ENTERPRISE_ID, Some(Priority::Low));
+        assert_eq!(batch_key.ticket(None).priority, Priority::Low);
+        assert_eq!(batch_key.ticket(Some(Priority::High)).priority, Priority::Low);
+
+        let enterprise = Caller::new("org1".to_string(), PLAN_ENTERPRISE_ID, None);
+        assert_eq!(enterprise.ticket(None).priority, Priority::Normal);
+        assert_eq!(enterprise.ticket(Some(Priority::High)).priority, Priority::High);
+        assert_eq!(enterprise.weight, 4.0);
+    }
+}
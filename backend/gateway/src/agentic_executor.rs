@@ -24,9 +24,9 @@ pub struct AgenticExecutor {
 }
 
 impl AgenticExecutor {
-    pub fn new(pool: PgPool, mcp_manager: Arc<RwLock<McpManager>>) -> Self {
-        let executor = Executor::new(pool.clone(), mcp_manager.clone());
-        Self { pool, executor, mcp_manager }
+    /// Runs tool calls through `executor`; clones share its caches and pools
+    pub fn new(executor: Executor) -> Self {
+        Self { pool: executor.pool.clone(), mcp_manager: executor.mcp_manager.clone(), executor }
     }
 
     pub fn execute_stream(
@@ -1561,6 +1561,11 @@ impl AgenticExecutor {
             stream: None,
             routing_strategy: None,
             response_format: None,
+            affinity_key: None,
+            priority: None,
+            cache: None,
+            cache_bypass: false,
+            api_key_hash: None,
         };
 
         let response = self.executor.execute_chat(&request, user_id).await?;
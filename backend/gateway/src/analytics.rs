@@ -51,6 +51,75 @@ pub struct TopModel {
     pub model_name: String,
     pub request_count: i64,
     pub total_cost: f64,
+    /// Currently out of rotation (maintenance window on the model or its provider)
+    pub drained: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SubmitFeedback {
+    /// `id` of the chat completion response being rated
+    pub response_id: String,
+    /// -1 (negative), 0 (neutral) or 1 (positive)
+    pub score: i32,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ExperimentComparison {
+    pub service_name: String,
+    pub experiment: String,
+    pub arms: Vec<ExperimentArmStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ExperimentArmStats {
+    pub arm: String,
+    pub attempts: i64,
+    pub successful_requests: i64,
+    pub failed_requests: i64,
+    pub error_rate: f64,
+    pub avg_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub total_cost_usd: f64,
+    pub avg_cost_per_success_usd: f64,
+    pub feedback_count: i64,
+    pub avg_feedback_score: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object, sqlx::FromRow)]
+pub struct ShadowModelStats {
+    pub shadow_model_id: String,
+    pub replays: i64,
+    pub failed_replays: i64,
+    pub avg_latency_ms: f64,
+    pub avg_primary_latency_ms: f64,
+    pub total_cost_usd: f64,
+    pub avg_similarity: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object, sqlx::FromRow)]
+pub struct GuardrailStats {
+    pub guardrail_id: String,
+    pub guardrail_name: String,
+    pub service_name: String,
+    /// input or output
+    pub stage: String,
+    /// block, redact, warn or log
+    pub action: String,
+    pub violations: i64,
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object, sqlx::FromRow)]
+pub struct GuardrailViolation {
+    pub guardrail_id: String,
+    pub guardrail_name: String,
+    pub service_name: String,
+    pub stage: String,
+    pub action: String,
+    /// Which check matched, never the matched text
+    pub detail: Option<String>,
+    pub created_at: i64,
 }
 
 pub struct AnalyticsApi {
@@ -205,16 +274,21 @@ impl AnalyticsApi {
              poem::error::Error::from_string("Unauthorized", poem::http::StatusCode::UNAUTHORIZED)
         })?;
 
-        let models = sqlx::query_as::<_, (String, String, i64, f64)>(
+        let models = sqlx::query_as::<_, (String, String, i64, f64, bool)>(
             "SELECT 
                 m.id as model_id,
                 m.name as model_name,
                 COUNT(rl.id) as request_count,
-                COALESCE(SUM(rl.cost_usd), 0.0)::FLOAT8 as total_cost
+                COALESCE(SUM(rl.cost_usd), 0.0)::FLOAT8 as total_cost,
+                EXISTS (
+                    SELECT 1 FROM active_maintenance am
+                    WHERE (am.scope = 'model' AND am.resource_id = m.id)
+                       OR (am.scope = 'provider' AND am.resource_id = m.provider_id)
+                ) as drained
              FROM models m
              LEFT JOIN request_logs rl ON m.id = rl.model_id
              WHERE rl.user_id = $1
-             GROUP BY m.id, m.name
+             GROUP BY m.id, m.name, m.provider_id
              HAVING COUNT(rl.id) > 0
              ORDER BY total_cost DESC
              LIMIT 10"
@@ -224,8 +298,8 @@ impl AnalyticsApi {
         .await
         .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
         
-        let top_models: Vec<TopModel> = models.into_iter().map(|(model_id, model_name, request_count, total_cost)| {
-            TopModel { model_id, model_name, request_count, total_cost }
+        let top_models: Vec<TopModel> = models.into_iter().map(|(model_id, model_name, request_count, total_cost, drained)| {
+            TopModel { model_id, model_name, request_count, total_cost, drained }
         }).collect();
         
         Ok(Json(top_models))
@@ -279,4 +353,254 @@ impl AnalyticsApi {
 
         Ok(Json(logs))
     }
+
+    /// Record user feedback on a chat completion response
+    #[oai(path = "/analytics/feedback", method = "post", tag = "ApiTags::Analytics")]
+    async fn submit_feedback(&self, req: &Request, body: Json<SubmitFeedback>) -> poem::Result<()> {
+        let user = req.extensions().get::<User>().ok_or_else(|| {
+             poem::error::Error::from_string("Unauthorized", poem::http::StatusCode::UNAUTHORIZED)
+        })?;
+
+        if !(-1..=1).contains(&body.score) {
+            return Err(poem::error::Error::from_string("Score must be -1, 0 or 1", poem::http::StatusCode::BAD_REQUEST));
+        }
+
+        // Only the caller's own responses can be rated
+        let owned: Option<i32> = sqlx::query_scalar(
+            "SELECT 1 FROM request_logs WHERE response_id = $1 AND user_id = $2 AND status = 'success' LIMIT 1"
+        )
+        .bind(&body.response_id)
+        .bind(&user.id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        if owned.is_none() {
+            return Err(poem::error::Error::from_string("Response not found", poem::http::StatusCode::NOT_FOUND));
+        }
+
+        sqlx::query(
+            "INSERT INTO request_feedback (id, response_id, user_id, score, comment)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (response_id, user_id) DO UPDATE SET score = EXCLUDED.score, comment = EXCLUDED.comment"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&body.response_id)
+        .bind(&user.id)
+        .bind(body.score)
+        .bind(&body.comment)
+        .execute(&self.pool)
+        .await
+        .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        Ok(())
+    }
+
+    /// Compare experiment arms on latency, error rate, cost and user feedback
+    #[oai(path = "/analytics/experiments/:service_name", method = "get", tag = "ApiTags::Analytics")]
+    async fn get_experiment_comparison(
+        &self,
+        req: &Request,
+        service_name: poem_openapi::param::Path<String>,
+        #[oai(name = "experiment")] experiment: poem_openapi::param::Query<Option<String>>, // defaults to the service's current experiment
+    ) -> poem::Result<Json<ExperimentComparison>> {
+        let user = req.extensions().get::<User>().ok_or_else(|| {
+             poem::error::Error::from_string("Unauthorized", poem::http::StatusCode::UNAUTHORIZED)
+        })?;
+
+        let experiment_name = match experiment.0 {
+            Some(name) => name,
+            None => {
+                let service = sqlx::query_as::<_, mawi_core::services::Service>(
+                    "SELECT * FROM services WHERE name = $1 AND user_id = $2"
+                )
+                .bind(&service_name.0)
+                .bind(&user.id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?
+                .ok_or_else(|| poem::error::Error::from_string("Service not found", poem::http::StatusCode::NOT_FOUND))?;
+
+                service.experiment.map(|e| e.name).ok_or_else(|| {
+                    poem::error::Error::from_string("Service has no experiment configured", poem::http::StatusCode::NOT_FOUND)
+                })?
+            }
+        };
+
+        #[derive(sqlx::FromRow)]
+        struct ArmRow {
+            arm: String,
+            attempts: i64,
+            successful_requests: i64,
+            failed_requests: i64,
+            avg_latency_ms: f64,
+            p95_latency_ms: f64,
+            total_cost_usd: f64,
+            feedback_count: i64,
+            avg_feedback_score: Option<f64>,
+        }
+
+        let rows = sqlx::query_as::<_, ArmRow>(
+            r#"
+            SELECT
+                rl.experiment_arm as arm,
+                COUNT(*) FILTER (WHERE rl.status <> 'cancelled') as attempts,
+                COUNT(*) FILTER (WHERE rl.status = 'success') as successful_requests,
+                COUNT(*) FILTER (WHERE rl.status = 'error') as failed_requests,
+                COALESCE(AVG(rl.latency_ms) FILTER (WHERE rl.status = 'success'), 0.0)::FLOAT8 as avg_latency_ms,
+                COALESCE(percentile_cont(0.95) WITHIN GROUP (ORDER BY rl.latency_ms) FILTER (WHERE rl.status = 'success'), 0.0)::FLOAT8 as p95_latency_ms,
+                COALESCE(SUM(rl.cost_usd), 0.0)::FLOAT8 as total_cost_usd,
+                COUNT(f.id) as feedback_count,
+                AVG(f.score)::FLOAT8 as avg_feedback_score
+            FROM request_logs rl
+            LEFT JOIN request_feedback f ON f.response_id = rl.response_id AND rl.status = 'success'
+            WHERE rl.user_id = $1
+            AND rl.service_name = $2
+            AND rl.experiment = $3
+            AND rl.experiment_arm IS NOT NULL
+            GROUP BY rl.experiment_arm
+            ORDER BY rl.experiment_arm
+            "#
+        )
+        .bind(&user.id)
+        .bind(&service_name.0)
+        .bind(&experiment_name)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        let arms = rows.into_iter().map(|row| ExperimentArmStats {
+            arm: row.arm,
+            attempts: row.attempts,
+            successful_requests: row.successful_requests,
+            failed_requests: row.failed_requests,
+            error_rate: if row.attempts > 0 {
+                row.failed_requests as f64 / row.attempts as f64
+            } else {
+                0.0
+            },
+            avg_latency_ms: row.avg_latency_ms,
+            p95_latency_ms: row.p95_latency_ms,
+            total_cost_usd: row.total_cost_usd,
+            avg_cost_per_success_usd: if row.successful_requests > 0 {
+                row.total_cost_usd / row.successful_requests as f64
+            } else {
+                0.0
+            },
+            feedback_count: row.feedback_count,
+            avg_feedback_score: row.avg_feedback_score,
+        }).collect();
+
+        Ok(Json(ExperimentComparison {
+            service_name: service_name.0,
+            experiment: experiment_name,
+            arms,
+        }))
+    }
+
+    /// Compare shadow models against the primary responses they mirrored
+    #[oai(path = "/analytics/shadow/:service_name", method = "get", tag = "ApiTags::Analytics")]
+    async fn get_shadow_comparison(
+        &self,
+        req: &Request,
+        service_name: poem_openapi::param::Path<String>,
+    ) -> poem::Result<Json<Vec<ShadowModelStats>>> {
+        let user = req.extensions().get::<User>().ok_or_else(|| {
+             poem::error::Error::from_string("Unauthorized", poem::http::StatusCode::UNAUTHORIZED)
+        })?;
+
+        let rows = sqlx::query_as::<_, ShadowModelStats>(
+            r#"
+            SELECT
+                shadow_model_id,
+                COUNT(*) as replays,
+                COUNT(*) FILTER (WHERE status = 'error') as failed_replays,
+                COALESCE(AVG(latency_ms) FILTER (WHERE status = 'success'), 0.0)::FLOAT8 as avg_latency_ms,
+                COALESCE(AVG(primary_latency_ms), 0.0)::FLOAT8 as avg_primary_latency_ms,
+                COALESCE(SUM(cost_usd), 0.0)::FLOAT8 as total_cost_usd,
+                AVG(similarity)::FLOAT8 as avg_similarity
+            FROM shadow_results
+            WHERE user_id = $1
+            AND service_name = $2
+            GROUP BY shadow_model_id
+            ORDER BY shadow_model_id
+            "#
+        )
+        .bind(&user.id)
+        .bind(&service_name.0)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        Ok(Json(rows))
+    }
+
+    /// Guardrail violations by guardrail, service, stage and action
+    #[oai(path = "/analytics/guardrails", method = "get", tag = "ApiTags::Analytics")]
+    async fn get_guardrail_stats(
+        &self,
+        req: &Request,
+        #[oai(name = "range")] range: poem_openapi::param::Query<Option<String>>, // 24h, 7d, 30d
+    ) -> poem::Result<Json<Vec<GuardrailStats>>> {
+        let user = req.extensions().get::<User>().ok_or_else(|| {
+             poem::error::Error::from_string("Unauthorized", poem::http::StatusCode::UNAUTHORIZED)
+        })?;
+
+        let interval_str = match range.0.as_deref() {
+            Some("7d") => "7 days",
+            Some("30d") => "30 days",
+            _ => "24 hours",
+        };
+
+        let rows = sqlx::query_as::<_, GuardrailStats>(
+            &format!(r#"
+            SELECT
+                guardrail_id,
+                MAX(guardrail_name) as guardrail_name,
+                service_name,
+                stage,
+                action,
+                COUNT(*)::BIGINT as violations,
+                MAX(created_at) as last_seen
+            FROM guardrail_violations
+            WHERE created_at >= EXTRACT(EPOCH FROM (NOW() - INTERVAL '{}'))::BIGINT
+            AND user_id = $1
+            GROUP BY guardrail_id, service_name, stage, action
+            ORDER BY violations DESC
+            "#, interval_str)
+        )
+        .bind(&user.id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        Ok(Json(rows))
+    }
+
+    /// Most recent guardrail violations
+    #[oai(path = "/analytics/guardrails/violations", method = "get", tag = "ApiTags::Analytics")]
+    async fn get_guardrail_violations(
+        &self,
+        req: &Request,
+        #[oai(name = "limit")] limit: poem_openapi::param::Query<Option<i64>>,
+    ) -> poem::Result<Json<Vec<GuardrailViolation>>> {
+        let user = req.extensions().get::<User>().ok_or_else(|| {
+             poem::error::Error::from_string("Unauthorized", poem::http::StatusCode::UNAUTHORIZED)
+        })?;
+
+        let rows = sqlx::query_as::<_, GuardrailViolation>(
+            "SELECT guardrail_id, guardrail_name, service_name, stage, action, detail, created_at
+             FROM guardrail_violations
+             WHERE user_id = $1
+             ORDER BY created_at DESC, id DESC
+             LIMIT $2"
+        )
+        .bind(&user.id)
+        .bind(limit.0.unwrap_or(50).min(500))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e: sqlx::Error| poem::error::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        Ok(Json(rows))
+    }
 }
@@ -1,10 +1,12 @@
-use poem_openapi::{payload::Json, OpenApi, param::Path, Tags};
+use poem_openapi::{payload::Json, OpenApi, param::{Path, Query}, Tags};
 use sqlx::{PgPool, Postgres};
 use mawi_core::models::{Model, CreateModel, UpdateModel, Provider, CreateProvider, UpdateProvider};
 use mawi_core::services::{Service, CreateService, UpdateService, AssignModel, UpdateModelAssignment, BulkUpdateServiceModels};
 use uuid::Uuid;
+use crate::config_sync::Invalidation;
+use crate::executor::Executor;
 use serde::Serialize;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use std::collections::HashMap;
 
 // Cache for environment variable presence to avoid syscalls in hot loops
@@ -27,6 +29,45 @@ fn check_env_key(key: &str) -> bool {
     *map.get(key).unwrap_or(&false)
 }
 
+/// Reject requests from non-admin users (the user is injected by AuthMiddleware)
+pub fn require_admin(req: &poem::Request) -> poem::Result<()> {
+    let user = req.extensions().get::<mawi_core::auth::User>()
+        .ok_or_else(|| poem::Error::from_string("Authentication required", poem::http::StatusCode::UNAUTHORIZED))?;
+    if !user.is_admin {
+        return Err(poem::Error::from_string("Admin access required", poem::http::StatusCode::FORBIDDEN));
+    }
+    Ok(())
+}
+
+/// Reject unknown provider key selection policies
+fn check_key_selection(policy: Option<&str>) -> poem::Result<()> {
+    match policy {
+        Some(policy) if !mawi_core::models::KEY_SELECTION_POLICIES.contains(&policy) => Err(poem::Error::from_string(
+            format!(
+                "Unknown key_selection '{}' (expected one of: {})",
+                policy,
+                mawi_core::models::KEY_SELECTION_POLICIES.join(", ")
+            ),
+            poem::http::StatusCode::BAD_REQUEST,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Reject services that attach guardrails which don't exist
+async fn check_guardrail_ids(pool: &PgPool, ids: &[String]) -> poem::Result<()> {
+    let unknown = crate::guardrails::unknown_ids(pool, ids).await.map_err(|e| {
+        poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(poem::Error::from_string(
+        format!("Unknown guardrails: {}", unknown.join(", ")),
+        poem::http::StatusCode::BAD_REQUEST,
+    ))
+}
+
 #[derive(Tags)]
 pub enum ApiTags {
     Providers,
@@ -90,6 +131,8 @@ impl From<Provider> for ProviderResponse {
 
 pub struct ModelsApi {
     pub pool: PgPool,
+    // Drops cached config after writes, here and on other replicas
+    pub executor: Arc<Executor>,
 }
 
 #[OpenApi]
@@ -145,6 +188,8 @@ impl ModelsApi {
             .ok_or_else(|| poem::error::Error::from_string("Authentication required", poem::http::StatusCode::UNAUTHORIZED))?;
         let user_id = &user.id;
         
+        check_key_selection(req.key_selection.as_deref())?;
+
         // Encrypt API key if present
         let encrypted_key = if let Some(key) = &req.api_key {
             Some(mawi_core::security::encrypt_key(key).map_err(|e| {
@@ -154,7 +199,7 @@ impl ModelsApi {
             None
         };
 
-        sqlx::query("INSERT INTO providers (id, name, provider_type, api_endpoint, api_version, api_key, description, icon_url, user_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+        sqlx::query("INSERT INTO providers (id, name, provider_type, api_endpoint, api_version, api_key, description, icon_url, user_id, requests_per_minute, tokens_per_minute, key_selection, max_concurrent) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)")
             .bind(&id)
             .bind(&req.name)
             .bind(&req.provider_type)
@@ -164,6 +209,10 @@ impl ModelsApi {
             .bind(&req.description)
             .bind(&req.icon_url)
             .bind(&user_id)
+            .bind(req.requests_per_minute.filter(|n| *n > 0))
+            .bind(req.tokens_per_minute.filter(|n| *n > 0))
+            .bind(&req.key_selection)
+            .bind(req.max_concurrent.filter(|n| *n > 0))
             .execute(&self.pool)
             .await
             .map_err(|e| {
@@ -251,6 +300,25 @@ impl ModelsApi {
             param_idx += 1;
             params.push(icon_url.clone());
         }
+        if let Some(key_selection) = &req.key_selection {
+            check_key_selection(Some(key_selection))?;
+            updates.push(format!("key_selection = ${}", param_idx));
+            param_idx += 1;
+            params.push(key_selection.clone());
+        }
+        // 0 clears a limit
+        let typed_updates = [
+            ("requests_per_minute", req.requests_per_minute),
+            ("tokens_per_minute", req.tokens_per_minute),
+            ("max_concurrent", req.max_concurrent),
+        ];
+        for (column, value) in typed_updates {
+            if let Some(value) = value {
+                updates.push(format!("{} = NULLIF(${}::integer, 0)", column, param_idx));
+                param_idx += 1;
+                params.push(value.max(0).to_string());
+            }
+        }
 
         if !updates.is_empty() {
             let query = format!("UPDATE providers SET {} WHERE id = ${}", updates.join(", "), param_idx);
@@ -277,7 +345,8 @@ impl ModelsApi {
                     poem::http::StatusCode::INTERNAL_SERVER_ERROR
                 )
             })?;
-        
+
+        self.executor.invalidate(Invalidation::Provider(id.0.clone())).await;
         Ok(Json(group))
     }
 
@@ -307,6 +376,7 @@ impl ModelsApi {
             ));
         }
 
+        self.executor.invalidate(Invalidation::Provider(id.0.clone())).await;
         Ok(Json("Provider deleted".to_string()))
     }
 
@@ -398,6 +468,28 @@ impl ModelsApi {
         )).map(Json)
     }
 
+    /// Active health probe history for a model, newest first
+    #[oai(path = "/models/:id/health/history", method = "get", tag = "ApiTags::Models")]
+    async fn get_model_health_history(
+        &self,
+        id: Path<String>,
+        /// Only probes at or after this Unix timestamp
+        since: Query<Option<i64>>,
+        /// Maximum entries to return (default 100, max 1000)
+        limit: Query<Option<i64>>,
+        poem_req: &poem::Request,
+    ) -> poem::Result<Json<Vec<crate::health::HealthHistoryEntry>>> {
+        require_admin(poem_req)?;
+        let limit = limit.0.unwrap_or(100).clamp(1, 1000);
+        crate::health::health_history(&self.pool, &id.0, since.0, limit)
+            .await
+            .map(Json)
+            .map_err(|e| poem::error::Error::from_string(
+                format!("Database error: {}", e),
+                poem::http::StatusCode::INTERNAL_SERVER_ERROR
+            ))
+    }
+
     /// Create model
     #[oai(path = "/models", method = "post", tag = "ApiTags::Models")]
     async fn create_model(&self, req: Json<CreateModel>, poem_req: &poem::Request) -> poem::Result<Json<Model>> {
@@ -440,7 +532,10 @@ impl ModelsApi {
         let id = Uuid::new_v4().to_string();
         let created_at = chrono::Utc::now().timestamp();
         
-        sqlx::query("INSERT INTO models (id, name, provider_id, modality, description, api_endpoint, api_version, api_key, created_at, tier_required, worker_type, user_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'A', 'text', $10)")
+        sqlx::query("INSERT INTO models (id, name, provider_id, modality, description, api_endpoint, api_version, api_key, created_at, tier_required, worker_type, user_id,
+                context_window, max_output_tokens, supports_vision, supports_tools, supports_json_mode, supports_json_schema, supports_streaming, supports_reasoning,
+                connect_timeout_ms, timeout_ms, first_token_timeout_ms, idle_timeout_ms, max_concurrent)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'A', 'text', $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23)")
             .bind(&id)
             .bind(&req.name)
             .bind(&req.provider)
@@ -451,6 +546,19 @@ impl ModelsApi {
             .bind(&req.api_key)
             .bind(created_at)
             .bind(&user_id)
+            .bind(req.context_window)
+            .bind(req.max_output_tokens)
+            .bind(req.supports_vision)
+            .bind(req.supports_tools)
+            .bind(req.supports_json_mode)
+            .bind(req.supports_json_schema)
+            .bind(req.supports_streaming)
+            .bind(req.supports_reasoning)
+            .bind(req.connect_timeout_ms)
+            .bind(req.timeout_ms)
+            .bind(req.first_token_timeout_ms)
+            .bind(req.idle_timeout_ms)
+            .bind(req.max_concurrent.filter(|n| *n > 0))
             .execute(&self.pool)
             .await
             .map_err(|e| {
@@ -479,7 +587,19 @@ impl ModelsApi {
             avg_ttft_ms: 0,
             max_tps: 0,
             
-            context_window: 8192, // Default context window
+            context_window: req.context_window,
+            max_output_tokens: req.max_output_tokens,
+            supports_vision: req.supports_vision,
+            supports_tools: req.supports_tools,
+            supports_json_mode: req.supports_json_mode,
+            supports_json_schema: req.supports_json_schema,
+            supports_streaming: req.supports_streaming,
+            supports_reasoning: req.supports_reasoning,
+            connect_timeout_ms: req.connect_timeout_ms,
+            timeout_ms: req.timeout_ms,
+            first_token_timeout_ms: req.first_token_timeout_ms,
+            idle_timeout_ms: req.idle_timeout_ms,
+            max_concurrent: req.max_concurrent.filter(|n| *n > 0),
             
             api_endpoint: req.api_endpoint.clone(),
             api_version: req.api_version.clone(),
@@ -550,6 +670,30 @@ impl ModelsApi {
             params.push(api_key.clone());
         }
 
+        // Limits and capabilities (bound as text, cast to the column type)
+        let typed_updates = [
+            ("context_window", "integer", req.context_window.map(|v| v.to_string())),
+            ("max_output_tokens", "integer", req.max_output_tokens.map(|v| v.to_string())),
+            ("supports_vision", "boolean", req.supports_vision.map(|v| v.to_string())),
+            ("supports_tools", "boolean", req.supports_tools.map(|v| v.to_string())),
+            ("supports_json_mode", "boolean", req.supports_json_mode.map(|v| v.to_string())),
+            ("supports_json_schema", "boolean", req.supports_json_schema.map(|v| v.to_string())),
+            ("supports_streaming", "boolean", req.supports_streaming.map(|v| v.to_string())),
+            ("supports_reasoning", "boolean", req.supports_reasoning.map(|v| v.to_string())),
+            ("connect_timeout_ms", "integer", req.connect_timeout_ms.map(|v| v.to_string())),
+            ("timeout_ms", "integer", req.timeout_ms.map(|v| v.to_string())),
+            ("first_token_timeout_ms", "integer", req.first_token_timeout_ms.map(|v| v.to_string())),
+            ("idle_timeout_ms", "integer", req.idle_timeout_ms.map(|v| v.to_string())),
+            ("max_concurrent", "integer", req.max_concurrent.map(|v| v.to_string())),
+        ];
+        for (column, sql_type, value) in typed_updates {
+            if let Some(value) = value {
+                updates.push(format!("{} = ${}::{}", column, param_idx, sql_type));
+                param_idx += 1;
+                params.push(value);
+            }
+        }
+
         if !updates.is_empty() {
             let query = format!("UPDATE models SET {} WHERE id = ${}", updates.join(", "), param_idx);
             let mut q = sqlx::query(&query);
@@ -575,7 +719,8 @@ impl ModelsApi {
                     poem::http::StatusCode::INTERNAL_SERVER_ERROR
                 )
             })?;
-        
+
+        self.executor.invalidate(Invalidation::Model(id.0.clone())).await;
         Ok(Json(model))
     }
 
@@ -605,6 +750,7 @@ impl ModelsApi {
             ));
         }
 
+        self.executor.invalidate(Invalidation::Model(id.0.clone())).await;
         Ok(Json("Model deleted".to_string()))
     }
 
@@ -674,6 +820,7 @@ impl ModelsApi {
         if !valid_types.contains(&req.service_type.to_lowercase().as_str()) {
              return Err(poem::error::Error::from_string(format!("Invalid service type. Must be one of: {:?}", valid_types), poem::http::StatusCode::BAD_REQUEST));
         }
+        check_guardrail_ids(&self.pool, &req.guardrails).await?;
 
         eprintln!("Creating service: name={}, type={}", req.name, req.service_type);
         
@@ -745,6 +892,7 @@ impl ModelsApi {
             params.push(strategy.clone());
         }
         if let Some(guardrails) = &req.guardrails {
+            check_guardrail_ids(&self.pool, guardrails).await?;
             updates.push(format!("guardrails = ${}", param_idx));
             param_idx += 1;
             params.push(serde_json::to_string(guardrails).unwrap_or("[]".to_string()));
@@ -769,6 +917,159 @@ impl ModelsApi {
             param_idx += 1;
             params.push(max_iterations.to_string());
         }
+        if let Some(hedge) = &req.hedge {
+            if hedge.enabled && hedge.max_fanout < 2 {
+                return Err(poem::error::Error::from_string(
+                    "Hedging requires max_fanout of at least 2",
+                    poem::http::StatusCode::BAD_REQUEST
+                ));
+            }
+            updates.push(format!("hedge_config = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(hedge).unwrap_or("{}".to_string()));
+        }
+        if let Some(experiment) = &req.experiment {
+            experiment.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+
+            // Arms may only reference models assigned to this service
+            let assigned: Vec<String> = sqlx::query_scalar("SELECT model_id FROM service_models WHERE service_name = $1")
+                .bind(&name.0)
+                .fetch_all(&self.pool)
+                .await
+                .unwrap_or_default();
+            for arm in &experiment.arms {
+                if let Some(missing) = arm.model_ids.iter().find(|m| !assigned.contains(m)) {
+                    return Err(poem::error::Error::from_string(
+                        format!("Experiment arm '{}' references model '{}' which is not assigned to this service", arm.name, missing),
+                        poem::http::StatusCode::BAD_REQUEST
+                    ));
+                }
+            }
+
+            updates.push(format!("experiment_config = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(experiment).unwrap_or("{}".to_string()));
+        }
+        if let Some(shadow) = &req.shadow {
+            if !(0.0..=100.0).contains(&shadow.sample_percent) {
+                return Err(poem::error::Error::from_string(
+                    "Shadow sample_percent must be between 0 and 100",
+                    poem::http::StatusCode::BAD_REQUEST
+                ));
+            }
+            if shadow.enabled && shadow.model_ids.is_empty() {
+                return Err(poem::error::Error::from_string(
+                    "Shadow traffic requires at least one shadow model",
+                    poem::http::StatusCode::BAD_REQUEST
+                ));
+            }
+            updates.push(format!("shadow_config = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(shadow).unwrap_or("{}".to_string()));
+        }
+        if let Some(fallback_services) = &req.fallback_services {
+            // Fallbacks must exist, and the resulting chain must not loop back on itself
+            let mut graph: std::collections::HashMap<String, Vec<String>> = sqlx::query_as::<_, Service>("SELECT * FROM services")
+                .fetch_all(&self.pool)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(|s| (s.name, s.fallback_services))
+                .collect();
+
+            if let Some(missing) = fallback_services.iter().find(|f| !graph.contains_key(*f)) {
+                return Err(poem::error::Error::from_string(
+                    format!("Fallback service '{}' not found", missing),
+                    poem::http::StatusCode::BAD_REQUEST
+                ));
+            }
+
+            graph.insert(name.0.clone(), fallback_services.clone());
+            if let Some(cycle) = mawi_core::services::find_fallback_cycle(&name.0, &graph) {
+                return Err(poem::error::Error::from_string(
+                    format!("Fallback chain forms a cycle: {}", cycle.join(" -> ")),
+                    poem::http::StatusCode::BAD_REQUEST
+                ));
+            }
+
+            updates.push(format!("fallback_services = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(fallback_services).unwrap_or("[]".to_string()));
+        }
+        if let Some(modality_strategies) = &req.modality_strategies {
+            if let Some(unknown) = modality_strategies.keys().find(|m| !matches!(m.as_str(), "text" | "image" | "audio" | "video")) {
+                return Err(poem::error::Error::from_string(
+                    format!("Unknown modality '{}' in modality_strategies (expected text, image, audio or video)", unknown),
+                    poem::http::StatusCode::BAD_REQUEST
+                ));
+            }
+            updates.push(format!("modality_strategies = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(modality_strategies).unwrap_or("{}".to_string()));
+        }
+        if let Some(ensemble) = &req.ensemble {
+            ensemble.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+            if let Some(judge_id) = &ensemble.judge_model_id {
+                let exists: Option<String> = sqlx::query_scalar("SELECT id FROM models WHERE id = $1")
+                    .bind(judge_id)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .unwrap_or(None);
+                if exists.is_none() {
+                    return Err(poem::error::Error::from_string(
+                        format!("Judge model '{}' not found", judge_id),
+                        poem::http::StatusCode::BAD_REQUEST
+                    ));
+                }
+            }
+            updates.push(format!("ensemble_config = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(ensemble).unwrap_or("{}".to_string()));
+        }
+        if let Some(budget_policy) = &req.budget_policy {
+            budget_policy.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+            updates.push(format!("budget_policy = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(budget_policy).unwrap_or("{}".to_string()));
+        }
+        if let Some(circuit_policy) = &req.circuit_policy {
+            circuit_policy.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+            updates.push(format!("circuit_policy = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(circuit_policy).unwrap_or("{}".to_string()));
+        }
+        if let Some(timeouts) = &req.timeouts {
+            timeouts.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+            updates.push(format!("timeouts = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(timeouts).unwrap_or("{}".to_string()));
+        }
+        if let Some(retry_policy) = &req.retry_policy {
+            retry_policy.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+            updates.push(format!("retry_policy = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(retry_policy).unwrap_or("{}".to_string()));
+        }
+        if let Some(response_cache) = &req.response_cache {
+            response_cache.validate().map_err(|e| {
+                poem::error::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST)
+            })?;
+            updates.push(format!("response_cache = ${}", param_idx));
+            param_idx += 1;
+            params.push(serde_json::to_string(response_cache).unwrap_or("{}".to_string()));
+        }
 
         if !updates.is_empty() {
             let query = format!("UPDATE services SET {} WHERE name = ${}", updates.join(", "), param_idx);
@@ -785,6 +1086,7 @@ impl ModelsApi {
             })?;
         }
 
+        self.executor.invalidate(Invalidation::Service(name.0.clone())).await;
         self.fetch_full_service(&name.0).await.map(Json)
     }
 
@@ -834,6 +1136,7 @@ impl ModelsApi {
             ));
         }
 
+        self.executor.invalidate(Invalidation::Service(name.0.clone())).await;
         Ok(Json("Service deleted".to_string()))
     }
 
@@ -975,7 +1278,8 @@ impl ModelsApi {
         
         // REORDER POSITIONS BY WEIGHT
         self.reorder_service_models_by_weight(&name.0).await?;
-        
+
+        self.executor.invalidate(Invalidation::Service(name.0.clone())).await;
         Ok(Json(format!("Model '{}' assigned to service and weights auto-balanced", model.name)))
     }
 
@@ -1141,6 +1445,7 @@ impl ModelsApi {
              poem::http::StatusCode::INTERNAL_SERVER_ERROR
         ))?;
 
+        self.executor.invalidate(Invalidation::Service(name.0.clone())).await;
         Ok(Json("Bulk update successful".to_string()))
     }
 
@@ -1266,6 +1571,7 @@ impl ModelsApi {
             self.reorder_service_models_by_weight(&name.0).await?;
         }
 
+        self.executor.invalidate(Invalidation::Service(name.0.clone())).await;
         Ok(Json("Model assignment updated and positions reordered by weight".to_string()))
     }
 
@@ -1337,6 +1643,7 @@ impl ModelsApi {
             }
         }
 
+        self.executor.invalidate(Invalidation::Service(name.0.clone())).await;
         Ok(Json(format!("Model '{}' removed from service and weights auto-balanced", model_id.0)))
     }
 
@@ -1,6 +1,6 @@
 use poem::{web::{Data, Json}, handler, Body, Response};
 use std::sync::Arc;
-use crate::executor::Executor;
+use crate::executor::{Executor, RequestRejection};
 use mawi_core::types::TextToSpeechRequest;
 
 #[handler]
@@ -20,7 +20,7 @@ pub async fn text_to_speech(
         .await
         .map_err(|e| poem::Error::from_string(
             format!("TTS failed: {}", e), 
-            poem::http::StatusCode::INTERNAL_SERVER_ERROR
+            RequestRejection::status_for(&e)
         ))?;
         
     Ok(Response::builder()
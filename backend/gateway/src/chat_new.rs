@@ -2,22 +2,66 @@ use poem_openapi::{
     payload::{Json, Binary},
     OpenApi, ApiResponse,
 };
-use poem::{web::Data, Request, Body};
+use poem::{http::StatusCode, web::Data, Request, Body};
 use mawi_core::unified::{UnifiedChatRequest, UnifiedChatResponse};
 use std::sync::Arc;
-use crate::executor::Executor;
+use crate::executor::{Executor, RequestRejection};
 use futures::StreamExt;
 
 #[derive(ApiResponse)]
 enum ChatResponse {
     #[oai(status = 200)]
-    Ok(Json<UnifiedChatResponse>),
+    Ok(
+        Json<UnifiedChatResponse>,
+        /// Quota usage threshold (percent) at which the request was routed to cheaper models
+        #[oai(header = "X-Budget-Downgrade")] Option<String>,
+    ),
     #[oai(status = 200, content_type = "text/event-stream")]
     Streaming(Binary<Body>),
+    #[oai(status = 400)]
+    BadRequest(Json<String>),
     #[oai(status = 401)]
     Unauthorized(Json<String>),
+    /// The response was blocked by an output guardrail
+    #[oai(status = 422)]
+    UnprocessableEntity(Json<String>),
+    /// Every provider key for the request is out of upstream budget
+    #[oai(status = 429)]
+    TooManyRequests(
+        Json<String>,
+        /// Seconds until a key has budget again
+        #[oai(header = "Retry-After")] Option<String>,
+    ),
     #[oai(status = 500)]
     InternalError(Json<String>),
+    /// No concurrency slot freed up within the queue timeout
+    #[oai(status = 503)]
+    ServiceUnavailable(Json<String>),
+    /// The upstream call or the service's request deadline timed out
+    #[oai(status = 504)]
+    GatewayTimeout(Json<String>),
+}
+
+impl ChatResponse {
+    /// Error response with the status `RequestRejection::status_for` gives, as on the media endpoints
+    fn from_error(e: &anyhow::Error) -> Self {
+        let message = match e.downcast_ref::<RequestRejection>() {
+            Some(rejection) => rejection.message.clone(),
+            None => e.to_string(),
+        };
+        match RequestRejection::status_for(e) {
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = e.downcast_ref::<crate::upstream_limits::UpstreamThrottled>()
+                    .map(|t| (t.retry_after.as_secs_f64().ceil().max(1.0) as u64).to_string());
+                ChatResponse::TooManyRequests(Json(message), retry_after)
+            }
+            StatusCode::UNPROCESSABLE_ENTITY => ChatResponse::UnprocessableEntity(Json(message)),
+            StatusCode::GATEWAY_TIMEOUT => ChatResponse::GatewayTimeout(Json(message)),
+            StatusCode::SERVICE_UNAVAILABLE => ChatResponse::ServiceUnavailable(Json(message)),
+            status if status.is_client_error() => ChatResponse::BadRequest(Json(message)),
+            _ => ChatResponse::InternalError(Json(format!("Request failed: {}", e))),
+        }
+    }
 }
 
 pub struct ChatApi {
@@ -42,6 +86,24 @@ impl ChatApi {
         };
         let user_id = user.id.clone();
 
+        // Conversation affinity can also be supplied as a header
+        let mut request = request;
+        if request.affinity_key.is_none() {
+            request.affinity_key = ["x-affinity-key", "x-conversation-id"]
+                .iter()
+                .find_map(|name| req.headers().get(*name))
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+        }
+        request.api_key_hash = mawi_core::auth::utils::api_key_hash(req);
+        request.cache_bypass = req
+            .headers()
+            .get_all("cache-control")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"));
+
         // Streaming Path
         if request.stream.unwrap_or(false) {
             let executor = self.executor.clone();
@@ -70,10 +132,15 @@ impl ChatApi {
 
         // Sync Path
         match self.executor.execute_chat(&request, &user_id).await {
-            Ok(response) => ChatResponse::Ok(Json(response)),
+            Ok(response) => {
+                let downgrade = response.routing_metadata.as_ref()
+                    .and_then(|m| m.actual_routing.budget_downgrade)
+                    .map(|threshold| threshold.to_string());
+                ChatResponse::Ok(Json(response), downgrade)
+            }
             Err(e) => {
                 eprintln!("Chat execution failed: {}", e);
-                ChatResponse::InternalError(Json(format!("Request failed: {}", e)))
+                ChatResponse::from_error(&e)
             }
         }
     }
@@ -83,3 +150,36 @@ impl ChatApi {
 enum ApiTags {
     Chat,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poem::IntoResponse;
+
+    fn status_of(error: anyhow::Error) -> StatusCode {
+        ChatResponse::from_error(&error).into_response().status()
+    }
+
+    #[test]
+    fn test_guardrail_blocks_keep_their_status() {
+        let blocked_output = RequestRejection { status: StatusCode::UNPROCESSABLE_ENTITY, message: "Response blocked".to_string() };
+        assert_eq!(status_of(blocked_output.into()), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status_of(RequestRejection::bad_request("Request blocked").into()), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_upstream_throttling_sets_retry_after() {
+        let throttled = crate::upstream_limits::UpstreamThrottled {
+            provider: "openai".to_string(),
+            retry_after: std::time::Duration::from_millis(2500),
+        };
+        let response = ChatResponse::from_error(&throttled.into()).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("retry-after").and_then(|v| v.to_str().ok()), Some("3"));
+    }
+
+    #[test]
+    fn test_other_errors_are_server_errors() {
+        assert_eq!(status_of(anyhow::anyhow!("boom")), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,159 @@
+//! Circuit Breaker Admin API
+//!
+//! Inspect circuit state, pin circuits open or closed, and manage
+//! per-model and per-provider circuit policies. Admin only.
+
+use poem_openapi::{
+    param::Path,
+    payload::Json,
+    Object, OpenApi, Tags,
+};
+use poem::Result;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use mawi_core::services::CircuitPolicy;
+
+use crate::api::require_admin;
+use crate::config_sync::Invalidation;
+use crate::circuit_breaker::{CircuitBreaker, CircuitSnapshot, ForcedState};
+use crate::executor::Executor;
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Circuit Breaker Administration
+    Circuits,
+}
+
+/// Request to override a circuit
+#[derive(Debug, Deserialize, Object)]
+pub struct ForceCircuitRequest {
+    /// open, closed, or auto to clear the override
+    pub state: String,
+}
+
+pub struct CircuitApi {
+    pub executor: Arc<Executor>,
+}
+
+/// Breaker key for a `model`/`provider` scope
+fn circuit_key(scope: &str, id: &str) -> Result<String> {
+    match scope {
+        "model" => Ok(CircuitBreaker::model_key(id)),
+        "provider" => Ok(CircuitBreaker::provider_key(id)),
+        _ => Err(poem::Error::from_string(
+            format!("Unknown circuit scope '{}' (expected model or provider)", scope),
+            poem::http::StatusCode::BAD_REQUEST,
+        )),
+    }
+}
+
+#[OpenApi]
+impl CircuitApi {
+    /// List tracked circuits, open and forced ones first
+    #[oai(path = "/admin/circuits", method = "get", tag = "ApiTags::Circuits")]
+    async fn list_circuits(&self, req: &poem::Request) -> Result<Json<Vec<CircuitSnapshot>>> {
+        require_admin(req)?;
+        Ok(Json(self.executor.circuit_breaker.snapshot()))
+    }
+
+    /// Force a circuit open or closed, or return it to automatic control
+    #[oai(path = "/admin/circuits/:scope/:id/force", method = "post", tag = "ApiTags::Circuits")]
+    async fn force_circuit(
+        &self,
+        scope: Path<String>,
+        id: Path<String>,
+        body: Json<ForceCircuitRequest>,
+        req: &poem::Request,
+    ) -> Result<Json<Vec<CircuitSnapshot>>> {
+        require_admin(req)?;
+        let key = circuit_key(&scope, &id)?;
+        let forced = match body.state.as_str() {
+            "open" => Some(ForcedState::Open),
+            "closed" => Some(ForcedState::Closed),
+            "auto" => None,
+            other => {
+                return Err(poem::Error::from_string(
+                    format!("Invalid circuit state '{}' (expected open, closed or auto)", other),
+                    poem::http::StatusCode::BAD_REQUEST,
+                ));
+            }
+        };
+
+        self.executor.circuit_breaker.force(&key, forced);
+        eprintln!("🔧 Circuit {} forced to {}", key, body.state);
+
+        let snapshot = self.executor.circuit_breaker.snapshot()
+            .into_iter()
+            .filter(|c| c.resource == key)
+            .collect();
+        Ok(Json(snapshot))
+    }
+
+    /// Get the circuit policy override for a model or provider
+    #[oai(path = "/admin/circuits/:scope/:id/policy", method = "get", tag = "ApiTags::Circuits")]
+    async fn get_policy(
+        &self,
+        scope: Path<String>,
+        id: Path<String>,
+        req: &poem::Request,
+    ) -> Result<Json<Option<CircuitPolicy>>> {
+        require_admin(req)?;
+        let key = circuit_key(&scope, &id)?;
+        let policy = sqlx::query_scalar::<_, String>("SELECT policy FROM circuit_policies WHERE resource = $1")
+            .bind(&key)
+            .fetch_optional(&self.executor.pool)
+            .await
+            .map_err(|e| poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?
+            .and_then(|json| serde_json::from_str(&json).ok());
+        Ok(Json(policy))
+    }
+
+    /// Set the circuit policy override for a model or provider
+    #[oai(path = "/admin/circuits/:scope/:id/policy", method = "put", tag = "ApiTags::Circuits")]
+    async fn set_policy(
+        &self,
+        scope: Path<String>,
+        id: Path<String>,
+        body: Json<CircuitPolicy>,
+        req: &poem::Request,
+    ) -> Result<Json<CircuitPolicy>> {
+        require_admin(req)?;
+        let key = circuit_key(&scope, &id)?;
+        body.validate().map_err(|e| poem::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST))?;
+
+        sqlx::query(
+            "INSERT INTO circuit_policies (resource, policy, updated_at) VALUES ($1, $2, $3)
+             ON CONFLICT (resource) DO UPDATE SET policy = EXCLUDED.policy, updated_at = EXCLUDED.updated_at"
+        )
+        .bind(&key)
+        .bind(serde_json::to_string(&body.0).unwrap_or("{}".to_string()))
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.executor.pool)
+        .await
+        .map_err(|e| poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        self.executor.invalidate(Invalidation::CircuitPolicy(key.clone())).await;
+        Ok(Json(body.0))
+    }
+
+    /// Remove the circuit policy override, falling back to the service or default policy
+    #[oai(path = "/admin/circuits/:scope/:id/policy", method = "delete", tag = "ApiTags::Circuits")]
+    async fn delete_policy(
+        &self,
+        scope: Path<String>,
+        id: Path<String>,
+        req: &poem::Request,
+    ) -> Result<Json<bool>> {
+        require_admin(req)?;
+        let key = circuit_key(&scope, &id)?;
+        let result = sqlx::query("DELETE FROM circuit_policies WHERE resource = $1")
+            .bind(&key)
+            .execute(&self.executor.pool)
+            .await
+            .map_err(|e| poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        self.executor.invalidate(Invalidation::CircuitPolicy(key.clone())).await;
+        Ok(Json(result.rows_affected() > 0))
+    }
+}
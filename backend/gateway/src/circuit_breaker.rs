@@ -1,4 +1,8 @@
 use dashmap::DashMap;
+use mawi_core::services::CircuitPolicy;
+use poem_openapi::Object;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -7,146 +11,440 @@ use std::time::{Duration, Instant};
 enum CircuitState {
     /// Circuit is closed (normal operation)
     Closed,
-    /// Circuit is open (failing fast)
-    Open { opened_at: Instant },
-    /// Circuit is half-open (testing recovery)
-    HalfOpen,
+    /// Circuit is open (failing fast) until `opened_at + open_for`
+    Open { opened_at: Instant, open_for: Duration },
+    /// Circuit is half-open (testing recovery with a single probe)
+    HalfOpen { probe_started: Option<Instant> },
+}
+
+/// Manual override set through the admin API
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForcedState {
+    /// Block every request regardless of outcomes
+    Open,
+    /// Allow every request and ignore failures
+    Closed,
 }
 
 /// Circuit Breaker Entry for a specific resource (model/provider)
 #[derive(Debug)]
 struct CircuitEntry {
     state: CircuitState,
-    failure_count: u32,
-    last_failure: Option<Instant>,
+    consecutive_failures: u32,
+    /// Outcomes inside the policy window: (when, succeeded)
+    window: VecDeque<(Instant, bool)>,
+    /// Consecutive failed recovery probes, drives the open-duration backoff
+    reopens: u32,
+    last_activity: Instant,
+    forced: Option<ForcedState>,
 }
 
 impl Default for CircuitEntry {
     fn default() -> Self {
         Self {
             state: CircuitState::Closed,
-            failure_count: 0,
-            last_failure: None,
+            consecutive_failures: 0,
+            window: VecDeque::new(),
+            reopens: 0,
+            last_activity: Instant::now(),
+            forced: None,
         }
     }
 }
 
+impl CircuitEntry {
+    fn is_tripped(&self) -> bool {
+        !matches!(self.state, CircuitState::Closed)
+    }
+
+    fn prune_window(&mut self, window: Duration) {
+        while let Some((at, _)) = self.window.front() {
+            if at.elapsed() > window {
+                self.window.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Move to a new state, keeping the open-circuit gauge in step
+    fn transition(&mut self, state: CircuitState) {
+        let was_tripped = self.is_tripped();
+        self.state = state;
+        match (was_tripped, self.is_tripped()) {
+            (false, true) => crate::metrics::CIRCUIT_BREAKER_OPEN.inc(),
+            (true, false) => crate::metrics::CIRCUIT_BREAKER_OPEN.dec(),
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, policy: &CircuitPolicy) {
+        let open_for = policy.open_duration(self.reopens);
+        self.transition(CircuitState::Open { opened_at: Instant::now(), open_for });
+    }
+
+    fn close(&mut self) {
+        self.transition(CircuitState::Closed);
+        self.consecutive_failures = 0;
+        self.reopens = 0;
+        self.window.clear();
+    }
+}
+
+/// What `CircuitBreaker::acquire` decided for a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Admission {
+    Blocked,
+    Allowed,
+    /// Allowed as the half-open probe; the caller holds the permit until an outcome is
+    /// recorded or it is released
+    Probe,
+}
+
+/// Point-in-time view of one circuit, for the admin API
+#[derive(Debug, Clone, Object)]
+pub struct CircuitSnapshot {
+    /// Resource key, `model:<id>` or `provider:<id>`
+    pub resource: String,
+    /// closed, open or half_open
+    pub state: String,
+    /// Manual override: open, closed, or unset
+    pub forced: Option<String>,
+    pub consecutive_failures: u32,
+    pub window_requests: u32,
+    pub window_failures: u32,
+    /// Consecutive failed recovery probes
+    pub reopens: u32,
+    /// Seconds until an open circuit lets a probe through
+    pub retry_in_secs: Option<u64>,
+    pub probe_in_flight: bool,
+    pub idle_secs: u64,
+}
+
 /// Circuit Breaker Manager
 #[derive(Debug, Clone)]
 pub struct CircuitBreaker {
-    // Map resource ID (model/provider ID) -> Circuit Entry
+    // Map resource key (`model:<id>` / `provider:<id>` / `credential:<id>`) -> Circuit Entry
     entries: Arc<DashMap<String, CircuitEntry>>,
-    // Configuration
-    failure_threshold: u32,
-    reset_timeout: Duration,
     max_entries: usize,
+    /// A half-open probe that hasn't reported back within this long is presumed lost:
+    /// the largest total timeout an upstream request has been given so far
+    probe_timeout_ms: Arc<AtomicU64>,
 }
 
 impl CircuitBreaker {
     pub fn new() -> Self {
         Self {
             entries: Arc::new(DashMap::new()),
-            failure_threshold: 3,           // Trip after 3 consecutive failures
-            reset_timeout: Duration::from_secs(60), // Wait 60s before retrying
-            max_entries: 10_000,            // Limit to 10k entries (prevent unbounded growth)
+            max_entries: 10_000, // Limit to 10k entries (prevent unbounded growth)
+            probe_timeout_ms: Arc::new(AtomicU64::new(mawi_core::services::DEFAULT_TOTAL_TIMEOUT_MS)),
         }
     }
 
-    /// Check if a request is allowed for a given resource
+    /// Note the total timeout of an upstream request, so a probe that is still within it
+    /// isn't presumed lost
+    pub fn cover_timeout(&self, total: Duration) {
+        let ms = u64::try_from(total.as_millis()).unwrap_or(u64::MAX);
+        self.probe_timeout_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    fn probe_timeout(&self) -> Duration {
+        Duration::from_millis(self.probe_timeout_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn model_key(model_id: &str) -> String {
+        format!("model:{}", model_id)
+    }
+
+    pub fn provider_key(provider_id: &str) -> String {
+        format!("provider:{}", provider_id)
+    }
+
+    /// Circuit of one pooled provider API key (`provider_keys.id`)
+    pub fn credential_key(key_id: &str) -> String {
+        format!("credential:{}", key_id)
+    }
+
+    /// Check if a request is allowed for a given resource.
+    ///
+    /// In half-open state only one probe is admitted; it holds the permit until its outcome is
+    /// recorded or `release_probe` is called. A probe that never reports back is presumed lost
+    /// after the longest upstream timeout seen (see `cover_timeout`) and another one is admitted.
     pub async fn allow_request(&self, resource_id: &str) -> bool {
+        self.acquire(resource_id).await != Admission::Blocked
+    }
+
+    /// `allow_request`, telling apart the request that took the half-open probe permit
+    pub async fn acquire(&self, resource_id: &str) -> Admission {
         // Fast path: Read-only check
         if let Some(entry) = self.entries.get(resource_id) {
-            if entry.state == CircuitState::Closed {
-                return true;
+            match entry.forced {
+                Some(ForcedState::Open) => return Admission::Blocked,
+                Some(ForcedState::Closed) => return Admission::Allowed,
+                None if entry.state == CircuitState::Closed => return Admission::Allowed,
+                None => {}
             }
+        } else {
+            // Never-failed resources don't need an entry
+            return Admission::Allowed;
         }
 
-        // Slow path: Potential mutation or first access
-        let mut entry = self.entries.entry(resource_id.to_string()).or_default();
-        
+        let Some(mut entry) = self.entries.get_mut(resource_id) else {
+            return Admission::Allowed;
+        };
+        entry.last_activity = Instant::now();
+
         match entry.state {
-            CircuitState::Closed => true,
-            CircuitState::Open { opened_at } => {
-                // If timeout expired, switch to HalfOpen and allow ONE request
-                if opened_at.elapsed() >= self.reset_timeout {
-                    eprintln!("🔄 Circuit Half-Open for resource: {}", resource_id);
-                    entry.state = CircuitState::HalfOpen;
-                    true
+            CircuitState::Closed => Admission::Allowed,
+            CircuitState::Open { opened_at, open_for } => {
+                if opened_at.elapsed() >= open_for {
+                    eprintln!("🔄 Circuit Half-Open for resource: {} (sending probe)", resource_id);
+                    entry.transition(CircuitState::HalfOpen { probe_started: Some(Instant::now()) });
+                    Admission::Probe
+                } else {
+                    Admission::Blocked // Still open, block request
+                }
+            }
+            CircuitState::HalfOpen { probe_started } => {
+                let probe_lost = probe_started.is_none_or(|started| started.elapsed() >= self.probe_timeout());
+                if probe_lost {
+                    entry.state = CircuitState::HalfOpen { probe_started: Some(Instant::now()) };
+                    Admission::Probe
                 } else {
-                    false // Still open, block request
+                    Admission::Blocked
                 }
-            },
-            CircuitState::HalfOpen => {
-                // Only allow one request at a time in HalfOpen state
-                true 
             }
         }
     }
 
-    /// Record a successful request
-    pub async fn record_success(&self, resource_id: &str) {
+    /// Give back a half-open probe permit without an outcome (e.g. the attempt was cancelled)
+    pub async fn release_probe(&self, resource_id: &str) {
+        self.release_probe_now(resource_id);
+    }
+
+    /// `release_probe` for callers that can't await, such as `Drop`
+    pub fn release_probe_now(&self, resource_id: &str) {
         if let Some(mut entry) = self.entries.get_mut(resource_id) {
-            match entry.state {
-                CircuitState::HalfOpen => {
-                     // Success in HalfOpen -> Reset to Closed
-                     crate::metrics::CIRCUIT_BREAKER_OPEN.dec();
-                     eprintln!("✅ Circuit Closed (recovered) for resource: {}", resource_id);
-                     entry.state = CircuitState::Closed;
-                     entry.failure_count = 0;
-                     entry.last_failure = None;
-                },
-                CircuitState::Closed => {
-                    // Reset failure count on success to prevent stale failures triggering open
-                    if entry.failure_count > 0 {
-                        entry.failure_count = 0;
-                    }
-                },
-                CircuitState::Open { .. } => {
-                    // Should not happen, but if logic permits, reset
-                    entry.state = CircuitState::Closed;
-                    entry.failure_count = 0;
-                }
+            if matches!(entry.state, CircuitState::HalfOpen { .. }) {
+                entry.state = CircuitState::HalfOpen { probe_started: None };
             }
         }
     }
 
-    /// Record a failed request
-    pub async fn record_failure(&self, resource_id: &str) {
-        // Evict oldest entry if at capacity
-        if self.entries.len() >= self.max_entries {
-            if let Some(entry) = self.entries.iter().next() {
-                let key_to_remove = entry.key().clone();
-                drop(entry); // Release the reference
-                self.entries.remove(&key_to_remove);
+    /// Record a successful request
+    pub async fn record_success(&self, resource_id: &str, policy: &CircuitPolicy) {
+        // The error rate needs successes too; consecutive-failure tracking only needs an entry once something fails
+        let mut entry = match self.entries.get_mut(resource_id) {
+            Some(entry) => entry,
+            None if policy.error_rate_percent.is_some() => {
+                self.make_room(resource_id);
+                self.entries.entry(resource_id.to_string()).or_default()
+            }
+            None => return,
+        };
+        entry.last_activity = Instant::now();
+        entry.consecutive_failures = 0;
+
+        match entry.state {
+            CircuitState::HalfOpen { .. } => {
+                eprintln!("✅ Circuit Closed (recovered) for resource: {}", resource_id);
+                entry.close();
+            }
+            CircuitState::Closed => {
+                if policy.error_rate_percent.is_some() {
+                    entry.window.push_back((Instant::now(), true));
+                    entry.prune_window(Duration::from_secs(policy.window_secs));
+                }
+            }
+            CircuitState::Open { .. } => {
+                // A request admitted before the trip finished; the probe decides recovery
             }
         }
-        
+    }
+
+    /// Record a failed request
+    pub async fn record_failure(&self, resource_id: &str, policy: &CircuitPolicy) {
+        self.make_room(resource_id);
         let mut entry = self.entries.entry(resource_id.to_string()).or_default();
+        entry.last_activity = Instant::now();
+        if entry.forced == Some(ForcedState::Closed) {
+            return;
+        }
 
         match entry.state {
             CircuitState::Closed => {
-                entry.failure_count += 1;
-                entry.last_failure = Some(Instant::now());
-                
-                eprintln!("⚠️ Circuit Failure {}/{} for resource: {}", 
-                    entry.failure_count, self.failure_threshold, resource_id);
+                entry.consecutive_failures += 1;
+                if policy.error_rate_percent.is_some() {
+                    entry.window.push_back((Instant::now(), false));
+                    entry.prune_window(Duration::from_secs(policy.window_secs));
+                }
+                let window_requests = entry.window.len() as u32;
+                let window_failures = entry.window.iter().filter(|(_, ok)| !ok).count() as u32;
 
-                if entry.failure_count >= self.failure_threshold {
+                eprintln!("⚠️ Circuit Failure {} (window {}/{}) for resource: {}",
+                    entry.consecutive_failures, window_failures, window_requests, resource_id);
+
+                if policy.should_trip(entry.consecutive_failures, window_requests, window_failures) {
                     crate::metrics::CIRCUIT_BREAKER_TRIPS.inc();
-                    crate::metrics::CIRCUIT_BREAKER_OPEN.inc();
-                    eprintln!("🚫 Circuit OPEN for resource: {} (Tripped after {} failures)", 
-                        resource_id, entry.failure_count);
-                    entry.state = CircuitState::Open { opened_at: Instant::now() };
+                    entry.open(policy);
+                    eprintln!("🚫 Circuit OPEN for resource: {} (Tripped after {} consecutive failures)",
+                        resource_id, entry.consecutive_failures);
                 }
-            },
-            CircuitState::HalfOpen => {
-                // Failure in HalfOpen -> Re-open immediately
-                eprintln!("🚫 Circuit Re-OPEN (Half-Open failed) for resource: {}", resource_id);
-                entry.state = CircuitState::Open { opened_at: Instant::now() };
-            },
+            }
+            CircuitState::HalfOpen { .. } => {
+                // Probe failed -> Re-open with a longer timeout
+                entry.reopens += 1;
+                entry.open(policy);
+                eprintln!("🚫 Circuit Re-OPEN (probe failed) for resource: {} (attempt {})", resource_id, entry.reopens);
+            }
             CircuitState::Open { .. } => {
-                // Already open, refresh valid? maybe not.
+                // Already open; late failures from requests admitted before the trip
             }
         }
     }
+
+    /// Pin a circuit open or closed, or clear the override with `None`.
+    /// Clearing resets the circuit so it starts again from a clean slate.
+    pub fn force(&self, resource_id: &str, forced: Option<ForcedState>) {
+        let mut entry = self.entries.entry(resource_id.to_string()).or_default();
+        entry.last_activity = Instant::now();
+        entry.forced = forced;
+        match forced {
+            Some(ForcedState::Open) => {
+                entry.transition(CircuitState::Open { opened_at: Instant::now(), open_for: Duration::MAX });
+            }
+            Some(ForcedState::Closed) | None => entry.close(),
+        }
+    }
+
+    /// Every tracked circuit, tripped and forced ones first
+    pub fn snapshot(&self) -> Vec<CircuitSnapshot> {
+        let mut snapshots: Vec<CircuitSnapshot> = self.entries.iter().map(|entry| {
+            let (state, retry_in_secs, probe_in_flight) = match entry.state {
+                CircuitState::Closed => ("closed", None, false),
+                CircuitState::Open { opened_at, open_for } => (
+                    "open",
+                    entry.forced.is_none().then(|| open_for.saturating_sub(opened_at.elapsed()).as_secs()),
+                    false,
+                ),
+                CircuitState::HalfOpen { probe_started } => ("half_open", None, probe_started.is_some()),
+            };
+            CircuitSnapshot {
+                resource: entry.key().clone(),
+                state: state.to_string(),
+                forced: entry.forced.map(|f| match f {
+                    ForcedState::Open => "open".to_string(),
+                    ForcedState::Closed => "closed".to_string(),
+                }),
+                consecutive_failures: entry.consecutive_failures,
+                window_requests: entry.window.len() as u32,
+                window_failures: entry.window.iter().filter(|(_, ok)| !ok).count() as u32,
+                reopens: entry.reopens,
+                retry_in_secs,
+                probe_in_flight,
+                idle_secs: entry.last_activity.elapsed().as_secs(),
+            }
+        }).collect();
+        snapshots.sort_by(|a, b| {
+            (a.state == "closed" && a.forced.is_none())
+                .cmp(&(b.state == "closed" && b.forced.is_none()))
+                .then_with(|| a.resource.cmp(&b.resource))
+        });
+        snapshots
+    }
+
+    /// Make room for a new entry by dropping the longest-idle closed circuit. Open, half-open
+    /// and forced circuits are never evicted, so capacity pressure can't silently reset them.
+    fn make_room(&self, resource_id: &str) {
+        if self.entries.len() < self.max_entries || self.entries.contains_key(resource_id) {
+            return;
+        }
+        let victim = self.entries.iter()
+            .filter(|entry| !entry.is_tripped() && entry.forced.is_none())
+            .min_by_key(|entry| entry.last_activity)
+            .map(|entry| entry.key().clone());
+        if let Some(key) = victim {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_half_open_admits_single_probe() {
+        let breaker = CircuitBreaker::new();
+        let policy = CircuitPolicy { consecutive_failures: 2, open_secs: 0, ..CircuitPolicy::default() };
+        breaker.record_failure("model:a", &policy).await;
+        assert!(breaker.allow_request("model:a").await);
+        breaker.record_failure("model:a", &policy).await;
+
+        // open_secs = 0: the first caller becomes the probe, the rest wait on it
+        assert!(breaker.allow_request("model:a").await);
+        assert!(!breaker.allow_request("model:a").await);
+
+        breaker.release_probe("model:a").await;
+        assert!(breaker.allow_request("model:a").await);
+
+        // Failed probe re-opens and counts towards the backoff
+        breaker.record_failure("model:a", &policy).await;
+        assert_eq!(breaker.snapshot()[0].reopens, 1);
+
+        assert!(breaker.allow_request("model:a").await);
+        breaker.record_success("model:a", &policy).await;
+        let snapshot = &breaker.snapshot()[0];
+        assert_eq!(snapshot.state, "closed");
+        assert_eq!(snapshot.reopens, 0);
+    }
+
+    #[test]
+    fn test_probe_timeout_covers_the_longest_upstream_timeout() {
+        let breaker = CircuitBreaker::new();
+        let default = Duration::from_millis(mawi_core::services::DEFAULT_TOTAL_TIMEOUT_MS);
+        assert_eq!(breaker.probe_timeout(), default);
+
+        breaker.cover_timeout(Duration::from_secs(600));
+        breaker.cover_timeout(Duration::from_secs(30));
+        assert_eq!(breaker.probe_timeout(), Duration::from_secs(600));
+        // Clones share it: the key pool and the executor use the same breaker
+        assert_eq!(breaker.clone().probe_timeout(), Duration::from_secs(600));
+    }
+
+    #[tokio::test]
+    async fn test_force_overrides_outcomes() {
+        let breaker = CircuitBreaker::new();
+        let policy = CircuitPolicy { consecutive_failures: 1, ..CircuitPolicy::default() };
+        breaker.record_failure("provider:p", &policy).await;
+        assert!(!breaker.allow_request("provider:p").await);
+
+        breaker.force("provider:p", Some(ForcedState::Closed));
+        breaker.record_failure("provider:p", &policy).await;
+        assert!(breaker.allow_request("provider:p").await);
+
+        breaker.force("provider:p", Some(ForcedState::Open));
+        assert!(!breaker.allow_request("provider:p").await);
+        assert_eq!(breaker.snapshot()[0].forced.as_deref(), Some("open"));
+
+        breaker.force("provider:p", None);
+        assert!(breaker.allow_request("provider:p").await);
+    }
+
+    #[tokio::test]
+    async fn test_error_rate_trips_after_min_requests() {
+        let breaker = CircuitBreaker::new();
+        let policy = CircuitPolicy {
+            consecutive_failures: 0,
+            error_rate_percent: Some(50.0),
+            min_requests: 4,
+            ..CircuitPolicy::default()
+        };
+        breaker.record_success("model:b", &policy).await;
+        breaker.record_failure("model:b", &policy).await;
+        breaker.record_success("model:b", &policy).await;
+        assert!(breaker.allow_request("model:b").await);
+        breaker.record_failure("model:b", &policy).await;
+        assert!(!breaker.allow_request("model:b").await);
+    }
 }
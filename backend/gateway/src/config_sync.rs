@@ -0,0 +1,130 @@
+//! Config Propagation
+//!
+//! Services, models, providers, their key pools and guardrails are cached per replica. Admin writes
+//! drop the affected entries right away and announce the change on a Postgres
+//! `LISTEN/NOTIFY` channel so every other gateway replica drops them too. If the listener
+//! loses its connection, notifications may have been missed, so the replica flushes
+//! everything once it reconnects.
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::executor::Executor;
+
+/// Postgres channel the replicas share
+pub const CHANNEL: &str = "mawi_config";
+
+/// A config change whose cached copies must be dropped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Invalidation {
+    /// A service's settings or its model list
+    Service(String),
+    Model(String),
+    /// A provider's settings or its pooled keys
+    Provider(String),
+    /// A model or provider circuit policy override, by breaker key
+    CircuitPolicy(String),
+    /// Drain or maintenance state, which can touch any service's model list
+    Maintenance,
+    Guardrail(String),
+    All,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Notification {
+    /// Replica that made the change (already applied there)
+    origin: String,
+    change: Invalidation,
+}
+
+static REPLICA_ID: OnceLock<String> = OnceLock::new();
+
+/// Identifies this process on the channel
+pub fn replica_id() -> &'static str {
+    REPLICA_ID.get_or_init(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Tell the other replicas about a change
+pub async fn publish(pool: &PgPool, change: &Invalidation) {
+    let payload = Notification { origin: replica_id().to_string(), change: change.clone() };
+    let payload = match serde_json::to_string(&payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!(error = %e, "failed to encode config invalidation");
+            return;
+        }
+    };
+    if let Err(e) = sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(&payload)
+        .execute(pool)
+        .await
+    {
+        warn!(error = %e, ?change, "failed to broadcast config invalidation");
+    }
+}
+
+/// Apply changes made by other replicas to `executor`'s caches until the process exits
+pub fn start(executor: Arc<Executor>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen(&executor).await {
+                warn!(error = %e, "config sync listener failed, retrying");
+            }
+            // Whatever was announced while we were away is lost
+            executor.apply_invalidation(&Invalidation::All).await;
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
+
+async fn listen(executor: &Executor) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(&executor.pool).await?;
+    listener.listen(CHANNEL).await?;
+    info!(channel = CHANNEL, replica = replica_id(), "listening for config changes");
+
+    loop {
+        // `None` means the connection dropped; the listener reconnects on the next call
+        let Some(notification) = listener.try_recv().await? else {
+            warn!("config sync connection lost, flushing caches");
+            executor.apply_invalidation(&Invalidation::All).await;
+            continue;
+        };
+        match decode(notification.payload()) {
+            Some(change) => {
+                debug!(?change, "applying config change from another replica");
+                executor.apply_invalidation(&change).await;
+            }
+            None => debug!("ignoring own or malformed config notification"),
+        }
+    }
+}
+
+/// The change announced by another replica, if any
+fn decode(payload: &str) -> Option<Invalidation> {
+    let notification: Notification = serde_json::from_str(payload).ok()?;
+    (notification.origin != replica_id()).then_some(notification.change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_changes_from_other_replicas_only() {
+        let foreign = serde_json::json!({
+            "origin": "another-replica",
+            "change": { "kind": "service", "id": "support-bot" },
+        });
+        assert_eq!(decode(&foreign.to_string()), Some(Invalidation::Service("support-bot".into())));
+
+        let own = Notification { origin: replica_id().to_string(), change: Invalidation::All };
+        assert_eq!(decode(&serde_json::to_string(&own).unwrap()), None);
+        assert_eq!(decode("not json"), None);
+    }
+}
@@ -67,7 +67,16 @@ impl ContextManager {
         pruned
     }
 
-    fn estimate_tokens(messages: &[ChatMessage]) -> usize {
+    /// Prune to a model's context window. An unset (or zero) window is unknown, so the
+    /// conversation is passed through whole and the provider decides.
+    pub fn prune_for_window(messages: Vec<ChatMessage>, context_window: Option<i32>) -> Vec<ChatMessage> {
+        match context_window.filter(|w| *w > 0) {
+            Some(window) => Self::prune_messages(messages, window as usize),
+            None => messages,
+        }
+    }
+
+    pub fn estimate_tokens(messages: &[ChatMessage]) -> usize {
         messages.iter().map(Self::estimate_single_token).sum()
     }
 
@@ -77,3 +86,31 @@ impl ContextManager {
         content_tokens + 4  // role, JSON structure, etc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(turns: usize) -> Vec<ChatMessage> {
+        let mut messages = vec![ChatMessage { role: "system".to_string(), content: "be brief".to_string() }];
+        messages.extend((0..turns).map(|i| ChatMessage {
+            role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+            content: "x".repeat(400),
+        }));
+        messages
+    }
+
+    #[test]
+    fn test_prune_for_window_keeps_system_and_recent_messages() {
+        let pruned = ContextManager::prune_for_window(history(100), Some(1000));
+        assert!(pruned.len() < 101);
+        assert_eq!(pruned[0].role, "system");
+        assert!(ContextManager::estimate_tokens(&pruned) <= 800);
+    }
+
+    #[test]
+    fn test_prune_for_window_passes_unknown_windows_through() {
+        assert_eq!(ContextManager::prune_for_window(history(100), None).len(), 101);
+        assert_eq!(ContextManager::prune_for_window(history(100), Some(0)).len(), 101);
+    }
+}
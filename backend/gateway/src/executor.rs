@@ -8,11 +8,316 @@ use moka::future::Cache;
 use std::time::Duration;
 use tracing::{debug, info, warn, error};
 
+use crate::guardrails::{CompiledGuardrail, GuardrailAction, Stage};
+
 use mawi_core::types::{ChatCompletionRequest, ImageGenerationRequest, ImageGenerationResponse};
+use mawi_core::services::DispatchKind;
 use mawi_core::unified::{UnifiedChatRequest, UnifiedChatResponse, ChatChoice, ChatMessage, TokenUsage, RoutingMetadata, RequestedRouting, ActualRouting, AgenticStreamEvent};
 use anyhow::{Result, Context};
 use futures::Stream;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+
+/// Maximum number of service hops when following fallback chains
+const MAX_FALLBACK_DEPTH: usize = 3;
+
+/// Hedge delay used when neither the service nor recent logs provide one
+const DEFAULT_HEDGE_DELAY_MS: u64 = 2000;
+
+/// Mask provider API keys that upstream errors sometimes echo back
+fn sanitize_error(error: &str) -> String {
+    // lazy_static regex for performance
+    // Matches OpenAI (sk-...) and Google (AIza...) style keys
+    static API_KEY_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let re = API_KEY_REGEX.get_or_init(|| {
+        regex::Regex::new(r"(sk-[a-zA-Z0-9\-_]{20,}|AIza[a-zA-Z0-9\-_]{20,})").expect("Invalid regex")
+    });
+
+    re.replace_all(error, |caps: &regex::Captures| {
+        let key = &caps[0];
+        if key.len() > 7 {
+            format!("{}...", &key[0..7])
+        } else {
+            "***".to_string()
+        }
+    }).to_string()
+}
+
+/// A request the gateway refuses as sent. Handlers map it to its 4xx status instead of a 500.
+#[derive(Debug)]
+pub struct RequestRejection {
+    pub status: poem::http::StatusCode,
+    pub message: String,
+}
+
+impl RequestRejection {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self { status: poem::http::StatusCode::BAD_REQUEST, message: message.into() }
+    }
+
+    /// HTTP status for an execution error: the rejection's own status, 504 for upstream
+    /// timeouts, 429 when every provider key was out of budget, 503 when no concurrency
+    /// slot freed up in time, otherwise 500
+    pub fn status_for(error: &anyhow::Error) -> poem::http::StatusCode {
+        if error.is::<UpstreamTimeout>() {
+            return poem::http::StatusCode::GATEWAY_TIMEOUT;
+        }
+        if error.is::<crate::admission::QueueTimeout>() {
+            return poem::http::StatusCode::SERVICE_UNAVAILABLE;
+        }
+        if error.is::<crate::upstream_limits::UpstreamThrottled>() {
+            return poem::http::StatusCode::TOO_MANY_REQUESTS;
+        }
+        error
+            .downcast_ref::<Self>()
+            .map(|r| r.status)
+            .unwrap_or(poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// Candidates left in rotation at `now`: those no maintenance window in effect covers,
+/// directly or through their provider
+fn without_drained(
+    models: Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>,
+    windows: &[crate::maintenance::MaintenanceWindow],
+    now: i64,
+) -> Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)> {
+    models
+        .into_iter()
+        .filter(|(model_id, provider_id, _, _)| {
+            !windows.iter().any(|w| w.in_effect(now) && w.covers(model_id, provider_id))
+        })
+        .collect()
+}
+
+/// Whether a failed service is worth following to its fallbacks: upstream failures,
+/// timeouts and throttling are; a 4xx rejection of the request itself isn't
+fn warrants_fallback(error: &anyhow::Error) -> bool {
+    let status = RequestRejection::status_for(error);
+    status.is_server_error() || status == poem::http::StatusCode::TOO_MANY_REQUESTS
+}
+
+impl std::fmt::Display for RequestRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RequestRejection {}
+
+/// Which part of an upstream call ran out of time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeoutPhase {
+    FirstToken,
+    Idle,
+    Total,
+    /// The service's overall request deadline (retries and failover included)
+    Deadline,
+}
+
+impl TimeoutPhase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TimeoutPhase::FirstToken => "first_token",
+            TimeoutPhase::Idle => "idle",
+            TimeoutPhase::Total => "total",
+            TimeoutPhase::Deadline => "deadline",
+        }
+    }
+}
+
+/// An upstream call exceeded one of its timeouts. Retriable: the attempt fails over to the
+/// next candidate and counts against the model's circuit like any other upstream failure.
+#[derive(Debug)]
+pub struct UpstreamTimeout {
+    pub phase: TimeoutPhase,
+    pub limit: Duration,
+}
+
+impl UpstreamTimeout {
+    /// Run `fut`, failing with a timeout once `limit` has passed
+    pub async fn guard<T>(phase: TimeoutPhase, limit: Duration, fut: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+        Self::guard_until(phase, limit, tokio::time::Instant::now() + limit, fut).await
+    }
+
+    /// Like `guard`, for a `limit` that started counting before this call
+    async fn guard_until<T>(
+        phase: TimeoutPhase,
+        limit: Duration,
+        deadline: tokio::time::Instant,
+        fut: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        match tokio::time::timeout_at(deadline, fut).await {
+            Ok(result) => result,
+            Err(_) => {
+                crate::metrics::UPSTREAM_TIMEOUTS.with_label_values(&[phase.as_str()]).inc();
+                Err(UpstreamTimeout { phase, limit }.into())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for UpstreamTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.phase {
+            TimeoutPhase::FirstToken => "no first token",
+            TimeoutPhase::Idle => "stream idle",
+            TimeoutPhase::Total => "no complete response",
+            TimeoutPhase::Deadline => "request deadline reached",
+        };
+        write!(f, "Upstream timed out: {} after {}ms", what, self.limit.as_millis())
+    }
+}
+
+impl std::error::Error for UpstreamTimeout {}
+
+/// Retry class of an attempt's error (see `mawi_core::services::RETRY_CLASSES`), or `None`
+/// for errors retrying can't fix, such as bad requests and auth failures
+pub fn retry_class(error: &anyhow::Error) -> Option<&'static str> {
+    if let Some(timeout) = error.downcast_ref::<UpstreamTimeout>() {
+        // Out of request budget: nothing left to retry with
+        return (timeout.phase != TimeoutPhase::Deadline).then_some("timeout");
+    }
+    if let Some(upstream) = error.downcast_ref::<mawi_core::providers::UpstreamHttpError>() {
+        return match upstream.status {
+            429 => Some("rate_limited"),
+            503 | 529 => Some("overloaded"),
+            500..=599 => Some("server_error"),
+            _ => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        if e.is_timeout() {
+            return Some("timeout");
+        }
+        if e.is_connect() || e.is_request() || e.is_body() {
+            return Some("connection");
+        }
+    }
+    None
+}
+
+/// Wakes the attempt loop when an attempt streams its first token
+#[derive(Clone, Copy)]
+struct FirstTokenSignal<'a> {
+    notify: &'a tokio::sync::Notify,
+    /// Set for the attempt that streamed; the loop clears it if that attempt then fails
+    seen: &'a std::sync::atomic::AtomicBool,
+}
+
+impl FirstTokenSignal<'_> {
+    fn fire(&self) {
+        self.seen.store(true, std::sync::atomic::Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+}
+
+/// Hooks and details shared between a model attempt and the code logging it
+#[derive(Default)]
+struct AttemptTrace<'a> {
+    /// Fired as soon as the provider streams non-empty content
+    first_token: Option<FirstTokenSignal<'a>>,
+    /// Masked credential the attempt called the provider with
+    credential: std::sync::OnceLock<String>,
+    /// Shadow replay: takes no concurrency slot and is dropped rather than queued when the
+    /// provider key is near its rate limit, so it never holds up primary traffic
+    shadow: bool,
+}
+
+impl<'a> AttemptTrace<'a> {
+    fn new(first_token: Option<FirstTokenSignal<'a>>) -> Self {
+        Self { first_token, ..Default::default() }
+    }
+
+    fn shadow() -> Self {
+        Self { shadow: true, ..Default::default() }
+    }
+
+    fn credential(&self) -> Option<String> {
+        self.credential.get().cloned()
+    }
+}
+
+/// A finished attempt: candidate index, start time, result and credential used
+type FinishedAttempt = (usize, std::time::Instant, Result<(UnifiedChatResponse, Option<i64>)>, Option<String>);
+
+/// Outcome of waiting on in-flight attempts in `execute_chat`
+enum AttemptEvent<T> {
+    Finished(Box<T>),
+    HedgeDue,
+    FirstToken,
+}
+
+/// Attempts racing for one request: the first candidate, its retries and any hedges
+struct HedgeRace<'a, T> {
+    in_flight: FuturesUnordered<futures::future::BoxFuture<'a, T>>,
+    /// Candidate index and start of each running attempt
+    active: Vec<(usize, std::time::Instant)>,
+    last_launch: std::time::Instant,
+}
+
+impl<'a, T> HedgeRace<'a, T> {
+    fn new() -> Self {
+        Self { in_flight: FuturesUnordered::new(), active: Vec::new(), last_launch: std::time::Instant::now() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Start an attempt on a new candidate; the hedge delay counts from here
+    fn launch(&mut self, idx: usize, attempt: futures::future::BoxFuture<'a, T>) {
+        self.retry(idx, attempt);
+        self.last_launch = std::time::Instant::now();
+    }
+
+    /// Start another attempt on a candidate that just failed
+    fn retry(&mut self, idx: usize, attempt: futures::future::BoxFuture<'a, T>) {
+        self.in_flight.push(attempt);
+        self.active.push((idx, std::time::Instant::now()));
+    }
+
+    fn finished(&mut self, idx: usize) {
+        self.active.retain(|(i, _)| *i != idx);
+    }
+
+    /// Whether a running attempt has streamed a token. One that failed doesn't count,
+    /// so it doesn't stop hedging.
+    fn first_token_seen(&self, first_tokens: &[std::sync::atomic::AtomicBool]) -> bool {
+        self.active.iter().any(|(i, _)| first_tokens[*i].load(std::sync::atomic::Ordering::Relaxed))
+    }
+
+    /// A hedge may launch: hedging is on, nothing has streamed yet, there's room for
+    /// another attempt and a candidate left to try
+    fn can_hedge(&self, max_in_flight: usize, first_token_seen: bool, candidates_left: bool) -> bool {
+        max_in_flight > 1 && !first_token_seen && self.in_flight.len() < max_in_flight && candidates_left
+    }
+
+    /// Wait for an attempt to finish, the hedge delay to pass (when `can_hedge`) or a first
+    /// token. `None` when nothing is left to wait on.
+    async fn next(
+        &mut self,
+        hedge_delay: Duration,
+        can_hedge: bool,
+        first_token: &tokio::sync::Notify,
+        first_token_seen: bool,
+    ) -> Option<AttemptEvent<T>> {
+        tokio::select! {
+            Some(finished) = self.in_flight.next() => Some(AttemptEvent::Finished(Box::new(finished))),
+            _ = tokio::time::sleep_until((self.last_launch + hedge_delay).into()), if can_hedge => Some(AttemptEvent::HedgeDue),
+            _ = first_token.notified(), if !first_token_seen => Some(AttemptEvent::FirstToken),
+            else => None,
+        }
+    }
 
+    /// Cancel the attempts still running, returning their candidate index and start
+    fn cancel_rest(&mut self) -> Vec<(usize, std::time::Instant)> {
+        self.in_flight = FuturesUnordered::new();
+        std::mem::take(&mut self.active)
+    }
+}
+
+#[derive(Clone)]
 pub struct Executor {
     pub pool: PgPool,
     pub http_client: reqwest::Client,
@@ -26,6 +331,25 @@ pub struct Executor {
     providers: HashMap<String, Arc<dyn ProviderAdapter>>,
     pub mcp_manager: Arc<RwLock<McpManager>>,
     pub circuit_breaker: Arc<crate::circuit_breaker::CircuitBreaker>,
+    // Per-model/provider circuit policy overrides, keyed by breaker key
+    circuit_policy_cache: Cache<String, Option<mawi_core::services::CircuitPolicy>>,
+    // Per-minute model/service outcome rollups for SLOs
+    rollups: Arc<crate::slo::RollupRecorder>,
+    // HTTP clients for non-default connect timeouts, keyed by timeout in ms
+    timeout_clients: Arc<dashmap::DashMap<u64, reqwest::Client>>,
+    // Client-side throttling to stay under provider rate limits
+    pub upstream_throttle: Arc<crate::upstream_limits::UpstreamThrottle>,
+    // Pooled provider API keys
+    pub key_pool: Arc<crate::key_pool::KeyPool>,
+    // Concurrency caps and priority queueing per model and provider
+    pub scheduler: Arc<crate::admission::Scheduler>,
+    // Opt-in per-service cache of chat responses
+    pub response_cache: Arc<crate::response_cache::ResponseCache>,
+    pub semantic_cache: Arc<crate::semantic_cache::SemanticCache>,
+    // Guardrails attached to services, run on requests and responses
+    pub guardrails: Arc<crate::guardrails::GuardrailEngine>,
+    // Recent p90 TTFT per model, the default hedge delay
+    hedge_delays: Cache<String, Duration>,
 }
 
 // async quota charging (prevents task explosion)
@@ -100,6 +424,58 @@ pub struct LogParams {
     pub failover_count: i32,
     pub cost_usd: Option<f64>,
     pub user_id: Option<String>,
+    pub hedged: bool,
+    pub ttft_ms: Option<i64>,
+    pub response_id: String,
+    pub experiment: Option<String>,
+    pub experiment_arm: Option<String>,
+    pub modality: String,
+    pub retry_count: i32,
+    pub credential: Option<String>,
+    pub cache_hit: bool,
+    pub cache_similarity: Option<f64>,
+}
+
+/// Number of `request_logs` columns written per entry
+const LOG_COLUMNS: usize = 25;
+
+/// Which attempt a request log row records: where it ran, how it ended and when it started
+pub struct AttemptLog<'a> {
+    pub key_id: Option<&'a str>,
+    pub service: &'a str,
+    pub model_id: &'a str,
+    pub provider_id: &'a str,
+    pub failover_count: i32,
+    /// "success", "error", "retry" or "cancelled"
+    pub status: &'a str,
+    pub error: Option<&'a str>,
+    /// Latency is measured from here
+    pub start_time: std::time::Instant,
+    pub user_id: Option<&'a str>,
+}
+
+/// Optional per-attempt details recorded alongside the core log fields
+#[derive(Debug, Clone, Default)]
+pub struct RequestLogExtras {
+    /// Attempt raced against a hedge (set on both winner and losers)
+    pub hedged: bool,
+    /// Time to first streamed token
+    pub ttft_ms: Option<i64>,
+    /// Experiment and arm the request was assigned to
+    pub experiment: Option<String>,
+    pub experiment_arm: Option<String>,
+    /// Request modality when not chat ("image", "audio", "video")
+    pub modality: Option<String>,
+    /// Explicit cost for requests not priced by tokens
+    pub cost_usd: Option<f64>,
+    /// Retries of the same model before this attempt
+    pub retry_count: i32,
+    /// Masked provider API key the attempt used
+    pub credential: Option<String>,
+    /// Served from the service's response cache
+    pub cache_hit: bool,
+    /// Similarity to the cached prompt, for semantic cache hits
+    pub cache_similarity: Option<f64>,
 }
 
 impl RequestLogger {
@@ -146,17 +522,17 @@ impl RequestLogger {
         // Build multi-row insert query
         let placeholders: Vec<String> = (0..batch.len())
             .map(|i| {
-                let base = i * 15 + 1;
-                format!("(${},${},${},${},${},${},${},${},${},${},${},${},${},${},${})",
-                    base, base+1, base+2, base+3, base+4, base+5, base+6, base+7,
-                    base+8, base+9, base+10, base+11, base+12, base+13, base+14)
+                let base = i * LOG_COLUMNS + 1;
+                let row: Vec<String> = (base..base + LOG_COLUMNS).map(|n| format!("${}", n)).collect();
+                format!("({})", row.join(","))
             })
             .collect();
         
         let query = format!(
             "INSERT INTO request_logs (id, virtual_key_id, service_name, model_id, provider_type, \
              tokens_prompt, tokens_completion, tokens_total, latency_ms, latency_us, status, \
-             error_message, failover_count, cost_usd, user_id) VALUES {}",
+             error_message, failover_count, cost_usd, user_id, hedged, ttft_ms, response_id, \
+             experiment, experiment_arm, modality, retry_count, credential, cache_hit, cache_similarity) VALUES {}",
             placeholders.join(",")
         );
         
@@ -178,7 +554,17 @@ impl RequestLogger {
                 .bind(&entry.params.error)
                 .bind(&entry.params.failover_count)
                 .bind(&entry.params.cost_usd)
-                .bind(&entry.params.user_id);
+                .bind(&entry.params.user_id)
+                .bind(entry.params.hedged)
+                .bind(entry.params.ttft_ms)
+                .bind(&entry.params.response_id)
+                .bind(&entry.params.experiment)
+                .bind(&entry.params.experiment_arm)
+                .bind(&entry.params.modality)
+                .bind(entry.params.retry_count)
+                .bind(&entry.params.credential)
+                .bind(entry.params.cache_hit)
+                .bind(entry.params.cache_similarity);
         }
         
         let _ = q.execute(&pool).await;
@@ -201,17 +587,19 @@ impl Executor {
     pub fn new(pool: PgPool, mcp_manager: Arc<RwLock<McpManager>>) -> Self {
         let providers: HashMap<String, Arc<dyn ProviderAdapter>> = HashMap::new();
         
-        // Configure high-performance connection pooling
-        let http_client = reqwest::Client::builder()
-            .pool_idle_timeout(std::time::Duration::from_secs(90))
-            .pool_max_idle_per_host(32)
-            .timeout(std::time::Duration::from_secs(120))
-            .tcp_keepalive(std::time::Duration::from_secs(60))
-            .build()
+        // Total, first-token and idle timeouts are enforced per request (see `TimeoutPolicy`)
+        let http_client = Self::build_http_client(mawi_core::services::TimeoutPolicy::default().connect())
             .expect("Failed to create HTTP client - check TLS/network configuration");
 
         let pool_for_logger = pool.clone();
         let pool_for_quota = pool.clone();
+        let rollups = crate::slo::RollupRecorder::global(&pool);
+        let circuit_breaker = Arc::new(crate::circuit_breaker::CircuitBreaker::new());
+        let key_pool = Arc::new(crate::key_pool::KeyPool::new(pool.clone(), circuit_breaker.clone()));
+        let scheduler = crate::admission::Scheduler::global(&pool);
+        let response_cache = crate::response_cache::ResponseCache::global(&pool);
+        let semantic_cache = crate::semantic_cache::SemanticCache::global(&pool);
+        let guardrails = crate::guardrails::GuardrailEngine::global(&pool);
 
         Self { 
             pool, 
@@ -236,150 +624,368 @@ impl Executor {
             quota_worker: Arc::new(QuotaWorker::new(pool_for_quota, 10)),
             logger: Arc::new(RequestLogger::new(pool_for_logger)),
             mcp_manager,
-            circuit_breaker: Arc::new(crate::circuit_breaker::CircuitBreaker::new()),
-        }
-    }
-    
-    pub fn with_client(pool: PgPool, http_client: reqwest::Client, mcp_manager: Arc<RwLock<McpManager>>) -> Self {
-         let pool_for_logger = pool.clone();
-         let pool_for_quota = pool.clone();
-         Self { 
-            pool, 
-            http_client, 
-            providers: HashMap::new(),
-            model_cache: Cache::builder()
+            circuit_breaker,
+            circuit_policy_cache: Cache::builder()
                 .max_capacity(10_000)
                 .time_to_live(Duration::from_secs(60))
                 .build(),
-            provider_cache: Cache::builder()
-                .max_capacity(1_000)
-                .time_to_live(Duration::from_secs(60))
-                .build(),
-            service_cache: Cache::builder()
-                .max_capacity(1_000)
-                .time_to_live(Duration::from_secs(60))
-                .build(),
-            service_models_cache: Cache::builder()
-                .max_capacity(5_000)
+            rollups,
+            timeout_clients: Arc::new(dashmap::DashMap::new()),
+            upstream_throttle: Arc::new(crate::upstream_limits::UpstreamThrottle::new(
+                crate::upstream_limits::UpstreamThrottleConfig::from_env(),
+            )),
+            key_pool,
+            scheduler,
+            response_cache,
+            semantic_cache,
+            guardrails,
+            hedge_delays: Cache::builder()
+                .max_capacity(10_000)
                 .time_to_live(Duration::from_secs(60))
                 .build(),
-            quota_worker: Arc::new(QuotaWorker::new(pool_for_quota, 10)),
-            logger: Arc::new(RequestLogger::new(pool_for_logger)),
-            mcp_manager,
-            circuit_breaker: Arc::new(crate::circuit_breaker::CircuitBreaker::new()),
         }
     }
+    
+    /// Configure high-performance connection pooling
+    fn build_http_client(connect_timeout: Duration) -> reqwest::Result<reqwest::Client> {
+        reqwest::Client::builder()
+            .pool_idle_timeout(std::time::Duration::from_secs(90))
+            .pool_max_idle_per_host(32)
+            .connect_timeout(connect_timeout)
+            .tcp_keepalive(std::time::Duration::from_secs(60))
+            .build()
+    }
 
-    /// Execute image generation request
-    pub async fn execute_image_generation(&self, request: &ImageGenerationRequest, user_id: &str) -> Result<ImageGenerationResponse> {
-        let estimated_cost = crate::pricing::PRICING.get_image_cost(&request.model, request.n.max(1) as i64);
-        let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
-        quota_manager.check_quota(user_id, estimated_cost).await?;
-
-        let model = self.get_model(&request.model).await?;
-        let provider = self.get_provider(&model.provider).await?;
-        let adapter = self.create_adapter(&provider, &model)?;
-        let response = adapter.generate_image(request).await?;
+    /// HTTP client honouring the policy's connect timeout
+    fn client_for(&self, timeouts: &mawi_core::services::TimeoutPolicy) -> reqwest::Client {
+        let connect_ms = match timeouts.connect_ms {
+            Some(ms) if ms != mawi_core::services::DEFAULT_CONNECT_TIMEOUT_MS => ms,
+            _ => return self.http_client.clone(),
+        };
+        if let Some(client) = self.timeout_clients.get(&connect_ms) {
+            return client.clone();
+        }
+        match Self::build_http_client(Duration::from_millis(connect_ms)) {
+            Ok(client) => self.timeout_clients.entry(connect_ms).or_insert(client).clone(),
+            Err(e) => {
+                warn!(connect_ms, error = %e, "failed to build HTTP client, using default connect timeout");
+                self.http_client.clone()
+            }
+        }
+    }
 
-        // Bill for actual images generated
-        let cost = crate::pricing::PRICING.get_image_cost(&request.model, response.data.len() as i64);
+    /// Effective timeouts for a model: its own settings, then the service's, then the defaults
+    async fn timeouts_for(&self, service_name: &str, model: &mawi_core::models::Model) -> mawi_core::services::TimeoutPolicy {
+        // Routed requests load (and cache) their service before dispatch; direct model calls have none
+        let service_timeouts = self.service_cache.get(service_name).await.and_then(|s| s.timeouts);
+        model.timeouts().or(&service_timeouts.unwrap_or_default())
+    }
 
-        if let Err(e) = quota_manager.charge_user(user_id, cost).await {
-            warn!(error = %e, user_id, "failed to charge user for image gen");
-        } else {
-             debug!(cost, user_id, "charged for image generation");
-        }
+    /// Timeouts for follow-up calls on an async job, which run outside the request that
+    /// started it: the service isn't necessarily cached anymore, so it is loaded
+    async fn job_timeouts(&self, service: Option<&str>, model: &mawi_core::models::Model) -> mawi_core::services::TimeoutPolicy {
+        let service_timeouts = match service {
+            Some(name) => self.get_service(name).await.ok().and_then(|s| s.timeouts),
+            None => None,
+        };
+        model.timeouts().or(&service_timeouts.unwrap_or_default())
+    }
 
-        Ok(response)
+    /// Execute image generation request
+    pub async fn execute_image_generation(&self, request: &ImageGenerationRequest, user_id: &str) -> Result<ImageGenerationResponse> {
+        self.execute_media(
+            &request.model,
+            DispatchKind::ImageGeneration,
+            user_id,
+            |model| crate::pricing::PRICING.get_image_cost(&model.name, request.n.max(1) as i64),
+            |adapter, model| async move {
+                let request = ImageGenerationRequest { model: model.name.clone(), ..request.clone() };
+                let response = adapter.generate_image(&request).await?;
+                // Bill for actual images generated
+                let cost = crate::pricing::PRICING.get_image_cost(&model.name, response.data.len() as i64);
+                Ok((response, cost))
+            },
+        ).await
     }
 
     /// Execute text-to-speech request
     pub async fn execute_text_to_speech(&self, request: &mawi_core::types::TextToSpeechRequest, user_id: &str) -> Result<(String, Vec<u8>)> {
-        let estimated_cost = crate::pricing::PRICING.get_tts_cost(&request.model, request.input.len());
-        let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
-        quota_manager.check_quota(user_id, 0.01_f64.max(estimated_cost)).await?;
-
-        let model = self.get_model(&request.model).await?;
-        let provider = self.get_provider(&model.provider).await?;
-        let adapter = self.create_adapter(&provider, &model)?;
-        let result = adapter.text_to_speech(request).await?;
-        
-        if let Err(e) = quota_manager.charge_user(user_id, estimated_cost).await {
-             warn!(error = %e, user_id, "failed to charge user for TTS");
-        } else {
-             debug!(cost = estimated_cost, user_id, "charged for TTS");
-        }
-        
-        Ok(result)
+        self.execute_media(
+            &request.model,
+            DispatchKind::TextToSpeech,
+            user_id,
+            |model| 0.01_f64.max(crate::pricing::PRICING.get_tts_cost(&model.name, request.input.len())),
+            |adapter, model| async move {
+                let request = mawi_core::types::TextToSpeechRequest { model: model.name.clone(), ..request.clone() };
+                let result = adapter.text_to_speech(&request).await?;
+                Ok((result, crate::pricing::PRICING.get_tts_cost(&model.name, request.input.len())))
+            },
+        ).await
     }
 
     /// Execute speech-to-text (transcription) request
     pub async fn execute_transcription(&self, audio_data: &[u8], request: &mawi_core::types::AudioTranscriptionRequest, user_id: &str) -> Result<String> {
-        let estimated_cost = crate::pricing::PRICING.get_transcription_cost(&request.model);
-        
-        let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
-        quota_manager.check_quota(user_id, estimated_cost).await?;
-
-        // Resolve model to provider
-        let model = self.get_model(&request.model).await?;
-        let provider = self.get_provider(&model.provider).await?;
-        
-        let adapter = self.create_adapter(&provider, &model)?;
-        
-        let result = adapter.transcribe_audio(audio_data, request).await?;
-
-        if let Err(e) = quota_manager.charge_user(user_id, estimated_cost).await {
-             warn!(error = %e, user_id, "failed to charge user for transcription");
-        } else {
-             debug!(cost = estimated_cost, user_id, "charged for transcription");
-        }
-
-        Ok(result)
+        self.execute_media(
+            &request.model,
+            DispatchKind::Transcription,
+            user_id,
+            |model| crate::pricing::PRICING.get_transcription_cost(&model.name),
+            |adapter, model| async move {
+                let request = mawi_core::types::AudioTranscriptionRequest { model: model.name.clone(), ..request.clone() };
+                let text = adapter.transcribe_audio(audio_data, &request).await?;
+                Ok((text, crate::pricing::PRICING.get_transcription_cost(&model.name)))
+            },
+        ).await
     }
 
     /// Execute speech-to-speech request
     pub async fn execute_speech_to_speech(&self, audio_data: &[u8], request: &mawi_core::types::SpeechToSpeechRequest) -> Result<Vec<u8>> {
+        let target = request.model.clone();
+        let model_id = self.resolve_media_model(&target, DispatchKind::SpeechToSpeech).await?;
+        let request = &mawi_core::types::SpeechToSpeechRequest { model: model_id, ..request.clone() };
+
         // Resolve model to provider
         let model = self.get_model(&request.model).await?;
         let provider = self.get_provider(&model.provider).await?;
         
-        let adapter = self.create_adapter(&provider, &model)?;
+        // Resolving the target cached its service, if it named one
+        let timeouts = self.timeouts_for(&target, &model).await;
+        let credential = self.key_pool.select(&provider, &model).await?;
+        let adapter = self.create_adapter(&provider, &model, &credential, &timeouts)?;
         
-        adapter.speech_to_speech(audio_data, request).await
+        let result = UpstreamTimeout::guard(TimeoutPhase::Total, timeouts.total(), adapter.speech_to_speech(audio_data, request)).await;
+        self.key_pool.record_outcome(&provider, &credential, result.as_ref().map(|_| ())).await;
+        result
     }
 
     /// Execute video generation request
     pub async fn execute_video_generation(&self, request: &mawi_core::types::VideoGenerationRequest, user_id: &str) -> Result<mawi_core::types::VideoGenerationResponse> {
-        let estimated_cost = crate::pricing::PRICING.get_video_cost(&request.model);
+        self.execute_media(
+            &request.model,
+            DispatchKind::VideoGeneration,
+            user_id,
+            |model| crate::pricing::PRICING.get_video_cost(&model.name),
+            |adapter, model| async move {
+                let request = mawi_core::types::VideoGenerationRequest { model: model.name.clone(), ..request.clone() };
+                let mut response = adapter.generate_video(&request).await?;
+
+                // Tag async jobs with the model that accepted them so polling reaches the same provider,
+                // even when the request named a service, and with that service for its timeouts
+                if let Some(url) = &response.url {
+                    if url.starts_with("JOB_ID:") {
+                        let mut tagged = format!("{}|MODEL:{}", url, model.id);
+                        if request.model != model.id {
+                            tagged.push_str(&format!("|SERVICE:{}", request.model));
+                        }
+                        response.url = Some(tagged);
+                    }
+                }
+
+                Ok((response, crate::pricing::PRICING.get_video_cost(&model.name)))
+            },
+        ).await
+    }
+
+    /// Run a media request through the same pipeline as chat: resolve the target to a
+    /// modality sub-pool (or a single model), check quota, skip circuit-open models and fail
+    /// over in order, recording passive health, metrics and a `request_logs` row per attempt.
+    ///
+    /// `estimate` prices the request for the quota check; `call` runs one attempt against
+    /// a model and returns its response with the actual cost, which is logged and charged.
+    async fn execute_media<T, E, F, Fut>(
+        &self,
+        target: &str,
+        kind: DispatchKind,
+        user_id: &str,
+        estimate: E,
+        call: F,
+    ) -> Result<T>
+    where
+        E: Fn(&mawi_core::models::Model) -> f64,
+        F: Fn(Arc<dyn ProviderAdapter>, mawi_core::models::Model) -> Fut,
+        Fut: std::future::Future<Output = Result<(T, f64)>>,
+    {
+        let (service, candidates) = self.resolve_media_candidates(target, kind).await?;
+
+        // Quota is priced against the preferred candidate
+        let primary = self.get_model(&candidates[0].0).await?;
+        let estimated_cost = estimate(&primary);
         let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
-        quota_manager.check_quota(user_id, estimated_cost).await?;
+        if !quota_manager.check_quota(user_id, estimated_cost).await.unwrap_or(false) {
+            eprintln!("🛑 Blocked user {} from running {} (Insufficient quota)", user_id, primary.name);
+            return Err(anyhow::anyhow!("Insufficient quota. Estimated: ${:.6}", estimated_cost));
+        }
 
-        let model = self.get_model(&request.model).await?;
-        let provider = self.get_provider(&model.provider).await?;
-        let adapter = self.create_adapter(&provider, &model)?;
-        let response = adapter.generate_video(request).await?;
-        
-        if let Err(e) = quota_manager.charge_user(user_id, estimated_cost).await {
-             warn!(error = %e, user_id, "failed to charge user for video");
-        } else {
-             debug!(cost = estimated_cost, user_id, "charged for video generation");
+        self.media_failover(target, kind, user_id, service.as_ref(), &candidates, call).await
+    }
+
+    /// Try each candidate in turn until one succeeds, skipping those with open circuits
+    async fn media_failover<T, F, Fut>(
+        &self,
+        target: &str,
+        kind: DispatchKind,
+        user_id: &str,
+        service: Option<&mawi_core::services::Service>,
+        candidates: &[(String, String, i32, mawi_core::rtcros::RtcrosConfig)],
+        call: F,
+    ) -> Result<T>
+    where
+        F: Fn(Arc<dyn ProviderAdapter>, mawi_core::models::Model) -> Fut,
+        Fut: std::future::Future<Output = Result<(T, f64)>>,
+    {
+        let service_policy = service.and_then(|s| s.circuit_policy.as_ref());
+        let service_timeouts = service.and_then(|s| s.timeouts).unwrap_or_default();
+        let start_time = std::time::Instant::now();
+        let ticket = self.scheduler.ticket(Some(user_id), None, None).await;
+        let mut failover_count = 0;
+        let mut last_error: Option<anyhow::Error> = None;
+        let mut next_idx = 0;
+        while let Some(idx) = self.next_available_candidate(candidates, &mut next_idx, &mut failover_count, &mut last_error).await {
+            let (model_id, provider_id, _, _) = &candidates[idx];
+            let attempt_start = std::time::Instant::now();
+            let mut credential_used = None;
+            let result = async {
+                let model = self.get_model(model_id).await?;
+                let provider = self.get_provider(&model.provider).await?;
+                let _slots = self.scheduler.admit(&provider, &model, &ticket).await?;
+                let timeouts = model.timeouts().or(&service_timeouts);
+                let credential = self.media_credential(kind, &provider, &model).await?;
+                credential_used = Some(credential.masked());
+                self.upstream_throttle.acquire(&provider, &credential.api_key, 0).await?;
+                let adapter = self.create_adapter(&provider, &model, &credential, &timeouts)?;
+                let result = UpstreamTimeout::guard(TimeoutPhase::Total, timeouts.total(), call(adapter, model)).await;
+                self.key_pool.record_outcome(&provider, &credential, result.as_ref().map(|_| ())).await;
+                result
+            }.await;
+            let latency = attempt_start.elapsed().as_millis() as i64;
+
+            let log_response = UnifiedChatResponse {
+                id: uuid::Uuid::new_v4().to_string(),
+                object: kind.to_string(),
+                created: chrono::Utc::now().timestamp(),
+                model: model_id.to_string(),
+                choices: vec![],
+                usage: None,
+                routing_metadata: None,
+            };
+
+            match result {
+                Ok((response, cost)) => {
+                    self.update_model_health(model_id, true, latency, None).await;
+                    self.record_circuit_outcome(service_policy, model_id, provider_id, true).await;
+                    if let Some(service) = service {
+                        self.rollups.record(crate::slo::SCOPE_SERVICE, &service.name, true, start_time.elapsed().as_millis() as i64);
+                    }
+                    crate::metrics::MEDIA_REQUESTS.with_label_values(&[kind.modality(), "success"]).inc();
+
+                    if failover_count > 0 {
+                        info!(model = %model_id, failures = failover_count, modality = kind.modality(), "failover successful");
+                    }
+
+                    self.log_request_with(
+                        AttemptLog {
+                            key_id: None,
+                            service: target,
+                            model_id,
+                            provider_id,
+                            failover_count,
+                            status: "success",
+                            error: None,
+                            start_time,
+                            user_id: Some(user_id),
+                        },
+                        &log_response,
+                        RequestLogExtras {
+                            modality: Some(kind.modality().to_string()),
+                            cost_usd: Some(cost),
+                            credential: credential_used,
+                            ..RequestLogExtras::default()
+                        },
+                    ).await;
+
+                    return Ok(response);
+                }
+                Err(e) => {
+                    self.record_attempt_failure(service_policy, model_id, provider_id, latency, &e).await;
+
+                    crate::metrics::FAILOVER_COUNT.inc();
+                    eprintln!("❌ Model {} failed {}: {}", model_id, kind, e);
+                    failover_count += 1;
+
+                    self.log_request_with(
+                        AttemptLog {
+                            key_id: None,
+                            service: target,
+                            model_id,
+                            provider_id,
+                            failover_count,
+                            status: "error",
+                            error: Some(&e.to_string()),
+                            start_time,
+                            user_id: Some(user_id),
+                        },
+                        &log_response,
+                        RequestLogExtras {
+                            modality: Some(kind.modality().to_string()),
+                            credential: credential_used,
+                            ..RequestLogExtras::default()
+                        },
+                    ).await;
+
+                    last_error = Some(e);
+                }
+            }
         }
-        
-        Ok(response)
+
+        // All candidates failed
+        crate::metrics::HTTP_REQUESTS_ERRORS.inc();
+        crate::metrics::MEDIA_REQUESTS.with_label_values(&[kind.modality(), "error"]).inc();
+        if let Some(service) = service {
+            self.rollups.record(crate::slo::SCOPE_SERVICE, &service.name, false, start_time.elapsed().as_millis() as i64);
+        }
+        eprintln!("💥 All {} candidates failed for {} on '{}'", failover_count, kind, target);
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All models failed")))
     }
 
-    pub async fn poll_video_job(&self, job_id: &str, model_id: &str) -> Result<serde_json::Value> {
-        let model = self.get_model(model_id).await?;
-        let provider = self.get_provider(&model.provider).await?;
-        let adapter = self.create_adapter(&provider, &model)?;
-        adapter.poll_video_job(job_id).await
+    /// Poll an async video job. `service` is the service the job was routed through, if
+    /// any, for its timeouts.
+    pub async fn poll_video_job(&self, job_id: &str, model_id: &str, service: Option<&str>) -> Result<serde_json::Value> {
+        let (adapter, _, timeouts) = self.video_job_target(model_id, service).await?;
+        UpstreamTimeout::guard(TimeoutPhase::Total, timeouts.total(), adapter.poll_video_job(job_id)).await
+    }
+
+    pub async fn get_video_content(&self, generation_id: &str, model_id: &str, service: Option<&str>) -> Result<Vec<u8>> {
+        let (adapter, _, timeouts) = self.video_job_target(model_id, service).await?;
+        UpstreamTimeout::guard(TimeoutPhase::Total, timeouts.total(), adapter.get_video_content(generation_id)).await
     }
 
-    pub async fn get_video_content(&self, generation_id: &str, model_id: &str) -> Result<Vec<u8>> {
+    /// Adapter, credential and timeouts for a follow-up call on a video job. The job only
+    /// exists under the account that created it, so the key is pinned as it was for the create.
+    async fn video_job_target(
+        &self,
+        model_id: &str,
+        service: Option<&str>,
+    ) -> Result<(Arc<dyn ProviderAdapter>, crate::key_pool::Credential, mawi_core::services::TimeoutPolicy)> {
         let model = self.get_model(model_id).await?;
         let provider = self.get_provider(&model.provider).await?;
-        let adapter = self.create_adapter(&provider, &model)?;
-        adapter.get_video_content(generation_id).await
+        let timeouts = self.job_timeouts(service, &model).await;
+        let credential = self.media_credential(DispatchKind::VideoGeneration, &provider, &model).await?;
+        let adapter = self.create_adapter(&provider, &model, &credential, &timeouts)?;
+        Ok((adapter, credential, timeouts))
+    }
+
+    /// Key for a media call. Video jobs are polled and fetched after the create returns, so
+    /// they always use the model's pinned key; other calls draw from the pool.
+    async fn media_credential(
+        &self,
+        kind: DispatchKind,
+        provider: &mawi_core::models::Provider,
+        model: &mawi_core::models::Model,
+    ) -> Result<crate::key_pool::Credential> {
+        match kind {
+            DispatchKind::VideoGeneration => Ok(self.key_pool.select_pinned(provider, model).await),
+            _ => self.key_pool.select(provider, model).await,
+        }
     }
 
     /// Execute with streaming support (Agentic only for now)
@@ -388,9 +994,8 @@ impl Executor {
         request: UnifiedChatRequest,
         user_id: &str,
     ) -> std::pin::Pin<Box<dyn Stream<Item = Result<AgenticStreamEvent>> + Send>> {
+        let executor = self.clone();
         let pool = self.pool.clone();
-        let mcp_manager = self.mcp_manager.clone();
-        let http_client = self.http_client.clone();
         let user_id = user_id.to_string(); // Capture for async block
         
         Box::pin(async_stream::try_stream! {
@@ -403,18 +1008,40 @@ impl Executor {
 
              if let Some(st) = service_type {
                  if st == "AGENTIC" {
-                      let agentic = crate::agentic_executor::AgenticExecutor::new(pool.clone(), mcp_manager.clone());
-                      let stream = agentic.execute_stream(request, user_id);
+                      let guardrails = executor.service_guardrails(&request.service).await;
+                      let mut warnings = Vec::new();
+                      let request = if guardrails.is_empty() {
+                          request
+                      } else {
+                          executor.guard_input(&guardrails, &request, &user_id, &mut warnings).await?
+                      };
+                      for warning in warnings.drain(..) {
+                          yield AgenticStreamEvent::Log { step: "guardrail".to_string(), content: warning };
+                      }
+
+                      // Output guardrails need the whole answer, so hold it back until the end
+                      let hold_answer = guardrails.iter().any(|g| g.applies(Stage::Output));
+                      let mut answer = String::new();
+                      let agentic = crate::agentic_executor::AgenticExecutor::new(executor.clone());
+                      let stream = agentic.execute_stream(request.clone(), user_id.clone());
                       for await event in stream {
-                          yield event?;
+                          match event? {
+                              AgenticStreamEvent::FinalResponse(chunk) if hold_answer => answer.push_str(&chunk),
+                              event => yield event,
+                          }
+                      }
+                      if hold_answer {
+                          executor.guard_output_text(&guardrails, &request.service, &user_id, &mut [&mut answer], &mut warnings)?;
+                          for warning in warnings.drain(..) {
+                              yield AgenticStreamEvent::Log { step: "guardrail".to_string(), content: warning };
+                          }
+                          yield AgenticStreamEvent::FinalResponse(answer);
                       }
                       return;
                  }
              }
              
-             // Fallback: Create ephemeral Executor for standard execution
-             // Reuse existing client to prevent connection churn
-             let executor = Executor::with_client(pool, http_client, mcp_manager.clone());
+             // Fallback: standard execution on the same executor and caches
              let response = executor.execute_chat(&request, &user_id).await?;
              if let Some(choice) = response.choices.first() {
                  yield AgenticStreamEvent::FinalResponse(choice.message.content.clone());
@@ -422,7 +1049,8 @@ impl Executor {
         })
     }
 
-    /// Execute request with weighted distribution and automatic failover
+    /// Execute request with weighted distribution and automatic failover,
+    /// following the service's fallback chain when all of its models fail
     pub async fn execute_chat(
         &self,
         request: &UnifiedChatRequest,
@@ -436,24 +1064,437 @@ impl Executor {
         let _guard = scopeguard::guard((), |_| {
             crate::metrics::REQUESTS_IN_FLIGHT.dec();
         });
-        let heuristic_input_tokens = request.messages.iter().map(|m| m.content.len() as i64 / 4).sum::<i64>().max(50);
-        let heuristic_output_tokens = request.params.as_ref().and_then(|p| p.max_tokens).unwrap_or(500) as i64;
-        
-        let (service, models_with_weights) = match self.get_service(&request.service).await {
-            Ok(s) => {
-                // Check if this is an agentic service - route to agentic executor
-                if matches!(s.service_type, mawi_core::services::ServiceType::Agentic) {
-                    info!(service = %request.service, "routing to agentic executor");
-                    
-                    // STRICT QUOTA CHECK for Agentic
-                    // Agentic runs are expensive. We check general availability first.
-                    let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
-                    let has_quota = quota_manager.check_quota(user_id, 0.05).await?; // Require at least $0.05 for agent start
-                    if !has_quota {
-                         anyhow::bail!("Insufficient quota for Agentic execution (requires > $0.05)");
-                    }
+
+        if let Some(priority) = request.priority.as_deref() {
+            if crate::admission::Priority::parse(priority).is_none() {
+                return Err(RequestRejection::bad_request(format!(
+                    "Unknown priority '{}' (expected one of: {})",
+                    priority,
+                    crate::admission::PRIORITIES.join(", ")
+                )).into());
+            }
+        }
+
+        // The requested service's guardrails also cover its fallbacks
+        let guardrails = self.service_guardrails(&request.service).await;
+        let mut guardrail_warnings = Vec::new();
+        let guarded;
+        let request = if guardrails.is_empty() {
+            request
+        } else {
+            guarded = self.guard_input(&guardrails, request, user_id, &mut guardrail_warnings).await?;
+            &guarded
+        };
+
+        // Depth-first walk of the fallback chain; `path` records every service tried
+        let mut stack = vec![(request.service.clone(), 0usize)];
+        let mut path: Vec<String> = Vec::new();
+        let mut last_error = None;
+
+        while let Some((service_name, depth)) = stack.pop() {
+            if path.contains(&service_name) {
+                warn!(service = %service_name, path = ?path, "fallback cycle detected, skipping service");
+                continue;
+            }
+            if let Some(previous) = path.last() {
+                info!(from = %previous, to = %service_name, depth, "falling back to service");
+                crate::metrics::SERVICE_FALLBACKS.with_label_values(&[&request.service, &service_name]).inc();
+            }
+            path.push(service_name.clone());
+
+            let hop_request;
+            let service_request = if depth == 0 {
+                request
+            } else {
+                // Fallback services apply their own strategy and RTCROS prompts
+                hop_request = UnifiedChatRequest { service: service_name.clone(), model: None, ..request.clone() };
+                &hop_request
+            };
+
+            let hop_start = std::time::Instant::now();
+            let result = self.execute_service_chat_cached(service_request, user_id).await;
+            let hop_latency = hop_start.elapsed().as_millis() as i64;
+            // A direct model ID isn't a service: its attempts are already recorded under the model scope
+            let service = self.get_service(&service_name).await.ok();
+            if service.is_some() {
+                match &result {
+                    Ok(_) => self.rollups.record(crate::slo::SCOPE_SERVICE, &service_name, true, hop_latency),
+                    Err(e) if crate::slo::counts_against_slo(e) => {
+                        self.rollups.record(crate::slo::SCOPE_SERVICE, &service_name, false, hop_latency)
+                    }
+                    Err(_) => {}
+                }
+            }
+
+            match result {
+                Ok(mut response) => {
+                    if !guardrails.is_empty() {
+                        self.guard_output(&guardrails, &request.service, user_id, &mut response, &mut guardrail_warnings)?;
+                    }
+                    if let Some(metadata) = response.routing_metadata.as_mut() {
+                        // Only a move to a fallback service counts; failover within a service doesn't
+                        metadata.actual_routing.fallback_used = path.len() > 1;
+                        if path.len() > 1 {
+                            metadata.actual_routing.fallback_path = path;
+                        }
+                        metadata.actual_routing.guardrail_warnings = guardrail_warnings;
+                    }
+                    return Ok(response);
+                }
+                Err(e) => {
+                    // A forced model only makes sense within the requested service, and a
+                    // request refused as sent would be refused by the fallbacks too
+                    let fall_back = request.model.is_none() && warrants_fallback(&e);
+                    last_error = Some(e);
+                    if !fall_back {
+                        break;
+                    }
+                    if depth >= MAX_FALLBACK_DEPTH {
+                        warn!(service = %service_name, depth, "maximum fallback depth reached");
+                        continue;
+                    }
+                    let fallbacks = service.map(|service| service.fallback_services).unwrap_or_default();
+                    // Push in reverse so the first listed fallback is tried first
+                    for fallback in fallbacks.into_iter().rev() {
+                        stack.push((fallback, depth + 1));
+                    }
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All services in fallback chain failed")))
+    }
+
+    /// `execute_service_chat` behind the service's response cache, where it applies: exact
+    /// matches first, then semantically close prompts. Hits are logged at $0 and never
+    /// reach a provider.
+    async fn execute_service_chat_cached(
+        &self,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+    ) -> Result<UnifiedChatResponse> {
+        let start_time = std::time::Instant::now();
+        let Some((policy, scope)) = self.response_cache_scope(request, user_id).await else {
+            return self.execute_service_chat(request, user_id).await;
+        };
+        let key = scope.exact_key(request);
+
+        if !request.cache_bypass {
+            if let Some(entry) = self.response_cache.lookup(&request.service, &policy, &key).await {
+                debug!(service = %request.service, model = %entry.model_id, "serving response from cache");
+                return Ok(self.serve_cached(request, user_id, &entry, None, start_time).await);
+            }
+        }
+
+        // A bypassed lookup doesn't need the embedding; the store does, once there is a response to store
+        let embedding = match (&policy.semantic, request.cache_bypass) {
+            (Some(semantic), false) => self.semantic_embedding(request, semantic, user_id).await,
+            _ => None,
+        };
+        if let (Some(semantic), Some(embedding)) = (&policy.semantic, &embedding) {
+            if let Some((entry, similarity)) = self.semantic_cache.lookup(&scope, &policy, semantic, embedding).await {
+                debug!(service = %request.service, model = %entry.model_id, similarity, "serving response from semantic cache");
+                return Ok(self.serve_cached(request, user_id, &entry, Some(similarity), start_time).await);
+            }
+        }
+
+        let response = self.execute_service_chat(request, user_id).await?;
+        let provider_id = match response.routing_metadata.as_ref() {
+            Some(metadata) => self.get_model(&metadata.actual_routing.model).await.ok().map(|m| m.provider),
+            None => None,
+        };
+        let entry = provider_id.and_then(|provider_id| {
+            crate::response_cache::CachedResponse::capture(&response, &provider_id, policy.ttl_secs)
+        });
+        if let Some(entry) = entry {
+            if let Some(semantic) = &policy.semantic {
+                let embedding = match embedding {
+                    None if request.cache_bypass => self.semantic_embedding(request, semantic, user_id).await,
+                    embedding => embedding,
+                };
+                if let Some(embedding) = embedding {
+                    self.semantic_cache.store(&scope, &policy, semantic, embedding, entry.clone()).await;
+                }
+            }
+            self.response_cache.store(&request.service, &policy, key, entry).await;
+        }
+        Ok(response)
+    }
+
+    /// Prompt embedding for the semantic cache. A failure only costs the semantic lookup or store.
+    async fn semantic_embedding(
+        &self,
+        request: &UnifiedChatRequest,
+        semantic: &mawi_core::services::SemanticCacheConfig,
+        user_id: &str,
+    ) -> Option<Vec<f32>> {
+        match self.embed_prompt(&request.service, &semantic.embedding_model_id, request, user_id).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                warn!(service = %request.service, model = %semantic.embedding_model_id, error = %e, "could not embed prompt for semantic cache");
+                None
+            }
+        }
+    }
+
+    /// Response for a cache hit, logged at $0. `similarity` is set for semantic hits.
+    async fn serve_cached(
+        &self,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+        entry: &crate::response_cache::CachedResponse,
+        similarity: Option<f64>,
+        start_time: std::time::Instant,
+    ) -> UnifiedChatResponse {
+        let response = entry.to_response(request, similarity);
+        self.log_request_with(
+            AttemptLog {
+                key_id: None,
+                service: &request.service,
+                model_id: &entry.model_id,
+                provider_id: &entry.provider_id,
+                failover_count: 0,
+                status: "success",
+                error: None,
+                start_time,
+                user_id: Some(user_id),
+            },
+            &response,
+            RequestLogExtras { cost_usd: Some(0.0), cache_hit: true, cache_similarity: similarity, ..Default::default() },
+        ).await;
+        response
+    }
+
+    /// Response cache policy and scope for a request, when the service caches it
+    async fn response_cache_scope(
+        &self,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+    ) -> Option<(mawi_core::services::ResponseCachePolicy, crate::response_cache::CacheScope)> {
+        let service = self.get_service(&request.service).await.ok()?;
+        if matches!(service.service_type, mawi_core::services::ServiceType::Agentic) {
+            return None;
+        }
+        let policy = crate::response_cache::applies(service.response_cache.as_ref(), request)?.clone();
+        let system_prompts: Vec<String> = self
+            .get_service_models_with_weights(&service.name)
+            .await
+            .ok()?
+            .iter()
+            .filter_map(|(_, _, _, rtcros)| rtcros.build_system_prompt())
+            .collect();
+        let tenant = self.scheduler.ticket(Some(user_id), request.api_key_hash.as_deref(), None).await.tenant;
+        Some((policy, crate::response_cache::CacheScope::new(&tenant, &service.name, &system_prompts, request)))
+    }
+
+    /// Embed a request's conversation with the semantic cache's embedding model. The call is
+    /// logged and charged to the user like any other; one embedding serves both the lookup
+    /// and the store.
+    async fn embed_prompt(&self, service: &str, model_id: &str, request: &UnifiedChatRequest, user_id: &str) -> Result<Vec<f32>> {
+        let model = self.get_model(model_id).await?;
+        let provider = self.get_provider(&model.provider).await?;
+        let timeouts = self.timeouts_for(service, &model).await;
+        let credential = self.key_pool.select(&provider, &model).await?;
+        let adapter = self.create_adapter(&provider, &model, &credential, &timeouts)?;
+
+        let start_time = std::time::Instant::now();
+        let input = vec![crate::semantic_cache::prompt_text(request)];
+        let result = UpstreamTimeout::guard(TimeoutPhase::Total, timeouts.total(), adapter.embed(&model.name, &input)).await;
+        self.key_pool.record_outcome(&provider, &credential, result.as_ref().map(|_| ())).await;
+
+        // Embedding APIs don't report usage here; billed as prompt tokens only
+        let error = result.as_ref().err().map(|e| e.to_string());
+        let log_response = UnifiedChatResponse {
+            id: uuid::Uuid::new_v4().to_string(),
+            object: "embedding".to_string(),
+            created: chrono::Utc::now().timestamp(),
+            model: model.name.clone(),
+            choices: vec![],
+            usage: result.is_ok().then(|| Self::estimate_usage(request, None, "")),
+            routing_metadata: None,
+        };
+        self.log_request_with(
+            AttemptLog {
+                key_id: None,
+                service,
+                model_id: &model.id,
+                provider_id: &model.provider,
+                failover_count: 0,
+                status: if error.is_none() { "success" } else { "error" },
+                error: error.as_deref(),
+                start_time,
+                user_id: Some(user_id),
+            },
+            &log_response,
+            RequestLogExtras {
+                modality: Some("embedding".to_string()),
+                credential: Some(credential.masked()),
+                ..RequestLogExtras::default()
+            },
+        ).await;
+
+        result?.into_iter().next().ok_or_else(|| anyhow::anyhow!("Embedding model '{}' returned no vectors", model.name))
+    }
+
+    /// Guardrails attached to a service; none for direct model IDs
+    async fn service_guardrails(&self, service: &str) -> Vec<Arc<CompiledGuardrail>> {
+        match self.get_service(service).await {
+            Ok(service) => self.guardrails.resolve(service.guardrails.as_deref()).await,
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Run input guardrails, returning the request with any redactions applied
+    async fn guard_input(
+        &self,
+        guardrails: &[Arc<CompiledGuardrail>],
+        request: &UnifiedChatRequest,
+        user_id: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<UnifiedChatRequest> {
+        // Topic rules label the latest user message up front
+        let mut topics = crate::guardrails::TopicLabels::new();
+        if let Some(message) = request.messages.iter().rev().find(|m| m.role == "user") {
+            for guardrail in guardrails.iter().filter(|g| g.applies(Stage::Input)) {
+                let Some(rule) = guardrail.rule.topic() else { continue };
+                let label = match self.classify_topic(rule, &message.content, request, user_id).await {
+                    Ok(label) => Some(label),
+                    Err(e) => {
+                        warn!(guardrail = %guardrail.id, error = %e, "topic classification failed");
+                        None
+                    }
+                };
+                topics.insert(guardrail.id.clone(), label);
+            }
+        }
+
+        let mut guarded = request.clone();
+        let mut texts: Vec<&mut String> = guarded.messages.iter_mut().map(|m| &mut m.content).collect();
+        let violations = crate::guardrails::check(guardrails, Stage::Input, &mut texts, &topics);
+        self.settle_violations(&request.service, user_id, &violations, warnings)?;
+        Ok(guarded)
+    }
+
+    /// Run output guardrails over every choice, redacting in place
+    fn guard_output(
+        &self,
+        guardrails: &[Arc<CompiledGuardrail>],
+        service: &str,
+        user_id: &str,
+        response: &mut UnifiedChatResponse,
+        warnings: &mut Vec<String>,
+    ) -> Result<()> {
+        let mut texts: Vec<&mut String> = response.choices.iter_mut().map(|c| &mut c.message.content).collect();
+        self.guard_output_text(guardrails, service, user_id, &mut texts, warnings)
+    }
+
+    fn guard_output_text(
+        &self,
+        guardrails: &[Arc<CompiledGuardrail>],
+        service: &str,
+        user_id: &str,
+        texts: &mut [&mut String],
+        warnings: &mut Vec<String>,
+    ) -> Result<()> {
+        let violations = crate::guardrails::check(guardrails, Stage::Output, texts, &crate::guardrails::TopicLabels::new());
+        self.settle_violations(service, user_id, &violations, warnings)
+    }
+
+    /// Record violations and collect `warn` notes; a `block` fails the call
+    fn settle_violations(
+        &self,
+        service: &str,
+        user_id: &str,
+        violations: &[crate::guardrails::Violation],
+        warnings: &mut Vec<String>,
+    ) -> Result<()> {
+        self.guardrails.record(service, user_id, violations);
+        for violation in violations {
+            match violation.action {
+                GuardrailAction::Warn => warnings.push(violation.warning()),
+                GuardrailAction::Block => {
+                    info!(service = %service, guardrail = %violation.guardrail_id, stage = violation.stage.as_str(), "blocked by guardrail");
+                    let (status, what) = match violation.stage {
+                        Stage::Input => (poem::http::StatusCode::BAD_REQUEST, "Request"),
+                        Stage::Output => (poem::http::StatusCode::UNPROCESSABLE_ENTITY, "Response"),
+                    };
+                    return Err(RequestRejection {
+                        status,
+                        message: format!("{} blocked by guardrail '{}'", what, violation.guardrail_name),
+                    }.into());
+                }
+                GuardrailAction::Redact | GuardrailAction::Log => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask a topic rule's classifier model to label `text`. Logged and charged like any call.
+    async fn classify_topic(
+        &self,
+        rule: &crate::guardrails::TopicRestrictionRule,
+        text: &str,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+    ) -> Result<String> {
+        let model = self.get_model(&rule.classifier_model_id).await?;
+        let classify_request = UnifiedChatRequest {
+            messages: vec![
+                ChatMessage { role: "system".to_string(), content: crate::guardrails::topic_prompt(rule) },
+                ChatMessage { role: "user".to_string(), content: text.to_string() },
+            ],
+            params: Some(mawi_core::unified::ChatParams { temperature: Some(0.0), max_tokens: Some(16), reasoning_effort: None }),
+            stream: Some(false),
+            model: None,
+            response_format: None,
+            affinity_key: None,
+            ..request.clone()
+        };
+        let start_time = std::time::Instant::now();
+        let (response, _) = self.execute_model_traced(&model.id, &model.provider, &classify_request, None, Some(user_id), &AttemptTrace::default()).await?;
+        self.log_request_with(
+            AttemptLog {
+                key_id: None,
+                service: &request.service,
+                model_id: &model.id,
+                provider_id: &model.provider,
+                failover_count: 0,
+                status: "success",
+                error: None,
+                start_time,
+                user_id: Some(user_id),
+            },
+            &response,
+            RequestLogExtras::default(),
+        ).await;
+        let reply = response.choices.first().map(|c| c.message.content.as_str()).unwrap_or("");
+        Ok(crate::guardrails::parse_topic(rule, reply))
+    }
+
+    /// Execute request against a single service with weighted distribution and automatic failover
+    async fn execute_service_chat(
+        &self,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+    ) -> Result<UnifiedChatResponse> {
+        let heuristic_input_tokens = request.messages.iter().map(|m| m.content.len() as i64 / 4).sum::<i64>().max(50);
+        let heuristic_output_tokens = request.params.as_ref().and_then(|p| p.max_tokens).unwrap_or(500) as i64;
+        
+        let (service, models_with_weights) = match self.get_service(&request.service).await {
+            Ok(s) => {
+                // Check if this is an agentic service - route to agentic executor
+                if matches!(s.service_type, mawi_core::services::ServiceType::Agentic) {
+                    info!(service = %request.service, "routing to agentic executor");
                     
-                    let agentic_executor = crate::agentic_executor::AgenticExecutor::new(self.pool.clone(), self.mcp_manager.clone());
+                    // STRICT QUOTA CHECK for Agentic
+                    // Agentic runs are expensive. We check general availability first.
+                    let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
+                    let has_quota = quota_manager.check_quota(user_id, 0.05).await?; // Require at least $0.05 for agent start
+                    if !has_quota {
+                         anyhow::bail!("Insufficient quota for Agentic execution (requires > $0.05)");
+                    }
+                    
+                    let agentic_executor = crate::agentic_executor::AgenticExecutor::new(self.clone());
                     return agentic_executor.execute(request, user_id).await;
                 }
                 
@@ -508,6 +1549,18 @@ impl Executor {
                     system_prompt: None,
                     max_iterations: None,
                     user_id: None,
+                    hedge: None,
+                    experiment: None,
+                    shadow: None,
+                    fallback_services: Vec::new(),
+                    modality_strategies: HashMap::new(),
+                    ensemble: None,
+                    budget_policy: None,
+                    circuit_policy: None,
+                    slo: None,
+                    timeouts: None,
+                    retry_policy: None,
+                    response_cache: None,
                 };
                 
                 // Create a single model entry with max weight
@@ -522,6 +1575,12 @@ impl Executor {
         if let Some(override_model_id) = &request.model {
             debug!(service = %request.service, model = %override_model_id, "model override requested");
             models.retain(|(mid, _, _, _)| mid == override_model_id);
+
+            // Explicit overrides still reach drained models
+            if models.is_empty() && service.name != "direct-execution" {
+                models = self.load_service_models(&request.service, true).await?;
+                models.retain(|(mid, _, _, _)| mid == override_model_id);
+            }
             
             if models.is_empty() {
                 // forced model must be in service config
@@ -607,135 +1666,1137 @@ impl Executor {
             anyhow::bail!("{}", error_msg);
         }
 
-        let strategy_str = service.strategy.as_str().to_lowercase();
-        let selected_models = match strategy_str.as_str() {
-            "health" | "leader-worker" | "priority" | "highest_quality" => {
-                if matches!(service.service_type, mawi_core::services::ServiceType::Pool) && models.len() > 1 {
-                    debug!(strategy = %strategy_str, "using weighted selection for pool service");
-                    self.select_weighted(&models)
-                } else {
-                    debug!(strategy = %strategy_str, "using priority failover strategy");
-                    models.to_vec()  // Ordered by position
+        // Capability and context-window filtering: only models that can serve the request as sent,
+        // rather than silently pruning history for a model that is too small
+        let requirements = Self::model_requirements(request);
+        let multi_modality = service.pool_type == Some(mawi_core::services::PoolType::MultiModality);
+        let mut rejections = Vec::new();
+        let mut qualified = Vec::with_capacity(models.len());
+        for candidate in models {
+            let model = match self.get_model(&candidate.0).await {
+                Ok(model) => model,
+                Err(_) => {
+                    qualified.push(candidate);
+                    continue;
                 }
-            },
-            "weighted_random" | "weighted" | "random" | "pool" => {
-                debug!("using weighted random strategy");
-                self.select_weighted(&models)
-            },
-            "least_cost" => {
-                debug!("using least cost strategy");
-                self.select_least_cost(&models).await
-            },
-            "least_latency" | "speed" => {
-                debug!("using least latency strategy");
-                self.select_least_latency(&models).await
-            },
-            _ => {
-                if matches!(service.service_type, mawi_core::services::ServiceType::Pool) {
-                    debug!(from = %strategy_str, "defaulting pool service to weighted strategy");
-                    self.select_weighted(&models)
-                } else {
-                    debug!(strategy = %strategy_str, "unknown strategy, using health");
-                    models.to_vec()
+            };
+            match Self::unmet_requirement(&model, &candidate.3, &requirements, multi_modality) {
+                Some(reason) => {
+                    debug!(model = %model.name, reason = %reason, "model does not qualify for request");
+                    rejections.push(format!("{}: {}", model.name, reason));
                 }
+                None => qualified.push(candidate),
             }
+        }
+        if qualified.is_empty() {
+            return Err(RequestRejection::bad_request(format!(
+                "No model in service '{}' can serve this request: {}",
+                request.service,
+                rejections.join("; ")
+            )).into());
+        }
+        let models = qualified;
+
+        // Budget-aware downgrade: near the end of the caller's monthly quota, prefer cheaper
+        // models over failing once quota checks start rejecting
+        let (models, budget_downgrade) = self.apply_budget_policy(&service, request, user_id, models).await;
+
+        let strategy = service.strategy_for(DispatchKind::Chat.modality()).to_string();
+        let selected_models = self.order_by_strategy(&service, &strategy, models).await;
+
+        // Conversation affinity: pin the conversation to a consistently-hashed model.
+        // Unhealthy models are already filtered out and circuit-open ones are skipped
+        // during failover, so the conversation only moves when its model is unavailable.
+        let selected_models = match request.affinity_key.as_deref().filter(|k| !k.is_empty()) {
+            Some(key) if selected_models.len() > 1 => {
+                let candidates: Vec<(&str, i32)> = selected_models.iter().map(|(m, _, w, _)| (m.as_str(), *w)).collect();
+                let order = mawi_core::routing::AffinityRouter::rank(key, &candidates);
+                debug!(affinity_key = %key, model = %selected_models[order[0]].0, "applying conversation affinity");
+                order.into_iter().map(|idx| selected_models[idx].clone()).collect()
+            }
+            _ => selected_models,
+        };
+
+        // Experiments: prefer the assigned arm's models, keeping the rest for failover
+        let now = chrono::Utc::now().timestamp();
+        let assignment = match &service.experiment {
+            Some(experiment) if request.model.is_none() && experiment.is_running(now) => {
+                let subject = match experiment.sticky_by {
+                    mawi_core::services::ExperimentSubject::ApiKey => request.api_key_hash.as_deref().unwrap_or(user_id),
+                    mawi_core::services::ExperimentSubject::User => user_id,
+                };
+                experiment.assign(subject).map(|arm| (experiment.name.clone(), arm))
+            }
+            _ => None,
+        };
+        let selected_models = match &assignment {
+            Some((experiment, arm)) => {
+                debug!(experiment = %experiment, arm = %arm.name, "applying experiment arm");
+                let (mut preferred, rest): (Vec<_>, Vec<_>) = selected_models
+                    .into_iter()
+                    .partition(|(m, _, _, _)| arm.model_ids.contains(m));
+                preferred.extend(rest);
+                preferred
+            }
+            None => selected_models,
+        };
+
+        // Keys about to hit their provider rate limit go last, before they start returning 429s
+        let selected_models = self.steer_from_exhausted_keys(selected_models).await;
+
+        let log_extras = RequestLogExtras {
+            experiment: assignment.as_ref().map(|(experiment, _)| experiment.clone()),
+            experiment_arm: assignment.as_ref().map(|(_, arm)| arm.name.clone()),
+            ..Default::default()
         };
 
         debug!(count = selected_models.len(), service = %request.service, strategy = %service.strategy, "models selected");
 
-        // Execute with failover
+        if strategy.eq_ignore_ascii_case("ensemble") {
+            let mut response = self.execute_ensemble(&service, request, user_id, &selected_models, log_extras).await?;
+            if let Some(metadata) = response.routing_metadata.as_mut() {
+                metadata.actual_routing.budget_downgrade = budget_downgrade;
+            }
+            return Ok(response);
+        }
+
+        // Execute with failover (racing hedges when the service enables them)
         let start_time = std::time::Instant::now();
         let mut last_error = None;
         let mut failover_count = 0;
 
-        for (model_id, provider_id, weight, rtcros_config) in selected_models.iter() {
-            debug!(model = %model_id, provider = %provider_id, weight, attempt = failover_count + 1, "attempting model");
+        // Retries stay on the same model; the deadline bounds retries and failover together
+        let retry_policy = service.retry_policy.as_ref();
+        let deadline = retry_policy
+            .and_then(|p| p.deadline_ms)
+            .map(|ms| (Duration::from_millis(ms), start_time + Duration::from_millis(ms)));
+        let mut retries = vec![0u32; selected_models.len()];
+
+        let hedge = service.hedge.clone().filter(|h| h.enabled && h.max_fanout > 1);
+        let max_in_flight = hedge.as_ref().map(|h| h.max_fanout as usize).unwrap_or(1);
+        let hedge_delay = match (&hedge, selected_models.first()) {
+            (Some(h), Some((model_id, _, _, _))) => self.hedge_delay(h, model_id).await,
+            _ => Duration::ZERO,
+        };
+        let mut hedged = false;
+        let first_token = tokio::sync::Notify::new();
+        // Per candidate: whether its current attempt has streamed a token
+        let first_tokens: Vec<std::sync::atomic::AtomicBool> =
+            selected_models.iter().map(|_| std::sync::atomic::AtomicBool::new(false)).collect();
+
+        let attempt = |idx: usize, backoff: Duration| {
+            let (model_id, provider_id, _, rtcros_config) = &selected_models[idx];
+            let signal = FirstTokenSignal { notify: &first_token, seen: &first_tokens[idx] };
+            async move {
+                if !backoff.is_zero() {
+                    tokio::time::sleep(backoff).await;
+                }
+                let attempt_start = std::time::Instant::now();
+                let trace = AttemptTrace::new(Some(signal));
+                let call = self.execute_model_traced(model_id, provider_id, request, Some(rtcros_config), Some(user_id), &trace);
+                let result = match deadline {
+                    Some((limit, at)) => UpstreamTimeout::guard_until(TimeoutPhase::Deadline, limit, at.into(), call).await,
+                    None => call.await,
+                };
+                (idx, attempt_start, result, trace.credential())
+            }.boxed()
+        };
 
-            // Circuit Breaker Check
-            if !self.circuit_breaker.allow_request(model_id).await {
-                warn!(model = %model_id, "circuit breaker open, skipping model");
-                last_error = Some(anyhow::anyhow!("Circuit Breaker Open"));
-                failover_count += 1; // Count as failure so we try next model
-                continue;
+        let mut race: HedgeRace<'_, FinishedAttempt> = HedgeRace::new();
+        let mut next_idx = 0;
+
+        loop {
+            // Nothing running: fail over to the next candidate, while there's budget left
+            if race.is_empty() {
+                if deadline.is_some_and(|(_, at)| std::time::Instant::now() >= at) {
+                    warn!(service = %request.service, failures = failover_count, "request deadline reached, not failing over");
+                    break;
+                }
+                match self.next_available_candidate(&selected_models, &mut next_idx, &mut failover_count, &mut last_error).await {
+                    Some(idx) => {
+                        let (model_id, provider_id, weight, _) = &selected_models[idx];
+                        debug!(model = %model_id, provider = %provider_id, weight, attempt = failover_count + 1, "attempting model");
+                        race.launch(idx, attempt(idx, Duration::ZERO));
+                    }
+                    None => break,
+                }
             }
-            
-            let attempt_start = std::time::Instant::now();
-            match self.execute_model(model_id, provider_id, request, Some(rtcros_config), user_id).await {
-                Ok(response) => {
+
+            let first_token_seen = race.first_token_seen(&first_tokens);
+            let can_hedge = race.can_hedge(max_in_flight, first_token_seen, next_idx < selected_models.len());
+            let Some(event) = race.next(hedge_delay, can_hedge, &first_token, first_token_seen).await else {
+                break;
+            };
+
+            match event {
+                AttemptEvent::FirstToken => {
+                    // Picked up from `first_tokens` on the next pass
+                }
+                AttemptEvent::HedgeDue => {
+                    if let Some(idx) = self.next_available_candidate(&selected_models, &mut next_idx, &mut failover_count, &mut last_error).await {
+                        if !hedged {
+                            crate::metrics::HEDGED_REQUESTS.inc();
+                        }
+                        hedged = true;
+                        info!(model = %selected_models[idx].0, delay_ms = hedge_delay.as_millis() as u64, "no first token yet, launching hedge");
+                        race.launch(idx, attempt(idx, Duration::ZERO));
+                    }
+                }
+                AttemptEvent::Finished(finished) => {
+                    let (idx, attempt_start, result, credential) = *finished;
+                    race.finished(idx);
+                    let (model_id, provider_id, weight, _) = &selected_models[idx];
                     let latency = attempt_start.elapsed().as_millis() as i64;
-                    // Passive Health Check: Success
-                    self.update_model_health(model_id, true, latency, None).await;
-                    // Circuit Breaker: Success
-                    self.circuit_breaker.record_success(model_id).await;
 
-                    if failover_count > 0 {
-                        info!(model = %model_id, failures = failover_count, "failover successful");
-                    } else {
-                        debug!(model = %model_id, weight, "primary model succeeded");
+                    match result {
+                        Ok((mut response, ttft_ms)) => {
+                            // Passive Health Check: Success
+                            self.update_model_health(model_id, true, latency, None).await;
+                            // Circuit Breaker: Success
+                            self.record_circuit_outcome(service.circuit_policy.as_ref(), model_id, provider_id, true).await;
+
+                            if failover_count > 0 {
+                                info!(model = %model_id, failures = failover_count, "failover successful");
+                            } else {
+                                debug!(model = %model_id, weight, "primary model succeeded");
+                            }
+                            if hedged && selected_models.first().map(|(m, _, _, _)| m) != Some(model_id) {
+                                crate::metrics::HEDGE_WINS.inc();
+                            }
+
+                            if let Some(metadata) = response.routing_metadata.as_mut() {
+                                metadata.actual_routing.experiment = log_extras.experiment.clone();
+                                metadata.actual_routing.experiment_arm = log_extras.experiment_arm.clone();
+                                metadata.actual_routing.budget_downgrade = budget_downgrade;
+                            }
+
+                            // Log success with actual latency
+                            self.log_request_with(
+                                AttemptLog {
+                                    key_id: None,
+                                    service: &request.service,
+                                    model_id,
+                                    provider_id,
+                                    failover_count,
+                                    status: "success",
+                                    error: None,
+                                    start_time,
+                                    user_id: Some(user_id),
+                                },
+                                &response,
+                                RequestLogExtras { hedged, ttft_ms, retry_count: retries[idx] as i32, credential, ..log_extras.clone() },
+                            ).await;
+
+                            for (loser_idx, loser_start) in race.cancel_rest() {
+                                let (loser_model, loser_provider, _, _) = &selected_models[loser_idx];
+                                self.release_circuit_probes(loser_model, loser_provider).await;
+                                let charge = hedge.as_ref().map(|h| h.charge_cancelled).unwrap_or(false);
+                                let cancelled = AttemptLog {
+                                    key_id: None,
+                                    service: &request.service,
+                                    model_id: loser_model,
+                                    provider_id: loser_provider,
+                                    failover_count,
+                                    status: "cancelled",
+                                    error: None,
+                                    start_time: loser_start,
+                                    user_id: Some(user_id),
+                                };
+                                self.log_cancelled_attempt(cancelled, charge.then_some(heuristic_input_tokens), log_extras.clone()).await;
+                            }
+
+                            self.spawn_shadow_replay(&service, request, &selected_models[idx], &response, latency, user_id);
+
+                            return Ok(response);
+                        }
+                        Err(e) => {
+                            first_tokens[idx].store(false, std::sync::atomic::Ordering::Relaxed);
+                            let error_response = UnifiedChatResponse {
+                                id: uuid::Uuid::new_v4().to_string(),
+                                object: "chat.completion".to_string(),
+                                created: chrono::Utc::now().timestamp(),
+                                model: model_id.to_string(),
+                                choices: vec![],
+                                usage: None,
+                                routing_metadata: None,
+                            };
+
+                            // Transient failures retry the same model; health and circuit state
+                            // only see the outcome once we give up on it
+                            if let Some((class, backoff)) = self.retry_backoff(retry_policy, &e, retries[idx] + 1, deadline.map(|(_, at)| at)) {
+                                crate::metrics::RETRIES.with_label_values(&[class]).inc();
+                                info!(model = %model_id, class, retry = retries[idx] + 1, backoff_ms = backoff.as_millis() as u64, "retrying model");
+                                self.log_request_with(
+                                    AttemptLog {
+                                        key_id: None,
+                                        service: &request.service,
+                                        model_id,
+                                        provider_id,
+                                        failover_count,
+                                        status: "retry",
+                                        error: Some(&e.to_string()),
+                                        start_time: attempt_start,
+                                        user_id: Some(user_id),
+                                    },
+                                    &error_response,
+                                    RequestLogExtras { hedged, retry_count: retries[idx] as i32, credential, ..log_extras.clone() },
+                                ).await;
+
+                                retries[idx] += 1;
+                                race.retry(idx, attempt(idx, backoff));
+                                continue;
+                            }
+
+                            // Passive health and circuit breaker: failure
+                            self.record_attempt_failure(service.circuit_policy.as_ref(), model_id, provider_id, latency, &e).await;
+
+                            crate::metrics::FAILOVER_COUNT.inc();
+                            eprintln!("❌ Model {} failed: {}", model_id, e);
+                            last_error = Some(e);
+                            failover_count += 1;
+                            
+                            // Log failed request
+                            self.log_request_with(
+                                AttemptLog {
+                                    key_id: None,
+                                    service: &request.service,
+                                    model_id,
+                                    provider_id,
+                                    failover_count,
+                                    status: "error",
+                                    error: last_error.as_ref().map(|e| e.to_string()).as_deref(),
+                                    start_time,
+                                    user_id: Some(user_id),
+                                },
+                                &error_response,
+                                RequestLogExtras { hedged, retry_count: retries[idx] as i32, credential, ..log_extras.clone() },
+                            ).await;
+                            
+                            // Continue with in-flight hedges, or the next model
+                            continue;
+                        }
                     }
-                    
-                    // Log success with actual latency
-                    self.log_request(
-                        None,
-                        &request.service,
-                        model_id,
-                        provider_id,
+                }
+            }
+        }
+
+        // All models failed
+        crate::metrics::HTTP_REQUESTS_ERRORS.inc();
+        eprintln!("💥 All {} models failed for service '{}'", failover_count, request.service);
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All models failed")))
+    }
+
+    /// Whether a failed attempt should be retried on the same model: the error's retry class
+    /// and the backoff before retry number `retry`. Retries that would start past the request
+    /// deadline are skipped in favour of failover.
+    fn retry_backoff(
+        &self,
+        policy: Option<&mawi_core::services::RetryPolicy>,
+        error: &anyhow::Error,
+        retry: u32,
+        deadline: Option<std::time::Instant>,
+    ) -> Option<(&'static str, Duration)> {
+        let policy = policy?;
+        let class = retry_class(error).filter(|class| policy.retries(class))?;
+        let retry_after = error
+            .downcast_ref::<mawi_core::providers::UpstreamHttpError>()
+            .and_then(|e| e.retry_after);
+        let backoff = policy.backoff(retry, retry_after, rand::random::<f64>())?;
+        if deadline.is_some_and(|at| std::time::Instant::now() + backoff >= at) {
+            return None;
+        }
+        Some((class, backoff))
+    }
+
+    /// Why a model can't serve the request as sent, or `None` when it can. Counts the RTCROS
+    /// system prompt toward the context window; multi-modality pools send chat to their text
+    /// sub-pool only.
+    fn unmet_requirement(
+        model: &mawi_core::models::Model,
+        rtcros: &mawi_core::rtcros::RtcrosConfig,
+        requirements: &mawi_core::models::ModelRequirements,
+        multi_modality: bool,
+    ) -> Option<String> {
+        if multi_modality && !DispatchKind::Chat.accepts(&model.modality, &model.worker_type) {
+            return Some(format!("{} model is not in the chat sub-pool", model.modality));
+        }
+        let system_tokens = rtcros.build_system_prompt().map(|p| p.len() / 4 + 4).unwrap_or(0);
+        model.unmet_requirement(&mawi_core::models::ModelRequirements {
+            prompt_tokens: requirements.prompt_tokens + system_tokens,
+            ..requirements.clone()
+        })
+    }
+
+    /// Apply the service's budget policy: once the caller's quota usage reaches a threshold,
+    /// restrict candidates to the allowed tiers and/or the threshold's fallback model.
+    /// Returns the candidates and the threshold applied, if any. When nothing cheaper is
+    /// available the full pool is kept so the request can still be served.
+    async fn apply_budget_policy(
+        &self,
+        service: &mawi_core::services::Service,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+        models: Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>,
+    ) -> (Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>, Option<f64>) {
+        // Forced models (playground, pinned requests) are never downgraded
+        let policy = match &service.budget_policy {
+            Some(policy) if policy.enabled && request.model.is_none() => policy,
+            _ => return (models, None),
+        };
+        let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
+        let usage = match quota_manager.usage_percent(user_id).await {
+            Ok(usage) => usage,
+            Err(e) => {
+                warn!(error = %e, user_id, "could not read quota usage, skipping budget policy");
+                return (models, None);
+            }
+        };
+        let Some(threshold) = policy.threshold_for(usage) else {
+            return (models, None);
+        };
+
+        let mut restricted = Vec::new();
+        if !threshold.allowed_tiers.is_empty() {
+            for candidate in &models {
+                if let Ok(model) = self.get_model(&candidate.0).await {
+                    if threshold.allowed_tiers.iter().any(|t| t.eq_ignore_ascii_case(&model.tier)) {
+                        restricted.push(candidate.clone());
+                    }
+                }
+            }
+        }
+        if let Some(fallback_id) = &threshold.fallback_model_id {
+            if !restricted.iter().any(|(m, _, _, _)| m == fallback_id) {
+                match models.iter().find(|(m, _, _, _)| m == fallback_id) {
+                    Some(candidate) => restricted.push(candidate.clone()),
+                    None => match self.get_model(fallback_id).await {
+                        Ok(model) => {
+                            // Candidates already passed the capability filter; a fallback from outside the pool must too
+                            let rtcros = mawi_core::rtcros::RtcrosConfig::default();
+                            let multi_modality = service.pool_type == Some(mawi_core::services::PoolType::MultiModality);
+                            match Self::unmet_requirement(&model, &rtcros, &Self::model_requirements(request), multi_modality) {
+                                Some(reason) => warn!(model = %fallback_id, reason = %reason, "budget fallback model can't serve this request"),
+                                None => restricted.push((model.id, model.provider, 100, rtcros)),
+                            }
+                        }
+                        Err(e) => warn!(model = %fallback_id, error = %e, "budget fallback model not found"),
+                    },
+                }
+            }
+        }
+
+        if restricted.is_empty() {
+            warn!(service = %service.name, usage_percent = usage, "budget threshold reached but no cheaper candidates, keeping full pool");
+            return (models, None);
+        }
+
+        info!(service = %service.name, usage_percent = usage, threshold = threshold.usage_percent, candidates = restricted.len(), "budget downgrade applied");
+        crate::metrics::BUDGET_DOWNGRADES.with_label_values(&[&service.name]).inc();
+        (restricted, Some(threshold.usage_percent))
+    }
+
+    /// Ensemble strategy: call the first `size` available members in parallel and combine
+    /// their answers. Every call, members and judge alike, is logged and charged.
+    async fn execute_ensemble(
+        &self,
+        service: &mawi_core::services::Service,
+        request: &UnifiedChatRequest,
+        user_id: &str,
+        candidates: &[(String, String, i32, mawi_core::rtcros::RtcrosConfig)],
+        log_extras: RequestLogExtras,
+    ) -> Result<UnifiedChatResponse> {
+        let config = service.ensemble.clone().unwrap_or_default();
+        let start_time = std::time::Instant::now();
+
+        let mut members = Vec::new();
+        for candidate in candidates {
+            if members.len() >= config.size as usize {
+                break;
+            }
+            if self.circuit_allows(&candidate.0, &candidate.1).await {
+                members.push(candidate);
+            } else {
+                warn!(model = %candidate.0, "circuit breaker open, leaving model out of ensemble");
+            }
+        }
+        if members.is_empty() {
+            anyhow::bail!("No ensemble members available for service '{}' (circuits open)", request.service);
+        }
+        info!(service = %request.service, members = members.len(), combiner = ?config.combiner, "running ensemble");
+
+        let results = futures::future::join_all(members.iter().map(|(model_id, provider_id, _, rtcros)| async move {
+            let attempt_start = std::time::Instant::now();
+            let trace = AttemptTrace::default();
+            let result = self.execute_model_traced(model_id, provider_id, request, Some(rtcros), Some(user_id), &trace).await;
+            // Usage from the prompt and answer text, so member costs and the total add up
+            let result = result.map(|(mut response, _)| {
+                let text = response.choices.first().map(|c| c.message.content.as_str()).unwrap_or("");
+                response.usage = Some(Self::estimate_usage(request, Some(rtcros), text));
+                response
+            });
+            (attempt_start.elapsed().as_millis() as i64, result, trace.credential())
+        })).await;
+
+        let mut member_results = Vec::with_capacity(results.len());
+        let mut answers: Vec<(usize, UnifiedChatResponse)> = Vec::new();
+        let mut last_error = None;
+        let mut total_usage = TokenUsage { prompt_tokens: 0, completion_tokens: 0, total_tokens: 0 };
+        let mut add_usage = |response: &UnifiedChatResponse| {
+            if let Some(usage) = &response.usage {
+                total_usage.prompt_tokens += usage.prompt_tokens;
+                total_usage.completion_tokens += usage.completion_tokens;
+                total_usage.total_tokens += usage.total_tokens;
+            }
+        };
+        for (idx, (latency, result, credential)) in results.into_iter().enumerate() {
+            let extras = RequestLogExtras { credential, ..log_extras.clone() };
+            let (model_id, provider_id, _, _) = members[idx];
+            match result {
+                Ok(response) => {
+                    self.update_model_health(model_id, true, latency, None).await;
+                    self.record_circuit_outcome(service.circuit_policy.as_ref(), model_id, provider_id, true).await;
+                    self.log_request_with(
+                        AttemptLog {
+                            key_id: None,
+                            service: &request.service,
+                            model_id,
+                            provider_id,
+                            failover_count: 0,
+                            status: "success",
+                            error: None,
+                            start_time,
+                            user_id: Some(user_id),
+                        },
                         &response,
-                        failover_count,
-                        "success",
-                        None,
-                        start_time,
-                        Some(user_id),
+                        extras,
+                    ).await;
+                    member_results.push(Self::ensemble_member(model_id, provider_id, latency, Ok(&response)));
+                    add_usage(&response);
+                    answers.push((idx, response));
+                }
+                Err(e) => {
+                    self.record_attempt_failure(service.circuit_policy.as_ref(), model_id, provider_id, latency, &e).await;
+                    let error_response = UnifiedChatResponse {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        object: "chat.completion".to_string(),
+                        created: chrono::Utc::now().timestamp(),
+                        model: model_id.to_string(),
+                        choices: vec![],
+                        usage: None,
+                        routing_metadata: None,
+                    };
+                    self.log_request_with(
+                        AttemptLog {
+                            key_id: None,
+                            service: &request.service,
+                            model_id,
+                            provider_id,
+                            failover_count: 0,
+                            status: "error",
+                            error: Some(&e.to_string()),
+                            start_time,
+                            user_id: Some(user_id),
+                        },
+                        &error_response,
+                        extras,
                     ).await;
+                    eprintln!("❌ Ensemble member {} failed: {}", model_id, e);
+                    member_results.push(Self::ensemble_member(model_id, provider_id, latency, Err(&e)));
+                    last_error = Some(e);
+                }
+            }
+        }
 
-                    return Ok(response);
+        if answers.is_empty() {
+            crate::metrics::HTTP_REQUESTS_ERRORS.inc();
+            eprintln!("💥 All {} ensemble members failed for service '{}'", member_results.len(), request.service);
+            return Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All ensemble members failed")));
+        }
+
+        let answer_text = |response: &UnifiedChatResponse| {
+            response.choices.first().map(|c| c.message.content.clone()).unwrap_or_default()
+        };
+        let vote = |answers: &[(usize, UnifiedChatResponse)]| {
+            let texts: Vec<String> = answers.iter().map(|(_, r)| answer_text(r)).collect();
+            let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+            mawi_core::routing::majority_vote(&refs).unwrap_or((0, 1))
+        };
+
+        let mut judge_result = None;
+        let mut votes = None;
+        let mut response = match config.combiner {
+            mawi_core::services::EnsembleCombiner::ReturnAll => {
+                // Numbered by successful answer, so failed members leave no gaps
+                let choices: Vec<ChatChoice> = answers
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_, r))| ChatChoice {
+                        index: index as i32,
+                        message: ChatMessage { role: "assistant".to_string(), content: answer_text(r) },
+                        finish_reason: Some("stop".to_string()),
+                    })
+                    .collect();
+                let (_, mut first) = answers.swap_remove(0);
+                first.choices = choices;
+                first
+            }
+            mawi_core::services::EnsembleCombiner::Judge => {
+                let judged = match &config.judge_model_id {
+                    Some(judge_id) => {
+                        let judge_start = std::time::Instant::now();
+                        let result = self.judge_ensemble(judge_id, request, &answers, user_id).await;
+                        let latency = judge_start.elapsed().as_millis() as i64;
+                        let judge_provider = self.get_model(judge_id).await.map(|m| m.provider).unwrap_or_default();
+                        match result {
+                            Ok(judged) => {
+                                self.log_request_with(
+                                    AttemptLog {
+                                        key_id: None,
+                                        service: &request.service,
+                                        model_id: judge_id,
+                                        provider_id: &judge_provider,
+                                        failover_count: 0,
+                                        status: "success",
+                                        error: None,
+                                        start_time,
+                                        user_id: Some(user_id),
+                                    },
+                                    &judged,
+                                    log_extras.clone(),
+                                ).await;
+                                judge_result = Some(Self::ensemble_member(judge_id, &judge_provider, latency, Ok(&judged)));
+                                add_usage(&judged);
+                                Some(judged)
+                            }
+                            Err(e) => {
+                                warn!(judge = %judge_id, error = %e, "ensemble judge failed, falling back to majority vote");
+                                judge_result = Some(Self::ensemble_member(judge_id, &judge_provider, latency, Err(&e)));
+                                None
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                match judged {
+                    Some(judged) => judged,
+                    None => {
+                        let (winner, count) = vote(&answers);
+                        votes = Some(count as u32);
+                        answers.swap_remove(winner).1
+                    }
+                }
+            }
+            mawi_core::services::EnsembleCombiner::MajorityVote => {
+                let (winner, count) = vote(&answers);
+                votes = Some(count as u32);
+                answers.swap_remove(winner).1
+            }
+        };
+
+        // The response accounts for every call the ensemble made
+        response.usage = Some(total_usage);
+        let total_cost_usd = member_results.iter().chain(judge_result.iter()).filter_map(|m| m.cost_usd).sum();
+        let selected_model = response.routing_metadata.as_ref().map(|m| m.actual_routing.model.clone());
+        if let Some(metadata) = response.routing_metadata.as_mut() {
+            metadata.actual_routing.experiment = log_extras.experiment.clone();
+            metadata.actual_routing.experiment_arm = log_extras.experiment_arm.clone();
+            metadata.ensemble = Some(mawi_core::unified::EnsembleMetadata {
+                combiner: serde_json::to_value(config.combiner).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default(),
+                selected_model,
+                votes,
+                members: member_results,
+                judge: judge_result,
+                total_cost_usd,
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Ask the judge model to pick or merge the best of the member answers
+    async fn judge_ensemble(
+        &self,
+        judge_id: &str,
+        request: &UnifiedChatRequest,
+        answers: &[(usize, UnifiedChatResponse)],
+        user_id: &str,
+    ) -> Result<UnifiedChatResponse> {
+        let judge = self.get_model(judge_id).await?;
+        let conversation = request.messages
+            .iter()
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let candidates = answers
+            .iter()
+            .enumerate()
+            .map(|(i, (_, r))| format!("### Answer {}\n{}", i + 1, r.choices.first().map(|c| c.message.content.as_str()).unwrap_or("")))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let judge_request = UnifiedChatRequest {
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: "You are judging answers that several models gave to the same request. \
+                        Return the single best answer, merging answers only where that makes it more accurate. \
+                        Reply with the answer alone, in the format the request asked for.".to_string(),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: format!("### Request\n{}\n\n{}", conversation, candidates),
+                },
+            ],
+            model: None,
+            affinity_key: None,
+            ..request.clone()
+        };
+
+        let (mut response, _) = self.execute_model_traced(&judge.id, &judge.provider, &judge_request, None, Some(user_id), &AttemptTrace::default()).await?;
+        let text = response.choices.first().map(|c| c.message.content.as_str()).unwrap_or("");
+        response.usage = Some(Self::estimate_usage(&judge_request, None, text));
+        Ok(response)
+    }
+
+    /// Summarise one ensemble call for `routing_metadata`
+    fn ensemble_member(
+        model_id: &str,
+        provider_id: &str,
+        latency_ms: i64,
+        result: std::result::Result<&UnifiedChatResponse, &anyhow::Error>,
+    ) -> mawi_core::unified::EnsembleMemberResult {
+        match result {
+            Ok(response) => mawi_core::unified::EnsembleMemberResult {
+                model: model_id.to_string(),
+                provider: provider_id.to_string(),
+                status: "success".to_string(),
+                content: response.choices.first().map(|c| c.message.content.clone()),
+                error: None,
+                latency_ms,
+                cost_usd: response.usage.as_ref().and_then(|u| {
+                    crate::pricing::PRICING.calculate_cost(&response.model, u.prompt_tokens as i64, u.completion_tokens as i64)
+                }),
+            },
+            Err(e) => mawi_core::unified::EnsembleMemberResult {
+                model: model_id.to_string(),
+                provider: provider_id.to_string(),
+                status: "error".to_string(),
+                content: None,
+                error: Some(sanitize_error(&e.to_string())),
+                latency_ms,
+                cost_usd: None,
+            },
+        }
+    }
+
+    /// Derive what the request needs from a model before candidate selection
+    fn model_requirements(request: &UnifiedChatRequest) -> mawi_core::models::ModelRequirements {
+        let format = request.response_format.as_ref().map(|f| f.type_.as_str());
+        mawi_core::models::ModelRequirements {
+            vision: request.messages.iter().any(|m| m.content.contains("data:image/")),
+            tools: false, // chat requests don't carry tool definitions yet
+            json_mode: format == Some("json_object"),
+            json_schema: format == Some("json_schema"),
+            streaming: request.stream.unwrap_or(false),
+            reasoning: request.params.as_ref().and_then(|p| p.reasoning_effort.as_ref()).is_some(),
+            prompt_tokens: crate::context_manager::ContextManager::estimate_tokens(&request.messages),
+            max_output_tokens: request.params.as_ref().and_then(|p| p.max_tokens),
+        }
+    }
+
+    /// Move candidates whose provider key is near its rate limit behind the rest, keeping
+    /// the order within each group
+    async fn steer_from_exhausted_keys(
+        &self,
+        models: Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>,
+    ) -> Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)> {
+        if models.len() < 2 {
+            return models;
+        }
+        let mut open = Vec::with_capacity(models.len());
+        let mut exhausted = Vec::new();
+        for candidate in models {
+            let near_limit = match (self.get_model(&candidate.0).await, self.get_provider(&candidate.1).await) {
+                (Ok(model), Ok(provider)) => {
+                    // Pooled providers are near their limit only once every key is
+                    let near = self.key_pool.candidate_keys(&provider, &model).await
+                        .iter()
+                        .all(|api_key| self.upstream_throttle.near_exhaustion(&provider, api_key));
+                    if near {
+                        crate::metrics::UPSTREAM_THROTTLED.with_label_values(&[&provider.name, "steered"]).inc();
+                    }
+                    near
+                }
+                _ => false,
+            };
+            if near_limit {
+                debug!(model = %candidate.0, "provider key near its rate limit, trying it last");
+                exhausted.push(candidate);
+            } else {
+                open.push(candidate);
+            }
+        }
+        open.extend(exhausted);
+        open
+    }
+
+    /// Advance to the next candidate whose circuit breaker allows a request.
+    /// Skipped candidates count as failovers.
+    async fn next_available_candidate(
+        &self,
+        models: &[(String, String, i32, mawi_core::rtcros::RtcrosConfig)],
+        next_idx: &mut usize,
+        failover_count: &mut i32,
+        last_error: &mut Option<anyhow::Error>,
+    ) -> Option<usize> {
+        while *next_idx < models.len() {
+            let idx = *next_idx;
+            *next_idx += 1;
+
+            // Circuit Breaker Check
+            if !self.circuit_allows(&models[idx].0, &models[idx].1).await {
+                warn!(model = %models[idx].0, "circuit breaker open, skipping model");
+                *last_error = Some(anyhow::anyhow!("Circuit Breaker Open"));
+                *failover_count += 1; // Count as failure so we try next model
+                continue;
+            }
+            return Some(idx);
+        }
+        None
+    }
+
+    /// Whether both the provider's and the model's circuits admit a request.
+    /// A provider probe permit is handed back if the model's circuit then refuses.
+    async fn circuit_allows(&self, model_id: &str, provider_id: &str) -> bool {
+        use crate::circuit_breaker::CircuitBreaker;
+        let provider_key = CircuitBreaker::provider_key(provider_id);
+        if !self.circuit_breaker.allow_request(&provider_key).await {
+            return false;
+        }
+        if !self.circuit_breaker.allow_request(&CircuitBreaker::model_key(model_id)).await {
+            self.circuit_breaker.release_probe(&provider_key).await;
+            return false;
+        }
+        true
+    }
+
+    /// Record an attempt's outcome on the model's and the provider's circuits.
+    /// Model policy: the model's own override, then the service's, then the default.
+    async fn record_circuit_outcome(
+        &self,
+        service_policy: Option<&mawi_core::services::CircuitPolicy>,
+        model_id: &str,
+        provider_id: &str,
+        success: bool,
+    ) {
+        use crate::circuit_breaker::CircuitBreaker;
+        let model_key = CircuitBreaker::model_key(model_id);
+        let model_policy = match self.circuit_policy_override(&model_key).await {
+            Some(policy) => policy,
+            None => service_policy.cloned().unwrap_or_default(),
+        };
+        let provider_key = CircuitBreaker::provider_key(provider_id);
+        let provider_policy = self.circuit_policy_override(&provider_key).await
+            .unwrap_or_else(mawi_core::services::CircuitPolicy::provider_default);
+
+        if success {
+            self.circuit_breaker.record_success(&model_key, &model_policy).await;
+            self.circuit_breaker.record_success(&provider_key, &provider_policy).await;
+        } else {
+            self.circuit_breaker.record_failure(&model_key, &model_policy).await;
+            self.circuit_breaker.record_failure(&provider_key, &provider_policy).await;
+        }
+    }
+
+    /// Record a failed attempt on the model's health and circuits. Running out of provider
+    /// budget (throttled, or a 429) says nothing about the provider's health, so those only
+    /// hand back probe permits.
+    async fn record_attempt_failure(
+        &self,
+        service_policy: Option<&mawi_core::services::CircuitPolicy>,
+        model_id: &str,
+        provider_id: &str,
+        latency: i64,
+        error: &anyhow::Error,
+    ) {
+        if crate::upstream_limits::is_capacity_error(error) {
+            self.release_circuit_probes(model_id, provider_id).await;
+            return;
+        }
+        self.update_model_health(model_id, false, latency, Some(error.to_string())).await;
+        self.record_circuit_outcome(service_policy, model_id, provider_id, false).await;
+    }
+
+    /// Hand back any half-open probe permits held by an attempt that was cancelled
+    async fn release_circuit_probes(&self, model_id: &str, provider_id: &str) {
+        use crate::circuit_breaker::CircuitBreaker;
+        self.circuit_breaker.release_probe(&CircuitBreaker::model_key(model_id)).await;
+        self.circuit_breaker.release_probe(&CircuitBreaker::provider_key(provider_id)).await;
+    }
+
+    /// Circuit policy override for a breaker key from `circuit_policies`
+    async fn circuit_policy_override(&self, key: &str) -> Option<mawi_core::services::CircuitPolicy> {
+        if let Some(policy) = self.circuit_policy_cache.get(key).await {
+            return policy;
+        }
+        let policy = sqlx::query_scalar::<_, String>("SELECT policy FROM circuit_policies WHERE resource = $1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or_else(|e| {
+                warn!(resource = %key, error = %e, "failed to load circuit policy");
+                None
+            })
+            .and_then(|json| serde_json::from_str(&json).ok());
+        self.circuit_policy_cache.insert(key.to_string(), policy.clone()).await;
+        policy
+    }
+
+    /// Replay a sampled request against the service's shadow models in the background.
+    /// Never affects the client: shadow calls skip the caller's quota and are capped
+    /// by the service's own shadow budget.
+    fn spawn_shadow_replay(
+        &self,
+        service: &mawi_core::services::Service,
+        request: &UnifiedChatRequest,
+        primary: &(String, String, i32, mawi_core::rtcros::RtcrosConfig),
+        primary_response: &UnifiedChatResponse,
+        primary_latency_ms: i64,
+        user_id: &str,
+    ) {
+        let shadow = match service.shadow.clone() {
+            Some(shadow) if shadow.enabled && !shadow.model_ids.is_empty() => shadow,
+            _ => return,
+        };
+        if rand::random::<f64>() * 100.0 >= shadow.sample_percent {
+            return;
+        }
+
+        let executor = self.clone();
+        let service_name = service.name.clone();
+        let request = request.clone();
+        let (primary_model_id, _, _, rtcros) = primary.clone();
+        let primary_response_id = primary_response.id.clone();
+        let primary_text = primary_response.choices.first().map(|c| c.message.content.clone()).unwrap_or_default();
+        let user_id = user_id.to_string();
+
+        tokio::spawn(async move {
+            for shadow_model_id in shadow.model_ids.iter().filter(|m| **m != primary_model_id) {
+                let model = match executor.get_model(shadow_model_id).await {
+                    Ok(model) => model,
+                    Err(e) => {
+                        warn!(model = %shadow_model_id, error = %e, "shadow model not found");
+                        continue;
+                    }
+                };
+
+                // Reserve the replay's cost up front, priced as if it answered at the primary's length
+                let reservation = match shadow.monthly_budget_usd {
+                    Some(budget) => {
+                        let usage = Self::estimate_usage(&request, Some(&rtcros), &primary_text);
+                        let estimate = crate::pricing::PRICING.estimate_cost(
+                            &model.name, &model.provider, usage.prompt_tokens as i64, usage.completion_tokens as i64,
+                        );
+                        match executor.reserve_shadow_spend(&service_name, estimate, budget).await {
+                            Ok(Some(month_start)) => Some((month_start, estimate)),
+                            Ok(None) => {
+                                warn!(service = %service_name, budget, "shadow budget exhausted, skipping replay");
+                                crate::metrics::SHADOW_REQUESTS.with_label_values(&[&service_name, "budget_exhausted"]).inc();
+                                return;
+                            }
+                            Err(e) => {
+                                error!(service = %service_name, error = %e, "failed to reserve shadow budget, skipping replay");
+                                return;
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
+                let started = std::time::Instant::now();
+                let result = executor.execute_model_traced(shadow_model_id, &model.provider, &request, Some(&rtcros), None, &AttemptTrace::shadow()).await;
+                let latency_ms = started.elapsed().as_millis() as i64;
+
+                let (status, response_text, error) = match &result {
+                    Ok((response, _)) => ("success", response.choices.first().map(|c| c.message.content.clone()), None),
+                    Err(e) if e.is::<crate::upstream_limits::UpstreamThrottled>() => {
+                        debug!(model = %shadow_model_id, "provider near its rate limit, skipping shadow replay");
+                        crate::metrics::SHADOW_REQUESTS.with_label_values(&[&service_name, "throttled"]).inc();
+                        if let Some((month_start, estimate)) = reservation {
+                            executor.settle_shadow_spend(&service_name, month_start, -estimate).await;
+                        }
+                        continue;
+                    }
+                    Err(e) => ("error", None, Some(sanitize_error(&e.to_string()))),
+                };
+                // Charged from the prompt and response text, so the budget tracks real spend
+                let usage = response_text.as_deref().map(|text| {
+                    let usage = Self::estimate_usage(&request, Some(&rtcros), text);
+                    (usage.prompt_tokens, usage.completion_tokens)
+                });
+                let cost_usd = usage.map(|(prompt, completion)| {
+                    crate::pricing::PRICING.estimate_cost(&model.name, &model.provider, prompt as i64, completion as i64)
+                });
+                if let Some((month_start, estimate)) = reservation {
+                    executor.settle_shadow_spend(&service_name, month_start, cost_usd.unwrap_or(0.0) - estimate).await;
                 }
-                Err(e) => {
-                    let latency = attempt_start.elapsed().as_millis() as i64;
-                    // Passive Health Check: Failure
-                    self.update_model_health(model_id, false, latency, Some(e.to_string())).await;
-                    // Circuit Breaker: Failure
-                    self.circuit_breaker.record_failure(model_id).await;
+                let similarity = response_text.as_deref()
+                    .filter(|_| shadow.similarity)
+                    .map(|text| mawi_core::utils::text_similarity(&primary_text, text));
 
-                    crate::metrics::FAILOVER_COUNT.inc();
-                    eprintln!("❌ Model {} failed: {}", model_id, e);
-                    last_error = Some(e);
-                    failover_count += 1;
-                    
-                    // Log failed request
-                    let error_response = UnifiedChatResponse {
-                        id: uuid::Uuid::new_v4().to_string(),
-                        object: "chat.completion".to_string(),
-                        created: chrono::Utc::now().timestamp(),
-                        model: model_id.to_string(),
-                        choices: vec![],
-                        usage: None,
-                        routing_metadata: None,
-                    };
-                    
-                    self.log_request(
-                        None,
-                        &request.service,
-                        model_id,
-                        provider_id,
-                        &error_response,
-                        failover_count,
-                        "error",
-                        last_error.as_ref().map(|e| e.to_string()).as_deref(),
-                        start_time,
-                        Some(user_id),
-                    ).await;
-                    
-                    // Continue to next model
-                    continue;
+                crate::metrics::SHADOW_REQUESTS.with_label_values(&[&service_name, status]).inc();
+
+                if let Err(e) = sqlx::query(
+                    "INSERT INTO shadow_results (id, service_name, primary_response_id, primary_model_id, shadow_model_id,
+                        status, error_message, primary_text, response_text, primary_latency_ms, latency_ms,
+                        tokens_prompt, tokens_completion, cost_usd, similarity, user_id)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)"
+                )
+                .bind(uuid::Uuid::new_v4().to_string())
+                .bind(&service_name)
+                .bind(&primary_response_id)
+                .bind(&primary_model_id)
+                .bind(shadow_model_id)
+                .bind(status)
+                .bind(&error)
+                .bind(&primary_text)
+                .bind(&response_text)
+                .bind(primary_latency_ms)
+                .bind(latency_ms)
+                .bind(usage.map(|(prompt, _)| prompt))
+                .bind(usage.map(|(_, completion)| completion))
+                .bind(cost_usd)
+                .bind(similarity)
+                .bind(&user_id)
+                .execute(&executor.pool)
+                .await
+                {
+                    error!(service = %service_name, error = %e, "failed to store shadow result");
                 }
             }
+        });
+    }
+
+    /// Token usage estimated from text length (about 4 characters per token), for calls
+    /// whose provider doesn't report usage
+    fn estimate_usage(
+        request: &UnifiedChatRequest,
+        rtcros: Option<&mawi_core::rtcros::RtcrosConfig>,
+        completion: &str,
+    ) -> TokenUsage {
+        let system_tokens = rtcros.and_then(|c| c.build_system_prompt()).map(|p| p.len() / 4 + 4).unwrap_or(0);
+        let prompt_tokens = (request.messages.iter().map(|m| m.content.len() / 4 + 4).sum::<usize>() + system_tokens) as i32;
+        let completion_tokens = (completion.len() / 4) as i32;
+        TokenUsage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens }
+    }
+
+    /// Add `estimate` to a service's shadow spend for the month if that stays within `budget`.
+    /// Returns the month it was booked against, or `None` when the budget is exhausted.
+    async fn reserve_shadow_spend(&self, service_name: &str, estimate: f64, budget: f64) -> Result<Option<i64>> {
+        let month_start = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO shadow_spend (service_name, month_start, spent_usd)
+             SELECT $1, EXTRACT(EPOCH FROM date_trunc('month', NOW()))::BIGINT, $2
+             WHERE $2 <= $3
+             ON CONFLICT (service_name, month_start) DO UPDATE
+               SET spent_usd = shadow_spend.spent_usd + EXCLUDED.spent_usd
+               WHERE shadow_spend.spent_usd + EXCLUDED.spent_usd <= $3
+             RETURNING month_start"
+        )
+        .bind(service_name)
+        .bind(estimate)
+        .bind(budget)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(month_start)
+    }
+
+    /// Correct a reservation once the replay's actual cost is known
+    async fn settle_shadow_spend(&self, service_name: &str, month_start: i64, delta: f64) {
+        if delta == 0.0 {
+            return;
+        }
+        if let Err(e) = sqlx::query(
+            "UPDATE shadow_spend SET spent_usd = GREATEST(spent_usd + $3, 0)
+             WHERE service_name = $1 AND month_start = $2"
+        )
+        .bind(service_name)
+        .bind(month_start)
+        .bind(delta)
+        .execute(&self.pool)
+        .await
+        {
+            error!(service = %service_name, error = %e, "failed to settle shadow spend");
         }
+    }
 
-        // All models failed
-        crate::metrics::HTTP_REQUESTS_ERRORS.inc();
-        eprintln!("💥 All {} models failed for service '{}'", failover_count, request.service);
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All models failed")))
+    /// Delay before launching a hedge: the configured value, else the model's recent p90 TTFT.
+    /// The percentile scans an hour of logs, so it is cached per model for a minute.
+    async fn hedge_delay(&self, hedge: &mawi_core::services::HedgeConfig, model_id: &str) -> Duration {
+        if let Some(ms) = hedge.delay_ms {
+            return Duration::from_millis(ms);
+        }
+        self.hedge_delays
+            .get_with(model_id.to_string(), self.recent_p90_ttft(model_id))
+            .await
+    }
+
+    async fn recent_p90_ttft(&self, model_id: &str) -> Duration {
+        let p90_ttft: Option<f64> = sqlx::query_scalar(
+            "SELECT percentile_cont(0.9) WITHIN GROUP (ORDER BY ttft_ms)
+             FROM request_logs
+             WHERE model_id = $1
+               AND status = 'success'
+               AND ttft_ms IS NOT NULL
+               AND created_at > EXTRACT(EPOCH FROM NOW())::BIGINT - 3600"
+        )
+        .bind(model_id)
+        .fetch_one(&self.pool)
+        .await
+        .ok()
+        .flatten();
+
+        let ms = match p90_ttft {
+            Some(p90) if p90 > 0.0 => p90 as u64,
+            // No recent data: fall back to the model's configured average TTFT
+            _ => self.get_model(model_id).await
+                .ok()
+                .map(|m| m.avg_ttft_ms)
+                .filter(|ttft| *ttft > 0)
+                .map(|ttft| ttft as u64)
+                .unwrap_or(DEFAULT_HEDGE_DELAY_MS),
+        };
+        Duration::from_millis(ms)
+    }
+
+    /// Log a hedge attempt that lost the race and was cancelled.
+    /// `charge_prompt_tokens` bills the estimated prompt for providers that charge aborted requests.
+    async fn log_cancelled_attempt(
+        &self,
+        attempt: AttemptLog<'_>,
+        charge_prompt_tokens: Option<i64>,
+        extras: RequestLogExtras,
+    ) {
+        let model_name = self.get_model(attempt.model_id).await.map(|m| m.name).unwrap_or_else(|_| attempt.model_id.to_string());
+        let usage = charge_prompt_tokens.map(|tokens| TokenUsage {
+            prompt_tokens: tokens as i32,
+            completion_tokens: 0,
+            total_tokens: tokens as i32,
+        });
+        let cancelled_response = UnifiedChatResponse {
+            id: uuid::Uuid::new_v4().to_string(),
+            object: "chat.completion".to_string(),
+            created: chrono::Utc::now().timestamp(),
+            model: model_name,
+            choices: vec![],
+            usage,
+            routing_metadata: None,
+        };
+
+        self.log_request_with(
+            AttemptLog { status: "cancelled", error: Some("Cancelled: hedged attempt lost the race"), ..attempt },
+            &cancelled_response,
+            RequestLogExtras { hedged: true, ..extras },
+        ).await;
     }
 
     async fn execute_model(
@@ -746,21 +2807,48 @@ impl Executor {
         rtcros: Option<&mawi_core::rtcros::RtcrosConfig>,
         user_id: &str,
     ) -> Result<UnifiedChatResponse> {
+        self.execute_model_traced(model_id, provider_id, request, rtcros, Some(user_id), &AttemptTrace::default())
+            .await
+            .map(|(response, _)| response)
+    }
+
+    /// Execute a model, returning the response and its time to first token.
+    /// `quota_user` must have quota for the estimated cost; `None` skips the check.
+    /// `trace` is notified of the first token and told which credential was used.
+    async fn execute_model_traced(
+        &self,
+        model_id: &str,
+        provider_id: &str,
+        request: &UnifiedChatRequest,
+        rtcros: Option<&mawi_core::rtcros::RtcrosConfig>,
+        quota_user: Option<&str>,
+        trace: &AttemptTrace<'_>,
+    ) -> Result<(UnifiedChatResponse, Option<i64>)> {
         // Get provider
         let provider = self.get_provider(provider_id).await?;
         // Get model details
         let model = self.get_model(model_id).await?;
 
-        // Create adapter
-        let adapter = self.create_adapter(&provider, &model)?;
+        // Wait for a concurrency slot where the model or provider caps them
+        let _slots = if trace.shadow {
+            None
+        } else {
+            let ticket = self.scheduler.ticket(quota_user, request.api_key_hash.as_deref(), request.priority.as_deref()).await;
+            Some(self.scheduler.admit(&provider, &model, &ticket).await?)
+        };
+
+        // Create adapter with a key from the provider's pool
+        let timeouts = self.timeouts_for(&request.service, &model).await;
+        let credential = self.key_pool.select(&provider, &model).await?;
+        let _ = trace.credential.set(credential.masked());
+        let adapter = self.create_adapter(&provider, &model, &credential, &timeouts)?;
 
         // SMART CONTEXT PRUNING
-        // Use model context window or default to 8192 (safe average)
-        let context_limit = if model.context_window > 0 { model.context_window as usize } else { 8192 };
-        let pruned_original_messages = crate::context_manager::ContextManager::prune_messages(request.messages.clone(), context_limit);
+        // Only against a known context window; unknown windows keep the whole conversation
+        let pruned_original_messages = crate::context_manager::ContextManager::prune_for_window(request.messages.clone(), model.context_window);
         
-        eprintln!("✂️ Context Manager: Prepared {} messages for model '{}' (Window: {})", 
-            pruned_original_messages.len(), model_id, context_limit);
+        eprintln!("✂️ Context Manager: Prepared {} messages for model '{}' (Window: {:?})", 
+            pruned_original_messages.len(), model_id, model.context_window);
 
         // Build messages and inject RTCROS if present
         let mut messages: Vec<mawi_core::types::ChatMessage> = Vec::new(); // define messages vec
@@ -788,13 +2876,15 @@ impl Executor {
         
         eprintln!("📊 Estimated cost for {}: ${:.6}", model.name, estimated_cost);
 
-        // STRICT CHECK:
-        let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
-        let has_enough = quota_manager.check_quota(user_id, 0.01_f64.max(estimated_cost)).await.unwrap_or(false);
-        
-        if !has_enough {
-             eprintln!("🛑 Blocked user {} from running {} (Insufficient quota)", user_id, model.name);
-             return Err(anyhow::anyhow!("Insufficient quota. Estimated: ${:.6}", estimated_cost));
+        // STRICT CHECK (skipped for gateway-funded traffic such as shadow replays):
+        if let Some(user_id) = quota_user {
+            let quota_manager = mawi_core::quota::QuotaManager::new(self.pool.clone());
+            let has_enough = quota_manager.check_quota(user_id, 0.01_f64.max(estimated_cost)).await.unwrap_or(false);
+            
+            if !has_enough {
+                 eprintln!("🛑 Blocked user {} from running {} (Insufficient quota)", user_id, model.name);
+                 return Err(anyhow::anyhow!("Insufficient quota. Estimated: ${:.6}", estimated_cost));
+            }
         }
 
         let chat_request = ChatCompletionRequest {
@@ -819,11 +2909,24 @@ impl Executor {
         // Call the actual provider API
         eprintln!("Calling provider {} for model {}", provider.provider_type, model.name);
         
+        // Stay under the provider key's rate limits, waiting briefly or failing over
+        if trace.shadow && self.upstream_throttle.near_exhaustion(&provider, &credential.api_key) {
+            return Err(crate::upstream_limits::UpstreamThrottled { provider: provider.name.clone(), retry_after: Duration::ZERO }.into());
+        }
+        let max_tokens = chat_request.max_tokens.unwrap_or(0).max(0) as u64;
+        self.upstream_throttle.acquire(&provider, &credential.api_key, estimated_input as u64 + max_tokens).await?;
+
         // Start timer
         let start = std::time::Instant::now();
 
-        let response_text = adapter.chat(&chat_request).await.map_err(|e| {
+        let collected = Self::collect_chat(adapter.as_ref(), &chat_request, &timeouts, trace.first_token).await;
+        self.key_pool.record_outcome(&provider, &credential, collected.as_ref().map(|_| ())).await;
+        let (response_text, ttft_ms) = collected.map_err(|e| {
             eprintln!("Provider call failed: {}", e);
+            // Keep timeouts and upstream HTTP errors typed for retry classification and 504s
+            if retry_class(&e).is_some() || e.is::<UpstreamTimeout>() || e.is::<mawi_core::providers::UpstreamHttpError>() {
+                return e;
+            }
             anyhow::anyhow!("Provider API error: {}", e)
         })?;
         
@@ -859,13 +2962,73 @@ impl Executor {
                     provider: provider.provider_type.clone(),
                     model: model_id.to_string(),
                     fallback_used: false,
+                    service: request.service.clone(),
+                    fallback_path: Vec::new(),
+                    experiment: None,
+                    experiment_arm: None,
+                    budget_downgrade: None,
+                    cache_hit: false,
+                    cache_similarity: None,
+                    guardrail_warnings: Vec::new(),
                 },
+                ensemble: None,
             }),
         };
         
         // Note: Logging is handled by execute_chat method to avoid duplicates
         
-        Ok(response)
+        Ok((response, ttft_ms))
+    }
+
+    /// Collect a streamed chat completion into a single string, enforcing the total,
+    /// first-token and inter-chunk idle timeouts.
+    /// Returns the text and the time to first non-empty chunk in ms.
+    async fn collect_chat(
+        adapter: &dyn ProviderAdapter,
+        request: &ChatCompletionRequest,
+        timeouts: &mawi_core::services::TimeoutPolicy,
+        first_token: Option<FirstTokenSignal<'_>>,
+    ) -> Result<(String, Option<i64>)> {
+        let collect = async {
+            let start = tokio::time::Instant::now();
+            // Connecting and waiting for response headers count toward the first-token limit
+            let first_token_deadline = timeouts.first_token().map(|limit| (limit, start + limit));
+            let mut stream = match first_token_deadline {
+                Some((limit, deadline)) => {
+                    UpstreamTimeout::guard_until(TimeoutPhase::FirstToken, limit, deadline, adapter.stream_chat(request)).await?
+                }
+                None => adapter.stream_chat(request).await?,
+            };
+            let mut content = String::new();
+            let mut ttft_ms = None;
+            let mut chunks = 0;
+
+            loop {
+                let next = stream.next().map(Ok::<_, anyhow::Error>);
+                let chunk = match (ttft_ms, first_token_deadline, timeouts.idle()) {
+                    (None, Some((limit, deadline)), _) => {
+                        UpstreamTimeout::guard_until(TimeoutPhase::FirstToken, limit, deadline, next).await?
+                    }
+                    (_, _, Some(idle)) if chunks > 0 => UpstreamTimeout::guard(TimeoutPhase::Idle, idle, next).await?,
+                    _ => next.await?,
+                };
+                let Some(chunk) = chunk else { break };
+                chunks += 1;
+
+                let text = chunk?;
+                if ttft_ms.is_none() && !text.is_empty() {
+                    ttft_ms = Some(start.elapsed().as_millis() as i64);
+                    if let Some(signal) = first_token {
+                        signal.fire();
+                    }
+                }
+                content.push_str(&text);
+            }
+
+            Ok((content, ttft_ms))
+        };
+
+        UpstreamTimeout::guard(TimeoutPhase::Total, timeouts.total(), collect).await
     }
 
     /// Execute a model directly by ID (used internally, esp. by agentic executor)
@@ -890,6 +3053,11 @@ impl Executor {
             stream: None,
             routing_strategy: None,
             response_format,
+            affinity_key: None,
+            priority: None,
+            cache: None,
+            cache_bypass: false,
+            api_key_hash: None,
         };
 
         self.execute_model(model_id, &model.provider, &request, None, user_id).await
@@ -910,7 +3078,14 @@ impl Executor {
     ) -> Result<std::pin::Pin<Box<dyn Stream<Item = Result<AgenticStreamEvent>> + Send>>> {
         let model = self.get_model(model_id).await?;
         let provider = self.get_provider(&model.provider).await?;
-        let adapter = self.create_adapter(&provider, &model)?;
+        let ticket = self.scheduler.ticket(Some(user_id), None, None).await;
+        let slots = self.scheduler.admit(&provider, &model, &ticket).await?;
+        let timeouts = model.timeouts();
+        let credential = self.key_pool.select(&provider, &model).await?;
+        let adapter = self.create_adapter(&provider, &model, &credential, &timeouts)?;
+
+        let estimated_tokens = messages.iter().map(|m| m.content.len() as u64 / 4).sum::<u64>();
+        self.upstream_throttle.acquire(&provider, &credential.api_key, estimated_tokens).await?;
 
         let request = ChatCompletionRequest {
             model: model.name,
@@ -926,11 +3101,35 @@ impl Executor {
             modality: Some(model.modality.clone()),
         };
 
-        // Convert the Provider's byte stream into AgenticStreamEvents
-        let stream = adapter.stream_chat(&request).await?;
+        // Convert the Provider's byte stream into AgenticStreamEvents. The stream is forwarded
+        // live, so only the first-token and idle timeouts apply
+        let first_token_deadline = timeouts.first_token().map(|limit| (limit, tokio::time::Instant::now() + limit));
+        let opened = match first_token_deadline {
+            Some((limit, deadline)) => {
+                UpstreamTimeout::guard_until(TimeoutPhase::FirstToken, limit, deadline, adapter.stream_chat(&request)).await
+            }
+            None => adapter.stream_chat(&request).await,
+        };
+        self.key_pool.record_outcome(&provider, &credential, opened.as_ref().map(|_| ())).await;
+        let mut stream = opened?;
         
         Ok(Box::pin(async_stream::try_stream! {
-            for await chunk_res in stream {
+            // The key and concurrency slots stay in use until the stream ends
+            let _credential = credential;
+            let _slots = slots;
+            let mut first = true;
+            loop {
+                let next = stream.next().map(Ok::<_, anyhow::Error>);
+                let chunk_res = match (first, first_token_deadline, timeouts.idle()) {
+                    (true, Some((limit, deadline)), _) => {
+                        UpstreamTimeout::guard_until(TimeoutPhase::FirstToken, limit, deadline, next).await?
+                    }
+                    (false, _, Some(idle)) => UpstreamTimeout::guard(TimeoutPhase::Idle, idle, next).await?,
+                    _ => next.await?,
+                };
+                let Some(chunk_res) = chunk_res else { break };
+                first = false;
+
                 let chunk: String = chunk_res?;
                 // Forward raw text chunks (we'll wrap them in ReasoningDelta upstream)
                 yield AgenticStreamEvent::FinalResponse(chunk);
@@ -947,7 +3146,7 @@ impl Executor {
 
         crate::metrics::CACHE_MISSES.inc();
         let service = sqlx::query_as::<_, mawi_core::services::Service>(
-            "SELECT name, service_type, description, strategy, guardrails, created_at FROM services WHERE name = $1"
+            "SELECT * FROM services WHERE name = $1"
         )
         .bind(name)
         .fetch_one(&self.pool)
@@ -959,6 +3158,9 @@ impl Executor {
         Ok(service)
     }
     
+    /// Healthy models in rotation for a service. Models that are drained, directly or through
+    /// their provider, are left out; drains take effect within the cache TTL unless
+    /// `invalidate(Invalidation::Maintenance)` is called.
     async fn get_service_models_with_weights(&self, service_name: &str) -> Result<Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>> {
         if let Some(models) = self.service_models_cache.get(service_name).await {
             crate::metrics::CACHE_HITS.inc();
@@ -966,6 +3168,54 @@ impl Executor {
         }
 
         crate::metrics::CACHE_MISSES.inc();
+        let result = self.load_service_models(service_name, false).await?;
+
+        // 3. Update cache (auto-eviction handled by moka)
+        self.service_models_cache.insert(service_name.to_string(), result.clone()).await;
+
+        Ok(result)
+    }
+
+    /// Drop cached copies of a config change here and on every other replica
+    pub async fn invalidate(&self, change: crate::config_sync::Invalidation) {
+        self.apply_invalidation(&change).await;
+        crate::config_sync::publish(&self.pool, &change).await;
+    }
+
+    /// Drop this replica's cached copies of a config change
+    pub async fn apply_invalidation(&self, change: &crate::config_sync::Invalidation) {
+        use crate::config_sync::Invalidation;
+        match change {
+            Invalidation::Service(name) => {
+                self.service_cache.invalidate(name).await;
+                self.service_models_cache.invalidate(name).await;
+            }
+            // Service model lists carry provider ids, so any list may hold the model
+            Invalidation::Model(id) => {
+                self.model_cache.invalidate(id).await;
+                self.service_models_cache.invalidate_all();
+            }
+            Invalidation::Provider(id) => {
+                self.provider_cache.invalidate(id).await;
+                self.key_pool.invalidate(id).await;
+                self.service_models_cache.invalidate_all();
+            }
+            Invalidation::CircuitPolicy(key) => self.circuit_policy_cache.invalidate(key).await,
+            Invalidation::Maintenance => self.service_models_cache.invalidate_all(),
+            Invalidation::Guardrail(id) => self.guardrails.invalidate(id).await,
+            Invalidation::All => {
+                self.model_cache.invalidate_all();
+                self.provider_cache.invalidate_all();
+                self.service_cache.invalidate_all();
+                self.service_models_cache.invalidate_all();
+                self.circuit_policy_cache.invalidate_all();
+                self.key_pool.invalidate_all();
+                self.guardrails.invalidate_all();
+            }
+        }
+    }
+
+    async fn load_service_models(&self, service_name: &str, include_drained: bool) -> Result<Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>> {
         let models = sqlx::query_as::<_, (String, i32, String, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>)>(
             "SELECT sm.model_id, sm.weight, m.provider_id,
                     sm.rtcros_role, sm.rtcros_task, sm.rtcros_context, 
@@ -1000,11 +3250,12 @@ impl Executor {
             };
             (model_id.clone(), provider_id.clone(), *weight as i32, config)
         }).collect();
+        if include_drained {
+            return Ok(result);
+        }
 
-        // 3. Update cache (auto-eviction handled by moka)
-        self.service_models_cache.insert(service_name.to_string(), result.clone()).await;
-
-        Ok(result)
+        let windows = crate::maintenance::open_windows(&self.pool).await?;
+        Ok(without_drained(result, &windows, chrono::Utc::now().timestamp()))
     }
 
     /// Get ALL service models (including unhealthy) for leader status checks
@@ -1079,6 +3330,7 @@ impl Executor {
     }
 
     async fn update_model_health(&self, model_id: &str, is_success: bool, latency_ms: i64, error_msg: Option<String>) {
+        self.rollups.record(crate::slo::SCOPE_MODEL, model_id, is_success, latency_ms);
         let timestamp = chrono::Utc::now().timestamp();
         if is_success {
              let _ = sqlx::query(
@@ -1115,6 +3367,107 @@ impl Executor {
         }
     }
 
+    /// Resolve the `model` field of a media request, which may name a service or a model,
+    /// to ordered candidates. Services dispatch to the modality sub-pool serving `kind`,
+    /// ordered by that sub-pool's strategy; anything else is treated as a model ID.
+    /// Returns the resolved service, if any, alongside the candidates.
+    async fn resolve_media_candidates(
+        &self,
+        target: &str,
+        kind: DispatchKind,
+    ) -> Result<(Option<mawi_core::services::Service>, Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>)> {
+        let service = match self.get_service(target).await {
+            Ok(service) => service,
+            Err(_) => {
+                let model = self.get_model(target).await.map_err(|_| {
+                    anyhow::anyhow!("'{}' is neither a valid Service nor a valid Model", target)
+                })?;
+                return Ok((None, vec![(model.id, model.provider, 100, mawi_core::rtcros::RtcrosConfig::default())]));
+            }
+        };
+
+        if matches!(service.service_type, mawi_core::services::ServiceType::Agentic) {
+            return Err(RequestRejection::bad_request(format!(
+                "Service '{}' is agentic and cannot serve {} requests", service.name, kind
+            )).into());
+        }
+
+        let mut candidates = Vec::new();
+        for candidate in self.get_service_models_with_weights(&service.name).await? {
+            if let Ok(model) = self.get_model(&candidate.0).await {
+                if kind.accepts(&model.modality, &model.worker_type) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return Err(RequestRejection::bad_request(format!(
+                "Service '{}' has no healthy {} models for {} requests", service.name, kind.modality(), kind
+            )).into());
+        }
+
+        let strategy = service.strategy_for(kind.modality()).to_string();
+        debug!(service = %service.name, modality = kind.modality(), strategy = %strategy, "dispatching to modality sub-pool");
+        let candidates = self.order_by_strategy(&service, &strategy, candidates).await;
+        Ok((Some(service), candidates))
+    }
+
+    /// Resolve a media request target to the model ID that will serve it
+    async fn resolve_media_model(&self, target: &str, kind: DispatchKind) -> Result<String> {
+        let (_, candidates) = self.resolve_media_candidates(target, kind).await?;
+        candidates
+            .into_iter()
+            .next()
+            .map(|(model_id, _, _, _)| model_id)
+            .ok_or_else(|| anyhow::anyhow!("No models available for '{}'", target))
+    }
+
+    /// Order candidates by a routing strategy (the service strategy, or a modality sub-pool's)
+    async fn order_by_strategy(
+        &self,
+        service: &mawi_core::services::Service,
+        strategy: &str,
+        models: Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)>,
+    ) -> Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)> {
+        let strategy_str = strategy.to_lowercase();
+        match strategy_str.as_str() {
+            "health" | "leader-worker" | "priority" | "highest_quality" => {
+                if matches!(service.service_type, mawi_core::services::ServiceType::Pool) && models.len() > 1 {
+                    debug!(strategy = %strategy_str, "using weighted selection for pool service");
+                    self.select_weighted(&models)
+                } else {
+                    debug!(strategy = %strategy_str, "using priority failover strategy");
+                    models  // Ordered by position
+                }
+            },
+            "weighted_random" | "weighted" | "random" | "pool" => {
+                debug!("using weighted random strategy");
+                self.select_weighted(&models)
+            },
+            "least_cost" => {
+                debug!("using least cost strategy");
+                self.select_least_cost(&models).await
+            },
+            "least_latency" | "speed" => {
+                debug!("using least latency strategy");
+                self.select_least_latency(&models).await
+            },
+            "ensemble" => {
+                debug!("using ensemble strategy, members in position order");
+                models
+            },
+            _ => {
+                if matches!(service.service_type, mawi_core::services::ServiceType::Pool) {
+                    debug!(from = %strategy_str, "defaulting pool service to weighted strategy");
+                    self.select_weighted(&models)
+                } else {
+                    debug!(strategy = %strategy_str, "unknown strategy, using health");
+                    models
+                }
+            }
+        }
+    }
+
     fn select_weighted(&self, models: &[(String, String, i32, mawi_core::rtcros::RtcrosConfig)]) -> Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)> {
         if models.is_empty() {
             return vec![];
@@ -1271,14 +3624,41 @@ impl Executor {
         start_time: std::time::Instant,
         user_id: Option<&str>,
     ) {
+        self.log_request_with(
+            AttemptLog {
+                key_id,
+                service,
+                model_id,
+                provider_id,
+                failover_count,
+                status,
+                error,
+                start_time,
+                user_id,
+            },
+            response,
+            RequestLogExtras::default(),
+        ).await;
+    }
+
+    /// Same as `log_request`, with additional per-attempt details
+    pub async fn log_request_with(
+        &self,
+        attempt: AttemptLog<'_>,
+        response: &UnifiedChatResponse,
+        extras: RequestLogExtras,
+    ) {
+        let AttemptLog { key_id, service, model_id, provider_id, failover_count, status, error, start_time, user_id } = attempt;
         let provider = self.get_provider(provider_id).await.ok();
         
         // Calculate latency in microseconds
         let latency_us = start_time.elapsed().as_micros() as i64;
         let latency_ms = (latency_us / 1000) as i64;
         
-        // Calculate cost based on token usage and model pricing
-        let cost_usd = if let Some(usage) = &response.usage {
+        // Calculate cost based on token usage and model pricing, unless priced per request
+        let cost_usd = if let Some(cost) = extras.cost_usd {
+            Some(cost)
+        } else if let Some(usage) = &response.usage {
             crate::pricing::PRICING.calculate_cost(
                 &response.model,
                 usage.prompt_tokens as i64,
@@ -1289,26 +3669,7 @@ impl Executor {
         };
 
         // Sanitize error message to hide API keys
-        let sanitized_error = error.map(|e| {
-            // lazy_static regex for performance
-            // Matches OpenAI (sk-...) and Google (AIza...) style keys
-            use regex::Regex;
-            use std::sync::OnceLock;
-            
-            static API_KEY_REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-            let re = API_KEY_REGEX.get_or_init(|| {
-                regex::Regex::new(r"(sk-[a-zA-Z0-9\-_]{20,}|AIza[a-zA-Z0-9\-_]{20,})").expect("Invalid regex")
-            });
-
-            re.replace_all(e, |caps: &regex::Captures| {
-                let key = &caps[0];
-                if key.len() > 7 {
-                    format!("{}...", &key[0..7])
-                } else {
-                    "***".to_string()
-                }
-            }).to_string()
-        });
+        let sanitized_error = error.map(sanitize_error);
         
         let pool = self.pool.clone();
         
@@ -1340,34 +3701,38 @@ impl Executor {
             failover_count,
             cost_usd: cost_usd_owned,
             user_id: user_id_owned.clone(), // Clone needed for charging below
+            hedged: extras.hedged,
+            ttft_ms: extras.ttft_ms,
+            response_id: response.id.clone(),
+            experiment: extras.experiment,
+            experiment_arm: extras.experiment_arm,
+            modality: extras.modality.unwrap_or_else(|| "text".to_string()),
+            retry_count: extras.retry_count,
+            credential: extras.credential,
+            cache_hit: extras.cache_hit,
+            cache_similarity: extras.cache_similarity,
         });
         
         // Charge user via worker pool (bounded concurrency)
-        if let Some(cost) = cost_usd_owned {
+        if let Some(cost) = cost_usd_owned.filter(|cost| *cost > 0.0) {
             if let Some(uid) = user_id_owned.as_deref().or(key_id_owned.as_deref()) {
                 self.quota_worker.charge(uid.to_string(), cost, pool.clone());
             }
         }
     }
 
-    /// Create provider adapter with resolved credentials
-    fn create_adapter(&self, provider: &mawi_core::models::Provider, model: &mawi_core::models::Model) -> Result<Arc<dyn ProviderAdapter>> {
-        // Resolve credentials (prefer model override)
-        let raw_api_key = model.api_key.as_deref()
-            .or(provider.api_key.as_deref())
-            .unwrap_or("")
-            .to_string();
-
-        let api_key = if !raw_api_key.is_empty() {
-             mawi_core::security::decrypt_key(&raw_api_key).unwrap_or_else(|e| {
-                 eprintln!("⚠️ Failed to decrypt API key for provider {}: {}", provider.name, e);
-                 raw_api_key.clone() // Fallback to raw (in case of migration or plain env vars)
-             })
-        } else {
-             String::new()
-        };
-
-        let base_url = model.api_endpoint.as_deref()
+    /// Create provider adapter for a credential from the key pool
+    fn create_adapter(
+        &self,
+        provider: &mawi_core::models::Provider,
+        model: &mawi_core::models::Model,
+        credential: &crate::key_pool::Credential,
+        timeouts: &mawi_core::services::TimeoutPolicy,
+    ) -> Result<Arc<dyn ProviderAdapter>> {
+        let api_key = credential.api_key.clone();
+
+        let base_url = credential.api_endpoint.as_deref()
+            .or(model.api_endpoint.as_deref())
             .or(provider.api_endpoint.as_deref())
             .unwrap_or("")
             .to_string();
@@ -1376,35 +3741,270 @@ impl Executor {
             .or(provider.api_version.as_deref())
             .map(|s| s.to_string());
 
+        // A half-open probe through this adapter may take this long to report back
+        self.circuit_breaker.cover_timeout(timeouts.total());
+        let http_client = self.client_for(timeouts);
+
         match provider.provider_type.to_lowercase().as_str() {
-            "openai" => Ok(Arc::new(OpenAIAdapter::new(self.http_client.clone(), api_key))),
+            "openai" => Ok(Arc::new(OpenAIAdapter::new(http_client.clone(), api_key))),
             "azure" => {
                 if base_url.is_empty() {
                     anyhow::bail!("Azure provider requires api_endpoint (Base URL)");
                 }
-                Ok(Arc::new(AzureProvider::new(self.http_client.clone(), api_key, base_url, api_version)))
+                Ok(Arc::new(AzureProvider::new(http_client.clone(), api_key, base_url, api_version)))
             },
-            "google" | "gemini" => Ok(Arc::new(GeminiAdapter::new(self.http_client.clone(), api_key))),
-            "anthropic" => Ok(Arc::new(AnthropicAdapter::new(self.http_client.clone(), api_key))),
-            "xai" => Ok(Arc::new(XaiAdapter::new(self.http_client.clone(), api_key))),
-            "mistral" => Ok(Arc::new(MistralAdapter::new(self.http_client.clone(), api_key))),
-            "perplexity" => Ok(Arc::new(PerplexityAdapter::new(self.http_client.clone(), api_key))),
-            "deepseek" => Ok(Arc::new(DeepSeekAdapter::new(self.http_client.clone(), api_key))),
-            "elevenlabs" => Ok(Arc::new(ElevenLabsAdapter::new(self.http_client.clone(), api_key))),
+            "google" | "gemini" => Ok(Arc::new(GeminiAdapter::new(http_client.clone(), api_key))),
+            "anthropic" => Ok(Arc::new(AnthropicAdapter::new(http_client.clone(), api_key))),
+            "xai" => Ok(Arc::new(XaiAdapter::new(http_client.clone(), api_key))),
+            "mistral" => Ok(Arc::new(MistralAdapter::new(http_client.clone(), api_key))),
+            "perplexity" => Ok(Arc::new(PerplexityAdapter::new(http_client.clone(), api_key))),
+            "deepseek" => Ok(Arc::new(DeepSeekAdapter::new(http_client.clone(), api_key))),
+            "elevenlabs" => Ok(Arc::new(ElevenLabsAdapter::new(http_client.clone(), api_key))),
             "selfhosted" | "ollama" => {
                  // Self-Hosted / Ollama
                 if base_url.is_empty() {
                      if provider.provider_type == "ollama" {
                          // Default for ollama
-                         Ok(Arc::new(SelfHostedAdapter::new(self.http_client.clone(), api_key, "http://localhost:11434".to_string())))
+                         Ok(Arc::new(SelfHostedAdapter::new(http_client.clone(), api_key, "http://localhost:11434".to_string())))
                      } else {
                         anyhow::bail!("Self-hosted provider requires api_endpoint (Base URL)");
                      }
                 } else {
-                    Ok(Arc::new(SelfHostedAdapter::new(self.http_client.clone(), api_key, base_url)))
+                    Ok(Arc::new(SelfHostedAdapter::new(http_client.clone(), api_key, base_url)))
                 }
             },
             _ => anyhow::bail!("Unsupported provider type: {}", provider.provider_type),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Sets its flag when dropped, to see that a losing attempt was cancelled
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn attempt<'a>(idx: usize, after: Duration, flag: Option<Arc<AtomicBool>>) -> futures::future::BoxFuture<'a, usize> {
+        async move {
+            let _flag = flag.map(DropFlag);
+            tokio::time::sleep(after).await;
+            idx
+        }.boxed()
+    }
+
+    /// Executor whose database is unreachable; lookups fail fast and tests seed its caches
+    fn executor() -> Executor {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(50))
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        Executor::new(pool, Arc::new(RwLock::new(McpManager::new())))
+    }
+
+    async fn seed_model(executor: &Executor, id: &str, modality: &str) {
+        let model: mawi_core::models::Model = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "provider": "p1",
+            "modality": modality,
+            "tier": "standard",
+            "avg_latency_ms": 0,
+            "avg_ttft_ms": 0,
+            "max_tps": 0,
+            "tier_required": "A",
+            "worker_type": modality,
+        }))
+        .unwrap();
+        executor.model_cache.insert(id.to_string(), model).await;
+    }
+
+    /// Provider `p1` with two pooled keys handed out round robin
+    async fn seed_provider(executor: &Executor) -> mawi_core::models::Provider {
+        let provider: mawi_core::models::Provider = serde_json::from_value(serde_json::json!({
+            "id": "p1",
+            "name": "openai",
+            "provider_type": "openai",
+            "api_key": "provider-key",
+            "key_selection": "round_robin",
+        }))
+        .unwrap();
+        executor.provider_cache.insert("p1".to_string(), provider.clone()).await;
+        executor.key_pool.seed("p1", &[("k1", "secret-1"), ("k2", "secret-2")]).await;
+        provider
+    }
+
+    fn candidates(ids: &[&str]) -> Vec<(String, String, i32, mawi_core::rtcros::RtcrosConfig)> {
+        ids.iter().map(|id| (id.to_string(), "p1".to_string(), 100, Default::default())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_media_failover_moves_to_next_candidate() {
+        let executor = executor();
+        seed_provider(&executor).await;
+        seed_model(&executor, "m1", "image").await;
+        seed_model(&executor, "m2", "image").await;
+
+        let tried = std::sync::Mutex::new(Vec::new());
+        let served = executor.media_failover("images", DispatchKind::ImageGeneration, "u1", None, &candidates(&["m1", "m2"]), |_, model| {
+            tried.lock().unwrap().push(model.id.clone());
+            async move {
+                match model.id.as_str() {
+                    "m1" => Err(anyhow::anyhow!("Server Error (500)")),
+                    _ => Ok((model.id, 0.01)),
+                }
+            }
+        }).await.unwrap();
+
+        assert_eq!(served, "m2");
+        assert_eq!(*tried.lock().unwrap(), ["m1", "m2"]);
+
+        // Once every candidate has failed, the last upstream error is returned
+        let error = executor.media_failover("images", DispatchKind::ImageGeneration, "u1", None, &candidates(&["m2"]), |_, _| async {
+            Err::<((), f64), _>(anyhow::anyhow!("Server Error (503)"))
+        }).await.unwrap_err();
+        assert_eq!(error.to_string(), "Server Error (503)");
+    }
+
+    #[tokio::test]
+    async fn test_video_create_poll_and_fetch_use_the_pinned_key() {
+        let executor = executor();
+        let provider = seed_provider(&executor).await;
+        seed_model(&executor, "v1", "video").await;
+        let model = executor.get_model("v1").await.unwrap();
+
+        for _ in 0..3 {
+            let create = executor.media_credential(DispatchKind::VideoGeneration, &provider, &model).await.unwrap();
+            assert_eq!(create.key_id.as_deref(), Some("k1"));
+            // Poll and fetch resolve their key the same way
+            let (_, follow_up, _) = executor.video_job_target("v1", None).await.unwrap();
+            assert_eq!(follow_up.key_id.as_deref(), Some("k1"));
+        }
+
+        // Other media calls rotate through the pool
+        let first = executor.media_credential(DispatchKind::ImageGeneration, &provider, &model).await.unwrap();
+        let second = executor.media_credential(DispatchKind::ImageGeneration, &provider, &model).await.unwrap();
+        assert_ne!(first.key_id, second.key_id);
+    }
+
+    fn window(scope: &str, resource_id: &str, starts_at: i64, ends_at: Option<i64>) -> crate::maintenance::MaintenanceWindow {
+        crate::maintenance::MaintenanceWindow {
+            id: format!("{}:{}", scope, resource_id),
+            scope: scope.to_string(),
+            resource_id: resource_id.to_string(),
+            starts_at,
+            ends_at,
+            reason: None,
+            created_by: None,
+            active: false,
+        }
+    }
+
+    fn in_rotation(windows: &[crate::maintenance::MaintenanceWindow], now: i64) -> Vec<String> {
+        let models = vec![
+            ("m1".to_string(), "p1".to_string(), 50, Default::default()),
+            ("m2".to_string(), "p2".to_string(), 50, Default::default()),
+        ];
+        without_drained(models, windows, now).into_iter().map(|(model_id, _, _, _)| model_id).collect()
+    }
+
+    #[test]
+    fn test_drained_model_leaves_rotation_until_its_window_ends() {
+        let windows = [window("model", "m1", 1_000, Some(2_000))];
+        assert_eq!(in_rotation(&windows, 1_000), ["m2"]);
+        assert_eq!(in_rotation(&windows, 1_999), ["m2"]);
+        assert_eq!(in_rotation(&windows, 2_000), ["m1", "m2"]);
+
+        // An open-ended drain lasts until the model is undrained
+        assert_eq!(in_rotation(&[window("model", "m1", 1_000, None)], 1_000_000), ["m2"]);
+    }
+
+    #[test]
+    fn test_scheduled_provider_window_drains_its_models_only_while_it_runs() {
+        let windows = [window("provider", "p2", 5_000, Some(6_000))];
+        assert_eq!(in_rotation(&windows, 4_999), ["m1", "m2"]);
+        assert_eq!(in_rotation(&windows, 5_000), ["m1"]);
+        assert_eq!(in_rotation(&windows, 6_000), ["m1", "m2"]);
+    }
+
+    #[test]
+    fn test_rejected_requests_do_not_fall_back() {
+        let rejected: anyhow::Error = RequestRejection::bad_request("No model in service 'a' can serve this request").into();
+        assert!(!warrants_fallback(&rejected));
+        let blocked: anyhow::Error = RequestRejection {
+            status: poem::http::StatusCode::UNPROCESSABLE_ENTITY,
+            message: "Response blocked".to_string(),
+        }.into();
+        assert!(!warrants_fallback(&blocked));
+
+        assert!(warrants_fallback(&anyhow::anyhow!("Server Error (502)")));
+        assert!(warrants_fallback(&UpstreamTimeout { phase: TimeoutPhase::Total, limit: Duration::from_secs(1) }.into()));
+        assert!(warrants_fallback(&crate::upstream_limits::UpstreamThrottled {
+            provider: "p".to_string(),
+            retry_after: Duration::from_secs(1),
+        }.into()));
+    }
+
+    #[tokio::test]
+    async fn test_hedge_due_after_delay_without_first_token() {
+        let first_token = tokio::sync::Notify::new();
+        let first_tokens = [AtomicBool::new(false), AtomicBool::new(false)];
+        let mut race = HedgeRace::new();
+        race.launch(0, attempt(0, Duration::from_secs(5), None));
+
+        let seen = race.first_token_seen(&first_tokens);
+        assert!(race.can_hedge(2, seen, true));
+        let started = std::time::Instant::now();
+        let event = race.next(Duration::from_millis(20), true, &first_token, seen).await;
+        assert!(matches!(event, Some(AttemptEvent::HedgeDue)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_no_hedge_once_a_running_attempt_streams() {
+        let first_token = tokio::sync::Notify::new();
+        let first_tokens = [AtomicBool::new(true), AtomicBool::new(false)];
+        let mut race = HedgeRace::new();
+        race.launch(0, attempt(0, Duration::from_millis(10), None));
+        assert!(!race.can_hedge(2, race.first_token_seen(&first_tokens), true));
+
+        // A token from an attempt that already failed doesn't count
+        let Some(AttemptEvent::Finished(failed)) = race.next(Duration::ZERO, false, &first_token, true).await else {
+            panic!("expected the attempt to finish");
+        };
+        race.finished(*failed);
+        race.launch(1, attempt(1, Duration::from_secs(5), None));
+        assert!(race.can_hedge(2, race.first_token_seen(&first_tokens), true));
+
+        // Nor does hedging go past the fan-out or the candidate list
+        assert!(!race.can_hedge(1, false, true));
+        assert!(!race.can_hedge(2, false, false));
+    }
+
+    #[tokio::test]
+    async fn test_winner_cancels_losing_attempts() {
+        let first_token = tokio::sync::Notify::new();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut race = HedgeRace::new();
+        race.launch(0, attempt(0, Duration::from_secs(5), Some(cancelled.clone())));
+        race.launch(1, attempt(1, Duration::from_millis(10), None));
+
+        let Some(AttemptEvent::Finished(winner)) = race.next(Duration::from_secs(5), false, &first_token, true).await else {
+            panic!("expected the hedge to finish first");
+        };
+        assert_eq!(*winner, 1);
+        race.finished(*winner);
+
+        let losers: Vec<usize> = race.cancel_rest().into_iter().map(|(idx, _)| idx).collect();
+        assert_eq!(losers, [0]);
+        assert!(cancelled.load(Ordering::Relaxed));
+        assert!(race.is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+//! Guardrails Admin API
+//!
+//! Define the guardrails services attach by ID in their `guardrails` list. Changes
+//! apply on every replica right away. Admin only.
+
+use poem_openapi::{
+    param::Path,
+    payload::Json,
+    Object, OpenApi, Tags,
+};
+use poem::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::require_admin;
+use crate::config_sync::Invalidation;
+use crate::executor::Executor;
+use crate::guardrails::{AppliesTo, CompiledGuardrail, GuardrailAction, GuardrailRow, GuardrailRule};
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Guardrails
+    Guardrails,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct Guardrail {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub rule: GuardrailRule,
+    pub action: GuardrailAction,
+    /// Stages checked; the rule's default when unset
+    pub applies_to: Option<AppliesTo>,
+    pub enabled: bool,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    /// Services that attach the guardrail
+    pub services: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct CreateGuardrail {
+    pub name: String,
+    pub description: Option<String>,
+    pub rule: GuardrailRule,
+    pub action: GuardrailAction,
+    /// Stages to check: input, output or both. Defaults to both for deny lists and secrets,
+    /// input for length and topic rules, output for JSON schemas.
+    pub applies_to: Option<AppliesTo>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct UpdateGuardrail {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub rule: Option<GuardrailRule>,
+    pub action: Option<GuardrailAction>,
+    pub applies_to: Option<AppliesTo>,
+    pub enabled: Option<bool>,
+}
+
+pub struct GuardrailsApi {
+    pub executor: Arc<Executor>,
+}
+
+fn db_error(e: sqlx::Error) -> poem::Error {
+    poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn not_found() -> poem::Error {
+    poem::Error::from_string("Guardrail not found", poem::http::StatusCode::NOT_FOUND)
+}
+
+fn bad_request(message: String) -> poem::Error {
+    poem::Error::from_string(message, poem::http::StatusCode::BAD_REQUEST)
+}
+
+impl GuardrailsApi {
+    async fn info(&self, row: GuardrailRow) -> Result<Guardrail> {
+        let services = crate::guardrails::attached_services(&self.executor.pool, &row.id).await.map_err(db_error)?;
+        let rule = serde_json::from_str(&row.rule)
+            .map_err(|e| poem::Error::from_string(format!("Stored rule is invalid: {}", e), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+        Ok(Guardrail {
+            action: GuardrailAction::parse(&row.action).unwrap_or(GuardrailAction::Block),
+            applies_to: row.applies_to.as_deref().and_then(AppliesTo::parse),
+            rule,
+            services,
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            enabled: row.enabled,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+
+    async fn fetch(&self, id: &str) -> Result<GuardrailRow> {
+        sqlx::query_as::<_, GuardrailRow>("SELECT * FROM guardrails WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.executor.pool)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(not_found)
+    }
+}
+
+#[OpenApi]
+impl GuardrailsApi {
+    /// List guardrails
+    #[oai(path = "/guardrails", method = "get", tag = "ApiTags::Guardrails")]
+    async fn list_guardrails(&self, req: &poem::Request) -> Result<Json<Vec<Guardrail>>> {
+        require_admin(req)?;
+        let rows = sqlx::query_as::<_, GuardrailRow>("SELECT * FROM guardrails ORDER BY name, id")
+            .fetch_all(&self.executor.pool)
+            .await
+            .map_err(db_error)?;
+        let mut guardrails = Vec::with_capacity(rows.len());
+        for row in rows {
+            guardrails.push(self.info(row).await?);
+        }
+        Ok(Json(guardrails))
+    }
+
+    /// Get a guardrail
+    #[oai(path = "/guardrails/:id", method = "get", tag = "ApiTags::Guardrails")]
+    async fn get_guardrail(&self, id: Path<String>, req: &poem::Request) -> Result<Json<Guardrail>> {
+        require_admin(req)?;
+        let row = self.fetch(&id).await?;
+        Ok(Json(self.info(row).await?))
+    }
+
+    /// Define a guardrail
+    #[oai(path = "/guardrails", method = "post", tag = "ApiTags::Guardrails")]
+    async fn create_guardrail(&self, body: Json<CreateGuardrail>, req: &poem::Request) -> Result<Json<Guardrail>> {
+        require_admin(req)?;
+        let name = body.name.trim();
+        if name.is_empty() {
+            return Err(bad_request("name is required".to_string()));
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        CompiledGuardrail::compile(&id, name, &body.rule, body.action, body.applies_to).map_err(bad_request)?;
+
+        let now = chrono::Utc::now().timestamp();
+        let row = sqlx::query_as::<_, GuardrailRow>(
+            "INSERT INTO guardrails (id, name, description, rule, action, applies_to, enabled, created_at, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8) RETURNING *"
+        )
+        .bind(&id)
+        .bind(name)
+        .bind(&body.description)
+        .bind(serde_json::to_string(&body.rule).unwrap_or_default())
+        .bind(body.action.as_str())
+        .bind(body.applies_to.map(|a| a.as_str()))
+        .bind(body.enabled.unwrap_or(true))
+        .bind(now)
+        .fetch_one(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        eprintln!("🛡️ Created guardrail '{}' ({})", name, id);
+        Ok(Json(self.info(row).await?))
+    }
+
+    /// Change a guardrail
+    #[oai(path = "/guardrails/:id", method = "put", tag = "ApiTags::Guardrails")]
+    async fn update_guardrail(&self, id: Path<String>, body: Json<UpdateGuardrail>, req: &poem::Request) -> Result<Json<Guardrail>> {
+        require_admin(req)?;
+        let current = self.fetch(&id).await?;
+        let current_rule: GuardrailRule = serde_json::from_str(&current.rule).map_err(|e| bad_request(format!("Stored rule is invalid: {}", e)))?;
+
+        let name = body.name.as_deref().map(str::trim).unwrap_or(&current.name).to_string();
+        if name.is_empty() {
+            return Err(bad_request("name cannot be empty".to_string()));
+        }
+        let rule = body.rule.clone().unwrap_or(current_rule);
+        let action = body.action.or_else(|| GuardrailAction::parse(&current.action)).unwrap_or(GuardrailAction::Block);
+        let applies_to = body.applies_to.or_else(|| current.applies_to.as_deref().and_then(AppliesTo::parse));
+        CompiledGuardrail::compile(&id.0, &name, &rule, action, applies_to).map_err(bad_request)?;
+
+        let row = sqlx::query_as::<_, GuardrailRow>(
+            "UPDATE guardrails SET name = $2, description = $3, rule = $4, action = $5, applies_to = $6,
+                enabled = $7, updated_at = $8
+             WHERE id = $1 RETURNING *"
+        )
+        .bind(&id.0)
+        .bind(&name)
+        .bind(body.description.clone().or(current.description))
+        .bind(serde_json::to_string(&rule).unwrap_or_default())
+        .bind(action.as_str())
+        .bind(applies_to.map(|a| a.as_str()))
+        .bind(body.enabled.unwrap_or(current.enabled))
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        self.executor.invalidate(Invalidation::Guardrail(id.0.clone())).await;
+        Ok(Json(self.info(row).await?))
+    }
+
+    /// Delete a guardrail no service attaches
+    #[oai(path = "/guardrails/:id", method = "delete", tag = "ApiTags::Guardrails")]
+    async fn delete_guardrail(&self, id: Path<String>, req: &poem::Request) -> Result<()> {
+        require_admin(req)?;
+        let services = crate::guardrails::attached_services(&self.executor.pool, &id).await.map_err(db_error)?;
+        if !services.is_empty() {
+            return Err(poem::Error::from_string(
+                format!("Guardrail is attached to: {}", services.join(", ")),
+                poem::http::StatusCode::CONFLICT,
+            ));
+        }
+        let result = sqlx::query("DELETE FROM guardrails WHERE id = $1")
+            .bind(&id.0)
+            .execute(&self.executor.pool)
+            .await
+            .map_err(db_error)?;
+        if result.rows_affected() == 0 {
+            return Err(not_found());
+        }
+
+        self.executor.invalidate(Invalidation::Guardrail(id.0.clone())).await;
+        Ok(())
+    }
+}
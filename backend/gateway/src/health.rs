@@ -4,88 +4,195 @@ use tokio::time::interval;
 use anyhow::Result;
 use poem::{handler, IntoResponse};
 use futures::{stream, StreamExt};
+use rand::Rng;
 use std::sync::Arc;
 
+use crate::key_pool::KeyPool;
+
 #[handler]
 pub fn health_check() -> impl IntoResponse {
     "OK"
 }
 
-#[allow(dead_code)]
+/// Failed probes in a row before a model leaves rotation, as on the passive path
+const UNHEALTHY_AFTER_FAILURES: i32 = 5;
+
+/// Active health monitor settings, read from the environment:
+/// `HEALTH_CHECK_ENABLED`, `HEALTH_CHECK_INTERVAL_SECS`, `HEALTH_CHECK_CONCURRENCY`,
+/// `HEALTH_CHECK_JITTER_SECS` and `HEALTH_HISTORY_RETENTION_DAYS`
+#[derive(Debug, Clone)]
+pub struct HealthMonitorConfig {
+    pub enabled: bool,
+    pub interval: Duration,
+    /// Probes in flight at once
+    pub concurrency: usize,
+    /// Each probe waits a random delay up to this long so providers aren't hit in bursts
+    pub jitter: Duration,
+    pub history_retention_days: i64,
+}
+
+impl Default for HealthMonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(300),
+            concurrency: 10,
+            jitter: Duration::from_secs(30),
+            history_retention_days: 7,
+        }
+    }
+}
+
+impl HealthMonitorConfig {
+    pub fn from_env() -> Self {
+        fn env<T: std::str::FromStr>(key: &str) -> Option<T> {
+            std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            enabled: env::<bool>("HEALTH_CHECK_ENABLED").unwrap_or(defaults.enabled),
+            interval: env::<u64>("HEALTH_CHECK_INTERVAL_SECS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.interval),
+            concurrency: env::<usize>("HEALTH_CHECK_CONCURRENCY")
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.concurrency),
+            jitter: env::<u64>("HEALTH_CHECK_JITTER_SECS").map(Duration::from_secs).unwrap_or(defaults.jitter),
+            history_retention_days: env::<i64>("HEALTH_HISTORY_RETENTION_DAYS").unwrap_or(defaults.history_retention_days),
+        }
+    }
+}
+
+/// How a model was probed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeKind {
+    /// One-token generation: proves the model actually serves requests
+    Completion,
+    /// Non-generating model lookup / model list: proves reachability and credentials for free
+    ModelList,
+}
+
+impl ProbeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeKind::Completion => "completion",
+            ProbeKind::ModelList => "model_list",
+        }
+    }
+
+    /// Text models get a real completion; image, audio and video models would be billed
+    /// per generation, so they get a model-list call instead. Providers without a
+    /// completion probe (ElevenLabs, Ollama) always use their list endpoint.
+    fn for_model(provider_type: &str, modality: &str) -> Self {
+        match provider_type {
+            "elevenlabs" | "ollama" => ProbeKind::ModelList,
+            _ if modality == "text" => ProbeKind::Completion,
+            _ => ProbeKind::ModelList,
+        }
+    }
+}
+
+/// One stored probe result
+#[derive(Debug, Clone, sqlx::FromRow, poem_openapi::Object)]
+pub struct HealthHistoryEntry {
+    pub checked_at: i64,
+    pub is_healthy: bool,
+    pub response_time_ms: Option<i64>,
+    /// completion or model_list
+    pub probe: String,
+    pub error: Option<String>,
+    /// healthy, failed or rate_limited
+    pub outcome: String,
+}
+
+/// Probe history for a model, newest first
+pub async fn health_history(pool: &PgPool, model_id: &str, since: Option<i64>, limit: i64) -> Result<Vec<HealthHistoryEntry>> {
+    let entries = sqlx::query_as::<_, HealthHistoryEntry>(
+        "SELECT checked_at, is_healthy = 1 AS is_healthy, response_time_ms, probe, error, outcome
+         FROM model_health_history
+         WHERE model_id = $1 AND checked_at >= $2
+         ORDER BY checked_at DESC
+         LIMIT $3"
+    )
+    .bind(model_id)
+    .bind(since.unwrap_or(0))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(entries)
+}
+
+#[derive(Clone)]
 pub struct HealthMonitor {
     pool: PgPool,
+    /// Probes use the key live traffic would, including `provider_keys`
+    key_pool: Arc<KeyPool>,
+    client: reqwest::Client,
 }
 
-#[allow(dead_code)]
 impl HealthMonitor {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, key_pool: Arc<KeyPool>) -> Self {
+        Self { pool, key_pool, client: reqwest::Client::new() }
     }
 
     /// Start background health monitoring
-    pub fn start(pool: PgPool) {
+    pub fn start(pool: PgPool, key_pool: Arc<KeyPool>, config: HealthMonitorConfig) {
+        if !config.enabled {
+            eprintln!("🏥 Health monitor disabled (HEALTH_CHECK_ENABLED=false)");
+            return;
+        }
+
         tokio::spawn(async move {
-            let monitor = Self::new(pool);
-            let mut ticker = interval(Duration::from_secs(300)); // Check every 5 minutes
+            let monitor = Self::new(pool, key_pool);
+            let mut ticker = interval(config.interval);
 
-            eprintln!("🏥 Health monitor started - checking every 5 minutes");
+            eprintln!("🏥 Health monitor started - checking every {}s (concurrency {}, jitter {}s)",
+                config.interval.as_secs(), config.concurrency, config.jitter.as_secs());
 
             loop {
                 ticker.tick().await;
-                if let Err(e) = monitor.check_all_models().await {
+                if let Err(e) = monitor.check_all_models(&config).await {
                     eprintln!("❌ Health check error: {}", e);
                 }
+                monitor.prune_history(config.history_retention_days).await;
             }
         });
     }
 
-    /// Check health of all registered models
-    async fn check_all_models(&self) -> Result<()> {
+    /// Probe models that are unhealthy or haven't been observed within the interval.
+    /// Passive health from live traffic counts as an observation; unhealthy models are
+    /// always probed because routing skips them, so probes are their only way back.
+    async fn check_all_models(&self, config: &HealthMonitorConfig) -> Result<()> {
+        let stale_before = chrono::Utc::now().timestamp() - config.interval.as_secs() as i64;
         let models_vec = sqlx::query_as::<_, (String, String, String, String)>(
-            "SELECT id, name, provider_id, modality FROM models"
+            "SELECT m.id, m.name, m.provider_id, m.modality
+             FROM models m
+             LEFT JOIN model_health h ON m.id = h.model_id
+             WHERE h.model_id IS NULL OR h.is_healthy = 0 OR h.last_check < $1"
         )
+        .bind(stale_before)
         .fetch_all(&self.pool)
         .await?;
 
-        eprintln!("🔍 Health check: scanning {} models", models_vec.len());
+        eprintln!("🔍 Health check: probing {} models", models_vec.len());
 
-        // Parallelize checks (Limit concurrency to 10 to avoid FD exhaustion)
+        // Parallelize checks (bounded to avoid FD exhaustion)
         stream::iter(models_vec)
-            .for_each_concurrent(Some(10), |(model_id, model_name, provider_id, modality)| {
-                // Clone pool for the task
-                let pool = self.pool.clone();
-                let monitor = Self::new(pool);
-                
+            .for_each_concurrent(Some(config.concurrency), |(model_id, model_name, provider_id, modality)| {
+                let jitter_ms = config.jitter.as_millis() as u64;
                 async move {
-                    let health = monitor.ping_single_model(&model_id, &model_name, &provider_id, &modality).await;
-                    
-                    // Update health table  
-                    let update_result = sqlx::query(
-                        "INSERT INTO model_health 
-                         (model_id, is_healthy, last_check, response_time_ms, consecutive_failures, last_error)
-                         VALUES ($1, $2, $3, $4, $5, $6)
-                         ON CONFLICT (model_id) DO UPDATE SET
-                           is_healthy = EXCLUDED.is_healthy,
-                           last_check = EXCLUDED.last_check,
-                           response_time_ms = EXCLUDED.response_time_ms,
-                           consecutive_failures = EXCLUDED.consecutive_failures,
-                           last_error = EXCLUDED.last_error"
-                    )
-                    .bind(&model_id)
-                    .bind(health.is_healthy as i64)
-                    .bind(chrono::Utc::now().timestamp())
-                    .bind(health.response_time_ms)
-                    .bind(health.consecutive_failures)
-                    .bind(&health.last_error)
-                    .execute(&monitor.pool)
-                    .await;
-
-                    if let Err(e) = update_result {
+                    if jitter_ms > 0 {
+                        let delay = rand::thread_rng().gen_range(0..jitter_ms);
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                    }
+
+                    let health = self.ping_single_model(&model_id, &model_name, &provider_id, &modality).await;
+                    if let Err(e) = self.record(&model_id, &health).await {
                         eprintln!("❌ Failed to update health for {}: {}", model_name, e);
                     } else {
                         let status = if health.is_healthy { "✅" } else { "❌" };
-                        // Only log errors or slow responses to reduce noise? No, keep existing behavior.
-                        eprintln!("{} {} - {}ms", status, model_name, health.response_time_ms.unwrap_or(0));
+                        eprintln!("{} {} ({}) - {}ms", status, model_name, health.probe.as_str(), health.response_time_ms.unwrap_or(0));
                     }
                 }
             })
@@ -94,89 +201,147 @@ impl HealthMonitor {
         Ok(())
     }
 
-    /// Ping a specific model with a lightweight request (public for manual checks)
-    pub async fn ping_single_model(&self, model_id: &str, model_name: &str, provider_id: &str, modality: &str) -> HealthStatus {
-        if modality == "image" {
-            // Skip expensive image generation health checks for now
-            // TODO: Implement lightweight health check for image models
-            return HealthStatus {
-                is_healthy: true,
-                response_time_ms: Some(0),
-                consecutive_failures: 0,
-                last_error: None,
-            };
-        }
+    /// Store a probe result: update the current health row and append to the history.
+    /// A success restores the model at once; it leaves rotation only after
+    /// `UNHEALTHY_AFTER_FAILURES` failures in a row. Rate-limited probes count neither way.
+    pub async fn record(&self, model_id: &str, health: &HealthStatus) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
 
-        let start = Instant::now();
+        let previous: Option<(i32, Option<i32>)> = sqlx::query_as(
+            "SELECT is_healthy, consecutive_failures FROM model_health WHERE model_id = $1 FOR UPDATE"
+        )
+        .bind(model_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let previous = previous.map(|(is_healthy, failures)| (is_healthy == 1, failures.unwrap_or(0)));
+        let (is_healthy, consecutive_failures) = next_health(previous, health.outcome());
+
+        sqlx::query(
+            "INSERT INTO model_health
+             (model_id, is_healthy, last_check, response_time_ms, consecutive_failures, last_error)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (model_id) DO UPDATE SET
+               is_healthy = EXCLUDED.is_healthy,
+               last_check = EXCLUDED.last_check,
+               response_time_ms = EXCLUDED.response_time_ms,
+               consecutive_failures = EXCLUDED.consecutive_failures,
+               last_error = EXCLUDED.last_error"
+        )
+        .bind(model_id)
+        .bind(is_healthy as i32)
+        .bind(now)
+        .bind(health.response_time_ms)
+        .bind(consecutive_failures)
+        .bind(&health.last_error)
+        .execute(&mut *tx)
+        .await?;
 
-        // Get provider details including endpoint
-        let provider = match sqlx::query_as::<_, (String, Option<String>, String)>(
-            "SELECT provider_type, api_endpoint, api_key FROM providers WHERE id = $1"
+        sqlx::query(
+            "INSERT INTO model_health_history (model_id, checked_at, is_healthy, response_time_ms, probe, error, outcome)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)"
         )
-        .bind(provider_id)
-        .fetch_one(&self.pool)
-        .await
+        .bind(model_id)
+        .bind(now)
+        .bind(health.is_healthy as i32)
+        .bind(health.response_time_ms)
+        .bind(health.probe.as_str())
+        .bind(&health.last_error)
+        .bind(health.outcome())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn prune_history(&self, retention_days: i64) {
+        if retention_days <= 0 {
+            return;
+        }
+        let cutoff = chrono::Utc::now().timestamp() - retention_days * 86_400;
+        if let Err(e) = sqlx::query("DELETE FROM model_health_history WHERE checked_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await
         {
-            Ok(p) => p,
-            Err(e) => {
-                return HealthStatus {
-                    is_healthy: false,
-                    response_time_ms: None,
-                    consecutive_failures: 1,
-                    last_error: Some(format!("Provider not found: {}", e)),
-                };
-            }
-        };
+            eprintln!("⚠️ Failed to prune health history: {}", e);
+        }
+    }
 
-        let (provider_type, api_endpoint, api_key) = provider;
-
-        // Determine endpoint/key based on provider type
-        let (endpoint, final_api_key) = match provider_type.as_str() {
-            "openai" => ("https://api.openai.com/v1".to_string(), api_key),
-            "google" | "gemini" => ("https://generativelanguage.googleapis.com/v1beta/models".to_string(), api_key),
-            "anthropic" => ("https://api.anthropic.com/v1".to_string(), api_key),
-            "xai" => ("https://api.x.ai/v1".to_string(), api_key),
-            "mistral" => ("https://api.mistral.ai/v1".to_string(), api_key),
-            "azure" => {
-                // Fetch model-specific overrides for Azure
-                let model_overrides = sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>)>(
-                    "SELECT api_endpoint, api_key, api_version FROM models WHERE id = $1"
-                )
-                .bind(model_id)
-                .fetch_optional(&self.pool)
-                .await
-                .ok()
-                .flatten();
-
-                let (m_endpoint, m_key, _m_ver) = model_overrides.unwrap_or((None, None, None));
-                
-                let ep = m_endpoint.or(api_endpoint).unwrap_or_else(|| {
-                   eprintln!("⚠️  Azure provider missing api_endpoint");
-                   "".to_string()
-                });
-                
-                let key = m_key.unwrap_or(api_key);
-                (ep, key)
-            },
-            _ => {
-                 return HealthStatus {
-                    is_healthy: false,
-                    response_time_ms: None,
-                    consecutive_failures: 1,
-                    last_error: Some(format!("Unknown provider type: {}", provider_type)),
-                 };
+    /// Ping a specific model with a lightweight request (public for manual checks)
+    pub async fn ping_single_model(&self, model_id: &str, model_name: &str, provider_id: &str, modality: &str) -> HealthStatus {
+        let start = Instant::now();
+
+        // Provider details, with model-level overrides (Azure deployments, self-hosted endpoints)
+        let provider = sqlx::query_as::<_, mawi_core::models::Provider>("SELECT * FROM providers WHERE id = $1")
+            .bind(provider_id)
+            .fetch_one(&self.pool)
+            .await;
+        let model = sqlx::query_as::<_, mawi_core::models::Model>("SELECT * FROM models WHERE id = $1")
+            .bind(model_id)
+            .fetch_one(&self.pool)
+            .await;
+        let (provider, model) = match (provider, model) {
+            (Ok(provider), Ok(model)) => (provider, model),
+            (Err(e), _) | (_, Err(e)) => {
+                return HealthStatus::failed(ProbeKind::Completion, None, format!("Model or provider not found: {}", e));
             }
         };
 
+        // The same key the executor pins for this model: the model's own, else the
+        // provider's first usable pooled key, else the provider's key
+        let credential = self.key_pool.select_pinned(&provider, &model).await;
+        let api_key = credential.api_key.clone();
+        let provider_type = provider.provider_type.to_lowercase();
+        let endpoint = probe_endpoint(&provider_type, [
+            credential.api_endpoint.as_deref(),
+            model.api_endpoint.as_deref(),
+            provider.api_endpoint.as_deref(),
+        ]);
+        let api_version = model.api_version.or(provider.api_version).unwrap_or_else(|| "2024-10-21".to_string());
+
+        let probe = ProbeKind::for_model(&provider_type, modality);
+
         // Simple health check request based on provider type - use model_name not model_id
-        let result = match provider_type.as_str() {
-            "openai" => self.ping_openai(&endpoint, &final_api_key, model_name).await,
-            "google" | "gemini" => self.ping_gemini(&endpoint, &final_api_key, model_name).await,
-            "azure" => self.ping_azure(&endpoint, &final_api_key, model_name).await,
-            "anthropic" => self.ping_anthropic(&endpoint, &final_api_key, model_name).await,
-            "xai" => self.ping_openai(&endpoint, &final_api_key, model_name).await,
-            "mistral" => self.ping_openai(&endpoint, &final_api_key, model_name).await,
-            _ => Err(anyhow::anyhow!("Unknown provider type")),
+        let result = match (provider_type.as_str(), probe) {
+            ("openai" | "xai" | "mistral" | "deepseek" | "perplexity", ProbeKind::Completion) => self.ping_openai(&endpoint, &api_key, model_name).await,
+            ("openai" | "xai" | "mistral", ProbeKind::ModelList) => self.probe_model_list(format!("{}/models/{}", endpoint, model_name), bearer(&api_key)).await,
+            ("deepseek", ProbeKind::ModelList) => self.probe_model_list(format!("{}/models", endpoint), bearer(&api_key)).await,
+            // Perplexity has no models endpoint; it only serves text
+            ("perplexity", _) => self.ping_openai(&endpoint, &api_key, model_name).await,
+            ("google" | "gemini", ProbeKind::Completion) => {
+                self.ping_gemini(&format!("{}/models", endpoint), &api_key, model_name).await
+            }
+            ("google" | "gemini", ProbeKind::ModelList) => {
+                self.probe_model_list(
+                    format!("{}/models/{}", endpoint, model_name),
+                    vec![("x-goog-api-key", api_key.clone())],
+                ).await
+            }
+            ("anthropic", ProbeKind::Completion) => self.ping_anthropic(&endpoint, &api_key, model_name).await,
+            ("anthropic", ProbeKind::ModelList) => {
+                self.probe_model_list(
+                    format!("{}/models/{}", endpoint, model_name),
+                    vec![("x-api-key", api_key.clone()), ("anthropic-version", "2023-06-01".to_string())],
+                ).await
+            }
+            ("azure", _) if endpoint.is_empty() => Err(anyhow::anyhow!("Azure provider missing api_endpoint")),
+            ("azure", ProbeKind::Completion) => self.ping_azure(&endpoint, &api_key, model_name).await,
+            ("azure", ProbeKind::ModelList) => {
+                self.probe_model_list(
+                    format!("{}/openai/models?api-version={}", endpoint, api_version),
+                    vec![("api-key", api_key.clone())],
+                ).await
+            }
+            ("elevenlabs", _) => {
+                self.probe_model_list(format!("{}/models", endpoint), vec![("xi-api-key", api_key.clone())]).await
+            }
+            ("ollama", _) => self.probe_model_list(format!("{}/api/tags", endpoint), vec![]).await,
+            ("selfhosted", _) if endpoint.is_empty() => Err(anyhow::anyhow!("Self-hosted provider missing api_endpoint")),
+            ("selfhosted", ProbeKind::Completion) => self.ping_openai(&format!("{}/v1", endpoint), &api_key, model_name).await,
+            ("selfhosted", ProbeKind::ModelList) => self.probe_model_list(format!("{}/v1/models", endpoint), bearer(&api_key)).await,
+            _ => Err(anyhow::anyhow!("Unknown provider type: {}", provider_type)),
         };
 
         let latency = start.elapsed().as_millis() as i64;
@@ -187,21 +352,28 @@ impl HealthMonitor {
                 response_time_ms: Some(latency),
                 consecutive_failures: 0,
                 last_error: None,
+                probe,
+                rate_limited: false,
             },
             Err(e) => HealthStatus {
-                is_healthy: false,
-                response_time_ms: Some(latency),
-                consecutive_failures: 1,
-                last_error: Some(e.to_string()),
+                rate_limited: e.is::<ProbeRateLimited>(),
+                ..HealthStatus::failed(probe, Some(latency), e.to_string())
             },
         }
     }
 
-    /// Ping OpenAI endpoint
+    /// Non-generating probe: GET a model-lookup or model-list endpoint
+    async fn probe_model_list(&self, url: String, headers: Vec<(&'static str, String)>) -> Result<()> {
+        let mut request = self.client.get(url).timeout(Duration::from_secs(10));
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        check_status(request.send().await?)
+    }
+
+    /// Ping an OpenAI-compatible chat completions endpoint
     async fn ping_openai(&self, endpoint: &str, api_key: &str, model: &str) -> Result<()> {
-        let client = reqwest::Client::new();
-        
-        let response: reqwest::Response = client
+        let response: reqwest::Response = self.client
             .post(format!("{}/chat/completions", endpoint))
             .header("Authorization", format!("Bearer {}", api_key))
             .json(&serde_json::json!({
@@ -213,24 +385,14 @@ impl HealthMonitor {
             .send()
             .await?;
 
-        let status = response.status();
-        if status.is_success() {
-            Ok(())
-        } else if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            Err(anyhow::anyhow!("Rate Limited (429)"))
-        } else if status.is_client_error() {
-            Err(anyhow::anyhow!("Client Error ({})", status))
-        } else {
-            Err(anyhow::anyhow!("Server Error ({})", status))
-        }
+        check_status(response)
     }
 
-    /// Ping Gemini endpoint
+    /// Ping Gemini endpoint. The key goes in a header: URLs end up in stored probe errors.
     async fn ping_gemini(&self, endpoint: &str, api_key: &str, model: &str) -> Result<()> {
-        let client = reqwest::Client::new();
-        
-        let response: reqwest::Response = client
-            .post(format!("{}/{}:generateContent?key={}", endpoint, model, api_key))
+        let response: reqwest::Response = self.client
+            .post(format!("{}/{}:generateContent", endpoint, model))
+            .header("x-goog-api-key", api_key)
             .json(&serde_json::json!({
                 "contents": [{
                     "parts": [{"text": "ping"}]
@@ -243,23 +405,12 @@ impl HealthMonitor {
             .send()
             .await?;
 
-        let status = response.status();
-        if status.is_success() {
-            Ok(())
-        } else if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            Err(anyhow::anyhow!("Rate Limited (429)"))
-        } else if status.is_client_error() {
-            Err(anyhow::anyhow!("Client Error ({})", status))
-        } else {
-            Err(anyhow::anyhow!("Server Error ({})", status))
-        }
+        check_status(response)
     }
 
     /// Ping Anthropic endpoint
     async fn ping_anthropic(&self, endpoint: &str, api_key: &str, model: &str) -> Result<()> {
-        let client = reqwest::Client::new();
-        
-        let response: reqwest::Response = client
+        let response: reqwest::Response = self.client
             .post(format!("{}/messages", endpoint))
             .header("x-api-key", api_key)
             .header("anthropic-version", "2023-06-01")
@@ -272,24 +423,14 @@ impl HealthMonitor {
             .send()
             .await?;
 
-        let status = response.status();
-        if status.is_success() {
-            Ok(())
-        } else if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            Err(anyhow::anyhow!("Rate Limited (429)"))
-        } else if status.is_client_error() {
-            Err(anyhow::anyhow!("Client Error ({})", status))
-        } else {
-            Err(anyhow::anyhow!("Server Error ({})", status))
-        }
+        check_status(response)
     }
 
     /// Health check for Azure OpenAI
     async fn ping_azure(&self, base_url: &str, api_key: &str, deployment: &str) -> Result<()> {
-        let client = reqwest::Client::new();
         let base_url = base_url.trim_end_matches('/');
-        
-        let response: reqwest::Response = client
+
+        let response: reqwest::Response = self.client
             .post(format!("{}/openai/deployments/{}/chat/completions?api-version=2024-12-01-preview", base_url, deployment))
             .header("api-key", api_key)
             .json(&serde_json::json!({
@@ -303,23 +444,165 @@ impl HealthMonitor {
             .send()
             .await?;
 
-        let status = response.status();
-        if status.is_success() {
-            Ok(())
-        } else if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            Err(anyhow::anyhow!("Rate Limited (429)"))
-        } else if status.is_client_error() {
-            Err(anyhow::anyhow!("Client Error ({})", status))
-        } else {
-            Err(anyhow::anyhow!("Server Error ({})", status))
-        }
+        check_status(response)
+    }
+}
+
+/// Public endpoint for a provider type when neither the key, the model nor the provider sets one.
+/// Azure and self-hosted providers have none.
+fn default_endpoint(provider_type: &str) -> Option<&'static str> {
+    match provider_type {
+        "openai" => Some("https://api.openai.com/v1"),
+        "xai" => Some("https://api.x.ai/v1"),
+        "mistral" => Some("https://api.mistral.ai/v1"),
+        "deepseek" => Some("https://api.deepseek.com/v1"),
+        "perplexity" => Some("https://api.perplexity.ai"),
+        "google" | "gemini" => Some("https://generativelanguage.googleapis.com/v1beta"),
+        "anthropic" => Some("https://api.anthropic.com/v1"),
+        "elevenlabs" => Some("https://api.elevenlabs.io/v1"),
+        "ollama" => Some("http://localhost:11434"),
+        _ => None,
+    }
+}
+
+/// Base URL to probe, resolved as the executor does: the key's endpoint, then the model's,
+/// then the provider's, then the provider type's default. Empty if there is none.
+fn probe_endpoint(provider_type: &str, overrides: [Option<&str>; 3]) -> String {
+    overrides.into_iter()
+        .flatten()
+        .find(|endpoint| !endpoint.is_empty())
+        .or_else(|| default_endpoint(provider_type))
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn bearer(api_key: &str) -> Vec<(&'static str, String)> {
+    vec![("Authorization", format!("Bearer {}", api_key))]
+}
+
+/// The provider turned a probe away for lack of capacity (429, or Anthropic's 529 overloaded).
+/// It is up, so the probe says nothing about the model's health.
+#[derive(Debug)]
+struct ProbeRateLimited(reqwest::StatusCode);
+
+impl std::fmt::Display for ProbeRateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate Limited ({})", self.0.as_u16())
+    }
+}
+
+impl std::error::Error for ProbeRateLimited {}
+
+fn check_status(response: reqwest::Response) -> Result<()> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 529 {
+        Err(ProbeRateLimited(status).into())
+    } else if status.is_client_error() {
+        Err(anyhow::anyhow!("Client Error ({})", status))
+    } else {
+        Err(anyhow::anyhow!("Server Error ({})", status))
     }
 }
 
-#[allow(dead_code)]
 pub struct HealthStatus {
     pub is_healthy: bool,
     pub response_time_ms: Option<i64>,
     pub consecutive_failures: i32,
     pub last_error: Option<String>,
+    pub probe: ProbeKind,
+    /// The probe was rate limited: not healthy, but not counted as a failure either
+    pub rate_limited: bool,
+}
+
+impl HealthStatus {
+    fn failed(probe: ProbeKind, response_time_ms: Option<i64>, error: String) -> Self {
+        Self {
+            is_healthy: false,
+            response_time_ms,
+            consecutive_failures: 1,
+            last_error: Some(error),
+            probe,
+            rate_limited: false,
+        }
+    }
+
+    /// healthy, failed or rate_limited
+    fn outcome(&self) -> &'static str {
+        match (self.is_healthy, self.rate_limited) {
+            (true, _) => "healthy",
+            (false, true) => "rate_limited",
+            (false, false) => "failed",
+        }
+    }
+}
+
+/// Health and failure streak after a probe, from the stored row (if any) and the probe's outcome.
+/// A rate-limited probe leaves both as they were; a new model starts healthy.
+fn next_health(previous: Option<(bool, i32)>, outcome: &str) -> (bool, i32) {
+    let (was_healthy, failures) = previous.unwrap_or((true, 0));
+    match outcome {
+        "healthy" => (true, 0),
+        "failed" => (was_healthy && failures + 1 < UNHEALTHY_AFTER_FAILURES, failures + 1),
+        _ => (was_healthy, failures),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16) -> reqwest::Response {
+        poem::http::Response::builder().status(status).body("").unwrap().into()
+    }
+
+    #[test]
+    fn test_consecutive_failures_take_a_model_out_of_rotation() {
+        let mut state = None;
+        for failures in 1..UNHEALTHY_AFTER_FAILURES {
+            let next = next_health(state, "failed");
+            assert_eq!(next, (true, failures));
+            state = Some(next);
+        }
+        let state = next_health(state, "failed");
+        assert_eq!(state, (false, UNHEALTHY_AFTER_FAILURES));
+        // Further failures keep it out; one success brings it back
+        assert_eq!(next_health(Some(state), "failed"), (false, UNHEALTHY_AFTER_FAILURES + 1));
+        assert_eq!(next_health(Some(state), "healthy"), (true, 0));
+    }
+
+    #[test]
+    fn test_rate_limited_probes_count_neither_way() {
+        for status in [429, 529] {
+            let err = check_status(response(status)).unwrap_err();
+            assert!(err.is::<ProbeRateLimited>());
+            let health = HealthStatus {
+                rate_limited: err.is::<ProbeRateLimited>(),
+                ..HealthStatus::failed(ProbeKind::Completion, Some(10), err.to_string())
+            };
+            assert_eq!(health.outcome(), "rate_limited");
+        }
+        assert!(!check_status(response(500)).unwrap_err().is::<ProbeRateLimited>());
+
+        assert_eq!(next_health(Some((true, 3)), "rate_limited"), (true, 3));
+        assert_eq!(next_health(Some((false, 7)), "rate_limited"), (false, 7));
+        assert_eq!(next_health(None, "rate_limited"), (true, 0));
+    }
+
+    #[test]
+    fn test_probe_endpoint_resolves_like_the_executor() {
+        assert_eq!(
+            probe_endpoint("openai", [Some("https://key.example/v1/"), Some("https://model.example/v1"), None]),
+            "https://key.example/v1"
+        );
+        assert_eq!(
+            probe_endpoint("mistral", [None, Some("https://model.example/v1"), Some("https://provider.example/v1")]),
+            "https://model.example/v1"
+        );
+        assert_eq!(probe_endpoint("xai", [None, Some(""), Some("https://provider.example/v1")]), "https://provider.example/v1");
+        assert_eq!(probe_endpoint("deepseek", [None, None, None]), "https://api.deepseek.com/v1");
+        assert_eq!(probe_endpoint("azure", [None, None, None]), "");
+    }
 }
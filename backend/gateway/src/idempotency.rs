@@ -0,0 +1,396 @@
+//! Idempotency Keys
+//!
+//! A client retrying an inference request with the same `Idempotency-Key` header gets the
+//! first response replayed instead of running (and paying for) the request again. Keys are
+//! per user and remember the request they were first used with: reusing one for a different
+//! request is rejected with 422, and reusing it while the first is still running gets a 409.
+//! Server errors and 429s aren't stored, so those requests can simply be retried.
+
+use poem::http::{header, Method, StatusCode};
+use poem::{Body, Endpoint, IntoResponse, Middleware, Request, Response, Result};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// Set on replayed responses
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Endpoints that honour the header
+const IDEMPOTENT_PATHS: [&str; 4] = [
+    "/v1/chat/completions",
+    "/v1/images/generations",
+    "/v1/audio/speech",
+    "/v1/videos/generations",
+];
+
+/// How long a request may stay in progress before its key can be claimed again
+const IN_PROGRESS_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Larger responses are passed through without being stored
+const MAX_STORED_BODY: usize = 16 * 1024 * 1024;
+
+const MAX_KEY_LEN: usize = 255;
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    /// How long completed responses are replayed
+    pub ttl: Duration,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self { ttl: Duration::from_secs(24 * 3600) }
+    }
+}
+
+impl IdempotencyConfig {
+    /// Read `IDEMPOTENCY_TTL_SECS`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            ttl: std::env::var("IDEMPOTENCY_TTL_SECS")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.ttl),
+        }
+    }
+}
+
+/// The earlier use of a key
+#[derive(Debug, sqlx::FromRow)]
+struct StoredRequest {
+    request_hash: String,
+    state: String,
+    response_status: Option<i32>,
+    response_content_type: Option<String>,
+    response_body: Option<Vec<u8>>,
+}
+
+impl StoredRequest {
+    /// What a repeat of the request gets back
+    fn replay(self, request_hash: &str) -> Response {
+        if self.request_hash != request_hash {
+            return Response::builder()
+                .status(StatusCode::UNPROCESSABLE_ENTITY)
+                .body("Idempotency-Key was already used for a different request");
+        }
+        let status = self.response_status.and_then(|s| StatusCode::from_u16(s as u16).ok());
+        let (Some(status), "completed") = (status, self.state.as_str()) else {
+            return Response::builder()
+                .status(StatusCode::CONFLICT)
+                .header(header::RETRY_AFTER, 1)
+                .body("A request with this Idempotency-Key is still in progress");
+        };
+        let mut response = Response::builder().status(status).header(REPLAYED_HEADER, "true");
+        if let Some(content_type) = self.response_content_type {
+            response = response.content_type(content_type);
+        }
+        response.body(self.response_body.unwrap_or_default())
+    }
+}
+
+/// Hash of what makes two requests the same: method, path and body. JSON bodies are
+/// compared by value, so re-serializing with different whitespace or key order still matches.
+fn request_hash(method: &str, path: &str, body: &[u8]) -> String {
+    let canonical = serde_json::from_slice::<serde_json::Value>(body)
+        .map(|value| value.to_string().into_bytes())
+        .unwrap_or_else(|_| body.to_vec());
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update([0]);
+    hasher.update(path.as_bytes());
+    hasher.update([0]);
+    hasher.update(&canonical);
+    hex::encode(hasher.finalize())
+}
+
+/// Whether a response is final: successes and client errors other than 429
+fn storable(status: StatusCode) -> bool {
+    status.is_success() || (status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS)
+}
+
+pub struct IdempotencyStore {
+    pool: PgPool,
+    config: IdempotencyConfig,
+}
+
+impl IdempotencyStore {
+    /// Create the store and start hourly cleanup of expired keys
+    pub fn start(pool: PgPool, config: IdempotencyConfig) -> Arc<Self> {
+        let store = Arc::new(Self { pool, config });
+        let pruner = store.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+            loop {
+                ticker.tick().await;
+                let _ = sqlx::query("DELETE FROM idempotency_keys WHERE expires_at < $1")
+                    .bind(chrono::Utc::now().timestamp())
+                    .execute(&pruner.pool)
+                    .await;
+            }
+        });
+        store
+    }
+
+    /// Claim a key for a request, or return its earlier use. Expired keys are claimed afresh.
+    async fn claim(&self, pending: &Pending) -> std::result::Result<Option<StoredRequest>, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let claimed = sqlx::query(
+            "INSERT INTO idempotency_keys (user_id, idempotency_key, request_hash, state, created_at, expires_at)
+             VALUES ($1, $2, $3, 'in_progress', $4, $5)
+             ON CONFLICT (user_id, idempotency_key) DO UPDATE SET
+                request_hash = EXCLUDED.request_hash,
+                state = 'in_progress',
+                response_status = NULL,
+                response_content_type = NULL,
+                response_body = NULL,
+                created_at = EXCLUDED.created_at,
+                expires_at = EXCLUDED.expires_at
+             WHERE idempotency_keys.expires_at < $4"
+        )
+        .bind(&pending.user_id)
+        .bind(&pending.key)
+        .bind(&pending.request_hash)
+        .bind(now)
+        .bind(now + IN_PROGRESS_TIMEOUT.as_secs() as i64)
+        .execute(&self.pool)
+        .await?;
+        if claimed.rows_affected() > 0 {
+            return Ok(None);
+        }
+
+        sqlx::query_as::<_, StoredRequest>(
+            "SELECT request_hash, state, response_status, response_content_type, response_body
+             FROM idempotency_keys WHERE user_id = $1 AND idempotency_key = $2"
+        )
+        .bind(&pending.user_id)
+        .bind(&pending.key)
+        .fetch_optional(&self.pool)
+        .await
+    }
+}
+
+/// A claimed key waiting for its response
+struct Pending {
+    store: Arc<IdempotencyStore>,
+    user_id: String,
+    key: String,
+    request_hash: String,
+}
+
+impl Pending {
+    /// Store the response for replay
+    async fn complete(self, status: StatusCode, content_type: Option<String>, body: &[u8]) {
+        let expires_at = chrono::Utc::now().timestamp() + self.store.config.ttl.as_secs() as i64;
+        let result = sqlx::query(
+            "UPDATE idempotency_keys SET state = 'completed', response_status = $4,
+                response_content_type = $5, response_body = $6, expires_at = $7
+             WHERE user_id = $1 AND idempotency_key = $2 AND request_hash = $3 AND state = 'in_progress'"
+        )
+        .bind(&self.user_id)
+        .bind(&self.key)
+        .bind(&self.request_hash)
+        .bind(status.as_u16() as i32)
+        .bind(content_type)
+        .bind(body)
+        .bind(expires_at)
+        .execute(&self.store.pool)
+        .await;
+        if let Err(e) = result {
+            warn!(user = %self.user_id, error = %e, "could not store idempotent response");
+        }
+    }
+
+    /// Give the key up so the request can be retried
+    async fn release(self) {
+        let _ = sqlx::query(
+            "DELETE FROM idempotency_keys
+             WHERE user_id = $1 AND idempotency_key = $2 AND request_hash = $3 AND state = 'in_progress'"
+        )
+        .bind(&self.user_id)
+        .bind(&self.key)
+        .bind(&self.request_hash)
+        .execute(&self.store.pool)
+        .await;
+    }
+}
+
+/// Releases the key of a stream that ends early (client gone, upstream error)
+struct StreamCompletion {
+    pending: Option<Pending>,
+    status: StatusCode,
+    content_type: Option<String>,
+    buffer: Vec<u8>,
+    failed: bool,
+}
+
+impl StreamCompletion {
+    fn record<B: AsRef<[u8]>>(&mut self, chunk: &std::result::Result<B, std::io::Error>) {
+        match chunk.as_ref().map(AsRef::as_ref) {
+            Ok(bytes) if self.buffer.len() + bytes.len() <= MAX_STORED_BODY => self.buffer.extend_from_slice(bytes),
+            _ => self.failed = true,
+        }
+    }
+
+    async fn finish(mut self) {
+        let Some(pending) = self.pending.take() else { return };
+        if self.failed {
+            pending.release().await;
+        } else {
+            pending.complete(self.status, self.content_type.take(), &self.buffer).await;
+        }
+    }
+}
+
+impl Drop for StreamCompletion {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            tokio::spawn(pending.release());
+        }
+    }
+}
+
+/// Honours `Idempotency-Key` on inference requests; runs after `AuthMiddleware`
+pub struct IdempotencyMiddleware {
+    pub store: Arc<IdempotencyStore>,
+}
+
+impl<E: Endpoint> Middleware<E> for IdempotencyMiddleware {
+    type Output = IdempotencyEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        IdempotencyEndpoint { ep, store: self.store.clone() }
+    }
+}
+
+pub struct IdempotencyEndpoint<E> {
+    ep: E,
+    store: Arc<IdempotencyStore>,
+}
+
+impl<E: Endpoint> Endpoint for IdempotencyEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        let key = req
+            .headers()
+            .get(IDEMPOTENCY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty());
+        let applies = req.method() == Method::POST && IDEMPOTENT_PATHS.contains(&req.original_uri().path());
+        let user = req.extensions().get::<mawi_core::auth::User>().cloned();
+        let (Some(key), true, Some(user)) = (key, applies, user) else {
+            return self.ep.call(req).await.map(IntoResponse::into_response);
+        };
+        if key.len() > MAX_KEY_LEN {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(format!("Idempotency-Key must be at most {} characters", MAX_KEY_LEN)));
+        }
+
+        // The body is put back for the handler
+        let body = req.take_body().into_bytes().await?;
+        let pending = Pending {
+            store: self.store.clone(),
+            user_id: user.id,
+            key,
+            request_hash: request_hash(req.method().as_str(), req.original_uri().path(), &body),
+        };
+        req.set_body(body);
+
+        match self.store.claim(&pending).await {
+            Ok(None) => {}
+            Ok(Some(stored)) => return Ok(stored.replay(&pending.request_hash)),
+            Err(e) => {
+                warn!(user = %pending.user_id, error = %e, "idempotency store unavailable, running request without it");
+                return self.ep.call(req).await.map(IntoResponse::into_response);
+            }
+        }
+
+        let mut response = match self.ep.call(req).await {
+            Ok(response) => response.into_response(),
+            Err(e) => {
+                pending.release().await;
+                return Err(e);
+            }
+        };
+        let status = response.status();
+        if !storable(status) {
+            pending.release().await;
+            return Ok(response);
+        }
+        let content_type = response.content_type().map(|t| t.to_string());
+
+        // Streams are forwarded as they arrive and stored once complete
+        if content_type.as_deref().is_some_and(|t| t.starts_with("text/event-stream")) {
+            let mut completion = StreamCompletion { pending: Some(pending), status, content_type, buffer: Vec::new(), failed: false };
+            let mut chunks = response.take_body().into_bytes_stream();
+            let body = async_stream::stream! {
+                while let Some(chunk) = chunks.next().await {
+                    completion.record(&chunk);
+                    yield chunk;
+                }
+                completion.finish().await;
+            };
+            response.set_body(Body::from_bytes_stream(body));
+            return Ok(response);
+        }
+
+        let body = match response.take_body().into_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                pending.release().await;
+                return Err(e.into());
+            }
+        };
+        if body.len() <= MAX_STORED_BODY {
+            pending.complete(status, content_type, &body).await;
+        } else {
+            pending.release().await;
+        }
+        response.set_body(body);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_hash_compares_json_by_value() {
+        let path = "/v1/chat/completions";
+        let a = request_hash("POST", path, br#"{"service":"chat","messages":[{"role":"user","content":"hi"}]}"#);
+        let b = request_hash("POST", path, br#"{ "messages": [{"content": "hi", "role": "user"}], "service": "chat" }"#);
+        assert_eq!(a, b);
+
+        assert_ne!(a, request_hash("POST", path, br#"{"service":"chat","messages":[{"role":"user","content":"hello"}]}"#));
+        assert_ne!(a, request_hash("POST", "/v1/images/generations", br#"{"service":"chat","messages":[{"role":"user","content":"hi"}]}"#));
+        assert_ne!(a, request_hash("PUT", path, br#"{"service":"chat","messages":[{"role":"user","content":"hi"}]}"#));
+    }
+
+    #[test]
+    fn test_replay_of_stored_request() {
+        let stored = |state: &str| StoredRequest {
+            request_hash: "abc".to_string(),
+            state: state.to_string(),
+            response_status: (state == "completed").then_some(200),
+            response_content_type: Some("application/json".to_string()),
+            response_body: Some(b"{}".to_vec()),
+        };
+
+        let replayed = stored("completed").replay("abc");
+        assert_eq!(replayed.status(), StatusCode::OK);
+        assert_eq!(replayed.headers().get(REPLAYED_HEADER).unwrap(), "true");
+
+        assert_eq!(stored("completed").replay("other").status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(stored("in_progress").replay("abc").status(), StatusCode::CONFLICT);
+    }
+}
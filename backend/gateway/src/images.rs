@@ -1,7 +1,7 @@
 use poem::{handler, web::{Json, Data}};
 use mawi_core::types::{ImageGenerationRequest, ImageGenerationResponse};
 use std::sync::Arc;
-use crate::executor::Executor;
+use crate::executor::{Executor, RequestRejection};
 
 #[handler]
 pub async fn image_generations(
@@ -19,7 +19,7 @@ pub async fn image_generations(
             eprintln!("Image generation failed: {}", e);
             Err(poem::Error::from_string(
                 format!("Request failed: {}", e),
-                poem::http::StatusCode::INTERNAL_SERVER_ERROR,
+                RequestRejection::status_for(&e),
             ))
         }
     }
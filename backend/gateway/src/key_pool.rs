@@ -0,0 +1,424 @@
+//! Provider Key Pools
+//!
+//! A provider can hold several API keys in `provider_keys` (org keys, Azure deployments).
+//! Each call draws one by the provider's `key_selection` policy, skipping keys whose circuit
+//! is open and keys quarantined after the provider rejected them (401/403). Providers
+//! without usable pooled keys keep using their own `api_key`; a model's `api_key` override
+//! always wins.
+
+use dashmap::DashMap;
+use mawi_core::models::{Model, Provider, ProviderKey};
+use mawi_core::providers::limits;
+use mawi_core::services::CircuitPolicy;
+use moka::future::Cache;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::circuit_breaker::{Admission, CircuitBreaker};
+
+/// Decrypt a stored API key, falling back to the raw value (plain env-style keys)
+pub fn decrypt_api_key(raw: &str, provider_name: &str) -> String {
+    if raw.is_empty() {
+        return String::new();
+    }
+    mawi_core::security::decrypt_key(raw).unwrap_or_else(|e| {
+        eprintln!("⚠️ Failed to decrypt API key for provider {}: {}", provider_name, e);
+        raw.to_string() // Fallback to raw (in case of migration or plain env vars)
+    })
+}
+
+/// A pooled key with its secret decrypted
+struct PooledKey {
+    id: String,
+    api_key: String,
+    api_endpoint: Option<String>,
+}
+
+/// The credential a call uses. Holds its key's in-use slot, and the half-open probe permit
+/// of its key's circuit if it took one, until dropped.
+pub struct Credential {
+    /// `provider_keys.id`; `None` for the provider's or model's own key
+    pub key_id: Option<String>,
+    pub api_key: String,
+    /// Per-key base URL, ahead of the model's and provider's
+    pub api_endpoint: Option<String>,
+    _in_use: Option<InUse>,
+    probe: Option<ProbePermit>,
+}
+
+impl Credential {
+    fn single(api_key: String) -> Self {
+        Self { key_id: None, api_key, api_endpoint: None, _in_use: None, probe: None }
+    }
+
+    /// Identifier safe to log
+    pub fn masked(&self) -> String {
+        mawi_core::utils::mask_api_key(&self.api_key)
+    }
+}
+
+struct InUse {
+    counts: Arc<DashMap<String, u32>>,
+    key_id: String,
+}
+
+impl Drop for InUse {
+    fn drop(&mut self) {
+        if let Some(mut count) = self.counts.get_mut(&self.key_id) {
+            *count = count.saturating_sub(1);
+        }
+        // Keys nothing holds don't keep an entry
+        self.counts.remove_if(&self.key_id, |_, count| *count == 0);
+    }
+}
+
+/// A key circuit's half-open probe permit. Given back on drop unless the call's outcome
+/// was recorded, so a call that fails before reaching the provider (throttled, queued out,
+/// cancelled) doesn't block the key until the probe times out.
+struct ProbePermit {
+    circuit_breaker: Arc<CircuitBreaker>,
+    circuit: String,
+    settled: AtomicBool,
+}
+
+impl Drop for ProbePermit {
+    fn drop(&mut self) {
+        if !self.settled.load(Ordering::Relaxed) {
+            self.circuit_breaker.release_probe_now(&self.circuit);
+        }
+    }
+}
+
+pub struct KeyPool {
+    pool: PgPool,
+    circuit_breaker: Arc<CircuitBreaker>,
+    // Usable (enabled, unquarantined) keys per provider
+    keys: Cache<String, Arc<Vec<Arc<PooledKey>>>>,
+    cursors: DashMap<String, AtomicUsize>,
+    in_use: Arc<DashMap<String, u32>>,
+}
+
+impl KeyPool {
+    pub fn new(pool: PgPool, circuit_breaker: Arc<CircuitBreaker>) -> Self {
+        Self {
+            pool,
+            circuit_breaker,
+            keys: Cache::builder()
+                .max_capacity(1_000)
+                .time_to_live(Duration::from_secs(60))
+                .build(),
+            cursors: DashMap::new(),
+            in_use: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Drop a provider's cached keys after they change
+    pub async fn invalidate(&self, provider_id: &str) {
+        self.keys.invalidate(provider_id).await;
+    }
+
+    /// Cache keys `(id, secret)` for a provider, so tests don't need a database
+    #[cfg(test)]
+    pub(crate) async fn seed(&self, provider_id: &str, keys: &[(&str, &str)]) {
+        let keys = keys
+            .iter()
+            .map(|(id, api_key)| Arc::new(PooledKey { id: id.to_string(), api_key: api_key.to_string(), api_endpoint: None }))
+            .collect();
+        self.keys.insert(provider_id.to_string(), Arc::new(keys)).await;
+    }
+
+    /// Drop every provider's cached keys
+    pub fn invalidate_all(&self) {
+        self.keys.invalidate_all();
+    }
+
+    /// Calls currently holding a pooled key
+    pub fn in_use(&self, key_id: &str) -> u32 {
+        self.in_use.get(key_id).map(|c| *c).unwrap_or(0)
+    }
+
+    async fn usable_keys(&self, provider: &Provider) -> Arc<Vec<Arc<PooledKey>>> {
+        if let Some(keys) = self.keys.get(&provider.id).await {
+            return keys;
+        }
+        let rows = sqlx::query_as::<_, ProviderKey>(
+            "SELECT * FROM provider_keys
+             WHERE provider_id = $1 AND enabled AND quarantined_at IS NULL
+             ORDER BY created_at, id"
+        )
+        .bind(&provider.id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            warn!(provider = %provider.name, error = %e, "could not load provider keys");
+            Vec::new()
+        });
+        let keys: Arc<Vec<Arc<PooledKey>>> = Arc::new(rows.into_iter().map(|row| Arc::new(PooledKey {
+            api_key: decrypt_api_key(&row.api_key, &provider.name),
+            id: row.id,
+            api_endpoint: row.api_endpoint.filter(|e| !e.is_empty()),
+        })).collect());
+        self.keys.insert(provider.id.clone(), keys.clone()).await;
+        keys
+    }
+
+    /// Every key calls to this model could use, for capacity checks
+    pub async fn candidate_keys(&self, provider: &Provider, model: &Model) -> Vec<String> {
+        if let Some(raw) = model.api_key.as_deref().filter(|k| !k.is_empty()) {
+            return vec![decrypt_api_key(raw, &provider.name)];
+        }
+        let keys = self.usable_keys(provider).await;
+        if keys.is_empty() {
+            return vec![decrypt_api_key(provider.api_key.as_deref().unwrap_or(""), &provider.name)];
+        }
+        keys.iter().map(|k| k.api_key.clone()).collect()
+    }
+
+    /// Pick the credential for a call to `model`
+    pub async fn select(&self, provider: &Provider, model: &Model) -> anyhow::Result<Credential> {
+        if let Some(raw) = model.api_key.as_deref().filter(|k| !k.is_empty()) {
+            return Ok(Credential::single(decrypt_api_key(raw, &provider.name)));
+        }
+        let keys = self.usable_keys(provider).await;
+        if keys.is_empty() {
+            return Ok(Credential::single(decrypt_api_key(provider.api_key.as_deref().unwrap_or(""), &provider.name)));
+        }
+
+        let mut order: Vec<Arc<PooledKey>> = keys.iter().cloned().collect();
+        match provider.key_selection.as_deref().unwrap_or("round_robin") {
+            "least_used" => order.sort_by_key(|k| self.in_use(&k.id)),
+            "remaining_quota" => {
+                // Unreported keys count as full
+                let now = Instant::now();
+                let headroom = |k: &PooledKey| {
+                    limits::observed(&limits::key_id(&k.api_key)).and_then(|o| o.headroom(now)).unwrap_or(1.0)
+                };
+                order.sort_by(|a, b| headroom(b).total_cmp(&headroom(a)));
+            }
+            _ => {
+                let start = self.cursors
+                    .entry(provider.id.clone())
+                    .or_default()
+                    .fetch_add(1, Ordering::Relaxed) % order.len();
+                order.rotate_left(start);
+            }
+        }
+
+        for key in order {
+            let circuit = CircuitBreaker::credential_key(&key.id);
+            let probe = match self.circuit_breaker.acquire(&circuit).await {
+                Admission::Blocked => continue,
+                Admission::Allowed => None,
+                Admission::Probe => Some(ProbePermit {
+                    circuit_breaker: self.circuit_breaker.clone(),
+                    circuit,
+                    settled: AtomicBool::new(false),
+                }),
+            };
+            *self.in_use.entry(key.id.clone()).or_insert(0) += 1;
+            return Ok(Credential {
+                key_id: Some(key.id.clone()),
+                api_key: key.api_key.clone(),
+                api_endpoint: key.api_endpoint.clone(),
+                _in_use: Some(InUse { counts: self.in_use.clone(), key_id: key.id.clone() }),
+                probe,
+            });
+        }
+        anyhow::bail!("All API keys for provider '{}' are unavailable (circuits open)", provider.name)
+    }
+
+    /// The provider's first usable key, ignoring the selection policy and circuits. Async
+    /// video jobs are only visible to the key that created them, so creating, polling and
+    /// fetching them all use this one.
+    pub async fn select_pinned(&self, provider: &Provider, model: &Model) -> Credential {
+        if let Some(raw) = model.api_key.as_deref().filter(|k| !k.is_empty()) {
+            return Credential::single(decrypt_api_key(raw, &provider.name));
+        }
+        match self.usable_keys(provider).await.first() {
+            Some(key) => Credential {
+                key_id: Some(key.id.clone()),
+                api_key: key.api_key.clone(),
+                api_endpoint: key.api_endpoint.clone(),
+                _in_use: None,
+                probe: None,
+            },
+            None => Credential::single(decrypt_api_key(provider.api_key.as_deref().unwrap_or(""), &provider.name)),
+        }
+    }
+
+    /// Record how a call with `credential` went on its key's circuit. Keys the provider
+    /// rejects as unauthorized are quarantined until restored through the admin API.
+    pub async fn record_outcome(&self, provider: &Provider, credential: &Credential, outcome: Result<(), &anyhow::Error>) {
+        let Some(key_id) = credential.key_id.as_deref() else { return };
+        if let Some(probe) = &credential.probe {
+            probe.settled.store(true, Ordering::Relaxed);
+        }
+        let circuit = CircuitBreaker::credential_key(key_id);
+        let policy = CircuitPolicy::provider_default();
+        let error = match outcome {
+            Ok(()) => {
+                self.circuit_breaker.record_success(&circuit, &policy).await;
+                return;
+            }
+            Err(error) => error,
+        };
+        if crate::upstream_limits::is_capacity_error(error) {
+            self.circuit_breaker.release_probe(&circuit).await;
+            return;
+        }
+        let status = error.downcast_ref::<mawi_core::providers::UpstreamHttpError>().map(|e| e.status);
+        if let Some(status @ (401 | 403)) = status {
+            self.quarantine(provider, key_id, &credential.masked(), &format!("Provider returned {}", status)).await;
+        }
+        self.circuit_breaker.record_failure(&circuit, &policy).await;
+    }
+
+    async fn quarantine(&self, provider: &Provider, key_id: &str, masked: &str, reason: &str) {
+        let result = sqlx::query(
+            "UPDATE provider_keys SET quarantined_at = $2, quarantine_reason = $3
+             WHERE id = $1 AND quarantined_at IS NULL"
+        )
+        .bind(key_id)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(reason)
+        .execute(&self.pool)
+        .await;
+        match result {
+            Ok(_) => {
+                eprintln!("🔒 Quarantined API key {} of provider {}: {}", masked, provider.name, reason);
+                crate::metrics::KEYS_QUARANTINED.with_label_values(&[&provider.name]).inc();
+            }
+            Err(e) => warn!(provider = %provider.name, key = %masked, error = %e, "could not quarantine API key"),
+        }
+        self.invalidate(&provider.id).await;
+        // Other replicas stop drawing the key too
+        crate::config_sync::publish(&self.pool, &crate::config_sync::Invalidation::Provider(provider.id.clone())).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_breaker::ForcedState;
+
+    fn provider(key_selection: &str) -> Provider {
+        serde_json::from_value(serde_json::json!({
+            "id": "p1",
+            "name": "openai",
+            "provider_type": "openai",
+            "api_key": "provider-key",
+            "key_selection": key_selection,
+        }))
+        .unwrap()
+    }
+
+    fn model(api_key: Option<&str>) -> Model {
+        serde_json::from_value(serde_json::json!({
+            "id": "m1",
+            "name": "gpt-4o",
+            "provider": "p1",
+            "modality": "text",
+            "tier": "standard",
+            "avg_latency_ms": 0,
+            "avg_ttft_ms": 0,
+            "max_tps": 0,
+            "tier_required": "A",
+            "worker_type": "text",
+            "api_key": api_key,
+        }))
+        .unwrap()
+    }
+
+    /// Pool with keys `k1`..`kN` already cached for provider `p1`, so no database is needed
+    async fn key_pool(count: usize) -> (KeyPool, Arc<CircuitBreaker>) {
+        let breaker = Arc::new(CircuitBreaker::new());
+        let pool = KeyPool::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap(), breaker.clone());
+        let keys: Vec<(String, String)> = (1..=count).map(|i| (format!("k{}", i), format!("secret-{}", i))).collect();
+        let keys: Vec<(&str, &str)> = keys.iter().map(|(id, secret)| (id.as_str(), secret.as_str())).collect();
+        pool.seed("p1", &keys).await;
+        (pool, breaker)
+    }
+
+    async fn selected(pool: &KeyPool, provider: &Provider) -> String {
+        pool.select(provider, &model(None)).await.unwrap().key_id.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_rotates_keys() {
+        let (pool, _) = key_pool(3).await;
+        let provider = provider("round_robin");
+        let mut picks = Vec::new();
+        for _ in 0..4 {
+            picks.push(selected(&pool, &provider).await);
+        }
+        assert_eq!(picks, ["k1", "k2", "k3", "k1"]);
+    }
+
+    #[tokio::test]
+    async fn test_least_used_prefers_idle_keys() {
+        let (pool, _) = key_pool(3).await;
+        let provider = provider("least_used");
+        let first = pool.select(&provider, &model(None)).await.unwrap();
+        let second = pool.select(&provider, &model(None)).await.unwrap();
+        assert_eq!(first.key_id.as_deref(), Some("k1"));
+        assert_eq!(second.key_id.as_deref(), Some("k2"));
+        assert_eq!(pool.in_use("k1"), 1);
+
+        drop(first);
+        assert_eq!(selected(&pool, &provider).await, "k1");
+        // Released keys don't keep an in-use entry
+        assert!(!pool.in_use.contains_key("k1"));
+        drop(second);
+        assert!(pool.in_use.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_skips_keys_with_open_circuits() {
+        let (pool, breaker) = key_pool(2).await;
+        let provider = provider("round_robin");
+        breaker.force(&CircuitBreaker::credential_key("k1"), Some(ForcedState::Open));
+        for _ in 0..3 {
+            assert_eq!(selected(&pool, &provider).await, "k2");
+        }
+
+        breaker.force(&CircuitBreaker::credential_key("k2"), Some(ForcedState::Open));
+        let error = pool.select(&provider, &model(None)).await.err().unwrap();
+        assert!(error.to_string().contains("circuits open"));
+    }
+
+    #[tokio::test]
+    async fn test_model_api_key_overrides_pool() {
+        let (pool, _) = key_pool(2).await;
+        let credential = pool.select(&provider("round_robin"), &model(Some("model-key"))).await.unwrap();
+        assert_eq!(credential.key_id, None);
+        assert_eq!(credential.api_key, "model-key");
+
+        let pinned = pool.select_pinned(&provider("round_robin"), &model(Some("model-key"))).await;
+        assert_eq!(pinned.api_key, "model-key");
+    }
+
+    #[tokio::test]
+    async fn test_unsettled_probe_is_released_on_drop() {
+        let (pool, breaker) = key_pool(1).await;
+        let provider = provider("round_robin");
+        let circuit = CircuitBreaker::credential_key("k1");
+        let policy = CircuitPolicy { consecutive_failures: 1, open_secs: 0, ..CircuitPolicy::default() };
+        breaker.record_failure(&circuit, &policy).await;
+
+        // The first call takes the half-open probe; nothing else gets through while it runs
+        let probe = pool.select(&provider, &model(None)).await.unwrap();
+        assert!(pool.select(&provider, &model(None)).await.is_err());
+
+        // Dropped before an outcome was recorded: the permit goes back
+        drop(probe);
+        let probe = pool.select(&provider, &model(None)).await.unwrap();
+
+        // Once the outcome is recorded, dropping the credential doesn't touch the circuit
+        pool.record_outcome(&provider, &probe, Ok(())).await;
+        drop(probe);
+        assert_eq!(breaker.snapshot().iter().find(|s| s.resource == circuit).map(|s| s.state.as_str()), Some("closed"));
+    }
+}
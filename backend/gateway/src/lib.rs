@@ -19,5 +19,21 @@ pub mod agentic_executor;
 pub mod mcp_client;
 pub mod mcp_api;
 pub mod circuit_breaker;
+pub mod circuit_api;
+pub mod slo;
+pub mod slo_api;
+pub mod maintenance;
+pub mod rate_limit;
+pub mod rate_limit_api;
+pub mod idempotency;
+pub mod upstream_limits;
+pub mod admission;
+pub mod response_cache;
+pub mod semantic_cache;
+pub mod guardrails;
+pub mod guardrails_api;
+pub mod key_pool;
+pub mod config_sync;
+pub mod provider_keys_api;
 pub mod context_manager;
 pub mod metrics;
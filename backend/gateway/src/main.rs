@@ -10,6 +10,14 @@ use gateway::user_api::UserApi;
 use gateway::organizations::OrganizationsApi;
 use gateway::executor::Executor;
 use gateway::mcp_api::McpApi;
+use gateway::circuit_api::CircuitApi;
+use gateway::slo_api::SloApi;
+use gateway::maintenance::MaintenanceApi;
+use gateway::rate_limit::{RateLimitConfig, RateLimitMiddleware, RateLimiter};
+use gateway::rate_limit_api::RateLimitApi;
+use gateway::idempotency::{IdempotencyConfig, IdempotencyMiddleware, IdempotencyStore};
+use gateway::provider_keys_api::ProviderKeysApi;
+use gateway::guardrails_api::GuardrailsApi;
 use mawi_core::auth::middleware::AuthMiddleware;
 use mawi_core::license::LicenseProvider;
 use gateway::chat_new::ChatApi;
@@ -20,6 +28,7 @@ use gateway::speech_to_speech;
 use gateway::video;
 use gateway::pricing;
 use gateway::health;
+use gateway::config_sync;
 
 use std::sync::Arc;
 
@@ -99,17 +108,35 @@ async fn main() -> Result<(), anyhow::Error> {
 
     // Create executor with real provider integration
     let executor = Arc::new(Executor::new(pool.clone(), mcp_manager.clone()));
+
+    // Active health probes (passive health is recorded by the executor on live traffic)
+    health::HealthMonitor::start(pool.clone(), executor.key_pool.clone(), health::HealthMonitorConfig::from_env());
+
+    // Drop cached config when another replica changes it
+    config_sync::start(executor.clone());
+
+    // Request and token rate limits on inference endpoints
+    let rate_limiter = Arc::new(RateLimiter::new(pool.clone(), RateLimitConfig::from_env()));
+
+    // Replay of inference responses for retried requests with an Idempotency-Key
+    let idempotency = IdempotencyStore::start(pool.clone(), IdempotencyConfig::from_env());
     
     // Create unified OpenAPI service for Swagger UI
     let api_service = OpenApiService::new(
         (
-            ModelsApi { pool: pool.clone() }, 
+            ModelsApi { pool: pool.clone(), executor: executor.clone() }, 
             TopologyApi { pool: pool.clone() },
             AnalyticsApi { pool: pool.clone() },
             AuthApi { pool: pool.clone() },
-            UserApi { pool: pool.clone() },
+            UserApi { pool: pool.clone(), key_pool: executor.key_pool.clone() },
             OrganizationsApi { pool: pool.clone() },
             ChatApi { executor: executor.clone() },
+            CircuitApi { executor: executor.clone() },
+            SloApi { pool: pool.clone() },
+            MaintenanceApi { executor: executor.clone() },
+            RateLimitApi { limiter: rate_limiter.clone() },
+            ProviderKeysApi { executor: executor.clone() },
+            GuardrailsApi { executor: executor.clone() },
             McpApi::new(pool.clone(), mcp_manager.clone())
         ), 
         "MaWi API", "1.0")
@@ -130,7 +157,7 @@ async fn main() -> Result<(), anyhow::Error> {
     let cors = Cors::new()
         .allow_origins(cors_origins)
         .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-        .allow_headers(vec!["Content-Type", "Authorization", "Cookie"])
+        .allow_headers(vec!["Content-Type", "Authorization", "Cookie", "Idempotency-Key", "Cache-Control"])
         .allow_credentials(true);
     
     // Protected Routes (require auth)
@@ -178,6 +205,10 @@ async fn main() -> Result<(), anyhow::Error> {
             get(video::proxy_video_content)
                 .data(executor.clone())
         )
+        // Rate limits need the authenticated user, so they sit inside AuthMiddleware.
+        // Replayed idempotent requests return before reaching the rate limiter.
+        .with(RateLimitMiddleware { limiter: rate_limiter.clone() })
+        .with(IdempotencyMiddleware { store: idempotency })
         .with(AuthMiddleware);
 
     // Build routes
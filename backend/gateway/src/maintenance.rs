@@ -0,0 +1,251 @@
+//! Drain / Maintenance API
+//!
+//! Take models or whole providers out of rotation without touching their
+//! service assignments. A drain is a maintenance window that starts now;
+//! scheduled windows drain and restore resources automatically. Admin only.
+
+use poem_openapi::{
+    param::{Path, Query},
+    payload::Json,
+    Object, OpenApi, Tags,
+};
+use poem::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::require_admin;
+use crate::config_sync::Invalidation;
+use crate::executor::Executor;
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Drain and Maintenance Windows
+    Maintenance,
+}
+
+/// A period during which a model or provider is out of rotation
+#[derive(Debug, Clone, Serialize, Deserialize, Object, sqlx::FromRow)]
+pub struct MaintenanceWindow {
+    pub id: String,
+    /// model or provider
+    pub scope: String,
+    pub resource_id: String,
+    pub starts_at: i64,
+    /// Unset: drained until explicitly undrained
+    pub ends_at: Option<i64>,
+    pub reason: Option<String>,
+    pub created_by: Option<String>,
+    /// In effect right now
+    pub active: bool,
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is in effect at `now`
+    pub fn in_effect(&self, now: i64) -> bool {
+        self.starts_at <= now && self.ends_at.is_none_or(|end| end > now)
+    }
+
+    /// Whether the window covers a model, directly or through the provider serving it
+    pub fn covers(&self, model_id: &str, provider_id: &str) -> bool {
+        match self.scope.as_str() {
+            "model" => self.resource_id == model_id,
+            "provider" => self.resource_id == provider_id,
+            _ => false,
+        }
+    }
+}
+
+/// Windows in effect now or starting later
+pub async fn open_windows(pool: &sqlx::PgPool) -> sqlx::Result<Vec<MaintenanceWindow>> {
+    sqlx::query_as::<_, MaintenanceWindow>(&format!(
+        "SELECT {} FROM maintenance_windows
+         WHERE ends_at IS NULL OR ends_at > EXTRACT(EPOCH FROM NOW())::BIGINT", WINDOW_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await
+}
+
+/// Request to drain a model or provider now
+#[derive(Debug, Deserialize, Object)]
+pub struct DrainRequest {
+    /// Unix timestamp to return to rotation automatically
+    pub until: Option<i64>,
+    pub reason: Option<String>,
+}
+
+/// Request to schedule a maintenance window
+#[derive(Debug, Deserialize, Object)]
+pub struct CreateMaintenanceWindow {
+    /// model or provider
+    pub scope: String,
+    pub resource_id: String,
+    pub starts_at: i64,
+    pub ends_at: Option<i64>,
+    pub reason: Option<String>,
+}
+
+pub struct MaintenanceApi {
+    pub executor: Arc<Executor>,
+}
+
+fn db_error(e: sqlx::Error) -> poem::Error {
+    poem::Error::from_string(format!("Database error: {}", e), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn bad_request(message: impl Into<String>) -> poem::Error {
+    poem::Error::from_string(message.into(), poem::http::StatusCode::BAD_REQUEST)
+}
+
+const WINDOW_COLUMNS: &str = "id, scope, resource_id, starts_at, ends_at, reason, created_by,
+    (starts_at <= EXTRACT(EPOCH FROM NOW())::BIGINT AND (ends_at IS NULL OR ends_at > EXTRACT(EPOCH FROM NOW())::BIGINT)) AS active";
+
+impl MaintenanceApi {
+    async fn ensure_resource(&self, scope: &str, resource_id: &str) -> Result<()> {
+        let table = match scope {
+            "model" => "models",
+            "provider" => "providers",
+            other => return Err(bad_request(format!("Unknown maintenance scope '{}' (expected model or provider)", other))),
+        };
+        let exists = sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {} WHERE id = $1", table))
+            .bind(resource_id)
+            .fetch_one(&self.executor.pool)
+            .await
+            .map_err(db_error)?;
+        if exists == 0 {
+            return Err(poem::Error::from_string(
+                format!("{} '{}' not found", scope, resource_id),
+                poem::http::StatusCode::NOT_FOUND,
+            ));
+        }
+        Ok(())
+    }
+
+    async fn insert_window(
+        &self,
+        scope: &str,
+        resource_id: &str,
+        starts_at: i64,
+        ends_at: Option<i64>,
+        reason: Option<String>,
+        req: &poem::Request,
+    ) -> Result<MaintenanceWindow> {
+        self.ensure_resource(scope, resource_id).await?;
+        if ends_at.is_some_and(|end| end <= starts_at) {
+            return Err(bad_request("Maintenance window must end after it starts"));
+        }
+
+        let created_by = req.extensions().get::<mawi_core::auth::User>().map(|u| u.id.clone());
+        let window = sqlx::query_as::<_, MaintenanceWindow>(&format!(
+            "INSERT INTO maintenance_windows (id, scope, resource_id, starts_at, ends_at, reason, created_by)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             RETURNING {}", WINDOW_COLUMNS
+        ))
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(scope)
+        .bind(resource_id)
+        .bind(starts_at)
+        .bind(ends_at)
+        .bind(&reason)
+        .bind(&created_by)
+        .fetch_one(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        self.executor.invalidate(Invalidation::Maintenance).await;
+        eprintln!("🚧 {} {} in maintenance from {} until {:?} ({})",
+            scope, resource_id, starts_at, ends_at, reason.as_deref().unwrap_or("no reason"));
+        Ok(window)
+    }
+
+    async fn drain(&self, scope: &str, resource_id: &str, body: DrainRequest, req: &poem::Request) -> Result<MaintenanceWindow> {
+        require_admin(req)?;
+        let now = chrono::Utc::now().timestamp();
+        self.insert_window(scope, resource_id, now, body.until, body.reason, req).await
+    }
+
+    /// End every window currently in effect for the resource
+    async fn undrain(&self, scope: &str, resource_id: &str, req: &poem::Request) -> Result<u64> {
+        require_admin(req)?;
+        let now = chrono::Utc::now().timestamp();
+        let result = sqlx::query(
+            "UPDATE maintenance_windows SET ends_at = $1
+             WHERE scope = $2 AND resource_id = $3 AND starts_at <= $1 AND (ends_at IS NULL OR ends_at > $1)"
+        )
+        .bind(now)
+        .bind(scope)
+        .bind(resource_id)
+        .execute(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        self.executor.invalidate(Invalidation::Maintenance).await;
+        eprintln!("✅ {} {} back in rotation", scope, resource_id);
+        Ok(result.rows_affected())
+    }
+}
+
+#[OpenApi]
+impl MaintenanceApi {
+    /// Take a model out of rotation now, optionally until a given time
+    #[oai(path = "/models/:id/drain", method = "post", tag = "ApiTags::Maintenance")]
+    async fn drain_model(&self, id: Path<String>, body: Json<DrainRequest>, req: &poem::Request) -> Result<Json<MaintenanceWindow>> {
+        self.drain("model", &id, body.0, req).await.map(Json)
+    }
+
+    /// Return a drained model to rotation
+    #[oai(path = "/models/:id/undrain", method = "post", tag = "ApiTags::Maintenance")]
+    async fn undrain_model(&self, id: Path<String>, req: &poem::Request) -> Result<Json<u64>> {
+        self.undrain("model", &id, req).await.map(Json)
+    }
+
+    /// Take every model of a provider out of rotation now, optionally until a given time
+    #[oai(path = "/providers/:id/drain", method = "post", tag = "ApiTags::Maintenance")]
+    async fn drain_provider(&self, id: Path<String>, body: Json<DrainRequest>, req: &poem::Request) -> Result<Json<MaintenanceWindow>> {
+        self.drain("provider", &id, body.0, req).await.map(Json)
+    }
+
+    /// Return a drained provider to rotation
+    #[oai(path = "/providers/:id/undrain", method = "post", tag = "ApiTags::Maintenance")]
+    async fn undrain_provider(&self, id: Path<String>, req: &poem::Request) -> Result<Json<u64>> {
+        self.undrain("provider", &id, req).await.map(Json)
+    }
+
+    /// List active and upcoming maintenance windows (and past ones with `include_past`)
+    #[oai(path = "/maintenance-windows", method = "get", tag = "ApiTags::Maintenance")]
+    async fn list_windows(&self, include_past: Query<Option<bool>>, req: &poem::Request) -> Result<Json<Vec<MaintenanceWindow>>> {
+        require_admin(req)?;
+        let windows = sqlx::query_as::<_, MaintenanceWindow>(&format!(
+            "SELECT {} FROM maintenance_windows
+             WHERE $1 OR ends_at IS NULL OR ends_at > EXTRACT(EPOCH FROM NOW())::BIGINT
+             ORDER BY starts_at DESC", WINDOW_COLUMNS
+        ))
+        .bind(include_past.0.unwrap_or(false))
+        .fetch_all(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(Json(windows))
+    }
+
+    /// Schedule a maintenance window; the resource drains and returns to rotation on its own
+    #[oai(path = "/maintenance-windows", method = "post", tag = "ApiTags::Maintenance")]
+    async fn create_window(&self, body: Json<CreateMaintenanceWindow>, req: &poem::Request) -> Result<Json<MaintenanceWindow>> {
+        require_admin(req)?;
+        let body = body.0;
+        self.insert_window(&body.scope, &body.resource_id, body.starts_at, body.ends_at, body.reason, req)
+            .await
+            .map(Json)
+    }
+
+    /// Cancel a maintenance window
+    #[oai(path = "/maintenance-windows/:id", method = "delete", tag = "ApiTags::Maintenance")]
+    async fn delete_window(&self, id: Path<String>, req: &poem::Request) -> Result<Json<bool>> {
+        require_admin(req)?;
+        let result = sqlx::query("DELETE FROM maintenance_windows WHERE id = $1")
+            .bind(&id.0)
+            .execute(&self.executor.pool)
+            .await
+            .map_err(db_error)?;
+        self.executor.invalidate(Invalidation::Maintenance).await;
+        Ok(Json(result.rows_affected() > 0))
+    }
+}
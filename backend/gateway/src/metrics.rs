@@ -1,9 +1,9 @@
 use prometheus::{
-    IntCounter, IntGauge, Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts,
+    IntCounter, IntGauge, IntGaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts,
     Registry, TextEncoder, Encoder,
     register_int_counter_with_registry, register_int_gauge_with_registry, 
     register_histogram_with_registry, register_histogram_vec_with_registry,
-    register_int_counter_vec_with_registry,
+    register_int_counter_vec_with_registry, register_int_gauge_vec_with_registry,
 };
 use lazy_static::lazy_static;
 use std::sync::Arc;
@@ -38,6 +38,27 @@ lazy_static! {
         METRICS_REGISTRY.clone()
     ).expect("Failed to register HTTP_REQUESTS_TOTAL metric");
     
+    /// Calls waiting in the admission queue, by lane (a model or provider with max_concurrent)
+    pub static ref ADMISSION_QUEUE_DEPTH: IntGaugeVec = register_int_gauge_vec_with_registry!(
+        Opts::new("admission_queue_depth", "Calls waiting for a concurrency slot, by scope and lane"),
+        &["scope", "lane"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register ADMISSION_QUEUE_DEPTH metric");
+    
+    /// Time spent waiting for a concurrency slot, by scope and priority
+    pub static ref ADMISSION_QUEUE_WAIT: HistogramVec = register_histogram_vec_with_registry!(
+        HistogramOpts::new("admission_queue_wait_seconds", "Time calls waited for a concurrency slot")
+            .buckets(vec![0.001, 0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+        &["scope", "priority"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register ADMISSION_QUEUE_WAIT metric");
+    
+    pub static ref ADMISSION_QUEUE_TIMEOUTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("admission_queue_timeouts_total", "Calls that gave up waiting for a concurrency slot, by scope and priority"),
+        &["scope", "priority"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register ADMISSION_QUEUE_TIMEOUTS metric");
+    
     /// Database query latency by operation
     pub static ref DB_QUERY_DURATION: HistogramVec = register_histogram_vec_with_registry!(
         HistogramOpts::new("db_query_duration_seconds", "Database query latency")
@@ -120,6 +141,26 @@ lazy_static! {
         METRICS_REGISTRY.clone()
     ).expect("Failed to register HTTP_REQUESTS_TOTAL metric");
     
+    pub static ref RESPONSE_CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("response_cache_lookups_total", "Response cache lookups by service and result (memory_hit, postgres_hit, semantic_hit, miss, semantic_miss)"),
+        &["service", "result"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register RESPONSE_CACHE_LOOKUPS metric");
+    
+    /// Similarity of the closest cached prompt on semantic cache lookups, for tuning thresholds
+    pub static ref SEMANTIC_CACHE_SIMILARITY: HistogramVec = register_histogram_vec_with_registry!(
+        HistogramOpts::new("semantic_cache_similarity", "Cosine similarity of the nearest cached prompt")
+            .buckets(vec![0.5, 0.6, 0.7, 0.8, 0.85, 0.9, 0.93, 0.95, 0.97, 0.99, 1.0]),
+        &["service"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register SEMANTIC_CACHE_SIMILARITY metric");
+    
+    pub static ref GUARDRAIL_VIOLATIONS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("guardrail_violations_total", "Guardrail violations by service, guardrail and action taken"),
+        &["service", "guardrail", "action"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register GUARDRAIL_VIOLATIONS metric");
+    
     // ============ WORKER/QUEUE METRICS ============
     
     pub static ref LOG_BUFFER_DEPTH: IntGauge = register_int_gauge_with_registry!(
@@ -155,6 +196,70 @@ lazy_static! {
         Opts::new("failover_total", "Total failover attempts"),
         METRICS_REGISTRY.clone()
     ).expect("Failed to register HTTP_REQUESTS_TOTAL metric");
+    
+    pub static ref HEDGED_REQUESTS: IntCounter = register_int_counter_with_registry!(
+        Opts::new("hedged_requests_total", "Requests that launched at least one hedge"),
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register HEDGED_REQUESTS metric");
+    
+    pub static ref HEDGE_WINS: IntCounter = register_int_counter_with_registry!(
+        Opts::new("hedge_wins_total", "Hedged requests won by a hedge rather than the primary"),
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register HEDGE_WINS metric");
+    
+    pub static ref SHADOW_REQUESTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("shadow_requests_total", "Shadow replays by outcome"),
+        &["service", "status"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register SHADOW_REQUESTS metric");
+    
+    pub static ref SERVICE_FALLBACKS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("service_fallback_total", "Cross-service fallbacks taken"),
+        &["service", "fallback_service"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register SERVICE_FALLBACKS metric");
+    
+    pub static ref MEDIA_REQUESTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("media_requests_total", "Image, audio and video requests by outcome"),
+        &["modality", "status"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register MEDIA_REQUESTS metric");
+    
+    pub static ref BUDGET_DOWNGRADES: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("budget_downgrades_total", "Requests routed to cheaper models by a budget policy"),
+        &["service"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register BUDGET_DOWNGRADES metric");
+    
+    pub static ref UPSTREAM_TIMEOUTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("upstream_timeouts_total", "Upstream calls cut off by a timeout, by phase"),
+        &["phase"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register UPSTREAM_TIMEOUTS metric");
+    
+    pub static ref RETRIES: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("retries_total", "Attempts retried on the same model, by error class"),
+        &["class"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register RETRIES metric");
+    
+    pub static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("rate_limited_total", "Requests rejected by a rate limit, by scope and limit kind"),
+        &["scope", "kind"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register RATE_LIMITED metric");
+    
+    pub static ref UPSTREAM_THROTTLED: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("upstream_throttled_total", "Calls held back to stay under provider rate limits, by provider and outcome (queued, rejected, steered)"),
+        &["provider", "outcome"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register UPSTREAM_THROTTLED metric");
+    
+    pub static ref KEYS_QUARANTINED: IntCounterVec = register_int_counter_vec_with_registry!(
+        Opts::new("provider_keys_quarantined_total", "Pooled provider API keys quarantined after 401/403 responses, by provider"),
+        &["provider"],
+        METRICS_REGISTRY.clone()
+    ).expect("Failed to register KEYS_QUARANTINED metric");
 }
 
 /// Get metrics as Prometheus-formatted text
@@ -164,6 +269,9 @@ pub fn gather_metrics() -> String {
     let _ = HTTP_REQUESTS_ERRORS.get();
     let _ = REQUEST_DURATION.get_sample_count();
     let _ = REQUESTS_IN_FLIGHT.get();
+    let _ = &*ADMISSION_QUEUE_DEPTH;
+    let _ = &*ADMISSION_QUEUE_WAIT;
+    let _ = &*ADMISSION_QUEUE_TIMEOUTS;
     let _ = SERVICE_HEALTH.get();
     let _ = DB_CONNECTIONS_ACTIVE.get();
     let _ = DB_CONNECTIONS_IDLE.get();
@@ -172,10 +280,24 @@ pub fn gather_metrics() -> String {
     let _ = CIRCUIT_BREAKER_OPEN.get();
     let _ = CACHE_HITS.get();
     let _ = CACHE_MISSES.get();
+    let _ = &*RESPONSE_CACHE_LOOKUPS;
+    let _ = &*SEMANTIC_CACHE_SIMILARITY;
+    let _ = &*GUARDRAIL_VIOLATIONS;
     let _ = LOG_BUFFER_DEPTH.get();
     let _ = LOG_DROPS.get();
     let _ = QUOTA_WORKER_QUEUE_DEPTH.get();
     let _ = FAILOVER_COUNT.get();
+    let _ = HEDGED_REQUESTS.get();
+    let _ = HEDGE_WINS.get();
+    let _ = &*SHADOW_REQUESTS;
+    let _ = &*SERVICE_FALLBACKS;
+    let _ = &*MEDIA_REQUESTS;
+    let _ = &*BUDGET_DOWNGRADES;
+    let _ = &*UPSTREAM_TIMEOUTS;
+    let _ = &*RETRIES;
+    let _ = &*RATE_LIMITED;
+    let _ = &*UPSTREAM_THROTTLED;
+    let _ = &*KEYS_QUARANTINED;
     
     let encoder = TextEncoder::new();
     let metric_families = METRICS_REGISTRY.gather();
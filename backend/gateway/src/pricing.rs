@@ -79,6 +79,20 @@ impl PricingData {
             completion_price_per_million: 12.0,
         });
 
+        // Embedding models (input only), used by the semantic response cache
+        prices.insert("text-embedding-3-small".to_string(), ModelPricing {
+            prompt_price_per_million: 0.02,
+            completion_price_per_million: 0.0,
+        });
+        prices.insert("text-embedding-3-large".to_string(), ModelPricing {
+            prompt_price_per_million: 0.13,
+            completion_price_per_million: 0.0,
+        });
+        prices.insert("text-embedding-ada-002".to_string(), ModelPricing {
+            prompt_price_per_million: 0.10,
+            completion_price_per_million: 0.0,
+        });
+
         Self { prices }
     }
 
@@ -0,0 +1,231 @@
+//! Provider Key Pool Admin API
+//!
+//! Add, disable and remove the pooled API keys of a provider, and restore keys
+//! quarantined after 401/403 responses. Keys are only ever returned masked. Admin only.
+
+use poem_openapi::{
+    param::Path,
+    payload::Json,
+    Object, OpenApi, Tags,
+};
+use poem::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use mawi_core::models::ProviderKey;
+
+use crate::api::require_admin;
+use crate::config_sync::Invalidation;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::executor::Executor;
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Provider Key Pools
+    ProviderKeys,
+}
+
+/// A pooled key as shown to admins
+#[derive(Debug, Serialize, Object)]
+pub struct ProviderKeyInfo {
+    pub id: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    /// Last four characters of the key
+    pub masked_key: String,
+    pub api_endpoint: Option<String>,
+    pub enabled: bool,
+    pub quarantined_at: Option<i64>,
+    pub quarantine_reason: Option<String>,
+    pub created_at: Option<i64>,
+    /// Calls currently using the key on this replica
+    pub in_use: u32,
+    /// Key circuit: closed, open or half_open
+    pub circuit_state: String,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct CreateProviderKey {
+    pub name: Option<String>,
+    pub api_key: String,
+    /// Base URL for this key, e.g. its Azure deployment; defaults to the model's or provider's
+    pub api_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct UpdateProviderKey {
+    pub name: Option<String>,
+    pub api_endpoint: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+pub struct ProviderKeysApi {
+    pub executor: Arc<Executor>,
+}
+
+fn db_error(e: sqlx::Error) -> poem::Error {
+    poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn not_found() -> poem::Error {
+    poem::Error::from_string("Provider key not found", poem::http::StatusCode::NOT_FOUND)
+}
+
+impl ProviderKeysApi {
+    fn info(&self, key: ProviderKey) -> ProviderKeyInfo {
+        let circuit = CircuitBreaker::credential_key(&key.id);
+        let circuit_state = self.executor.circuit_breaker.snapshot()
+            .into_iter()
+            .find(|c| c.resource == circuit)
+            .map(|c| c.state)
+            .unwrap_or_else(|| "closed".to_string());
+        let masked_key = mawi_core::utils::mask_api_key(&crate::key_pool::decrypt_api_key(&key.api_key, &key.provider_id));
+        ProviderKeyInfo {
+            in_use: self.executor.key_pool.in_use(&key.id),
+            circuit_state,
+            masked_key,
+            id: key.id,
+            provider_id: key.provider_id,
+            name: key.name,
+            api_endpoint: key.api_endpoint,
+            enabled: key.enabled,
+            quarantined_at: key.quarantined_at,
+            quarantine_reason: key.quarantine_reason,
+            created_at: key.created_at,
+        }
+    }
+
+    async fn fetch(&self, provider_id: &str, key_id: &str) -> Result<ProviderKey> {
+        sqlx::query_as::<_, ProviderKey>("SELECT * FROM provider_keys WHERE id = $1 AND provider_id = $2")
+            .bind(key_id)
+            .bind(provider_id)
+            .fetch_optional(&self.executor.pool)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(not_found)
+    }
+}
+
+#[OpenApi]
+impl ProviderKeysApi {
+    /// List a provider's pooled keys
+    #[oai(path = "/providers/:id/keys", method = "get", tag = "ApiTags::ProviderKeys")]
+    async fn list_keys(&self, id: Path<String>, req: &poem::Request) -> Result<Json<Vec<ProviderKeyInfo>>> {
+        require_admin(req)?;
+        let keys = sqlx::query_as::<_, ProviderKey>(
+            "SELECT * FROM provider_keys WHERE provider_id = $1 ORDER BY created_at, id"
+        )
+        .bind(&id.0)
+        .fetch_all(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(Json(keys.into_iter().map(|key| self.info(key)).collect()))
+    }
+
+    /// Add a key to a provider's pool
+    #[oai(path = "/providers/:id/keys", method = "post", tag = "ApiTags::ProviderKeys")]
+    async fn create_key(&self, id: Path<String>, body: Json<CreateProviderKey>, req: &poem::Request) -> Result<Json<ProviderKeyInfo>> {
+        require_admin(req)?;
+        if body.api_key.trim().is_empty() {
+            return Err(poem::Error::from_string("api_key is required", poem::http::StatusCode::BAD_REQUEST));
+        }
+        let provider_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM providers WHERE id = $1")
+            .bind(&id.0)
+            .fetch_one(&self.executor.pool)
+            .await
+            .map_err(db_error)? > 0;
+        if !provider_exists {
+            return Err(poem::Error::from_string(
+                format!("Provider '{}' not found", id.0),
+                poem::http::StatusCode::NOT_FOUND,
+            ));
+        }
+
+        let encrypted = mawi_core::security::encrypt_key(body.api_key.trim()).map_err(|e| {
+            poem::Error::from_string(format!("Encryption failed: {}", e), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        let key = sqlx::query_as::<_, ProviderKey>(
+            "INSERT INTO provider_keys (id, provider_id, name, api_key, api_endpoint, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&id.0)
+        .bind(&body.name)
+        .bind(&encrypted)
+        .bind(body.api_endpoint.as_deref().filter(|e| !e.is_empty()))
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        self.executor.invalidate(Invalidation::Provider(id.0.clone())).await;
+        eprintln!("🔑 Added API key {} to provider {}", mawi_core::utils::mask_api_key(body.api_key.trim()), id.0);
+        Ok(Json(self.info(key)))
+    }
+
+    /// Rename, re-point, enable or disable a pooled key
+    #[oai(path = "/providers/:id/keys/:key_id", method = "put", tag = "ApiTags::ProviderKeys")]
+    async fn update_key(
+        &self,
+        id: Path<String>,
+        key_id: Path<String>,
+        body: Json<UpdateProviderKey>,
+        req: &poem::Request,
+    ) -> Result<Json<ProviderKeyInfo>> {
+        require_admin(req)?;
+        let current = self.fetch(&id, &key_id).await?;
+        let key = sqlx::query_as::<_, ProviderKey>(
+            "UPDATE provider_keys SET name = $3, api_endpoint = $4, enabled = $5
+             WHERE id = $1 AND provider_id = $2 RETURNING *"
+        )
+        .bind(&key_id.0)
+        .bind(&id.0)
+        .bind(body.name.clone().or(current.name))
+        .bind(body.api_endpoint.clone().or(current.api_endpoint).filter(|e| !e.is_empty()))
+        .bind(body.enabled.unwrap_or(current.enabled))
+        .fetch_one(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        self.executor.invalidate(Invalidation::Provider(id.0.clone())).await;
+        Ok(Json(self.info(key)))
+    }
+
+    /// Return a quarantined key to the pool
+    #[oai(path = "/providers/:id/keys/:key_id/restore", method = "post", tag = "ApiTags::ProviderKeys")]
+    async fn restore_key(&self, id: Path<String>, key_id: Path<String>, req: &poem::Request) -> Result<Json<ProviderKeyInfo>> {
+        require_admin(req)?;
+        self.fetch(&id, &key_id).await?;
+        let key = sqlx::query_as::<_, ProviderKey>(
+            "UPDATE provider_keys SET quarantined_at = NULL, quarantine_reason = NULL
+             WHERE id = $1 AND provider_id = $2 RETURNING *"
+        )
+        .bind(&key_id.0)
+        .bind(&id.0)
+        .fetch_one(&self.executor.pool)
+        .await
+        .map_err(db_error)?;
+
+        self.executor.invalidate(Invalidation::Provider(id.0.clone())).await;
+        eprintln!("🔓 Restored API key {} of provider {}", key_id.0, id.0);
+        Ok(Json(self.info(key)))
+    }
+
+    /// Remove a key from a provider's pool
+    #[oai(path = "/providers/:id/keys/:key_id", method = "delete", tag = "ApiTags::ProviderKeys")]
+    async fn delete_key(&self, id: Path<String>, key_id: Path<String>, req: &poem::Request) -> Result<()> {
+        require_admin(req)?;
+        let result = sqlx::query("DELETE FROM provider_keys WHERE id = $1 AND provider_id = $2")
+            .bind(&key_id.0)
+            .bind(&id.0)
+            .execute(&self.executor.pool)
+            .await
+            .map_err(db_error)?;
+        if result.rows_affected() == 0 {
+            return Err(not_found());
+        }
+
+        self.executor.invalidate(Invalidation::Provider(id.0.clone())).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,594 @@
+//! Rate Limiting
+//!
+//! Token-bucket limits on requests and tokens per minute, plus concurrent-request caps,
+//! per API key, user, org and service. Limits come from the `rate_limits` table (subject
+//! `*` is the scope-wide default). Buckets live in memory per replica, or in Postgres when
+//! `RATE_LIMIT_BACKEND=postgres` so every replica draws from the same budget. In-flight
+//! counts always stay per replica: a crashed replica must not leave requests counted forever.
+
+use dashmap::DashMap;
+use moka::future::Cache;
+use poem::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use poem::{Body, Endpoint, IntoResponse, Middleware, Request, Response, Result};
+use poem_openapi::Object;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+/// Scopes a limit can apply to
+pub const SCOPES: [&str; 4] = ["api_key", "user", "org", "service"];
+
+/// Paths that run inference; management APIs aren't limited
+const LIMITED_PATHS: [&str; 4] = ["/v1/chat/completions", "/v1/images/", "/v1/audio/", "/v1/videos/generations"];
+
+/// Limits for one subject; unset fields are unlimited
+#[derive(Debug, Clone, Serialize, Deserialize, Object, sqlx::FromRow)]
+pub struct RateLimit {
+    /// api_key, user, org or service
+    pub scope: String,
+    /// Key ID, user ID, org ID or service name; `*` for the scope default
+    pub subject: String,
+    pub requests_per_minute: Option<i32>,
+    /// Charged at admission from the prompt size plus the requested max_tokens
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrent: Option<i32>,
+}
+
+/// Where token buckets are kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitBackend {
+    /// Per replica
+    Memory,
+    /// Shared by all replicas through `rate_limit_buckets`
+    Postgres,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub backend: RateLimitBackend,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { enabled: true, backend: RateLimitBackend::Memory }
+    }
+}
+
+impl RateLimitConfig {
+    /// Read `RATE_LIMIT_ENABLED` and `RATE_LIMIT_BACKEND` (memory | postgres)
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: std::env::var("RATE_LIMIT_ENABLED")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.enabled),
+            backend: match std::env::var("RATE_LIMIT_BACKEND").unwrap_or_default().trim().to_lowercase().as_str() {
+                "postgres" => RateLimitBackend::Postgres,
+                _ => defaults.backend,
+            },
+        }
+    }
+}
+
+/// Token bucket refilled continuously, `capacity` per minute
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Bucket {
+    pub(crate) tokens: f64,
+    pub(crate) refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Refill for the time elapsed, then take `cost`. The balance may go negative;
+    /// denied requests refund what they took.
+    pub(crate) fn take(&mut self, capacity: f64, cost: f64, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * capacity / 60.0).min(capacity);
+        self.refilled_at = now;
+        self.tokens -= cost;
+        self.tokens
+    }
+
+    /// Untouched for a whole refill period, so full again. Balances don't stay negative
+    /// (denied requests refund), so a fresh bucket would be the same.
+    pub(crate) fn is_idle(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.refilled_at) >= BUCKET_PERIOD
+    }
+}
+
+/// Time for any bucket to refill completely
+const BUCKET_PERIOD: Duration = Duration::from_secs(60);
+
+/// Allowance left on the tightest bucket of one kind, reported in `x-ratelimit-*` headers
+#[derive(Debug, Clone, Copy)]
+pub struct Allowance {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Until the bucket is full again
+    pub reset: Duration,
+}
+
+impl Allowance {
+    fn new(capacity: f64, balance: f64) -> Self {
+        let missing = (capacity - balance).max(0.0);
+        Self {
+            limit: capacity as u64,
+            remaining: balance.max(0.0) as u64,
+            reset: Duration::from_secs_f64(missing * 60.0 / capacity),
+        }
+    }
+
+    fn tighter(current: Option<Self>, candidate: Self) -> Option<Self> {
+        match current {
+            Some(current) if current.remaining <= candidate.remaining => Some(current),
+            _ => Some(candidate),
+        }
+    }
+}
+
+/// Releases concurrency slots when the request (or its stream) finishes
+pub struct InFlightGuard {
+    counts: Arc<DashMap<String, u32>>,
+    keys: Vec<String>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        for key in &self.keys {
+            if let Some(mut count) = self.counts.get_mut(key) {
+                *count = count.saturating_sub(1);
+            }
+            // Subjects with nothing in flight don't keep an entry
+            self.counts.remove_if(key, |_, count| *count == 0);
+        }
+    }
+}
+
+/// A request let through, with what to report back
+#[derive(Default)]
+pub struct Admission {
+    pub requests: Option<Allowance>,
+    pub tokens: Option<Allowance>,
+    guard: Option<InFlightGuard>,
+}
+
+/// A request turned away
+#[derive(Debug)]
+pub struct Denial {
+    pub scope: &'static str,
+    /// requests, tokens or concurrency
+    pub kind: &'static str,
+    pub retry_after: Duration,
+    pub requests: Option<Allowance>,
+    pub tokens: Option<Allowance>,
+}
+
+fn set_allowance_headers(headers: &mut HeaderMap, requests: Option<Allowance>, tokens: Option<Allowance>) {
+    for (kind, allowance) in [("requests", requests), ("tokens", tokens)] {
+        let Some(allowance) = allowance else { continue };
+        let values = [
+            ("limit", allowance.limit.to_string()),
+            ("remaining", allowance.remaining.to_string()),
+            ("reset", format!("{}s", allowance.reset.as_secs_f64().ceil() as u64)),
+        ];
+        for (field, value) in values {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(format!("x-ratelimit-{}-{}", field, kind)),
+                HeaderValue::from_str(&value),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+impl IntoResponse for Denial {
+    fn into_response(self) -> Response {
+        let retry_after = self.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        let what = match self.kind {
+            "requests" => "requests per minute",
+            "tokens" => "tokens per minute",
+            _ => "concurrent requests",
+        };
+        let mut response = Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::RETRY_AFTER, retry_after)
+            .body(format!("Rate limit exceeded: {} for {}. Retry in {}s", what, self.scope, retry_after));
+        set_allowance_headers(response.headers_mut(), self.requests, self.tokens);
+        response
+    }
+}
+
+pub struct RateLimiter {
+    pool: PgPool,
+    config: RateLimitConfig,
+    buckets: DashMap<String, Bucket>,
+    /// When idle buckets were last dropped from `buckets`
+    buckets_swept: std::sync::Mutex<Instant>,
+    in_flight: Arc<DashMap<String, u32>>,
+    limits: Cache<String, Option<RateLimit>>,
+    api_key_ids: Cache<String, Option<String>>,
+}
+
+impl RateLimiter {
+    pub fn new(pool: PgPool, config: RateLimitConfig) -> Self {
+        if config.enabled {
+            eprintln!("🚦 Rate limiting enabled ({:?} buckets)", config.backend);
+        }
+        Self {
+            pool,
+            config,
+            buckets: DashMap::new(),
+            buckets_swept: std::sync::Mutex::new(Instant::now()),
+            in_flight: Arc::new(DashMap::new()),
+            limits: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(60))
+                .build(),
+            api_key_ids: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(60))
+                .build(),
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Drop cached limits after they change
+    pub fn invalidate_limits(&self) {
+        self.limits.invalidate_all();
+    }
+
+    /// Subject's own limit, else its scope default
+    async fn limit_for(&self, scope: &str, subject: &str) -> Option<RateLimit> {
+        let cache_key = format!("{}:{}", scope, subject);
+        if let Some(limit) = self.limits.get(&cache_key).await {
+            return limit;
+        }
+        let limit = sqlx::query_as::<_, RateLimit>(
+            "SELECT scope, subject, requests_per_minute, tokens_per_minute, max_concurrent
+             FROM rate_limits WHERE scope = $1 AND subject IN ($2, '*')
+             ORDER BY subject = '*' LIMIT 1"
+        )
+        .bind(scope)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await;
+        match limit {
+            Ok(limit) => {
+                self.limits.insert(cache_key, limit.clone()).await;
+                limit
+            }
+            Err(e) => {
+                warn!(scope, subject, error = %e, "could not load rate limit, allowing request");
+                None
+            }
+        }
+    }
+
+    /// `api_keys.id` for a key hash
+    async fn api_key_id(&self, key_hash: &str) -> Option<String> {
+        if let Some(id) = self.api_key_ids.get(key_hash).await {
+            return id;
+        }
+        let id = sqlx::query_scalar::<_, String>("SELECT id FROM api_keys WHERE key_hash = $1")
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()?;
+        self.api_key_ids.insert(key_hash.to_string(), id.clone()).await;
+        id
+    }
+
+    /// Take `cost` from a bucket, returning the balance left (negative when over the limit)
+    async fn take(&self, key: &str, capacity: f64, cost: f64) -> f64 {
+        match self.config.backend {
+            RateLimitBackend::Memory => {
+                let now = Instant::now();
+                let balance = self.buckets
+                    .entry(key.to_string())
+                    .or_insert(Bucket { tokens: capacity, refilled_at: now })
+                    .take(capacity, cost, now);
+                self.sweep_buckets(now);
+                balance
+            }
+            RateLimitBackend::Postgres => {
+                let now = chrono::Utc::now().timestamp_micros() as f64 / 1_000_000.0;
+                let balance = sqlx::query_scalar::<_, f64>(
+                    "INSERT INTO rate_limit_buckets (bucket, tokens, refilled_at) VALUES ($1, $2 - $4, $5)
+                     ON CONFLICT (bucket) DO UPDATE SET
+                        tokens = LEAST($2, rate_limit_buckets.tokens
+                            + GREATEST(0, $5 - rate_limit_buckets.refilled_at) * $3) - $4,
+                        refilled_at = GREATEST($5, rate_limit_buckets.refilled_at)
+                     RETURNING tokens"
+                )
+                .bind(key)
+                .bind(capacity)
+                .bind(capacity / 60.0)
+                .bind(cost)
+                .bind(now)
+                .fetch_one(&self.pool)
+                .await;
+                balance.unwrap_or_else(|e| {
+                    warn!(bucket = key, error = %e, "shared rate limit bucket unavailable, allowing request");
+                    capacity
+                })
+            }
+        }
+    }
+
+    /// Drop idle buckets, at most once a refill period, so subjects that stop sending
+    /// requests don't stay in memory
+    fn sweep_buckets(&self, now: Instant) {
+        {
+            let mut swept = self.buckets_swept.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if now.saturating_duration_since(*swept) < BUCKET_PERIOD {
+                return;
+            }
+            *swept = now;
+        }
+        self.buckets.retain(|_, bucket| !bucket.is_idle(now));
+    }
+
+    async fn refund(&self, key: &str, cost: f64) {
+        match self.config.backend {
+            RateLimitBackend::Memory => {
+                if let Some(mut bucket) = self.buckets.get_mut(key) {
+                    bucket.tokens += cost;
+                }
+            }
+            RateLimitBackend::Postgres => {
+                let _ = sqlx::query("UPDATE rate_limit_buckets SET tokens = tokens + $2 WHERE bucket = $1")
+                    .bind(key)
+                    .bind(cost)
+                    .execute(&self.pool)
+                    .await;
+            }
+        }
+    }
+
+    /// Charge a request to every subject with a limit. Either every bucket is charged and a
+    /// concurrency slot held, or nothing is and the tightest limit is reported.
+    pub async fn admit(&self, subjects: &[(&'static str, String)], estimated_tokens: u64) -> std::result::Result<Admission, Denial> {
+        let mut limits = Vec::new();
+        for (scope, subject) in subjects {
+            if let Some(limit) = self.limit_for(scope, subject).await {
+                limits.push((*scope, subject, limit));
+            }
+        }
+        if limits.is_empty() {
+            return Ok(Admission::default());
+        }
+
+        let mut admission = Admission::default();
+        let mut taken: Vec<(String, f64)> = Vec::new();
+        let mut denial: Option<(&'static str, &'static str, Duration)> = None;
+        for (scope, subject, limit) in &limits {
+            let per_minute = [
+                ("requests", limit.requests_per_minute, 1.0),
+                ("tokens", limit.tokens_per_minute, estimated_tokens as f64),
+            ];
+            for (kind, per_minute, cost) in per_minute {
+                let Some(capacity) = per_minute.filter(|n| *n > 0).map(f64::from) else { continue };
+                // A request bigger than the whole budget could otherwise never pass
+                let cost = cost.min(capacity);
+                let key = format!("{}:{}:{}", kind, scope, subject);
+                let balance = self.take(&key, capacity, cost).await;
+                taken.push((key, cost));
+
+                if balance < 0.0 {
+                    let retry_after = Duration::from_secs_f64(-balance * 60.0 / capacity);
+                    if denial.is_none_or(|(_, _, longest)| retry_after > longest) {
+                        denial = Some((scope, kind, retry_after));
+                    }
+                }
+                let allowance = Allowance::new(capacity, balance);
+                match kind {
+                    "requests" => admission.requests = Allowance::tighter(admission.requests, allowance),
+                    _ => admission.tokens = Allowance::tighter(admission.tokens, allowance),
+                }
+            }
+        }
+
+        let deny = |scope, kind, retry_after| Denial {
+            scope,
+            kind,
+            retry_after,
+            requests: admission.requests,
+            tokens: admission.tokens,
+        };
+        let denied = match denial {
+            Some((scope, kind, retry_after)) => Some(deny(scope, kind, retry_after)),
+            None => {
+                let mut guard = InFlightGuard { counts: self.in_flight.clone(), keys: Vec::new() };
+                let mut full = None;
+                for (scope, subject, limit) in &limits {
+                    let Some(cap) = limit.max_concurrent.filter(|n| *n > 0) else { continue };
+                    let key = format!("concurrent:{}:{}", scope, subject);
+                    let mut count = self.in_flight.entry(key.clone()).or_insert(0);
+                    if *count >= cap as u32 {
+                        full = Some(*scope);
+                        break;
+                    }
+                    *count += 1;
+                    drop(count);
+                    guard.keys.push(key);
+                }
+                match full {
+                    // Releases the slots taken so far
+                    Some(scope) => Some(deny(scope, "concurrency", Duration::from_secs(1))),
+                    None => {
+                        admission.guard = Some(guard);
+                        None
+                    }
+                }
+            }
+        };
+
+        match denied {
+            Some(denial) => {
+                for (key, cost) in taken {
+                    self.refund(&key, cost).await;
+                }
+                crate::metrics::RATE_LIMITED.with_label_values(&[denial.scope, denial.kind]).inc();
+                Err(denial)
+            }
+            None => Ok(admission),
+        }
+    }
+}
+
+/// Service named by a JSON inference request and its estimated token cost: prompt size
+/// (about four bytes a token) plus the requested `max_tokens`
+fn request_usage(body: &[u8]) -> (Option<String>, u64) {
+    let value: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    let service = value
+        .get("service")
+        .or_else(|| value.get("model"))
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+    let max_tokens = value
+        .get("params")
+        .and_then(|p| p.get("max_tokens"))
+        .or_else(|| value.get("max_tokens"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+    (service, body.len() as u64 / 4 + max_tokens)
+}
+
+/// Enforces rate limits on inference requests; runs after `AuthMiddleware`
+pub struct RateLimitMiddleware {
+    pub limiter: Arc<RateLimiter>,
+}
+
+impl<E: Endpoint> Middleware<E> for RateLimitMiddleware {
+    type Output = RateLimitEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RateLimitEndpoint { ep, limiter: self.limiter.clone() }
+    }
+}
+
+pub struct RateLimitEndpoint<E> {
+    ep: E,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<E: Endpoint> Endpoint for RateLimitEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        let limited = self.limiter.config.enabled
+            && req.method() == Method::POST
+            && LIMITED_PATHS.iter().any(|p| req.original_uri().path().starts_with(p));
+        let user = match req.extensions().get::<mawi_core::auth::User>() {
+            Some(user) if limited => user.clone(),
+            _ => return self.ep.call(req).await.map(IntoResponse::into_response),
+        };
+
+        // JSON bodies name the service and size the token estimate; the body is put back for the handler
+        let is_json = req.content_type().is_some_and(|t| t.starts_with("application/json"));
+        let (service, estimated_tokens) = if is_json {
+            let body = req.take_body().into_bytes().await?;
+            let usage = request_usage(&body);
+            req.set_body(body);
+            usage
+        } else {
+            (None, 0)
+        };
+
+        let mut subjects = vec![("user", user.id.clone())];
+        if let Some(hash) = mawi_core::auth::utils::api_key_hash(&req) {
+            if let Some(id) = self.limiter.api_key_id(&hash).await {
+                subjects.push(("api_key", id));
+            }
+        }
+        if let Some(org_id) = user.org_id.clone() {
+            subjects.push(("org", org_id));
+        }
+        if let Some(service) = service {
+            subjects.push(("service", service));
+        }
+
+        let admission = match self.limiter.admit(&subjects, estimated_tokens).await {
+            Ok(admission) => admission,
+            Err(denial) => {
+                warn!(user = %user.id, scope = denial.scope, kind = denial.kind, "rate limit exceeded");
+                return Ok(denial.into_response());
+            }
+        };
+
+        let mut response = self.ep.call(req).await?.into_response();
+        set_allowance_headers(response.headers_mut(), admission.requests, admission.tokens);
+
+        // Streams hold their concurrency slot until the last chunk is sent
+        let streaming = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|t| t.starts_with("text/event-stream"));
+        if let (true, Some(guard)) = (streaming, admission.guard) {
+            let body = response.take_body().into_bytes_stream().map(move |chunk| {
+                let _held = &guard;
+                chunk
+            });
+            response.set_body(Body::from_bytes_stream(body));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_refills_per_minute() {
+        let start = Instant::now();
+        let mut bucket = Bucket { tokens: 60.0, refilled_at: start };
+
+        assert_eq!(bucket.take(60.0, 60.0, start), 0.0);
+        assert_eq!(bucket.take(60.0, 1.0, start), -1.0);
+        // One token a second comes back
+        assert_eq!(bucket.take(60.0, 0.0, start + Duration::from_secs(11)), 10.0);
+        // Never above capacity
+        assert_eq!(bucket.take(60.0, 0.0, start + Duration::from_secs(600)), 60.0);
+    }
+
+    #[test]
+    fn test_bucket_idle_after_refill_period() {
+        let start = Instant::now();
+        let bucket = Bucket { tokens: 0.0, refilled_at: start };
+
+        assert!(!bucket.is_idle(start + Duration::from_secs(59)));
+        assert!(bucket.is_idle(start + BUCKET_PERIOD));
+    }
+
+    #[test]
+    fn test_in_flight_entries_removed_when_released() {
+        let counts = Arc::new(DashMap::new());
+        counts.insert("concurrent:user:a".to_string(), 2);
+        counts.insert("concurrent:user:b".to_string(), 1);
+
+        drop(InFlightGuard { counts: counts.clone(), keys: vec!["concurrent:user:a".into(), "concurrent:user:b".into()] });
+        assert_eq!(counts.get("concurrent:user:a").map(|c| *c), Some(1));
+        assert!(!counts.contains_key("concurrent:user:b"));
+    }
+
+    #[test]
+    fn test_request_usage_estimate() {
+        let body = br#"{"service":"chat","messages":[{"role":"user","content":"hello there"}],"params":{"max_tokens":100}}"#;
+        let (service, tokens) = request_usage(body);
+        assert_eq!(service.as_deref(), Some("chat"));
+        assert_eq!(tokens, body.len() as u64 / 4 + 100);
+
+        assert_eq!(request_usage(b"not json"), (None, 2));
+    }
+}
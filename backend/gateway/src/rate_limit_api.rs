@@ -0,0 +1,138 @@
+//! Rate Limit Admin API
+//!
+//! Manage requests-per-minute, tokens-per-minute and concurrency limits
+//! per API key, user, org and service. Admin only.
+
+use poem_openapi::{
+    param::Path,
+    payload::Json,
+    Object, OpenApi, Tags,
+};
+use poem::Result;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::api::require_admin;
+use crate::rate_limit::{RateLimit, RateLimiter, SCOPES};
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Rate Limit Administration
+    RateLimits,
+}
+
+/// Limits to set for a subject; omitted fields are unlimited
+#[derive(Debug, Deserialize, Object)]
+pub struct SetRateLimitRequest {
+    pub requests_per_minute: Option<i32>,
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrent: Option<i32>,
+}
+
+pub struct RateLimitApi {
+    pub limiter: Arc<RateLimiter>,
+}
+
+fn check_scope(scope: &str) -> Result<()> {
+    if SCOPES.contains(&scope) {
+        Ok(())
+    } else {
+        Err(poem::Error::from_string(
+            format!("Unknown rate limit scope '{}' (expected one of: {})", scope, SCOPES.join(", ")),
+            poem::http::StatusCode::BAD_REQUEST,
+        ))
+    }
+}
+
+fn db_error(e: sqlx::Error) -> poem::Error {
+    poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[OpenApi]
+impl RateLimitApi {
+    /// List configured rate limits
+    #[oai(path = "/admin/rate-limits", method = "get", tag = "ApiTags::RateLimits")]
+    async fn list_rate_limits(&self, req: &poem::Request) -> Result<Json<Vec<RateLimit>>> {
+        require_admin(req)?;
+        let limits = sqlx::query_as::<_, RateLimit>(
+            "SELECT scope, subject, requests_per_minute, tokens_per_minute, max_concurrent
+             FROM rate_limits ORDER BY scope, subject"
+        )
+        .fetch_all(self.limiter.pool())
+        .await
+        .map_err(db_error)?;
+        Ok(Json(limits))
+    }
+
+    /// Set the limits for a subject, or for a whole scope with subject `*`
+    #[oai(path = "/admin/rate-limits/:scope/:subject", method = "put", tag = "ApiTags::RateLimits")]
+    async fn set_rate_limit(
+        &self,
+        scope: Path<String>,
+        subject: Path<String>,
+        body: Json<SetRateLimitRequest>,
+        req: &poem::Request,
+    ) -> Result<Json<RateLimit>> {
+        require_admin(req)?;
+        check_scope(&scope)?;
+        let values = [
+            ("requests_per_minute", body.requests_per_minute),
+            ("tokens_per_minute", body.tokens_per_minute),
+            ("max_concurrent", body.max_concurrent),
+        ];
+        if let Some((field, _)) = values.iter().find(|(_, v)| v.is_some_and(|n| n <= 0)) {
+            return Err(poem::Error::from_string(
+                format!("{} must be positive", field),
+                poem::http::StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let limit = sqlx::query_as::<_, RateLimit>(
+            "INSERT INTO rate_limits (scope, subject, requests_per_minute, tokens_per_minute, max_concurrent, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (scope, subject) DO UPDATE SET
+                requests_per_minute = EXCLUDED.requests_per_minute,
+                tokens_per_minute = EXCLUDED.tokens_per_minute,
+                max_concurrent = EXCLUDED.max_concurrent,
+                updated_at = EXCLUDED.updated_at
+             RETURNING scope, subject, requests_per_minute, tokens_per_minute, max_concurrent"
+        )
+        .bind(&scope.0)
+        .bind(&subject.0)
+        .bind(body.requests_per_minute)
+        .bind(body.tokens_per_minute)
+        .bind(body.max_concurrent)
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(self.limiter.pool())
+        .await
+        .map_err(db_error)?;
+
+        self.limiter.invalidate_limits();
+        eprintln!("🚦 Rate limit set for {} {}", limit.scope, limit.subject);
+        Ok(Json(limit))
+    }
+
+    /// Remove the limits for a subject, falling back to the scope default
+    #[oai(path = "/admin/rate-limits/:scope/:subject", method = "delete", tag = "ApiTags::RateLimits")]
+    async fn delete_rate_limit(
+        &self,
+        scope: Path<String>,
+        subject: Path<String>,
+        req: &poem::Request,
+    ) -> Result<()> {
+        require_admin(req)?;
+        check_scope(&scope)?;
+        let result = sqlx::query("DELETE FROM rate_limits WHERE scope = $1 AND subject = $2")
+            .bind(&scope.0)
+            .bind(&subject.0)
+            .execute(self.limiter.pool())
+            .await
+            .map_err(db_error)?;
+        if result.rows_affected() == 0 {
+            return Err(poem::Error::from_string("Rate limit not found", poem::http::StatusCode::NOT_FOUND));
+        }
+
+        self.limiter.invalidate_limits();
+        Ok(())
+    }
+}
@@ -0,0 +1,391 @@
+//! Response Cache
+//!
+//! Serves repeated chat requests from cache for services that opt in (`response_cache`).
+//! Entries are keyed on the tenant (org, or user without one), the resolved service and
+//! the request as sent upstream: messages with the service's RTCROS prompts, params and
+//! response format. Only requests at temperature 0, or that ask for it (`cache: true`),
+//! are cached. `Cache-Control: no-cache` skips the lookup and refreshes the entry.
+//!
+//! The in-memory tier is per replica and bounded by RESPONSE_CACHE_MAX_MB; services with
+//! `persistent` set also keep entries in Postgres, so hits survive restarts and are
+//! shared across replicas. Near-duplicate prompts are matched by `semantic_cache`.
+
+use mawi_core::services::ResponseCachePolicy;
+use mawi_core::unified::{
+    ActualRouting, ChatChoice, ChatMessage, RequestedRouting, RoutingMetadata, TokenUsage,
+    UnifiedChatRequest, UnifiedChatResponse,
+};
+use moka::future::Cache;
+use moka::Expiry;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone)]
+pub struct ResponseCacheConfig {
+    /// Memory held by the in-memory tier, in bytes
+    pub max_bytes: u64,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self { max_bytes: 256 * 1024 * 1024 }
+    }
+}
+
+impl ResponseCacheConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_bytes: std::env::var("RESPONSE_CACHE_MAX_MB")
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(|mb| mb * 1024 * 1024)
+                .unwrap_or(defaults.max_bytes),
+        }
+    }
+}
+
+/// Whether the service's cache applies to a request: opted-in pool services, non-streamed
+/// requests at temperature 0 or that ask for caching, unless they opt out with `cache: false`
+pub fn applies<'a>(policy: Option<&'a ResponseCachePolicy>, request: &UnifiedChatRequest) -> Option<&'a ResponseCachePolicy> {
+    let policy = policy.filter(|p| p.enabled)?;
+    if request.stream.unwrap_or(false) || request.cache == Some(false) {
+        return None;
+    }
+    let deterministic = request.params.as_ref().and_then(|p| p.temperature) == Some(0.0);
+    (deterministic || request.cache == Some(true)).then_some(policy)
+}
+
+fn sha256_hex(value: &serde_json::Value) -> String {
+    hex::encode(Sha256::digest(value.to_string().as_bytes()))
+}
+
+/// Everything a request is cached on apart from its messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheScope {
+    /// Org ID, or user ID for users outside an org
+    pub tenant: String,
+    pub service: String,
+    /// Hash of the RTCROS prompts of the service's models, so editing a prompt stops
+    /// earlier answers from being served
+    pub prompts_hash: String,
+    /// Hash of the model override, params and response format
+    pub params_hash: String,
+}
+
+impl CacheScope {
+    pub fn new(tenant: &str, service: &str, system_prompts: &[String], request: &UnifiedChatRequest) -> Self {
+        let mut system_prompts = system_prompts.to_vec();
+        system_prompts.sort();
+        system_prompts.dedup();
+        let params = request.params.as_ref();
+        Self {
+            tenant: tenant.to_string(),
+            service: service.to_string(),
+            prompts_hash: sha256_hex(&serde_json::json!(system_prompts)),
+            params_hash: sha256_hex(&serde_json::json!({
+                "model": request.model,
+                "temperature": params.and_then(|p| p.temperature),
+                "max_tokens": params.and_then(|p| p.max_tokens),
+                "reasoning_effort": params.and_then(|p| p.reasoning_effort.as_deref()),
+                "response_format": request.response_format.as_ref().map(|f| f.type_.as_str()),
+            })),
+        }
+    }
+
+    /// Key of the exact-match entry for a request's messages
+    pub fn exact_key(&self, request: &UnifiedChatRequest) -> String {
+        sha256_hex(&serde_json::json!({
+            "tenant": self.tenant,
+            "service": self.service,
+            "prompts": self.prompts_hash,
+            "params": self.params_hash,
+            "messages": request.messages,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedChoice {
+    pub message: ChatMessage,
+    pub finish_reason: Option<String>,
+}
+
+/// A response as stored, with the model that produced it for logging hits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub model_id: String,
+    pub provider_id: String,
+    pub provider_type: String,
+    pub model: String,
+    pub choices: Vec<CachedChoice>,
+    pub usage: Option<(i32, i32, i32)>,
+    pub expires_at: i64,
+}
+
+impl CachedResponse {
+    /// Capture a response for caching. Responses shaped by the caller's experiment arm or
+    /// budget downgrade aren't cached, so they can't outlive the assignment.
+    pub fn capture(response: &UnifiedChatResponse, provider_id: &str, ttl_secs: u64) -> Option<Self> {
+        let routing = &response.routing_metadata.as_ref()?.actual_routing;
+        if response.choices.is_empty() || routing.experiment_arm.is_some() || routing.budget_downgrade.is_some() {
+            return None;
+        }
+        Some(Self {
+            model_id: routing.model.clone(),
+            provider_id: provider_id.to_string(),
+            provider_type: routing.provider.clone(),
+            model: response.model.clone(),
+            choices: response
+                .choices
+                .iter()
+                .map(|c| CachedChoice { message: c.message.clone(), finish_reason: c.finish_reason.clone() })
+                .collect(),
+            usage: response.usage.as_ref().map(|u| (u.prompt_tokens, u.completion_tokens, u.total_tokens)),
+            expires_at: chrono::Utc::now().timestamp() + ttl_secs as i64,
+        })
+    }
+
+    /// Response for a hit, marked as served from the cache. `similarity` is set for
+    /// semantic hits.
+    pub fn to_response(&self, request: &UnifiedChatRequest, similarity: Option<f64>) -> UnifiedChatResponse {
+        UnifiedChatResponse {
+            id: uuid::Uuid::new_v4().to_string(),
+            object: "chat.completion".to_string(),
+            created: chrono::Utc::now().timestamp(),
+            model: self.model.clone(),
+            choices: self
+                .choices
+                .iter()
+                .enumerate()
+                .map(|(index, c)| ChatChoice {
+                    index: index as i32,
+                    message: c.message.clone(),
+                    finish_reason: c.finish_reason.clone(),
+                })
+                .collect(),
+            usage: self.usage.map(|(prompt_tokens, completion_tokens, total_tokens)| TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens,
+            }),
+            routing_metadata: Some(RoutingMetadata {
+                requested_routing: RequestedRouting {
+                    service: request.service.clone(),
+                    model_override: request.model.clone(),
+                    routing_strategy: request.routing_strategy.as_ref().map(|s| format!("{:?}", s)),
+                },
+                actual_routing: ActualRouting {
+                    provider: self.provider_type.clone(),
+                    model: self.model_id.clone(),
+                    fallback_used: false,
+                    service: request.service.clone(),
+                    fallback_path: Vec::new(),
+                    experiment: None,
+                    experiment_arm: None,
+                    budget_downgrade: None,
+                    cache_hit: true,
+                    cache_similarity: similarity,
+                    guardrail_warnings: Vec::new(),
+                },
+                ensemble: None,
+            }),
+        }
+    }
+
+    fn remaining(&self) -> Duration {
+        Duration::from_secs((self.expires_at - chrono::Utc::now().timestamp()).max(0) as u64)
+    }
+
+    /// Approximate memory held by the entry
+    fn weight(&self) -> u32 {
+        let text: usize = self.choices.iter().map(|c| c.message.content.len() + c.message.role.len()).sum();
+        (text + self.model.len() + self.model_id.len() + 256).min(u32::MAX as usize) as u32
+    }
+}
+
+/// Evicts in-memory entries at their own expiry
+struct UntilExpiry;
+
+impl Expiry<String, Arc<CachedResponse>> for UntilExpiry {
+    fn expire_after_create(&self, _key: &String, value: &Arc<CachedResponse>, _created_at: std::time::Instant) -> Option<Duration> {
+        Some(value.remaining())
+    }
+}
+
+pub struct ResponseCache {
+    pool: PgPool,
+    memory: Cache<String, Arc<CachedResponse>>,
+}
+
+static RESPONSE_CACHE: OnceLock<Arc<ResponseCache>> = OnceLock::new();
+
+impl ResponseCache {
+    pub fn new(pool: PgPool, config: ResponseCacheConfig) -> Self {
+        Self {
+            pool,
+            memory: Cache::builder()
+                .max_capacity(config.max_bytes)
+                .weigher(|key: &String, value: &Arc<CachedResponse>| value.weight().saturating_add(key.len() as u32))
+                .expire_after(UntilExpiry)
+                .build(),
+        }
+    }
+
+    /// Shared cache, so every executor in the process sees the same entries. Starts hourly
+    /// cleanup of expired Postgres entries.
+    pub fn global(pool: &PgPool) -> Arc<Self> {
+        RESPONSE_CACHE
+            .get_or_init(|| {
+                let cache = Arc::new(Self::new(pool.clone(), ResponseCacheConfig::from_env()));
+                let pruner = cache.clone();
+                tokio::spawn(async move {
+                    let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+                    loop {
+                        ticker.tick().await;
+                        let _ = sqlx::query("DELETE FROM response_cache WHERE expires_at < $1")
+                            .bind(chrono::Utc::now().timestamp())
+                            .execute(&pruner.pool)
+                            .await;
+                    }
+                });
+                cache
+            })
+            .clone()
+    }
+
+    /// Cached response for a key, from memory or (for persistent services) Postgres
+    pub async fn lookup(&self, service: &str, policy: &ResponseCachePolicy, key: &str) -> Option<Arc<CachedResponse>> {
+        let result = self.find(policy, key).await;
+        let label = match &result {
+            Some((_, tier)) => *tier,
+            None => "miss",
+        };
+        crate::metrics::RESPONSE_CACHE_LOOKUPS.with_label_values(&[service, label]).inc();
+        result.map(|(entry, _)| entry)
+    }
+
+    async fn find(&self, policy: &ResponseCachePolicy, key: &str) -> Option<(Arc<CachedResponse>, &'static str)> {
+        if let Some(entry) = self.memory.get(key).await {
+            return Some((entry, "memory_hit"));
+        }
+        if !policy.persistent {
+            return None;
+        }
+        let row = sqlx::query_as::<_, (String,)>(
+            "SELECT response FROM response_cache WHERE cache_key = $1 AND expires_at > $2"
+        )
+        .bind(key)
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_optional(&self.pool)
+        .await;
+        let (raw,) = match row {
+            Ok(row) => row?,
+            Err(e) => {
+                warn!(error = %e, "response cache lookup failed");
+                return None;
+            }
+        };
+        let entry = Arc::new(serde_json::from_str::<CachedResponse>(&raw).ok()?);
+        self.memory.insert(key.to_string(), entry.clone()).await;
+        Some((entry, "postgres_hit"))
+    }
+
+    /// Store a response, skipping those over the policy's size limit. The Postgres write
+    /// happens in the background.
+    pub async fn store(&self, service: &str, policy: &ResponseCachePolicy, key: String, entry: CachedResponse) {
+        let raw = match serde_json::to_string(&entry) {
+            Ok(raw) => raw,
+            Err(_) => return,
+        };
+        if raw.len() as u64 > policy.max_entry_bytes {
+            debug!(service, bytes = raw.len(), "response too large to cache");
+            return;
+        }
+        let expires_at = entry.expires_at;
+        self.memory.insert(key.clone(), Arc::new(entry)).await;
+
+        if policy.persistent {
+            let pool = self.pool.clone();
+            let service = service.to_string();
+            tokio::spawn(async move {
+                let stored = sqlx::query(
+                    "INSERT INTO response_cache (cache_key, service_name, response, created_at, expires_at)
+                     VALUES ($1, $2, $3, $4, $5)
+                     ON CONFLICT (cache_key) DO UPDATE SET
+                        service_name = EXCLUDED.service_name,
+                        response = EXCLUDED.response,
+                        created_at = EXCLUDED.created_at,
+                        expires_at = EXCLUDED.expires_at"
+                )
+                .bind(&key)
+                .bind(&service)
+                .bind(&raw)
+                .bind(chrono::Utc::now().timestamp())
+                .bind(expires_at)
+                .execute(&pool)
+                .await;
+                if let Err(e) = stored {
+                    warn!(service = %service, error = %e, "could not persist cached response");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mawi_core::unified::ChatParams;
+
+    fn request(temperature: Option<f64>, cache: Option<bool>) -> UnifiedChatRequest {
+        UnifiedChatRequest {
+            service: "support".to_string(),
+            messages: vec![ChatMessage { role: "user".to_string(), content: "Where is my order?".to_string() }],
+            params: Some(ChatParams { temperature, max_tokens: Some(200), reasoning_effort: None }),
+            stream: None,
+            model: None,
+            routing_strategy: None,
+            response_format: None,
+            affinity_key: None,
+            priority: None,
+            cache,
+            cache_bypass: false,
+            api_key_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_applies_at_temperature_zero_or_on_request() {
+        let policy = ResponseCachePolicy { enabled: true, ttl_secs: 60, max_entry_bytes: 1024, persistent: false, semantic: None };
+        assert!(applies(Some(&policy), &request(Some(0.0), None)).is_some());
+        assert!(applies(Some(&policy), &request(Some(0.7), None)).is_none());
+        assert!(applies(Some(&policy), &request(Some(0.7), Some(true))).is_some());
+        assert!(applies(Some(&policy), &request(Some(0.0), Some(false))).is_none());
+        assert!(applies(None, &request(Some(0.0), None)).is_none());
+
+        let disabled = ResponseCachePolicy { enabled: false, ..policy };
+        assert!(applies(Some(&disabled), &request(Some(0.0), None)).is_none());
+    }
+
+    #[test]
+    fn test_cache_key_isolates_tenants_and_prompts() {
+        let req = request(Some(0.0), None);
+        let prompts = vec!["## Role\nSupport agent".to_string()];
+        let key = |tenant: &str, prompts: &[String], req: &UnifiedChatRequest| {
+            CacheScope::new(tenant, "support", prompts, req).exact_key(req)
+        };
+        let expected = key("org-a", &prompts, &req);
+
+        assert_eq!(expected, key("org-a", &prompts, &req));
+        assert_ne!(expected, key("org-b", &prompts, &req));
+        assert_ne!(expected, key("org-a", &[], &req));
+        assert_ne!(expected, key("org-a", &prompts, &request(Some(0.5), None)));
+        // Opting in doesn't change what the request is keyed on
+        assert_eq!(expected, key("org-a", &prompts, &request(Some(0.0), Some(true))));
+    }
+}
@@ -0,0 +1,550 @@
+//! Semantic Cache
+//!
+//! Answers a prompt from the response cache when it is close in meaning to an earlier one.
+//! Prompts are embedded with the service's embedding model, and the nearest earlier prompt
+//! is served when its cosine similarity reaches the threshold. Entries are scoped like the
+//! exact cache (service, tenant, RTCROS prompts and params, see `CacheScope`), and editing
+//! a service's RTCROS prompts drops its entries.
+//!
+//! Each scope is searched through an in-process index (brute force or HNSW). For
+//! `persistent` services the vectors live in Postgres; indexes are loaded from there and
+//! reloaded every minute so replicas see each other's entries.
+
+use crate::response_cache::{CacheScope, CachedResponse};
+use mawi_core::services::{ResponseCachePolicy, SemanticCacheConfig, VectorIndexKind};
+use mawi_core::unified::UnifiedChatRequest;
+use moka::future::Cache;
+use sqlx::PgPool;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// How often indexes of persistent caches are reloaded from Postgres
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Neighbours per node above layer 0 (twice as many on layer 0)
+const HNSW_M: usize = 16;
+/// Candidates considered when linking a new node
+const HNSW_EF_CONSTRUCTION: usize = 64;
+/// Candidates considered per search
+const HNSW_EF_SEARCH: usize = 48;
+
+/// Text embedded for a request: its conversation, one message per line
+pub fn prompt_text(request: &UnifiedChatRequest) -> String {
+    request
+        .messages
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn normalise(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+/// Cosine similarity of two unit vectors; vectors of different sizes never match
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return -1.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// A node and its similarity to the query, ordered by similarity
+#[derive(Debug, Clone, Copy)]
+struct Scored(f32, usize);
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Hierarchical navigable small world graph (Malkov & Yashunin) over unit vectors
+#[derive(Default)]
+struct Hnsw {
+    /// Neighbours of each node, per layer from 0 up to the node's level
+    links: Vec<Vec<Vec<usize>>>,
+    entry: Option<usize>,
+}
+
+impl Hnsw {
+    fn random_level() -> usize {
+        let r = rand::random::<f64>().max(f64::MIN_POSITIVE);
+        ((-r.ln()) / (HNSW_M as f64).ln()).floor().min(16.0) as usize
+    }
+
+    fn max_links(layer: usize) -> usize {
+        if layer == 0 { 2 * HNSW_M } else { HNSW_M }
+    }
+
+    fn level(&self, node: usize) -> usize {
+        self.links[node].len() - 1
+    }
+
+    /// The `ef` nodes most similar to `query` reachable from `entries` on one layer,
+    /// most similar first
+    fn search_layer(&self, vectors: &[Vec<f32>], query: &[f32], entries: &[usize], ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut found = BinaryHeap::new();
+        for &node in entries {
+            let scored = Scored(similarity(query, &vectors[node]), node);
+            candidates.push(scored);
+            found.push(Reverse(scored));
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(current) = candidates.pop() {
+            let worst = found.peek().map(|Reverse(s)| s.0).unwrap_or(f32::MIN);
+            if found.len() >= ef && current.0 < worst {
+                break;
+            }
+            for &neighbour in self.links[current.1].get(layer).map(Vec::as_slice).unwrap_or_default() {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored(similarity(query, &vectors[neighbour]), neighbour);
+                let worst = found.peek().map(|Reverse(s)| s.0).unwrap_or(f32::MIN);
+                if found.len() < ef || scored.0 > worst {
+                    candidates.push(scored);
+                    found.push(Reverse(scored));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut found: Vec<Scored> = found.into_iter().map(|Reverse(s)| s).collect();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+
+    /// Greedy descent from the entry point to `layer`
+    fn descend(&self, vectors: &[Vec<f32>], query: &[f32], mut entry: usize, layer: usize) -> usize {
+        for l in (layer + 1..=self.level(entry)).rev() {
+            entry = self.search_layer(vectors, query, &[entry], 1, l)[0].1;
+        }
+        entry
+    }
+
+    /// Link `node` (the last of `vectors`) into the graph
+    fn insert(&mut self, vectors: &[Vec<f32>], node: usize) {
+        let level = Self::random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+
+        let query = &vectors[node];
+        let top = self.level(entry);
+        let mut entries = vec![self.descend(vectors, query, entry, level.min(top))];
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(vectors, query, &entries, HNSW_EF_CONSTRUCTION, layer);
+            let neighbours: Vec<usize> = found.iter().take(HNSW_M).map(|s| s.1).collect();
+            for &neighbour in &neighbours {
+                let base = &vectors[neighbour];
+                let links = &mut self.links[neighbour][layer];
+                links.push(node);
+                if links.len() > Self::max_links(layer) {
+                    links.sort_by(|a, b| similarity(base, &vectors[*b]).total_cmp(&similarity(base, &vectors[*a])));
+                    links.truncate(Self::max_links(layer));
+                }
+            }
+            self.links[node][layer] = neighbours;
+            entries = found.iter().map(|s| s.1).collect();
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    fn search(&self, vectors: &[Vec<f32>], query: &[f32], ef: usize) -> Vec<Scored> {
+        let Some(entry) = self.entry else { return Vec::new() };
+        let entry = self.descend(vectors, query, entry, 0);
+        self.search_layer(vectors, query, &[entry], ef, 0)
+    }
+}
+
+/// Nearest-neighbour search over one scope's prompts
+struct VectorIndex {
+    kind: VectorIndexKind,
+    vectors: Vec<Vec<f32>>,
+    hnsw: Hnsw,
+}
+
+impl VectorIndex {
+    fn new(kind: VectorIndexKind) -> Self {
+        Self { kind, vectors: Vec::new(), hnsw: Hnsw::default() }
+    }
+
+    fn insert(&mut self, vector: Vec<f32>) -> usize {
+        let node = self.vectors.len();
+        self.vectors.push(vector);
+        if self.kind == VectorIndexKind::Hnsw {
+            self.hnsw.insert(&self.vectors, node);
+        }
+        node
+    }
+
+    /// Most similar node that `live` accepts, with its similarity
+    fn nearest(&self, query: &[f32], live: impl Fn(usize) -> bool) -> Option<(usize, f32)> {
+        match self.kind {
+            VectorIndexKind::BruteForce => self
+                .vectors
+                .iter()
+                .enumerate()
+                .filter(|(node, _)| live(*node))
+                .map(|(node, vector)| (node, similarity(query, vector)))
+                .max_by(|a, b| a.1.total_cmp(&b.1)),
+            VectorIndexKind::Hnsw => self
+                .hnsw
+                .search(&self.vectors, query, HNSW_EF_SEARCH)
+                .into_iter()
+                .find(|s| live(s.1))
+                .map(|s| (s.1, s.0)),
+        }
+    }
+}
+
+/// Cached prompts of one scope. Dropped entries stay in the index until it's rebuilt.
+struct Partition {
+    index: VectorIndex,
+    /// Response for each index node; `None` once dropped
+    responses: Vec<Option<Arc<CachedResponse>>>,
+    /// Live nodes, oldest first
+    order: VecDeque<usize>,
+    max_entries: usize,
+    loaded_at: Instant,
+}
+
+impl Partition {
+    fn new(kind: VectorIndexKind, max_entries: usize) -> Self {
+        Self {
+            index: VectorIndex::new(kind),
+            responses: Vec::new(),
+            order: VecDeque::new(),
+            max_entries: max_entries.max(1),
+            loaded_at: Instant::now(),
+        }
+    }
+
+    fn add(&mut self, vector: Vec<f32>, response: Arc<CachedResponse>) {
+        while self.order.len() >= self.max_entries {
+            if let Some(oldest) = self.order.pop_front() {
+                self.responses[oldest] = None;
+            }
+        }
+        let node = self.index.insert(vector);
+        self.responses.push(Some(response));
+        self.order.push_back(node);
+
+        if self.responses.len() > 2 * self.max_entries.max(64) {
+            self.rebuild();
+        }
+    }
+
+    /// Rebuild the index from live entries
+    fn rebuild(&mut self) {
+        let live: Vec<(Vec<f32>, Arc<CachedResponse>)> = self
+            .order
+            .iter()
+            .filter_map(|&node| Some((self.index.vectors[node].clone(), self.responses[node].clone()?)))
+            .collect();
+        self.index = VectorIndex::new(self.index.kind);
+        self.responses.clear();
+        self.order.clear();
+        for (vector, response) in live {
+            self.add(vector, response);
+        }
+    }
+
+    fn nearest(&self, query: &[f32], now: i64) -> Option<(Arc<CachedResponse>, f32)> {
+        let live = |node: usize| self.responses[node].as_ref().is_some_and(|r| r.expires_at > now);
+        let (node, similarity) = self.index.nearest(query, live)?;
+        Some((self.responses[node].clone()?, similarity))
+    }
+}
+
+pub struct SemanticCache {
+    pool: PgPool,
+    partitions: Cache<String, Arc<Mutex<Partition>>>,
+}
+
+static SEMANTIC_CACHE: OnceLock<Arc<SemanticCache>> = OnceLock::new();
+
+impl SemanticCache {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            partitions: Cache::builder()
+                .max_capacity(1_000)
+                .time_to_idle(Duration::from_secs(24 * 3600))
+                .build(),
+        }
+    }
+
+    /// Shared cache, so every executor in the process sees the same indexes. Starts hourly
+    /// cleanup of expired Postgres entries.
+    pub fn global(pool: &PgPool) -> Arc<Self> {
+        SEMANTIC_CACHE
+            .get_or_init(|| {
+                let cache = Arc::new(Self::new(pool.clone()));
+                let pruner = cache.clone();
+                tokio::spawn(async move {
+                    let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+                    loop {
+                        ticker.tick().await;
+                        let _ = sqlx::query("DELETE FROM semantic_cache WHERE expires_at < $1")
+                            .bind(chrono::Utc::now().timestamp())
+                            .execute(&pruner.pool)
+                            .await;
+                    }
+                });
+                cache
+            })
+            .clone()
+    }
+
+    fn partition_key(scope: &CacheScope, config: &SemanticCacheConfig) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            scope.service, scope.tenant, scope.prompts_hash, scope.params_hash, config.embedding_model_id
+        )
+    }
+
+    async fn partition(&self, scope: &CacheScope, policy: &ResponseCachePolicy, config: &SemanticCacheConfig) -> Arc<Mutex<Partition>> {
+        let key = Self::partition_key(scope, config);
+        if let Some(partition) = self.partitions.get(&key).await {
+            let stale = policy.persistent && partition.lock().unwrap().loaded_at.elapsed() >= REFRESH_INTERVAL;
+            if !stale {
+                return partition;
+            }
+        }
+
+        let mut partition = Partition::new(config.index, config.max_entries as usize);
+        if policy.persistent {
+            self.load(scope, config, &mut partition).await;
+        }
+        let partition = Arc::new(Mutex::new(partition));
+        self.partitions.insert(key, partition.clone()).await;
+        partition
+    }
+
+    async fn load(&self, scope: &CacheScope, config: &SemanticCacheConfig, partition: &mut Partition) {
+        // Entries made under earlier RTCROS prompts can never match again
+        let _ = sqlx::query("DELETE FROM semantic_cache WHERE service_name = $1 AND prompts_hash <> $2")
+            .bind(&scope.service)
+            .bind(&scope.prompts_hash)
+            .execute(&self.pool)
+            .await;
+
+        let rows = sqlx::query_as::<_, (Vec<f32>, String)>(
+            "SELECT embedding, response FROM semantic_cache
+             WHERE service_name = $1 AND tenant = $2 AND prompts_hash = $3 AND params_hash = $4
+               AND embedding_model_id = $5 AND expires_at > $6
+             ORDER BY created_at DESC
+             LIMIT $7"
+        )
+        .bind(&scope.service)
+        .bind(&scope.tenant)
+        .bind(&scope.prompts_hash)
+        .bind(&scope.params_hash)
+        .bind(&config.embedding_model_id)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(config.max_entries as i64)
+        .fetch_all(&self.pool)
+        .await;
+
+        match rows {
+            Ok(rows) => {
+                for (embedding, raw) in rows.into_iter().rev() {
+                    if let Ok(response) = serde_json::from_str::<CachedResponse>(&raw) {
+                        partition.add(embedding, Arc::new(response));
+                    }
+                }
+            }
+            Err(e) => warn!(service = %scope.service, error = %e, "could not load semantic cache"),
+        }
+    }
+
+    /// Cached response for the prompt nearest to `embedding`, with its similarity, when
+    /// that reaches the threshold
+    pub async fn lookup(
+        &self,
+        scope: &CacheScope,
+        policy: &ResponseCachePolicy,
+        config: &SemanticCacheConfig,
+        embedding: &[f32],
+    ) -> Option<(Arc<CachedResponse>, f64)> {
+        let query = normalise(embedding.to_vec());
+        let partition = self.partition(scope, policy, config).await;
+        let nearest = partition.lock().unwrap().nearest(&query, chrono::Utc::now().timestamp());
+
+        if let Some((_, similarity)) = &nearest {
+            crate::metrics::SEMANTIC_CACHE_SIMILARITY.with_label_values(&[&scope.service]).observe(*similarity as f64);
+        }
+        let hit = nearest
+            .map(|(response, similarity)| (response, similarity as f64))
+            .filter(|(_, similarity)| *similarity >= config.similarity_threshold);
+        let result = if hit.is_some() { "semantic_hit" } else { "semantic_miss" };
+        crate::metrics::RESPONSE_CACHE_LOOKUPS.with_label_values(&[&scope.service, result]).inc();
+        hit
+    }
+
+    /// Store a response under its prompt's embedding, skipping those over the policy's size
+    /// limit. The Postgres write happens in the background.
+    pub async fn store(
+        &self,
+        scope: &CacheScope,
+        policy: &ResponseCachePolicy,
+        config: &SemanticCacheConfig,
+        embedding: Vec<f32>,
+        entry: CachedResponse,
+    ) {
+        let raw = match serde_json::to_string(&entry) {
+            Ok(raw) => raw,
+            Err(_) => return,
+        };
+        if raw.len() as u64 > policy.max_entry_bytes {
+            debug!(service = %scope.service, bytes = raw.len(), "response too large to cache");
+            return;
+        }
+        let embedding = normalise(embedding);
+        let expires_at = entry.expires_at;
+        let partition = self.partition(scope, policy, config).await;
+        partition.lock().unwrap().add(embedding.clone(), Arc::new(entry));
+
+        if policy.persistent {
+            let pool = self.pool.clone();
+            let scope = scope.clone();
+            let embedding_model_id = config.embedding_model_id.clone();
+            let max_entries = config.max_entries as i64;
+            tokio::spawn(async move {
+                let stored = sqlx::query(
+                    "INSERT INTO semantic_cache (id, service_name, tenant, prompts_hash, params_hash,
+                        embedding_model_id, embedding, response, created_at, expires_at)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
+                )
+                .bind(uuid::Uuid::new_v4().to_string())
+                .bind(&scope.service)
+                .bind(&scope.tenant)
+                .bind(&scope.prompts_hash)
+                .bind(&scope.params_hash)
+                .bind(&embedding_model_id)
+                .bind(&embedding)
+                .bind(&raw)
+                .bind(chrono::Utc::now().timestamp())
+                .bind(expires_at)
+                .execute(&pool)
+                .await;
+                if let Err(e) = stored {
+                    warn!(service = %scope.service, error = %e, "could not persist semantic cache entry");
+                    return;
+                }
+                // Keep the newest `max_entries` per scope
+                let _ = sqlx::query(
+                    "DELETE FROM semantic_cache WHERE id IN (
+                        SELECT id FROM semantic_cache
+                        WHERE service_name = $1 AND tenant = $2 AND prompts_hash = $3
+                          AND params_hash = $4 AND embedding_model_id = $5
+                        ORDER BY created_at DESC
+                        OFFSET $6
+                     )"
+                )
+                .bind(&scope.service)
+                .bind(&scope.tenant)
+                .bind(&scope.prompts_hash)
+                .bind(&scope.params_hash)
+                .bind(&embedding_model_id)
+                .bind(max_entries)
+                .execute(&pool)
+                .await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> Arc<CachedResponse> {
+        Arc::new(CachedResponse {
+            model_id: "m1".to_string(),
+            provider_id: "p1".to_string(),
+            provider_type: "openai".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            choices: Vec::new(),
+            usage: None,
+            expires_at: chrono::Utc::now().timestamp() + 60,
+        })
+    }
+
+    fn random_unit(dims: usize) -> Vec<f32> {
+        normalise((0..dims).map(|_| rand::random::<f32>() - 0.5).collect())
+    }
+
+    #[test]
+    fn test_hnsw_finds_the_same_nearest_as_brute_force() {
+        let mut exact = Partition::new(VectorIndexKind::BruteForce, 1_000);
+        let mut approximate = Partition::new(VectorIndexKind::Hnsw, 1_000);
+        let vectors: Vec<Vec<f32>> = (0..500).map(|_| random_unit(16)).collect();
+        for vector in &vectors {
+            let entry = response();
+            exact.add(vector.clone(), entry.clone());
+            approximate.add(vector.clone(), entry);
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut agreed = 0;
+        for vector in vectors.iter().take(50) {
+            let (_, exact_similarity) = exact.nearest(vector, now).unwrap();
+            let (_, approximate_similarity) = approximate.nearest(vector, now).unwrap();
+            assert!((exact_similarity - 1.0).abs() < 1e-4);
+            if (approximate_similarity - exact_similarity).abs() < 1e-4 {
+                agreed += 1;
+            }
+        }
+        assert!(agreed >= 48, "HNSW recall too low: {}/50", agreed);
+    }
+
+    #[test]
+    fn test_partition_drops_oldest_entries_past_its_cap() {
+        let mut partition = Partition::new(VectorIndexKind::BruteForce, 2);
+        let first = normalise(vec![1.0, 0.0]);
+        partition.add(first.clone(), response());
+        partition.add(normalise(vec![0.0, 1.0]), response());
+        partition.add(normalise(vec![-1.0, 0.0]), response());
+
+        let now = chrono::Utc::now().timestamp();
+        let (_, similarity) = partition.nearest(&first, now).unwrap();
+        assert!(similarity < 0.5, "dropped entry was still served");
+        assert_eq!(partition.order.len(), 2);
+    }
+}
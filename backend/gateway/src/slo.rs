@@ -0,0 +1,370 @@
+//! Health rollups and SLO reporting
+//!
+//! Outcomes are aggregated in memory per (scope, resource, minute) and flushed
+//! to `health_rollups` with additive upserts, so any number of gateway instances
+//! can write the same minute. SLO compliance, error budgets and burn rates are
+//! computed from those rollups.
+
+use dashmap::DashMap;
+use sqlx::PgPool;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use mawi_core::services::SloConfig;
+
+/// Upper bounds (ms) of the latency histogram buckets; one overflow bucket follows
+pub const LATENCY_BUCKETS_MS: [i64; 8] = [100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000];
+const BUCKET_COUNT: usize = LATENCY_BUCKETS_MS.len() + 1;
+const BUCKET_COLUMNS: [&str; BUCKET_COUNT] = [
+    "latency_le_100", "latency_le_250", "latency_le_500", "latency_le_1000", "latency_le_2500",
+    "latency_le_5000", "latency_le_10000", "latency_le_30000", "latency_gt_30000",
+];
+/// Columns written per rollup row
+const ROLLUP_COLUMNS: usize = 7 + BUCKET_COUNT;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(15);
+const ROLLUP_RETENTION_DAYS: i64 = 90;
+
+pub const SCOPE_MODEL: &str = "model";
+pub const SCOPE_SERVICE: &str = "service";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rollup {
+    pub success: i64,
+    pub errors: i64,
+    pub latency_sum_ms: i64,
+    pub latency_max_ms: i64,
+    pub buckets: [i64; BUCKET_COUNT],
+}
+
+impl Rollup {
+    fn record(&mut self, success: bool, latency_ms: i64) {
+        if success {
+            self.success += 1;
+        } else {
+            self.errors += 1;
+        }
+        let latency_ms = latency_ms.max(0);
+        self.latency_sum_ms += latency_ms;
+        self.latency_max_ms = self.latency_max_ms.max(latency_ms);
+        let bucket = LATENCY_BUCKETS_MS.iter().position(|bound| latency_ms <= *bound).unwrap_or(BUCKET_COUNT - 1);
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn total(&self) -> i64 {
+        self.success + self.errors
+    }
+
+    /// Availability in percent, `None` without traffic
+    pub fn availability(&self) -> Option<f64> {
+        (self.total() > 0).then(|| self.success as f64 * 100.0 / self.total() as f64)
+    }
+
+    /// Latency percentile estimated from the histogram: the upper bound of the bucket the
+    /// percentile falls in, or the observed maximum for the overflow bucket
+    pub fn latency_percentile(&self, quantile: f64) -> Option<i64> {
+        let count: i64 = self.buckets.iter().sum();
+        if count == 0 {
+            return None;
+        }
+        let rank = (count as f64 * quantile).ceil().max(1.0) as i64;
+        let mut seen = 0;
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                return Some(LATENCY_BUCKETS_MS.get(idx).copied().unwrap_or(self.latency_max_ms).min(self.latency_max_ms));
+            }
+        }
+        Some(self.latency_max_ms)
+    }
+}
+
+/// Process-wide buffer of outcomes waiting to be flushed
+pub struct RollupRecorder {
+    pending: DashMap<(&'static str, String, i64), Rollup>,
+}
+
+static RECORDER: OnceLock<Arc<RollupRecorder>> = OnceLock::new();
+
+impl RollupRecorder {
+    /// Shared recorder; the first call starts the background flush task
+    pub fn global(pool: &PgPool) -> Arc<Self> {
+        RECORDER.get_or_init(|| {
+            let recorder = Arc::new(Self { pending: DashMap::new() });
+            let flusher = recorder.clone();
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+                let mut last_prune = std::time::Instant::now();
+                loop {
+                    ticker.tick().await;
+                    flusher.flush(&pool).await;
+                    if last_prune.elapsed() >= Duration::from_secs(3600) {
+                        last_prune = std::time::Instant::now();
+                        let cutoff = chrono::Utc::now().timestamp() - ROLLUP_RETENTION_DAYS * 86_400;
+                        let _ = sqlx::query("DELETE FROM health_rollups WHERE minute < $1").bind(cutoff).execute(&pool).await;
+                    }
+                }
+            });
+            recorder
+        }).clone()
+    }
+
+    pub fn record(&self, scope: &'static str, resource_id: &str, success: bool, latency_ms: i64) {
+        let minute = chrono::Utc::now().timestamp() / 60 * 60;
+        self.pending
+            .entry((scope, resource_id.to_string(), minute))
+            .or_default()
+            .record(success, latency_ms);
+    }
+
+    async fn flush(&self, pool: &PgPool) {
+        let keys: Vec<_> = self.pending.iter().map(|entry| entry.key().clone()).collect();
+        let rows: Vec<_> = keys.into_iter().filter_map(|key| self.pending.remove(&key)).collect();
+
+        // Stay well under the Postgres bind-parameter limit
+        for chunk in rows.chunks(500) {
+            let placeholders: Vec<String> = (0..chunk.len())
+                .map(|i| {
+                    let base = i * ROLLUP_COLUMNS + 1;
+                    let row: Vec<String> = (base..base + ROLLUP_COLUMNS).map(|n| format!("${}", n)).collect();
+                    format!("({})", row.join(","))
+                })
+                .collect();
+            let additive: Vec<String> = ["success_count", "error_count", "latency_sum_ms"]
+                .iter()
+                .chain(BUCKET_COLUMNS.iter())
+                .map(|col| format!("{col} = health_rollups.{col} + EXCLUDED.{col}"))
+                .collect();
+            let query = format!(
+                "INSERT INTO health_rollups (scope, resource_id, minute, success_count, error_count, \
+                 latency_sum_ms, latency_max_ms, {}) VALUES {} \
+                 ON CONFLICT (scope, resource_id, minute) DO UPDATE SET {}, \
+                 latency_max_ms = GREATEST(health_rollups.latency_max_ms, EXCLUDED.latency_max_ms)",
+                BUCKET_COLUMNS.join(", "),
+                placeholders.join(","),
+                additive.join(", "),
+            );
+
+            let mut q = sqlx::query(&query);
+            for ((scope, resource_id, minute), rollup) in chunk {
+                q = q.bind(*scope)
+                    .bind(resource_id)
+                    .bind(minute)
+                    .bind(rollup.success)
+                    .bind(rollup.errors)
+                    .bind(rollup.latency_sum_ms)
+                    .bind(rollup.latency_max_ms);
+                for count in rollup.buckets {
+                    q = q.bind(count);
+                }
+            }
+            if let Err(e) = q.execute(pool).await {
+                eprintln!("⚠️ Failed to flush {} health rollups: {}", chunk.len(), e);
+            }
+        }
+    }
+}
+
+/// Whether an execution error is the service's fault. Caller-side rejections
+/// (bad requests, exhausted quota) don't spend the error budget.
+pub fn counts_against_slo(error: &anyhow::Error) -> bool {
+    if crate::executor::RequestRejection::status_for(error).is_client_error() {
+        return false;
+    }
+    !error.to_string().starts_with("Insufficient quota")
+}
+
+fn sum_columns() -> String {
+    let mut columns = vec![
+        "COALESCE(SUM(success_count), 0)::BIGINT".to_string(),
+        "COALESCE(SUM(error_count), 0)::BIGINT".to_string(),
+        "COALESCE(SUM(latency_sum_ms), 0)::BIGINT".to_string(),
+        "COALESCE(MAX(latency_max_ms), 0)::BIGINT".to_string(),
+    ];
+    columns.extend(BUCKET_COLUMNS.iter().map(|col| format!("COALESCE(SUM({col}), 0)::BIGINT")));
+    columns.join(", ")
+}
+
+fn rollup_from_row(row: &sqlx::postgres::PgRow, offset: usize) -> Rollup {
+    use sqlx::Row;
+    let mut rollup = Rollup {
+        success: row.get(offset),
+        errors: row.get(offset + 1),
+        latency_sum_ms: row.get(offset + 2),
+        latency_max_ms: row.get(offset + 3),
+        ..Rollup::default()
+    };
+    for (idx, bucket) in rollup.buckets.iter_mut().enumerate() {
+        *bucket = row.get(offset + 4 + idx);
+    }
+    rollup
+}
+
+/// Combined rollup for a resource since a Unix timestamp
+pub async fn rollup_since(pool: &PgPool, scope: &str, resource_id: &str, since: i64) -> anyhow::Result<Rollup> {
+    let query = format!(
+        "SELECT {} FROM health_rollups WHERE scope = $1 AND resource_id = $2 AND minute >= $3",
+        sum_columns()
+    );
+    let row = sqlx::query(&query)
+        .bind(scope)
+        .bind(resource_id)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+    Ok(rollup_from_row(&row, 0))
+}
+
+/// One point of a rollup time series
+#[derive(Debug, Clone, poem_openapi::Object)]
+pub struct RollupPoint {
+    /// Unix timestamp at the start of the step
+    pub start: i64,
+    pub requests: i64,
+    pub errors: i64,
+    pub availability: Option<f64>,
+    pub avg_latency_ms: Option<i64>,
+    pub p95_latency_ms: Option<i64>,
+}
+
+/// Rollups for a resource bucketed into `step_minutes` steps
+pub async fn rollup_series(
+    pool: &PgPool,
+    scope: &str,
+    resource_id: &str,
+    since: i64,
+    until: i64,
+    step_minutes: i64,
+) -> anyhow::Result<Vec<RollupPoint>> {
+    let step = step_minutes.max(1) * 60;
+    let query = format!(
+        "SELECT (minute / $4) * $4 AS start, {} FROM health_rollups
+         WHERE scope = $1 AND resource_id = $2 AND minute >= $3 AND minute < $5
+         GROUP BY start ORDER BY start",
+        sum_columns()
+    );
+    let rows = sqlx::query(&query)
+        .bind(scope)
+        .bind(resource_id)
+        .bind(since)
+        .bind(step)
+        .bind(until)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.iter().map(|row| {
+        use sqlx::Row;
+        let rollup = rollup_from_row(row, 1);
+        RollupPoint {
+            start: row.get(0),
+            requests: rollup.total(),
+            errors: rollup.errors,
+            availability: rollup.availability(),
+            avg_latency_ms: (rollup.total() > 0).then(|| rollup.latency_sum_ms / rollup.total()),
+            p95_latency_ms: rollup.latency_percentile(0.95),
+        }
+    }).collect())
+}
+
+/// SLO compliance for a service over its window
+#[derive(Debug, Clone, poem_openapi::Object)]
+pub struct SloReport {
+    pub service: String,
+    pub slo: SloConfig,
+    pub total_requests: i64,
+    pub failed_requests: i64,
+    /// Measured availability in percent over the window (None without traffic)
+    pub availability: Option<f64>,
+    pub availability_met: bool,
+    /// Estimated p95 latency over the window
+    pub p95_latency_ms: Option<i64>,
+    /// None when the SLO has no latency target or there was no traffic
+    pub latency_met: Option<bool>,
+    /// Failures the window can absorb at the current volume
+    pub error_budget: f64,
+    /// Share of the error budget left, in percent (negative when overspent)
+    pub error_budget_remaining_percent: f64,
+    /// Error rate over the last hour relative to the budgeted rate (1.0 = spending exactly on budget)
+    pub burn_rate_1h: Option<f64>,
+    pub burn_rate_6h: Option<f64>,
+}
+
+/// How fast the error budget is being spent: observed error rate over allowed error rate
+pub fn burn_rate(rollup: &Rollup, slo: &SloConfig) -> Option<f64> {
+    let budget = slo.error_budget_fraction();
+    (rollup.total() > 0 && budget > 0.0).then(|| rollup.errors as f64 / rollup.total() as f64 / budget)
+}
+
+/// Error budget for the window and the share of it left, in percent
+pub fn error_budget(rollup: &Rollup, slo: &SloConfig) -> (f64, f64) {
+    let budget = rollup.total() as f64 * slo.error_budget_fraction();
+    let remaining = if budget > 0.0 {
+        (1.0 - rollup.errors as f64 / budget) * 100.0
+    } else if rollup.errors == 0 {
+        100.0
+    } else {
+        -100.0
+    };
+    (budget, remaining)
+}
+
+pub async fn slo_report(pool: &PgPool, service: &str, slo: &SloConfig) -> anyhow::Result<SloReport> {
+    let now = chrono::Utc::now().timestamp();
+    let window = rollup_since(pool, SCOPE_SERVICE, service, now - slo.window_days * 86_400).await?;
+    let last_hour = rollup_since(pool, SCOPE_SERVICE, service, now - 3_600).await?;
+    let last_six_hours = rollup_since(pool, SCOPE_SERVICE, service, now - 6 * 3_600).await?;
+
+    let availability = window.availability();
+    let p95_latency_ms = window.latency_percentile(0.95);
+    let (error_budget, error_budget_remaining_percent) = error_budget(&window, slo);
+
+    Ok(SloReport {
+        service: service.to_string(),
+        total_requests: window.total(),
+        failed_requests: window.errors,
+        availability,
+        availability_met: availability.is_none_or(|a| a >= slo.availability_target),
+        p95_latency_ms,
+        latency_met: slo.latency_p95_ms.zip(p95_latency_ms).map(|(target, p95)| p95 <= target),
+        error_budget,
+        error_budget_remaining_percent,
+        burn_rate_1h: burn_rate(&last_hour, slo),
+        burn_rate_6h: burn_rate(&last_six_hours, slo),
+        slo: slo.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup_percentile_and_availability() {
+        let mut rollup = Rollup::default();
+        for _ in 0..90 {
+            rollup.record(true, 80);
+        }
+        for _ in 0..9 {
+            rollup.record(true, 900);
+        }
+        rollup.record(false, 45_000);
+
+        assert_eq!(rollup.availability(), Some(99.0));
+        assert_eq!(rollup.latency_percentile(0.5), Some(100));
+        assert_eq!(rollup.latency_percentile(0.95), Some(1_000));
+        assert_eq!(rollup.latency_percentile(1.0), Some(45_000));
+        assert_eq!(Rollup::default().latency_percentile(0.95), None);
+    }
+
+    #[test]
+    fn test_error_budget_and_burn_rate() {
+        let slo = SloConfig { availability_target: 99.0, latency_p95_ms: None, window_days: 30 };
+        let rollup = Rollup { success: 995, errors: 5, ..Rollup::default() };
+
+        let (budget, remaining) = error_budget(&rollup, &slo);
+        assert!((budget - 10.0).abs() < 1e-9);
+        assert!((remaining - 50.0).abs() < 1e-9);
+        assert!((burn_rate(&rollup, &slo).unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(burn_rate(&Rollup::default(), &slo), None);
+    }
+}
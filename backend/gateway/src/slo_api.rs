@@ -0,0 +1,142 @@
+//! SLO API
+//!
+//! Service level objectives, error budgets and the per-minute health
+//! rollups they are computed from. Defining SLOs is admin only.
+
+use poem_openapi::{
+    param::{Path, Query},
+    payload::Json,
+    OpenApi, Tags,
+};
+use poem::Result;
+use sqlx::PgPool;
+
+use mawi_core::services::{Service, SloConfig};
+
+use crate::api::require_admin;
+use crate::slo::{RollupPoint, SloReport, SCOPE_MODEL, SCOPE_SERVICE};
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Service Level Objectives
+    Slo,
+}
+
+pub struct SloApi {
+    pub pool: PgPool,
+}
+
+fn internal_error(e: impl std::fmt::Display) -> poem::Error {
+    poem::Error::from_string(format!("Database error: {}", e), poem::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+impl SloApi {
+    async fn fetch_service(&self, name: &str) -> Result<Service> {
+        sqlx::query_as::<_, Service>("SELECT * FROM services WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| poem::Error::from_string(
+                format!("Service '{}' not found", name),
+                poem::http::StatusCode::NOT_FOUND,
+            ))
+    }
+}
+
+#[OpenApi]
+impl SloApi {
+    /// Compliance for every service with an SLO
+    #[oai(path = "/slo", method = "get", tag = "ApiTags::Slo")]
+    async fn list_slo_reports(&self) -> Result<Json<Vec<SloReport>>> {
+        let services = sqlx::query_as::<_, Service>("SELECT * FROM services WHERE slo IS NOT NULL ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal_error)?;
+
+        let mut reports = Vec::new();
+        for service in services {
+            if let Some(slo) = &service.slo {
+                reports.push(crate::slo::slo_report(&self.pool, &service.name, slo).await.map_err(internal_error)?);
+            }
+        }
+        Ok(Json(reports))
+    }
+
+    /// Current compliance, remaining error budget and burn rate for a service
+    #[oai(path = "/services/:name/slo", method = "get", tag = "ApiTags::Slo")]
+    async fn get_slo_report(&self, name: Path<String>) -> Result<Json<SloReport>> {
+        let service = self.fetch_service(&name).await?;
+        let slo = service.slo.ok_or_else(|| poem::Error::from_string(
+            format!("Service '{}' has no SLO", name.0),
+            poem::http::StatusCode::NOT_FOUND,
+        ))?;
+        crate::slo::slo_report(&self.pool, &service.name, &slo)
+            .await
+            .map(Json)
+            .map_err(internal_error)
+    }
+
+    /// Define or replace a service's SLO
+    #[oai(path = "/services/:name/slo", method = "put", tag = "ApiTags::Slo")]
+    async fn set_slo(&self, name: Path<String>, body: Json<SloConfig>, req: &poem::Request) -> Result<Json<SloConfig>> {
+        require_admin(req)?;
+        body.validate().map_err(|e| poem::Error::from_string(e, poem::http::StatusCode::BAD_REQUEST))?;
+        self.fetch_service(&name).await?;
+
+        sqlx::query("UPDATE services SET slo = $1 WHERE name = $2")
+            .bind(serde_json::to_string(&body.0).unwrap_or("{}".to_string()))
+            .bind(&name.0)
+            .execute(&self.pool)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(body.0))
+    }
+
+    /// Remove a service's SLO
+    #[oai(path = "/services/:name/slo", method = "delete", tag = "ApiTags::Slo")]
+    async fn delete_slo(&self, name: Path<String>, req: &poem::Request) -> Result<Json<bool>> {
+        require_admin(req)?;
+        let result = sqlx::query("UPDATE services SET slo = NULL WHERE name = $1 AND slo IS NOT NULL")
+            .bind(&name.0)
+            .execute(&self.pool)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(result.rows_affected() > 0))
+    }
+
+    /// Availability and latency over time for a model or service
+    #[oai(path = "/rollups/:scope/:id", method = "get", tag = "ApiTags::Slo")]
+    async fn get_rollups(
+        &self,
+        /// model or service
+        scope: Path<String>,
+        id: Path<String>,
+        /// Unix timestamp (default: 24 hours ago)
+        since: Query<Option<i64>>,
+        /// Unix timestamp (default: now)
+        until: Query<Option<i64>>,
+        /// Minutes per point (default 60)
+        step_minutes: Query<Option<i64>>,
+    ) -> Result<Json<Vec<RollupPoint>>> {
+        let scope = match scope.0.as_str() {
+            "model" => SCOPE_MODEL,
+            "service" => SCOPE_SERVICE,
+            other => {
+                return Err(poem::Error::from_string(
+                    format!("Unknown rollup scope '{}' (expected model or service)", other),
+                    poem::http::StatusCode::BAD_REQUEST,
+                ));
+            }
+        };
+        let now = chrono::Utc::now().timestamp();
+        let until = until.0.unwrap_or(now);
+        let since = since.0.unwrap_or(until - 86_400);
+        let step_minutes = step_minutes.0.unwrap_or(60).clamp(1, 7 * 24 * 60);
+
+        crate::slo::rollup_series(&self.pool, scope, &id.0, since, until, step_minutes)
+            .await
+            .map(Json)
+            .map_err(internal_error)
+    }
+}
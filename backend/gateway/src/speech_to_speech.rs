@@ -1,6 +1,6 @@
 use poem::{web::{Data, Multipart}, handler, Response, Body};
 use std::sync::Arc;
-use crate::executor::Executor;
+use crate::executor::{Executor, RequestRejection};
 use mawi_core::types::SpeechToSpeechRequest;
 
 #[handler]
@@ -61,7 +61,7 @@ pub async fn speech_to_speech_endpoint(
         .await
         .map_err(|e| poem::Error::from_string(
             format!("Speech-to-speech failed: {}", e),
-            poem::http::StatusCode::INTERNAL_SERVER_ERROR
+            RequestRejection::status_for(&e)
         ))?;
 
     Ok(Response::builder()
@@ -22,6 +22,8 @@ pub struct ServiceModelInfo {
     pub modality: String,
     pub is_healthy: Option<bool>,
     pub health_status: Option<String>,
+    /// Out of rotation through a maintenance window on the model or its provider
+    pub drained: bool,
 }
 
 #[derive(Debug, Serialize, Object, sqlx::FromRow, Clone)]
@@ -37,6 +39,8 @@ pub struct TopologyResponse {
     pub providers: Vec<ProviderResponse>,
     pub services: Vec<ServiceWithModels>,
     pub models: Vec<Model>,
+    /// Active and upcoming maintenance windows for the user's models and providers
+    pub maintenance: Vec<crate::maintenance::MaintenanceWindow>,
 }
 
 pub struct TopologyApi {
@@ -136,7 +140,12 @@ impl TopologyApi {
             let models = sqlx::query_as::<_, ServiceModelInfo>(
                 "SELECT sm.model_id, m.name as model_name, sm.position,
                         sm.weight, m.provider_id, m.modality,
-                        h.is_healthy, NULL as health_status
+                        h.is_healthy, NULL as health_status,
+                        EXISTS (
+                            SELECT 1 FROM active_maintenance am
+                            WHERE (am.scope = 'model' AND am.resource_id = m.id)
+                               OR (am.scope = 'provider' AND am.resource_id = m.provider_id)
+                        ) AS drained
                  FROM service_models sm
                  JOIN models m ON sm.model_id = m.id
                  LEFT JOIN model_health h ON m.id = h.model_id
@@ -156,6 +165,7 @@ impl TopologyApi {
                 }
                 // Health status string
                 m.health_status = match m.is_healthy {
+                    _ if m.drained => Some("drained".to_string()),
                     Some(true) => Some("healthy".to_string()),
                     Some(false) => Some("unhealthy".to_string()),
                     None => Some("unknown".to_string()),
@@ -183,10 +193,27 @@ impl TopologyApi {
             });
         }
 
+        let maintenance = sqlx::query_as::<_, crate::maintenance::MaintenanceWindow>(
+            "SELECT w.id, w.scope, w.resource_id, w.starts_at, w.ends_at, w.reason, w.created_by,
+                    (w.starts_at <= EXTRACT(EPOCH FROM NOW())::BIGINT
+                     AND (w.ends_at IS NULL OR w.ends_at > EXTRACT(EPOCH FROM NOW())::BIGINT)) AS active
+             FROM maintenance_windows w
+             LEFT JOIN models m ON w.scope = 'model' AND w.resource_id = m.id
+             LEFT JOIN providers p ON w.scope = 'provider' AND w.resource_id = p.id
+             WHERE (m.user_id = $1 OR p.user_id = $1)
+             AND (w.ends_at IS NULL OR w.ends_at > EXTRACT(EPOCH FROM NOW())::BIGINT)
+             ORDER BY w.starts_at"
+        )
+        .bind(&user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default();
+
         Ok(Json(TopologyResponse {
             providers: providers.into_iter().map(ProviderResponse::from).collect(),
             services: services_with_models,
             models: all_models,
+            maintenance,
         }))
     }
 }
@@ -1,6 +1,6 @@
 use poem::{web::{Data, Multipart}, handler};
 use std::sync::Arc;
-use crate::executor::Executor;
+use crate::executor::{Executor, RequestRejection};
 use mawi_core::types::{AudioTranscriptionRequest, AudioTranscriptionResponse};
 use poem::web::Json;
 
@@ -69,7 +69,7 @@ pub async fn transcribe_audio(
         .await
         .map_err(|e| poem::Error::from_string(
             format!("Transcription failed: {}", e),
-            poem::http::StatusCode::INTERNAL_SERVER_ERROR
+            RequestRejection::status_for(&e)
         ))?;
 
     Ok(Json(AudioTranscriptionResponse { text }))
@@ -0,0 +1,164 @@
+//! Upstream Throttling
+//!
+//! Keeps traffic on each provider key under the provider's own limits: the per-minute
+//! limits configured on the provider, and the budget the provider last reported in its
+//! rate-limit headers (see `mawi_core::providers::limits`). A request waits briefly for
+//! budget to come back, and fails over once the wait would be too long, instead of being
+//! sent upstream only to collect a 429. Keys close to exhaustion are tried last.
+
+use dashmap::DashMap;
+use mawi_core::models::Provider;
+use mawi_core::providers::limits;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::rate_limit::Bucket;
+
+#[derive(Debug, Clone)]
+pub struct UpstreamThrottleConfig {
+    /// Longest a request waits locally for provider budget before failing over
+    pub max_queue: Duration,
+    /// Keys with less than this fraction of their budget left are tried last
+    pub steer_below: f64,
+}
+
+impl Default for UpstreamThrottleConfig {
+    fn default() -> Self {
+        Self { max_queue: Duration::from_secs(2), steer_below: 0.1 }
+    }
+}
+
+impl UpstreamThrottleConfig {
+    /// Read `UPSTREAM_MAX_QUEUE_MS` and `UPSTREAM_STEER_HEADROOM`
+    pub fn from_env() -> Self {
+        fn env<T: std::str::FromStr>(key: &str) -> Option<T> {
+            std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            max_queue: env::<u64>("UPSTREAM_MAX_QUEUE_MS").map(Duration::from_millis).unwrap_or(defaults.max_queue),
+            steer_below: env::<f64>("UPSTREAM_STEER_HEADROOM").map(|f| f.clamp(0.0, 1.0)).unwrap_or(defaults.steer_below),
+        }
+    }
+}
+
+/// A provider key has no budget left for longer than the request may wait. Not an upstream
+/// failure: the attempt fails over without counting against health or circuits.
+#[derive(Debug)]
+pub struct UpstreamThrottled {
+    pub provider: String,
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for UpstreamThrottled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is at its rate limit, capacity returns in {:.1}s", self.provider, self.retry_after.as_secs_f64())
+    }
+}
+
+impl std::error::Error for UpstreamThrottled {}
+
+/// Whether an attempt failed for lack of provider capacity (throttled locally, a 429, or
+/// no concurrency slot in time) rather than because the provider is unhealthy
+pub fn is_capacity_error(error: &anyhow::Error) -> bool {
+    error.is::<UpstreamThrottled>()
+        || error.is::<crate::admission::QueueTimeout>()
+        || error
+            .downcast_ref::<mawi_core::providers::UpstreamHttpError>()
+            .is_some_and(|e| e.status == 429)
+}
+
+pub struct UpstreamThrottle {
+    config: UpstreamThrottleConfig,
+    // Configured per-minute budgets, keyed "{kind}:{key}"
+    buckets: DashMap<String, Bucket>,
+}
+
+impl UpstreamThrottle {
+    pub fn new(config: UpstreamThrottleConfig) -> Self {
+        Self { config, buckets: DashMap::new() }
+    }
+
+    /// Identity of the key a provider call uses; keyless providers share one budget
+    fn key(provider: &Provider, api_key: &str) -> String {
+        if api_key.is_empty() {
+            format!("provider:{}", provider.id)
+        } else {
+            limits::key_id(api_key)
+        }
+    }
+
+    /// Reserve budget for a call of `tokens`, or say how long until there is some
+    fn reserve(&self, provider: &Provider, key: &str, tokens: u64) -> Option<Duration> {
+        let now = Instant::now();
+        if let Some(wait) = limits::observed(key).and_then(|o| o.wait_for(tokens, now)) {
+            return Some(wait);
+        }
+
+        let configured = [
+            ("requests", provider.requests_per_minute, 1.0),
+            ("tokens", provider.tokens_per_minute, tokens as f64),
+        ];
+        let mut taken = Vec::new();
+        let mut wait = None;
+        for (kind, per_minute, cost) in configured {
+            let Some(capacity) = per_minute.filter(|n| *n > 0).map(f64::from) else { continue };
+            let cost = cost.min(capacity);
+            let bucket_key = format!("{}:{}", kind, key);
+            let balance = self.buckets
+                .entry(bucket_key.clone())
+                .or_insert(Bucket { tokens: capacity, refilled_at: now })
+                .take(capacity, cost, now);
+            taken.push((bucket_key, cost));
+            if balance < 0.0 {
+                wait = wait.max(Some(Duration::from_secs_f64(-balance * 60.0 / capacity)));
+            }
+        }
+        if wait.is_some() {
+            for (bucket_key, cost) in taken {
+                if let Some(mut bucket) = self.buckets.get_mut(&bucket_key) {
+                    bucket.tokens += cost;
+                }
+            }
+        }
+        wait
+    }
+
+    /// Wait for budget on the provider key for a call of about `tokens`. Fails with
+    /// `UpstreamThrottled` when budget won't be back within `max_queue`.
+    pub async fn acquire(&self, provider: &Provider, api_key: &str, tokens: u64) -> Result<(), UpstreamThrottled> {
+        let key = Self::key(provider, api_key);
+        let started = Instant::now();
+        let mut queued = false;
+        while let Some(wait) = self.reserve(provider, &key, tokens) {
+            if started.elapsed() + wait > self.config.max_queue {
+                crate::metrics::UPSTREAM_THROTTLED.with_label_values(&[&provider.name, "rejected"]).inc();
+                return Err(UpstreamThrottled { provider: provider.name.clone(), retry_after: wait });
+            }
+            debug!(provider = %provider.name, wait_ms = wait.as_millis() as u64, "waiting for provider rate limit");
+            queued = true;
+            tokio::time::sleep(wait).await;
+        }
+        if queued {
+            crate::metrics::UPSTREAM_THROTTLED.with_label_values(&[&provider.name, "queued"]).inc();
+        }
+        Ok(())
+    }
+
+    /// Whether the key is close enough to its limits that routing should try it last
+    pub fn near_exhaustion(&self, provider: &Provider, api_key: &str) -> bool {
+        let key = Self::key(provider, api_key);
+        let now = Instant::now();
+        let observed = limits::observed(&key).and_then(|o| o.headroom(now));
+        let configured = [("requests", provider.requests_per_minute), ("tokens", provider.tokens_per_minute)]
+            .into_iter()
+            .filter_map(|(kind, per_minute)| {
+                let capacity = per_minute.filter(|n| *n > 0).map(f64::from)?;
+                let mut bucket = *self.buckets.get(&format!("{}:{}", kind, key))?;
+                Some(bucket.take(capacity, 0.0, now) / capacity)
+            })
+            .fold(None, |tightest: Option<f64>, f| Some(tightest.map_or(f, |t| t.min(f))));
+
+        observed.into_iter().chain(configured).any(|headroom| headroom < self.config.steer_below)
+    }
+}
@@ -43,6 +43,7 @@ pub struct ApiKeyInfo {
     created_at: String,
     expires_at: Option<String>,
     last_used_at: Option<String>,
+    priority: Option<String>, // Queue priority for requests made with the key
 }
 
 #[derive(Serialize, Object)]
@@ -81,10 +82,12 @@ pub struct QuotaStatusResponse {
 pub struct CreateApiKeyRequest {
     name: String,
     expires_in_days: Option<i64>, // None = never
+    priority: Option<String>, // low | normal | high; None = the plan's default
 }
 
 pub struct UserApi {
     pub pool: PgPool,
+    pub key_pool: std::sync::Arc<crate::key_pool::KeyPool>,
 }
 
 #[OpenApi]
@@ -451,30 +454,13 @@ impl UserApi {
         let (model_name, provider_id, modality) = model;
 
         // Use HealthMonitor to check health
-        let monitor = crate::health::HealthMonitor::new(self.pool.clone());
+        let monitor = crate::health::HealthMonitor::new(self.pool.clone(), self.key_pool.clone());
         let health = monitor.ping_single_model(&model_id.0, &model_name, &provider_id, &modality).await;
 
-        // Update health table
-        sqlx::query(
-            "INSERT INTO model_health 
-             (model_id, is_healthy, last_check, response_time_ms, consecutive_failures, last_error)
-             VALUES ($1, $2, $3, $4, $5, $6)
-             ON CONFLICT (model_id) DO UPDATE SET 
-               is_healthy = EXCLUDED.is_healthy,
-               last_check = EXCLUDED.last_check,
-               response_time_ms = EXCLUDED.response_time_ms,
-               consecutive_failures = EXCLUDED.consecutive_failures,
-               last_error = EXCLUDED.last_error"
-        )
-        .bind(&model_id.0)
-        .bind(health.is_healthy as i64)
-        .bind(chrono::Utc::now().timestamp())
-        .bind(health.response_time_ms)
-        .bind(health.consecutive_failures)
-        .bind(&health.last_error)
-        .execute(&self.pool)
-        .await
-        .map_err(|e: sqlx::Error| poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
+        // Update health table and history
+        monitor.record(&model_id.0, &health)
+            .await
+            .map_err(|e| poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
 
         Ok(Json(json!({
             "model_id": model_id.0,
@@ -506,7 +492,7 @@ impl UserApi {
         };
 
         let rows = sqlx::query(
-            "SELECT id, name, CAST(created_at AS TEXT) as created_at_str, CAST(expires_at AS TEXT) as expires_at_str, CAST(last_used_at AS TEXT) as last_used_at_str 
+            "SELECT id, name, priority, CAST(created_at AS TEXT) as created_at_str, CAST(expires_at AS TEXT) as expires_at_str, CAST(last_used_at AS TEXT) as last_used_at_str 
              FROM api_keys 
              WHERE user_id = $1 
              ORDER BY created_at DESC"
@@ -541,6 +527,7 @@ impl UserApi {
                 created_at: parse_ts("created_at_str").unwrap_or_default(),
                 expires_at: parse_ts("expires_at_str"),
                 last_used_at: parse_ts("last_used_at_str"),
+                priority: row.try_get("priority").ok().flatten(),
             }
         }).collect();
 
@@ -565,6 +552,17 @@ impl UserApi {
             Err(_) => return Err(poem::Error::from_string("Invalid session", StatusCode::UNAUTHORIZED)),
         };
 
+        let priority = match body.priority.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            Some(p) => match crate::admission::Priority::parse(p) {
+                Some(priority) => Some(priority.as_str()),
+                None => return Err(poem::Error::from_string(
+                    format!("Unknown priority '{}' (expected one of: {})", p, crate::admission::PRIORITIES.join(", ")),
+                    StatusCode::BAD_REQUEST,
+                )),
+            },
+            None => None,
+        };
+
         // Generate ID and Key
         let raw_key = format!("sk_live_{}", uuid::Uuid::new_v4().simple());
         // For actual security, we should generate a high-entropy random string
@@ -593,8 +591,8 @@ impl UserApi {
         );
 
         sqlx::query(
-            "INSERT INTO api_keys (id, user_id, name, key_hash, created_at, expires_at)
-             VALUES ($1, $2, $3, $4, $5, $6)"
+            "INSERT INTO api_keys (id, user_id, name, key_hash, created_at, expires_at, priority)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)"
         )
         .bind(&db_id)
         .bind(&user.id)
@@ -602,6 +600,7 @@ impl UserApi {
         .bind(&key_hash)
         .bind(created_at)
         .bind(expires_at)
+        .bind(priority)
         .execute(&self.pool)
         .await
         .map_err(|e| poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
@@ -1,6 +1,6 @@
 use poem::{web::Json, handler};
 use std::sync::Arc;
-use crate::executor::Executor;
+use crate::executor::{Executor, RequestRejection};
 use mawi_core::types::{VideoGenerationRequest, VideoGenerationResponse};
 use poem::web::Data;
 
@@ -20,31 +20,32 @@ pub async fn generate_video(
     #[cfg(not(debug_assertions))]
     eprintln!("📝 Prompt: [REDACTED]");
 
-    let mut response: VideoGenerationResponse = executor
+    // Job IDs come back tagged with the resolved model ID for frontend polling
+    let response: VideoGenerationResponse = executor
         .execute_video_generation(&req.0, &user.id)
         .await
         .map_err(|e| poem::Error::from_string(
             format!("Video generation failed: {}", e),
-            poem::http::StatusCode::INTERNAL_SERVER_ERROR
+            RequestRejection::status_for(&e)
         ))?;
 
-    // Append model ID to job ID for frontend polling
-    if let Some(url) = &response.url {
-        if url.starts_with("JOB_ID:") {
-            response.url = Some(format!("{}|MODEL:{}", url, req.model));
-        }
-    }
-
     Ok(Json(response))
 }
 
+/// Service a video job was routed through (the `SERVICE:` part of its job tag)
+#[derive(serde::Deserialize)]
+pub struct JobQuery {
+    service: Option<String>,
+}
+
 #[handler]
 pub async fn poll_video_job(
     poem::web::Path((job_id, model_id)): poem::web::Path<(String, String)>,
+    poem::web::Query(query): poem::web::Query<JobQuery>,
     executor: Data<&Arc<Executor>>,
 ) -> poem::Result<poem::web::Json<serde_json::Value>> {
-    let status = executor.poll_video_job(&job_id, &model_id).await
-        .map_err(|e| poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+    let status = executor.poll_video_job(&job_id, &model_id, query.service.as_deref()).await
+        .map_err(|e| poem::Error::from_string(e.to_string(), RequestRejection::status_for(&e)))?;
     
     Ok(poem::web::Json(status))
 }
@@ -52,11 +53,12 @@ pub async fn poll_video_job(
 #[handler]
 pub async fn proxy_video_content(
     poem::web::Path((generation_id, model_id)): poem::web::Path<(String, String)>,
+    poem::web::Query(query): poem::web::Query<JobQuery>,
     executor: Data<&Arc<Executor>>,
 ) -> poem::Result<poem::Response> {
     // Get video content from provider with authentication
-    let video_data = executor.get_video_content(&generation_id, &model_id).await
-        .map_err(|e| poem::Error::from_string(e.to_string(), poem::http::StatusCode::INTERNAL_SERVER_ERROR))?;
+    let video_data = executor.get_video_content(&generation_id, &model_id, query.service.as_deref()).await
+        .map_err(|e| poem::Error::from_string(e.to_string(), RequestRejection::status_for(&e)))?;
     
     Ok(poem::Response::builder()
         .content_type("video/mp4")
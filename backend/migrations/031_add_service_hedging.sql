@@ -0,0 +1,12 @@
+-- Hedged requests: per-service hedge policy and per-attempt logging
+
+-- JSON HedgeConfig (enabled, delay_ms, max_fanout, charge_cancelled)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS hedge_config TEXT;
+
+-- Attempts that raced against a hedge (winner and losers)
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS hedged BOOLEAN DEFAULT FALSE;
+
+-- Time to first streamed token, used to derive hedge delays
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS ttft_ms BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_request_logs_ttft ON request_logs(model_id, created_at) WHERE ttft_ms IS NOT NULL;
@@ -0,0 +1,25 @@
+-- A/B experiments and canary rollouts on services
+
+-- JSON ExperimentConfig (name, arms, sticky_by, starts_at, ends_at)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS experiment_config TEXT;
+
+-- Experiment assignment per request
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS experiment TEXT;
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS experiment_arm TEXT;
+
+-- Response ID returned to the client, so feedback can be joined back to the request
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS response_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_request_logs_experiment ON request_logs(experiment, experiment_arm) WHERE experiment IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_request_logs_response_id ON request_logs(response_id);
+
+-- User feedback on responses (e.g. thumbs up/down from a chat UI)
+CREATE TABLE IF NOT EXISTS request_feedback (
+    id TEXT PRIMARY KEY,
+    response_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    score INTEGER NOT NULL,          -- -1 (negative) to 1 (positive)
+    comment TEXT,
+    created_at BIGINT DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT),
+    UNIQUE (response_id, user_id)
+);
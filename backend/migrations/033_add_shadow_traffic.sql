@@ -0,0 +1,28 @@
+-- Shadow traffic mirroring to candidate models
+
+-- JSON ShadowConfig (enabled, sample_percent, model_ids, similarity, monthly_budget_usd)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS shadow_config TEXT;
+
+-- Shadow replays, kept apart from request_logs so they never touch caller analytics or quota
+CREATE TABLE IF NOT EXISTS shadow_results (
+    id TEXT PRIMARY KEY,
+    service_name TEXT NOT NULL,
+    primary_response_id TEXT NOT NULL,  -- Response returned to the client
+    primary_model_id TEXT NOT NULL,
+    shadow_model_id TEXT NOT NULL,
+    status TEXT NOT NULL,               -- "success", "error"
+    error_message TEXT,
+    primary_text TEXT,
+    response_text TEXT,
+    primary_latency_ms BIGINT,
+    latency_ms BIGINT NOT NULL,
+    tokens_prompt INTEGER,
+    tokens_completion INTEGER,
+    cost_usd DOUBLE PRECISION,          -- Counts against the service's shadow budget
+    similarity DOUBLE PRECISION,        -- 0-1 similarity to the primary answer, if enabled
+    user_id TEXT,
+    created_at BIGINT DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT)
+);
+
+CREATE INDEX IF NOT EXISTS idx_shadow_results_service ON shadow_results(service_name, created_at);
+CREATE INDEX IF NOT EXISTS idx_shadow_results_model ON shadow_results(shadow_model_id);
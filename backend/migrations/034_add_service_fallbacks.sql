@@ -0,0 +1,4 @@
+-- Cross-service fallback chains
+
+-- JSON array of service names, tried in order when every model in this service fails
+ALTER TABLE services ADD COLUMN IF NOT EXISTS fallback_services TEXT DEFAULT '[]';
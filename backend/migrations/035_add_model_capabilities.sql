@@ -0,0 +1,10 @@
+-- Model capability metadata for candidate filtering
+-- NULL means unknown and is treated as supported, so existing models keep routing
+
+ALTER TABLE models ADD COLUMN IF NOT EXISTS max_output_tokens INTEGER;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS supports_vision BOOLEAN;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS supports_tools BOOLEAN;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS supports_json_mode BOOLEAN;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS supports_json_schema BOOLEAN;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS supports_streaming BOOLEAN;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS supports_reasoning BOOLEAN;
@@ -0,0 +1,4 @@
+-- Per-modality routing for MULTI_MODALITY pools
+
+-- JSON object of modality -> strategy ("text", "image", "audio", "video"); missing entries use services.strategy
+ALTER TABLE services ADD COLUMN IF NOT EXISTS modality_strategies TEXT DEFAULT '{}';
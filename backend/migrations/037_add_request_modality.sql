@@ -0,0 +1,4 @@
+-- Media requests (image, audio, video) share the chat routing pipeline and request_logs
+
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS modality TEXT DEFAULT 'text';
+CREATE INDEX IF NOT EXISTS idx_request_logs_modality ON request_logs(modality, created_at);
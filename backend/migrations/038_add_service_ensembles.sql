@@ -0,0 +1,4 @@
+-- Ensemble strategy: parallel member calls combined by vote, judge or return-all
+
+-- JSON EnsembleConfig (size, combiner, judge_model_id)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS ensemble_config TEXT;
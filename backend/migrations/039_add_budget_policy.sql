@@ -0,0 +1,4 @@
+-- Budget-aware downgrade: route to cheaper models as caller quota runs down
+
+-- JSON BudgetPolicy (enabled, thresholds[{usage_percent, allowed_tiers, fallback_model_id}])
+ALTER TABLE services ADD COLUMN IF NOT EXISTS budget_policy TEXT;
@@ -0,0 +1,12 @@
+-- Configurable circuit breaker policies
+
+-- JSON CircuitPolicy for a service's models (consecutive_failures, error_rate_percent, window_secs,
+-- min_requests, open_secs, backoff_multiplier, max_open_secs)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS circuit_policy TEXT;
+
+-- Per-model and per-provider overrides, keyed by breaker key ('model:<id>' / 'provider:<id>')
+CREATE TABLE IF NOT EXISTS circuit_policies (
+    resource TEXT PRIMARY KEY,
+    policy TEXT NOT NULL,
+    updated_at BIGINT DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT)
+);
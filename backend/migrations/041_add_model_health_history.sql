@@ -0,0 +1,15 @@
+-- Active health probe history
+
+CREATE TABLE IF NOT EXISTS model_health_history (
+    id BIGSERIAL PRIMARY KEY,
+    model_id TEXT NOT NULL,
+    checked_at BIGINT NOT NULL,             -- Unix timestamp
+    is_healthy INTEGER NOT NULL,            -- 1 = healthy, 0 = unhealthy
+    response_time_ms BIGINT,
+    probe TEXT NOT NULL,                    -- completion | model_list
+    error TEXT,
+    FOREIGN KEY (model_id) REFERENCES models(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_model_health_history_model ON model_health_history(model_id, checked_at DESC);
+CREATE INDEX IF NOT EXISTS idx_model_health_history_checked ON model_health_history(checked_at);
@@ -0,0 +1,28 @@
+-- Per-minute health rollups and service SLOs
+
+-- JSON SloConfig (availability_target, latency_p95_ms, window_days)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS slo TEXT;
+
+-- Outcome counts and latency histogram per minute, per model ('model') and per service ('service').
+-- Latency buckets are non-cumulative counts with upper bounds in milliseconds.
+CREATE TABLE IF NOT EXISTS health_rollups (
+    scope TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    minute BIGINT NOT NULL,                 -- Unix timestamp, truncated to the minute
+    success_count BIGINT NOT NULL DEFAULT 0,
+    error_count BIGINT NOT NULL DEFAULT 0,
+    latency_sum_ms BIGINT NOT NULL DEFAULT 0,
+    latency_max_ms BIGINT NOT NULL DEFAULT 0,
+    latency_le_100 BIGINT NOT NULL DEFAULT 0,
+    latency_le_250 BIGINT NOT NULL DEFAULT 0,
+    latency_le_500 BIGINT NOT NULL DEFAULT 0,
+    latency_le_1000 BIGINT NOT NULL DEFAULT 0,
+    latency_le_2500 BIGINT NOT NULL DEFAULT 0,
+    latency_le_5000 BIGINT NOT NULL DEFAULT 0,
+    latency_le_10000 BIGINT NOT NULL DEFAULT 0,
+    latency_le_30000 BIGINT NOT NULL DEFAULT 0,
+    latency_gt_30000 BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, resource_id, minute)
+);
+
+CREATE INDEX IF NOT EXISTS idx_health_rollups_minute ON health_rollups(minute);
@@ -0,0 +1,24 @@
+-- Drain / maintenance mode for models and providers
+
+-- A drain is a window starting now; scheduled maintenance is a window starting later.
+-- ends_at NULL keeps the resource drained until it is explicitly undrained.
+CREATE TABLE IF NOT EXISTS maintenance_windows (
+    id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,                    -- model | provider
+    resource_id TEXT NOT NULL,
+    starts_at BIGINT NOT NULL,              -- Unix timestamp
+    ends_at BIGINT,
+    reason TEXT,
+    created_by TEXT,
+    created_at BIGINT DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT)
+);
+
+CREATE INDEX IF NOT EXISTS idx_maintenance_windows_resource ON maintenance_windows(scope, resource_id);
+CREATE INDEX IF NOT EXISTS idx_maintenance_windows_time ON maintenance_windows(starts_at, ends_at);
+
+-- Windows in effect right now
+CREATE OR REPLACE VIEW active_maintenance AS
+SELECT id, scope, resource_id, starts_at, ends_at, reason
+FROM maintenance_windows
+WHERE starts_at <= EXTRACT(EPOCH FROM NOW())::BIGINT
+  AND (ends_at IS NULL OR ends_at > EXTRACT(EPOCH FROM NOW())::BIGINT);
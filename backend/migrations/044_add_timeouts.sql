@@ -0,0 +1,10 @@
+-- Per-service and per-model timeouts
+-- Service timeouts are a JSON TimeoutPolicy; model columns override them field by field.
+-- NULL means inherit (service setting, then gateway default)
+
+ALTER TABLE services ADD COLUMN IF NOT EXISTS timeouts TEXT;
+
+ALTER TABLE models ADD COLUMN IF NOT EXISTS connect_timeout_ms INTEGER;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS timeout_ms INTEGER;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS first_token_timeout_ms INTEGER;
+ALTER TABLE models ADD COLUMN IF NOT EXISTS idle_timeout_ms INTEGER;
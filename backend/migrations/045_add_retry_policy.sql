@@ -0,0 +1,7 @@
+-- Per-service retry policy (JSON RetryPolicy)
+-- Retries against the same model are logged as their own attempts, separate from failovers
+
+ALTER TABLE services ADD COLUMN IF NOT EXISTS retry_policy TEXT;
+
+-- Which retry of the same model an attempt was (0 = first try); retried attempts have status 'retry'
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS retry_count INTEGER DEFAULT 0;
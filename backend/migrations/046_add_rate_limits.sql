@@ -0,0 +1,19 @@
+-- Request and token rate limits per API key, user, org and service
+
+-- subject '*' is the default for every subject in the scope without its own row
+CREATE TABLE IF NOT EXISTS rate_limits (
+    scope TEXT NOT NULL,                    -- api_key | user | org | service
+    subject TEXT NOT NULL,                  -- api_keys.id, users.id, organizations.id, services.name or '*'
+    requests_per_minute INTEGER,
+    tokens_per_minute INTEGER,
+    max_concurrent INTEGER,
+    updated_at BIGINT DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT),
+    PRIMARY KEY (scope, subject)
+);
+
+-- Token buckets shared by all replicas (RATE_LIMIT_BACKEND=postgres)
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limit_buckets (
+    bucket TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    refilled_at DOUBLE PRECISION NOT NULL   -- Unix timestamp with fractional seconds
+);
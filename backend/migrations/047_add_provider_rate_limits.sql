@@ -0,0 +1,5 @@
+-- Per-key rate limits the provider enforces, for gateway-side throttling.
+-- NULL means learn them from the provider's rate-limit response headers only.
+
+ALTER TABLE providers ADD COLUMN IF NOT EXISTS requests_per_minute INTEGER;
+ALTER TABLE providers ADD COLUMN IF NOT EXISTS tokens_per_minute INTEGER;
@@ -0,0 +1,22 @@
+-- Pools of API keys per provider (several org keys, Azure deployments)
+-- When a provider has usable pooled keys they replace providers.api_key; a model's own api_key still wins.
+
+CREATE TABLE IF NOT EXISTS provider_keys (
+    id TEXT PRIMARY KEY,
+    provider_id TEXT NOT NULL REFERENCES providers(id) ON DELETE CASCADE,
+    name TEXT,
+    api_key TEXT NOT NULL,                  -- Encrypted like providers.api_key
+    api_endpoint TEXT,                      -- Per-key base URL, e.g. one Azure deployment per key
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    quarantined_at BIGINT,                  -- Set when the provider rejects the key (401/403)
+    quarantine_reason TEXT,
+    created_at BIGINT DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT)
+);
+
+CREATE INDEX IF NOT EXISTS idx_provider_keys_provider ON provider_keys(provider_id);
+
+-- round_robin (default) | least_used | remaining_quota
+ALTER TABLE providers ADD COLUMN IF NOT EXISTS key_selection TEXT;
+
+-- Masked identifier of the credential a request used
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS credential TEXT;
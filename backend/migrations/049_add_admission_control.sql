@@ -0,0 +1,8 @@
+-- Concurrency caps for the admission queue. Calls past the cap wait their turn by
+-- priority, with tenants sharing the queue fairly. NULL means unlimited.
+
+ALTER TABLE models ADD COLUMN IF NOT EXISTS max_concurrent INTEGER;
+ALTER TABLE providers ADD COLUMN IF NOT EXISTS max_concurrent INTEGER;
+
+-- Queue priority for requests made with the key: low | normal | high (NULL = the plan's default)
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS priority TEXT;
@@ -0,0 +1,19 @@
+-- Idempotency keys for inference requests (Idempotency-Key header)
+
+-- One row per user and key: the request it was first used with, then the stored response.
+-- In-progress rows expire quickly so a crashed request doesn't hold its key forever;
+-- completed rows are replayed until expires_at.
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    user_id TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,             -- SHA-256 of the method, path and body
+    state TEXT NOT NULL DEFAULT 'in_progress', -- in_progress | completed
+    response_status INTEGER,
+    response_content_type TEXT,
+    response_body BYTEA,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,             -- Unix timestamp
+    PRIMARY KEY (user_id, idempotency_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_expires ON idempotency_keys(expires_at);
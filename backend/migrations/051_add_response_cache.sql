@@ -0,0 +1,17 @@
+-- Per-service response cache (JSON ResponseCachePolicy)
+ALTER TABLE services ADD COLUMN IF NOT EXISTS response_cache TEXT;
+
+-- Shared tier of the response cache, for services with `persistent` set.
+-- Entries are keyed per tenant and served until expires_at.
+CREATE TABLE IF NOT EXISTS response_cache (
+    cache_key TEXT PRIMARY KEY,             -- SHA-256 of the tenant and normalised request
+    service_name TEXT NOT NULL,
+    response TEXT NOT NULL,                 -- JSON of the cached response
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL              -- Unix timestamp
+);
+
+CREATE INDEX IF NOT EXISTS idx_response_cache_expires ON response_cache(expires_at);
+
+-- Responses served from the cache (logged at $0)
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS cache_hit BOOLEAN DEFAULT FALSE;
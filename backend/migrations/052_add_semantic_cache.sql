@@ -0,0 +1,22 @@
+-- Semantic tier of the response cache, for services whose response_cache policy sets
+-- `semantic` and `persistent`. Entries are scoped per service, tenant, RTCROS prompts
+-- (prompts_hash) and params; rows left over from earlier RTCROS prompts are deleted.
+CREATE TABLE IF NOT EXISTS semantic_cache (
+    id TEXT PRIMARY KEY,
+    service_name TEXT NOT NULL,
+    tenant TEXT NOT NULL,                   -- Org ID, or user ID outside an org
+    prompts_hash TEXT NOT NULL,
+    params_hash TEXT NOT NULL,
+    embedding_model_id TEXT NOT NULL,
+    embedding REAL[] NOT NULL,              -- Unit-length prompt embedding
+    response TEXT NOT NULL,                 -- JSON of the cached response
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL              -- Unix timestamp
+);
+
+CREATE INDEX IF NOT EXISTS idx_semantic_cache_scope
+    ON semantic_cache(service_name, tenant, prompts_hash, params_hash, embedding_model_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_semantic_cache_expires ON semantic_cache(expires_at);
+
+-- Similarity to the cached prompt for semantic cache hits
+ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS cache_similarity DOUBLE PRECISION;
@@ -0,0 +1,28 @@
+-- Guardrail definitions. Services attach them by ID (services.guardrails, a JSON array).
+CREATE TABLE IF NOT EXISTS guardrails (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    rule TEXT NOT NULL,                     -- JSON GuardrailRule, tagged by "type"
+    action TEXT NOT NULL DEFAULT 'block',   -- block | redact | warn | log
+    applies_to TEXT,                        -- input | output | both (NULL = the rule's default)
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at BIGINT,
+    updated_at BIGINT
+);
+
+-- Every time a guardrail fired, whatever its action
+CREATE TABLE IF NOT EXISTS guardrail_violations (
+    id BIGSERIAL PRIMARY KEY,
+    guardrail_id TEXT NOT NULL,
+    guardrail_name TEXT NOT NULL,
+    service_name TEXT NOT NULL,
+    user_id TEXT,
+    stage TEXT NOT NULL,                    -- input | output
+    action TEXT NOT NULL,
+    detail TEXT,                            -- which check matched, never the matched text
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_guardrail_violations_user ON guardrail_violations(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_guardrail_violations_guardrail ON guardrail_violations(guardrail_id, created_at);
@@ -0,0 +1,5 @@
+-- Migration 030 gave every model an 8192-token context window by default. Now that
+-- candidates are filtered on it, that would reject long prompts on larger models, so the
+-- filled-in value is forgotten: NULL means unknown and the window is not pre-checked.
+ALTER TABLE models ALTER COLUMN context_window DROP DEFAULT;
+UPDATE models SET context_window = NULL WHERE context_window = 8192;
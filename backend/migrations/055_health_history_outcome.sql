@@ -0,0 +1,5 @@
+-- Probe history stored rate-limited probes as healthy. Each row now keeps its outcome
+-- (healthy, failed or rate_limited), and is_healthy is 1 only for a healthy probe.
+ALTER TABLE model_health_history ADD COLUMN IF NOT EXISTS outcome TEXT NOT NULL DEFAULT 'failed';
+UPDATE model_health_history SET outcome = 'healthy' WHERE is_healthy = 1;
+UPDATE model_health_history SET outcome = 'rate_limited', is_healthy = 0 WHERE error LIKE 'Rate Limited (%';
@@ -0,0 +1,15 @@
+-- Shadow spend per service and month. Replays reserve their estimated cost here before they
+-- run and settle the difference afterwards, so concurrent replays can't overrun the budget.
+CREATE TABLE IF NOT EXISTS shadow_spend (
+    service_name TEXT NOT NULL,
+    month_start BIGINT NOT NULL,        -- Unix timestamp of the first of the month
+    spent_usd DOUBLE PRECISION NOT NULL DEFAULT 0,
+    PRIMARY KEY (service_name, month_start)
+);
+
+INSERT INTO shadow_spend (service_name, month_start, spent_usd)
+SELECT service_name, EXTRACT(EPOCH FROM date_trunc('month', NOW()))::BIGINT, COALESCE(SUM(cost_usd), 0)
+FROM shadow_results
+WHERE created_at >= EXTRACT(EPOCH FROM date_trunc('month', NOW()))::BIGINT
+GROUP BY service_name
+ON CONFLICT (service_name, month_start) DO NOTHING;
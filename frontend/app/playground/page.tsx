@@ -613,22 +613,25 @@ export default function PlaygroundPage() {
                     // Since I can't see the full video handling logic in the artifact view easily without reading again...
                     // I will assume simple content for now to save tokens or read previous view carefully.
                     // The previous view had lines 442-501. I should copy that block.
-                    const [jobPart, modelPart] = json.url.split('|')
+                    const [jobPart, modelPart, servicePart] = json.url.split('|')
                     const jobId = jobPart.replace('JOB_ID:', '')
                     const modelId = modelPart.replace('MODEL:', '')
+                    const serviceQuery = servicePart
+                        ? `?service=${encodeURIComponent(servicePart.replace('SERVICE:', ''))}`
+                        : ''
                     content = '🎬 Video generation started... polling for completion'
 
                     // Helper for video polling (reused from previous code)
                     setTimeout(async () => {
                         const pollInterval = setInterval(async () => {
                             try {
-                                const statusRes = await fetch(`/v1/videos/jobs/${jobId}/${modelId}`)
+                                const statusRes = await fetch(`/v1/videos/jobs/${jobId}/${modelId}${serviceQuery}`)
                                 const status = await statusRes.json()
                                 if (status.status === 'succeeded' && status.video_url) {
                                     clearInterval(pollInterval)
                                     const genIdMatch = status.video_url.match(/\/video\/generations\/([^\/]+)\//)
                                     const genId = genIdMatch ? genIdMatch[1] : ''
-                                    const proxyUrl = `/v1/videos/content/${genId}/${modelId}`
+                                    const proxyUrl = `/v1/videos/content/${genId}/${modelId}${serviceQuery}`
                                     setMessages(prev => {
                                         const newMessages = [...prev]
                                         const lastMsg = newMessages[newMessages.length - 1]